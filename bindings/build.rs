@@ -3,8 +3,10 @@ fn main() {
         Windows::Win32::Devices::PortableDevices::{
             IEnumPortableDeviceObjectIDs,
             IPortableDevice,
+            IPortableDeviceCapabilities,
             IPortableDeviceContent,
             IPortableDeviceDataStream,
+            IPortableDeviceEventCallback,
             IPortableDeviceKeyCollection,
             IPortableDeviceManager,
             IPortableDeviceProperties,
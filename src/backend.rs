@@ -0,0 +1,5 @@
+pub mod composite;
+pub mod content_object;
+pub mod device;
+pub mod manager;
+pub mod resource;
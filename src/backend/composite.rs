@@ -0,0 +1,122 @@
+use super::device::Device;
+use super::manager::{DeviceInfo, DeviceInfoIterator, Manager};
+
+/// A manager that combines devices of multiple backends.
+///
+/// Device IDs are prefixed with the index of the backend, like `<index>|<id>`.
+pub struct CompositeManager {
+    managers: Vec<Box<dyn Manager>>,
+}
+
+impl CompositeManager {
+    pub fn new() -> CompositeManager {
+        CompositeManager {
+            managers: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, manager: Box<dyn Manager>) {
+        self.managers.push(manager);
+    }
+}
+
+impl Manager for CompositeManager {
+    fn get_device_iterator(
+        &self,
+    ) -> Result<Box<dyn DeviceInfoIterator + '_>, Box<dyn std::error::Error>> {
+        Ok(Box::new(CompositeDeviceInfoIterator {
+            managers: &self.managers,
+            index: 0,
+            current: None,
+        }))
+    }
+
+    fn open_device(
+        &self,
+        info: &DeviceInfo,
+    ) -> Result<Box<dyn Device>, Box<dyn std::error::Error>> {
+        let (index_str, id) = match info.id.find('|') {
+            Some(pos) => (&info.id[..pos], &info.id[pos + 1..]),
+            None => return Err(format!("invalid device id: {}", &info.id).into()),
+        };
+        match index_str
+            .parse::<usize>()
+            .ok()
+            .and_then(|i| self.managers.get(i))
+        {
+            Some(manager) => manager.open_device(&DeviceInfo {
+                id: id.to_string(),
+                name: info.name.clone(),
+            }),
+            None => Err(format!("invalid device id: {}", &info.id).into()),
+        }
+    }
+}
+
+struct CompositeDeviceInfoIterator<'a> {
+    managers: &'a Vec<Box<dyn Manager>>,
+    index: usize,
+    current: Option<Box<dyn DeviceInfoIterator + 'a>>,
+}
+
+impl<'a> DeviceInfoIterator for CompositeDeviceInfoIterator<'a> {
+    fn next(&mut self) -> Result<Option<DeviceInfo>, Box<dyn std::error::Error>> {
+        loop {
+            if let Some(iter) = self.current.as_mut() {
+                if let Some(info) = iter.next()? {
+                    return Ok(Some(DeviceInfo {
+                        id: format!("{}|{}", self.index - 1, &info.id),
+                        name: info.name,
+                    }));
+                }
+                self.current = None;
+            }
+            match self.managers.get(self.index) {
+                Some(manager) => {
+                    self.current = Some(manager.get_device_iterator()?);
+                    self.index += 1;
+                }
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::manager::MemoryManager;
+
+    #[test]
+    fn test_composite_manager() {
+        let mut manager1 = MemoryManager::new();
+        manager1.add_device("Phone 1").add_storage("Internal");
+        manager1.add_device("Phone 2").add_storage("SD Card");
+        let mut manager2 = MemoryManager::new();
+        manager2.add_device("Camera").add_storage("Card");
+
+        let mut manager = CompositeManager::new();
+        manager.add(Box::new(manager1));
+        manager.add(Box::new(MemoryManager::new()));
+        manager.add(Box::new(manager2));
+
+        let mut device_infos = Vec::<DeviceInfo>::new();
+        let mut iter = manager.get_device_iterator().unwrap();
+        while let Some(info) = iter.next().unwrap() {
+            device_infos.push(info);
+        }
+        let names: Vec<&str> = device_infos.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(names, vec!["Phone 1", "Phone 2", "Camera"]);
+        let ids: Vec<&str> = device_infos.iter().map(|i| i.id.as_str()).collect();
+        assert_eq!(ids, vec!["0|0", "0|1", "2|0"]);
+
+        let device = manager.open_device(&device_infos[2]).unwrap();
+        assert_eq!(device.name(), "Camera");
+
+        let invalid = DeviceInfo {
+            id: "1|0".to_string(),
+            name: "Camera".to_string(),
+        };
+        assert!(manager.open_device(&invalid).is_err());
+    }
+}
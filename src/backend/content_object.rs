@@ -0,0 +1,68 @@
+use chrono::NaiveDateTime;
+
+/// Identifies an object on the device.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ContentObject {
+    /// Object ID (format depends on the backend)
+    pub id: String,
+}
+
+impl ContentObject {
+    pub fn new(id: String) -> ContentObject {
+        ContentObject { id }
+    }
+}
+
+/// Kind of the content object
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentObjectKind {
+    /// Functional object that represents the device itself
+    Device,
+    /// Functional object that represents a storage
+    Storage,
+    /// Other functional objects
+    FunctionalObject,
+    /// Folder
+    Folder,
+    /// File (any content that is not a folder)
+    File,
+}
+
+#[derive(Debug, Clone)]
+pub struct ContentObjectInfo {
+    pub content_object: ContentObject,
+    /// Name to display
+    pub name: String,
+    /// Kind of the object
+    pub kind: ContentObjectKind,
+    /// Size of the resource data
+    pub data_size: u64,
+    /// Hidden flag
+    pub is_hidden: bool,
+    /// System flag
+    pub is_system: bool,
+    /// Whether the object can be deleted
+    pub can_delete: bool,
+    /// Time created (or None if not provided)
+    pub time_created: Option<NaiveDateTime>,
+    /// Time modified (or None if not provided)
+    pub time_modified: Option<NaiveDateTime>,
+}
+
+impl ContentObjectInfo {
+    pub fn is_device(&self) -> bool {
+        self.kind == ContentObjectKind::Device
+    }
+
+    pub fn is_storage(&self) -> bool {
+        self.kind == ContentObjectKind::Storage
+    }
+
+    pub fn is_folder(&self) -> bool {
+        self.kind == ContentObjectKind::Folder
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.kind == ContentObjectKind::File
+    }
+}
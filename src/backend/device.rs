@@ -0,0 +1,138 @@
+use chrono::NaiveDateTime;
+use std::time::Duration;
+
+use super::content_object::{ContentObject, ContentObjectInfo};
+use super::resource::{ResourceReader, ResourceWriter};
+
+/// An opened portable device.
+///
+/// Objects on the device form a tree whose root is returned by
+/// `get_root_object()`. The root contains the device object, the device object
+/// contains the storage objects, and each storage contains folders and files.
+pub trait Device {
+    /// Returns the name of the device.
+    fn name(&self) -> &str;
+
+    fn get_root_object(&self) -> ContentObject;
+
+    fn get_object_iterator(
+        &self,
+        parent: &ContentObject,
+    ) -> Result<Box<dyn ContentObjectIterator>, Box<dyn std::error::Error>>;
+
+    fn get_object_info(
+        &self,
+        object: ContentObject,
+    ) -> Result<ContentObjectInfo, Box<dyn std::error::Error>>;
+
+    /// Opens the default resource (content data) of the object.
+    fn get_resoure(
+        &self,
+        object: &ContentObject,
+    ) -> Result<Box<dyn ResourceReader>, Box<dyn std::error::Error>>;
+
+    /// Creates a new file.
+    /// The object is created when `ResourceWriter::commit()` was called.
+    fn create_file(
+        &self,
+        parent: &ContentObject,
+        name: &str,
+        size: u64,
+        created: &Option<NaiveDateTime>,
+        modified: &Option<NaiveDateTime>,
+    ) -> Result<Box<dyn ResourceWriter>, Box<dyn std::error::Error>>;
+
+    fn create_folder(
+        &self,
+        parent: &ContentObject,
+        name: &str,
+    ) -> Result<ContentObject, Box<dyn std::error::Error>>;
+
+    /// Deletes the object. Folders are deleted recursively.
+    fn delete(&self, object: &ContentObject) -> Result<(), Box<dyn std::error::Error>>;
+
+    /// Returns whether the object can be renamed by `rename()`.
+    fn can_rename(&self, _object: &ContentObject) -> bool {
+        false
+    }
+
+    /// Renames the file or folder.
+    /// Returns the renamed object, whose ID may be changed by renaming.
+    fn rename(
+        &self,
+        object: &ContentObject,
+        _name: &str,
+    ) -> Result<ContentObject, Box<dyn std::error::Error>> {
+        Err(format!("renaming is not supported: {}", &object.id).into())
+    }
+
+    /// Returns whether the file can be copied on the device by `copy_object()`.
+    fn can_copy_on_device(&self, _object: &ContentObject) -> bool {
+        false
+    }
+
+    /// Returns whether the file can be moved on the device by `move_object()`.
+    fn can_move_on_device(&self, _object: &ContentObject) -> bool {
+        false
+    }
+
+    /// Copies the file into the folder on the device, without transferring the data to the host.
+    /// Returns the new object.
+    fn copy_object(
+        &self,
+        object: &ContentObject,
+        _parent: &ContentObject,
+    ) -> Result<ContentObject, Box<dyn std::error::Error>> {
+        Err(format!("copying on the device is not supported: {}", &object.id).into())
+    }
+
+    /// Moves the file into the folder on the device, without transferring the data to the host.
+    /// Returns the moved object, whose ID may be changed by moving.
+    fn move_object(
+        &self,
+        object: &ContentObject,
+        _parent: &ContentObject,
+    ) -> Result<ContentObject, Box<dyn std::error::Error>> {
+        Err(format!("moving on the device is not supported: {}", &object.id).into())
+    }
+
+    /// Starts receiving events from the device.
+    /// Events are delivered until the subscription is dropped.
+    fn subscribe_events(&self) -> Result<Box<dyn EventSubscription>, Box<dyn std::error::Error>> {
+        Err(format!("events are not supported: {}", self.name()).into())
+    }
+}
+
+/// Iterates child objects.
+pub trait ContentObjectIterator {
+    fn next(&mut self) -> Result<Option<ContentObject>, Box<dyn std::error::Error>>;
+}
+
+/// Notification from the device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceEvent {
+    /// A file or folder was added.
+    /// The parent is given if the device told it.
+    ObjectAdded {
+        object: ContentObject,
+        parent: Option<ContentObject>,
+    },
+    /// A file or folder was removed.
+    ObjectRemoved(ContentObject),
+    /// Properties of a file or folder were changed.
+    ObjectInfoChanged(ContentObject),
+    StorageAdded(ContentObject),
+    StorageRemoved(ContentObject),
+    /// The device was disconnected. No more events are delivered.
+    DeviceRemoved,
+}
+
+/// Receives events from the device.
+pub trait EventSubscription {
+    /// Waits for the next event.
+    /// Returns None if no event occurred within the timeout.
+    fn next_event(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Option<DeviceEvent>, Box<dyn std::error::Error>>;
+}
@@ -0,0 +1,24 @@
+use super::device::Device;
+
+#[derive(Debug, Clone)]
+pub struct DeviceInfo {
+    /// Device ID (format depends on the backend)
+    pub id: String,
+    /// Name to display
+    pub name: String,
+}
+
+/// Enumerates and opens portable devices.
+pub trait Manager {
+    fn get_device_iterator(
+        &self,
+    ) -> Result<Box<dyn DeviceInfoIterator + '_>, Box<dyn std::error::Error>>;
+
+    fn open_device(&self, info: &DeviceInfo)
+        -> Result<Box<dyn Device>, Box<dyn std::error::Error>>;
+}
+
+/// Iterates detected devices.
+pub trait DeviceInfoIterator {
+    fn next(&mut self) -> Result<Option<DeviceInfo>, Box<dyn std::error::Error>>;
+}
@@ -0,0 +1,15 @@
+use super::content_object::ContentObject;
+
+/// Reads the resource data of an object.
+pub trait ResourceReader {
+    fn get_optimized_buffer_size(&self) -> u32;
+    fn next(&mut self, max_size: u32) -> Result<Option<&[u8]>, Box<dyn std::error::Error>>;
+}
+
+/// Writes the resource data of a new object.
+pub trait ResourceWriter {
+    fn get_buffer_size(&self) -> u32;
+    fn write(&mut self, data: &[u8]) -> Result<(), Box<dyn std::error::Error>>;
+    /// Completes writing, and returns the created object.
+    fn commit(&mut self) -> Result<ContentObject, Box<dyn std::error::Error>>;
+}
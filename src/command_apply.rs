@@ -0,0 +1,148 @@
+use std::path::Path;
+
+use crate::backend::manager::Manager;
+use crate::command_copy::apply_plan;
+use crate::copy::copy_processor::CopyOptions;
+use crate::copy::plan::TransferPlan;
+
+pub fn command_apply(
+    manager: &dyn Manager,
+    plan_file: &str,
+    options: &CopyOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    log::trace!("COMMAND apply");
+    log::trace!("  plan_file = {}", plan_file);
+    log::trace!("  options = {:?}", options);
+
+    let plan = TransferPlan::load(Path::new(plan_file))?;
+    apply_plan(manager, &plan, options, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command_copy::command_copy;
+    use crate::memory::device::MemoryDevice;
+    use crate::memory::manager::MemoryManager;
+    use crate::Paths;
+
+    fn setup() -> (MemoryManager, MemoryDevice, MemoryDevice) {
+        let mut manager = MemoryManager::new();
+        let src = manager.add_device("Src Phone");
+        let dest = manager.add_device("Dest Phone");
+        let storage = src.add_storage("Internal");
+        let music = src.add_folder(&storage, "Music");
+        src.add_file(&music, "a.mp3", b"aaaa");
+        let album = src.add_folder(&music, "Album");
+        src.add_file(&album, "b.mp3", b"bbbbbb");
+        let storage = dest.add_storage("SD Card");
+        let music = dest.add_folder(&storage, "Music");
+        dest.add_file(&music, "extra.mp3", b"x");
+        (manager, src, dest)
+    }
+
+    fn save_plan(
+        manager: &MemoryManager,
+        plan_file: &Path,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let paths = Paths {
+            src: "Src Phone:Internal:\\Music".to_string(),
+            dest: "Dest Phone:SD Card:\\Music".to_string(),
+        };
+        let options = CopyOptions {
+            recursive: true,
+            mirror: true,
+            plan_file: Some(plan_file.to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+        command_copy(manager, &paths, &options)
+    }
+
+    #[test]
+    fn test_apply_saved_plan() -> Result<(), Box<dyn std::error::Error>> {
+        let (manager, _src, dest) = setup();
+        let tempdir = tempfile::tempdir()?;
+        let plan_file = tempdir.path().join("plan.json");
+
+        save_plan(&manager, &plan_file)?;
+        let music = dest.find("SD Card\\Music").unwrap();
+        assert_eq!(dest.child_names(&music), vec!["extra.mp3"]);
+
+        command_apply(
+            &manager,
+            plan_file.to_str().unwrap(),
+            &CopyOptions::default(),
+        )?;
+        let mut names = dest.child_names(&music);
+        names.sort();
+        assert_eq!(names, vec!["Album", "a.mp3"]);
+        let file_b = dest.find("SD Card\\Music\\Album\\b.mp3").unwrap();
+        assert_eq!(dest.data(&file_b), b"bbbbbb");
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_fails_if_source_was_changed() -> Result<(), Box<dyn std::error::Error>> {
+        let (manager, src, dest) = setup();
+        let tempdir = tempfile::tempdir()?;
+        let plan_file = tempdir.path().join("plan.json");
+
+        save_plan(&manager, &plan_file)?;
+        let file_b = src.find("Internal\\Music\\Album\\b.mp3").unwrap();
+        src.set_data(&file_b, b"bbbbbbbb");
+
+        assert!(command_apply(
+            &manager,
+            plan_file.to_str().unwrap(),
+            &CopyOptions::default()
+        )
+        .is_err());
+        let music = dest.find("SD Card\\Music").unwrap();
+        assert_eq!(dest.child_names(&music), vec!["extra.mp3"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_fails_if_destination_was_changed() -> Result<(), Box<dyn std::error::Error>> {
+        let (manager, _src, dest) = setup();
+        let tempdir = tempfile::tempdir()?;
+        let plan_file = tempdir.path().join("plan.json");
+
+        save_plan(&manager, &plan_file)?;
+        let music = dest.find("SD Card\\Music").unwrap();
+        dest.add_file(&music, "a.mp3", b"new");
+
+        assert!(command_apply(
+            &manager,
+            plan_file.to_str().unwrap(),
+            &CopyOptions::default()
+        )
+        .is_err());
+        let file_a = dest.find("SD Card\\Music\\a.mp3").unwrap();
+        assert_eq!(dest.data(&file_a), b"new");
+        assert!(dest.find("SD Card\\Music\\extra.mp3").is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_invalid_plan() -> Result<(), Box<dyn std::error::Error>> {
+        let (manager, _src, _dest) = setup();
+        let tempdir = tempfile::tempdir()?;
+        let plan_file = tempdir.path().join("plan.json");
+        std::fs::write(&plan_file, b"{\"source\": \"x\"}")?;
+
+        assert!(command_apply(
+            &manager,
+            plan_file.to_str().unwrap(),
+            &CopyOptions::default()
+        )
+        .is_err());
+        assert!(command_apply(
+            &manager,
+            tempdir.path().join("none.json").to_str().unwrap(),
+            &CopyOptions::default()
+        )
+        .is_err());
+        Ok(())
+    }
+}
@@ -1,62 +1,365 @@
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 
-use crate::copy::copy_processor::CopyProcessor;
+use crate::backend::content_object::ContentObjectInfo;
+use crate::backend::device::Device;
+use crate::backend::manager::{DeviceInfo, Manager};
+use crate::copy::copy_processor::{CopyOptions, CopyProcessor};
 use crate::copy::destination_folder::DestinationFolder;
 use crate::copy::device_copy_processor::DeviceCopyProcessor;
 use crate::copy::device_destination_folder::DeviceDestinationFolder;
+use crate::copy::device_plan_endpoint::DevicePlanEndpoint;
+use crate::copy::executor::{Executor, PlanDestination, PlanSource};
 use crate::copy::file_info::FileInfo;
+use crate::copy::journal::{default_journal_path, Journal};
 use crate::copy::local_copy_processor::LocalCopyProcessor;
 use crate::copy::local_destination_folder::LocalDestinationFolder;
+use crate::copy::local_plan_endpoint::LocalPlanEndpoint;
+use crate::copy::plan::{normalize_path, Planner, TransferPlan};
+use crate::copy::planned_folder::PlannedFolder;
+use crate::copy::reporter::{create_reporter, report_plan, Event, Reporter};
+use crate::copy::source_cache::CachedPlanSource;
 use crate::finders::*;
 use crate::path::get_path_type;
 use crate::path::DeviceStoragePath;
 use crate::path::PathType;
 use crate::path::SEPARATORS;
 use crate::path::WILDCARD_CHARACTERS;
-use crate::wpd::device::ContentObjectInfo;
-use crate::wpd::device::Device;
-use crate::wpd::manager::DeviceInfo;
-use crate::wpd::manager::Manager;
 use crate::Paths;
 
 pub fn command_copy(
+    manager: &dyn Manager,
     paths: &Paths,
-    recursive: bool,
-    mirror: bool,
+    options: &CopyOptions,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    log::trace!("command_copy paths={:?}", paths);
-    let manager = Manager::get_portable_device_manager()?;
+    log::trace!("command_copy paths={:?} options={:?}", paths, options);
+    let reporter = create_reporter(options);
+    with_source(manager, &paths.src, |source, source_device| {
+        copy_from(
+            manager,
+            paths,
+            options,
+            reporter.as_ref(),
+            source,
+            source_device,
+        )
+    })
+}
 
+/// Copies the source to each of the destinations.
+/// The device name and the storage name of the source and the destinations can contain
+/// wildcard characters to match several devices or storages, e.g. `*:SD*:\Music`.
+pub fn command_copy_to_all(
+    manager: &dyn Manager,
+    src: &str,
+    dest_patterns: &[String],
+    options: &CopyOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    log::trace!(
+        "command_copy_to_all src={:?} dest_patterns={:?} options={:?}",
+        src,
+        dest_patterns,
+        options
+    );
+    let sources = expand_storages(manager, src)?;
+    let mut dests = Vec::<String>::new();
+    for pattern in dest_patterns {
+        dests.extend(expand_storages(manager, pattern)?);
+    }
+    if sources.len() == 1 && dests.len() == 1 {
+        let paths = Paths {
+            src: sources[0].clone(),
+            dest: dests.pop().unwrap(),
+        };
+        return command_copy(manager, &paths, options);
+    }
+    if options.plan_file.is_some() {
+        return Err("--plan cannot be used with multiple sources or destinations.".into());
+    }
+    if options.journal_file.is_some() {
+        return Err("--journal cannot be used with multiple sources or destinations.".into());
+    }
+
+    let reporter = create_reporter(options);
+    let mut failed = 0;
+    for src in sources.iter() {
+        if sources.len() > 1 {
+            reporter.report(&Event::Message {
+                text: &format!("source: \"{}\"", src),
+            });
+        }
+        with_source(manager, src, |source, source_device| {
+            // the files on the device are read once, and kept until all destinations are done
+            let mut cached_source;
+            let source: &mut dyn PlanSource = if get_path_type(src) == PathType::DeviceStorage {
+                cached_source = CachedPlanSource::new(source)?;
+                &mut cached_source
+            } else {
+                source
+            };
+
+            for dest in dests.iter() {
+                let paths = Paths {
+                    src: src.to_string(),
+                    dest: dest.clone(),
+                };
+                if options.resume && !journal_path(&paths, options).exists() {
+                    reporter.report(&Event::Message {
+                        text: &format!("nothing to resume: \"{}\"", dest),
+                    });
+                    continue;
+                }
+                reporter.report(&Event::Destination { destination: dest });
+                // the other destinations are copied even if one of them failed
+                let result = copy_from(
+                    manager,
+                    &paths,
+                    options,
+                    reporter.as_ref(),
+                    source,
+                    source_device,
+                );
+                if let Err(err) = result {
+                    reporter.report(&Event::Error {
+                        destination: Some(dest),
+                        message: &err.to_string(),
+                    });
+                    failed += 1;
+                }
+            }
+            Ok(())
+        })?;
+    }
+    if failed > 0 {
+        if sources.len() == 1 {
+            return Err(format!(
+                "failed to copy to {} of {} destinations.",
+                failed,
+                dests.len()
+            )
+            .into());
+        }
+        return Err(format!(
+            "{} of {} copies failed.",
+            failed,
+            sources.len() * dests.len()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Returns the paths matched by the pattern.
+/// Only the device name and the storage name are matched.
+fn expand_storages(
+    manager: &dyn Manager,
+    pattern: &str,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    if get_path_type(pattern) != PathType::DeviceStorage {
+        return Ok(vec![pattern.to_string()]);
+    }
+    let storage_path = DeviceStoragePath::from(pattern)?;
+    if !storage_path.device_name.contains(WILDCARD_CHARACTERS)
+        && !storage_path.storage_name.contains(WILDCARD_CHARACTERS)
+    {
+        return Ok(vec![pattern.to_string()]);
+    }
+
+    let mut paths = Vec::<String>::new();
+    for device_info in device_find_devices(manager, Some(&storage_path.device_name))? {
+        let device = manager.open_device(&device_info)?;
+        for storage_object in
+            device_find_storage_objects(device.as_ref(), Some(&storage_path.storage_name))?
+        {
+            paths.push(format!(
+                "{}:{}:{}",
+                &device_info.name, &storage_object.name, &storage_path.path
+            ));
+        }
+    }
+    if paths.is_empty() {
+        return Err(format!("no storage was matched: {}", pattern).into());
+    }
+    Ok(paths)
+}
+
+/// The device opened for the source.
+/// It is also used for the destination on the same device.
+type SourceDevice<'a> = Option<(&'a DeviceInfo, &'a dyn Device)>;
+
+/// Opens the source of the copy.
+fn with_source<F>(
+    manager: &dyn Manager,
+    src_path: &str,
+    f: F,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    F: FnOnce(&mut dyn PlanSource, SourceDevice) -> Result<(), Box<dyn std::error::Error>>,
+{
+    match get_path_type(src_path) {
+        PathType::DeviceStorage => {
+            let (device_info, device, storage_object) = open_storage(manager, src_path)?;
+            let mut source = DevicePlanEndpoint::new(device.as_ref(), storage_object);
+            f(&mut source, Some((&device_info, device.as_ref())))
+        }
+        PathType::Local => f(&mut LocalPlanEndpoint, None),
+        PathType::Invalid => Err("invalid source path.".into()),
+    }
+}
+
+/// Copies the source to the destination.
+fn copy_from(
+    manager: &dyn Manager,
+    paths: &Paths,
+    options: &CopyOptions,
+    reporter: &dyn Reporter,
+    source: &mut dyn PlanSource,
+    source_device: SourceDevice,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if options.resume {
+        if options.dry_run || options.plan_file.is_some() {
+            return Err("--resume cannot be used with --dry-run or --plan.".into());
+        }
+        let (journal, plan) = Journal::open(&journal_path(paths, options))?;
+        if plan.source != source_root(&paths.src)?
+            || plan.destination != normalize_path(&paths.dest)
+        {
+            return Err(format!(
+                "the journal is for another copy: \"{}\" -> \"{}\"",
+                &plan.source, &plan.destination
+            )
+            .into());
+        }
+        reporter.report(&Event::Message {
+            text: &format!(
+                "resuming: {} of {} actions were completed.",
+                journal.completed_count(),
+                plan.actions.len()
+            ),
+        });
+        return apply_plan_with_journal(
+            manager,
+            &plan,
+            source,
+            source_device,
+            options,
+            reporter,
+            journal,
+        );
+    }
+
+    let plan = plan_copy(manager, paths, options)?;
+
+    if let Some(plan_file) = options.plan_file.as_ref() {
+        report_plan(&plan, reporter);
+        plan.save(Path::new(plan_file))?;
+        reporter.report(&Event::Message {
+            text: &format!("plan was saved to \"{}\".", plan_file),
+        });
+        return Ok(());
+    }
+    if options.dry_run {
+        reporter.report(&Event::Message {
+            text: "dry run: nothing will be changed.",
+        });
+        report_plan(&plan, reporter);
+        return Ok(());
+    }
+    let journal = Journal::create(&journal_path(paths, options), &plan)?;
+    apply_plan_with_journal(
+        manager,
+        &plan,
+        source,
+        source_device,
+        options,
+        reporter,
+        journal,
+    )
+}
+
+fn journal_path(paths: &Paths, options: &CopyOptions) -> PathBuf {
+    match options.journal_file.as_ref() {
+        Some(journal_file) => PathBuf::from(journal_file),
+        None => default_journal_path(&normalize_path(&paths.src), &normalize_path(&paths.dest)),
+    }
+}
+
+fn apply_plan_with_journal(
+    manager: &dyn Manager,
+    plan: &TransferPlan,
+    source: &mut dyn PlanSource,
+    source_device: SourceDevice,
+    options: &CopyOptions,
+    reporter: &dyn Reporter,
+    journal: Journal,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let journal_path = journal.path().to_path_buf();
+    let result = apply_plan_from(
+        manager,
+        plan,
+        source,
+        source_device,
+        options,
+        reporter,
+        Some(journal),
+    );
+    // the journal is removed when all actions were completed, then nothing is left to resume
+    if result.is_err() && journal_path.exists() {
+        reporter.report(&Event::Message {
+            text: "the copy can be continued with \"--resume\".",
+        });
+    }
+    result
+}
+
+/// Makes the plan to copy the source to the destination.
+/// Nothing is changed on the destination.
+pub fn plan_copy(
+    manager: &dyn Manager,
+    paths: &Paths,
+    options: &CopyOptions,
+) -> Result<TransferPlan, Box<dyn std::error::Error>> {
     let src_path = paths.src.as_str();
     let dest_path = paths.dest.as_str();
 
     let src_path_type = get_path_type(src_path);
     let dest_path_type = get_path_type(dest_path);
 
-    if has_wildcard(src_path, src_path_type)? {
-        return Err("wildcard characters in the source path are not allowed.".into());
+    let src_has_wildcard = has_wildcard(src_path, src_path_type)?;
+    if src_has_wildcard {
+        if src_path_type != PathType::DeviceStorage {
+            return Err("wildcard characters in the local source path are not supported.".into());
+        }
+        if options.move_source {
+            return Err(
+                "wildcard characters in the source path cannot be used with \"move\".".into(),
+            );
+        }
     }
     if has_wildcard(dest_path, dest_path_type)? {
         return Err("wildcard characters in the destination path are not allowed.".into());
     }
+    if options.move_source && !options.recursive {
+        // the contents of the folder would be left
+        let src_inspection = inspect_path(manager, src_path, src_path_type)?;
+        if src_inspection.target_status == TargetStatus::Folder {
+            return Err("a folder cannot be moved without -R.".into());
+        }
+    }
 
-    let dest_inspection = inspect_path(&manager, dest_path, dest_path_type)?;
+    let dest_inspection = inspect_path(manager, dest_path, dest_path_type)?;
     log::trace!("dest_inspection = {:?}", &dest_inspection);
 
-    let dest_is_parent_folder: bool;
-    match dest_inspection.target_status {
-        TargetStatus::NotExist => {
-            dest_is_parent_folder = true;
-        }
+    let dest_is_parent_folder = match dest_inspection.target_status {
+        TargetStatus::NotExist => true,
         TargetStatus::Hidden => {
             return Err("destination path is a hidden file or folder.".into());
         }
-        TargetStatus::File => {
-            dest_is_parent_folder = true;
-        }
-        TargetStatus::Folder => {
-            dest_is_parent_folder = false;
-        }
+        TargetStatus::File => true,
+        TargetStatus::Folder => false,
+    };
+    if src_has_wildcard && dest_is_parent_folder {
+        return Err("the destination must be an existing folder to copy the matched files.".into());
     }
 
     let dest_base_path: &str;
@@ -65,10 +368,7 @@ pub fn command_copy(
         match dest_inspection.parent_status {
             TargetStatus::Folder => {
                 dest_base_path = dest_inspection.parent_path.as_ref().unwrap();
-                dest_name = dest_inspection
-                    .target_name
-                    .as_ref()
-                    .and_then(|v| Some(v as &str));
+                dest_name = dest_inspection.target_name.as_ref().map(|v| v as &str);
             }
             _ => {
                 return Err("cannot create the destination path.".into());
@@ -79,45 +379,122 @@ pub fn command_copy(
         dest_name = None;
     }
 
+    let mut planner = Planner::new(&source_root(src_path)?, dest_path, options)?;
+    let dest_folder_path = normalize_path(dest_base_path);
+
     match dest_path_type {
         PathType::DeviceStorage => {
             let storage_path = DeviceStoragePath::from(dest_base_path)?;
 
             if let Some((_device_info, device, object_info)) =
-                find_device_file_or_folder(&manager, &storage_path)?
+                find_device_file_or_folder(manager, &storage_path)?
             {
-                let mut destination_folder = DeviceDestinationFolder::new(&device, object_info)?;
-                do_copy(
-                    &manager,
+                let destination_folder =
+                    DeviceDestinationFolder::new(device.as_ref(), object_info)?;
+                plan_from_source(
+                    manager,
                     src_path,
                     src_path_type,
-                    &mut destination_folder,
+                    &mut PlannedFolder::new(destination_folder, &dest_folder_path),
                     dest_is_parent_folder,
                     dest_name,
-                    recursive,
-                    mirror,
-                )
+                    &mut planner,
+                )?;
             } else {
                 return Err(format!("filed to open folder: {}", dest_base_path).into());
             }
         }
         PathType::Local => {
-            let mut destination_folder = LocalDestinationFolder::new(PathBuf::from(dest_base_path));
-            do_copy(
-                &manager,
+            let destination_folder = LocalDestinationFolder::new(PathBuf::from(dest_base_path));
+            plan_from_source(
+                manager,
                 src_path,
                 src_path_type,
-                &mut destination_folder,
+                &mut PlannedFolder::new(destination_folder, &dest_folder_path),
                 dest_is_parent_folder,
                 dest_name,
-                recursive,
-                mirror,
-            )
+                &mut planner,
+            )?;
+        }
+        PathType::Invalid => return Err("invalid destination path.".into()),
+    }
+    Ok(planner.into_plan())
+}
+
+/// Checks that the sources and the destinations have not been changed since planning,
+/// then performs the actions of the plan.
+/// The progress is recorded to the journal if specified.
+pub fn apply_plan(
+    manager: &dyn Manager,
+    plan: &TransferPlan,
+    options: &CopyOptions,
+    journal: Option<Journal>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let reporter = create_reporter(options);
+    with_source(manager, &plan.source, |source, source_device| {
+        apply_plan_from(
+            manager,
+            plan,
+            source,
+            source_device,
+            options,
+            reporter.as_ref(),
+            journal,
+        )
+    })
+}
+
+fn apply_plan_from(
+    manager: &dyn Manager,
+    plan: &TransferPlan,
+    source: &mut dyn PlanSource,
+    source_device: SourceDevice,
+    options: &CopyOptions,
+    reporter: &dyn Reporter,
+    journal: Option<Journal>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match get_path_type(&plan.destination) {
+        PathType::DeviceStorage => {
+            let storage_path = DeviceStoragePath::from(&plan.destination)?;
+            if let Some((device_info, device)) = source_device {
+                if is_same_device(manager, device_info, &storage_path)? {
+                    // the device copies the files by itself if it can
+                    let storage_object = find_storage_object(device, &storage_path)?;
+                    let mut destination = DevicePlanEndpoint::new(device, storage_object);
+                    let mut executor = Executor::new(source, &mut destination, reporter);
+                    executor.set_on_device(true);
+                    return run_executor(executor, plan, options, journal);
+                }
+            }
+            let (_device_info, device, storage_object) = open_storage(manager, &plan.destination)?;
+            let mut destination = DevicePlanEndpoint::new(device.as_ref(), storage_object);
+            let executor = Executor::new(source, &mut destination, reporter);
+            run_executor(executor, plan, options, journal)
+        }
+        PathType::Local => {
+            let mut destination = LocalPlanEndpoint;
+            let executor = Executor::new(source, &mut destination, reporter);
+            run_executor(executor, plan, options, journal)
         }
         PathType::Invalid => Err("invalid destination path.".into()),
     }
 }
 
+fn run_executor<D: PlanDestination>(
+    mut executor: Executor<D>,
+    plan: &TransferPlan,
+    options: &CopyOptions,
+    journal: Option<Journal>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    executor.set_verify(options.verify);
+    executor.set_move_source(plan.move_source);
+    executor.set_bwlimit(options.bwlimit);
+    if let Some(journal) = journal {
+        executor.set_journal(journal);
+    }
+    executor.run(plan)
+}
+
 fn has_wildcard(path: &str, path_type: PathType) -> Result<bool, Box<dyn std::error::Error>> {
     let storage_path: DeviceStoragePath;
     let path_to_check: &str;
@@ -142,31 +519,38 @@ fn has_wildcard(path: &str, path_type: PathType) -> Result<bool, Box<dyn std::er
     Ok(false)
 }
 
-fn do_copy(
-    manager: &Manager,
+fn plan_from_source<D: DestinationFolder>(
+    manager: &dyn Manager,
     src_path: &str,
     src_path_type: PathType,
-    destination_folder: &mut impl DestinationFolder,
+    destination_folder: &mut PlannedFolder<D>,
     dest_is_parent_folder: bool,
     dest_name: Option<&str>,
-    recursive: bool,
-    mirror: bool,
+    planner: &mut Planner,
 ) -> Result<(), Box<dyn std::error::Error>> {
     match src_path_type {
         PathType::DeviceStorage => {
             let storage_path = DeviceStoragePath::from(src_path)?;
+            if has_wildcard(src_path, src_path_type)? {
+                return plan_from_matched_sources(
+                    manager,
+                    &storage_path,
+                    destination_folder,
+                    planner,
+                );
+            }
 
             if let Some((_device_info, device, content_object)) =
                 find_device_file_or_folder(manager, &storage_path)?
             {
-                let processor = DeviceCopyProcessor::new(&device, content_object.clone());
+                let processor =
+                    DeviceCopyProcessor::new(device.as_ref(), content_object.clone(), src_path);
                 let real_dest_name = dest_name.unwrap_or(&content_object.name);
-                processor.copy_as(
+                processor.plan_as(
                     real_dest_name,
                     destination_folder,
                     dest_is_parent_folder,
-                    recursive,
-                    mirror,
+                    planner,
                 )
             } else {
                 Err("failed to open source path.".into())
@@ -192,18 +576,167 @@ fn do_copy(
                 }
             }
             let processor = LocalCopyProcessor::new(src_path);
-            processor.copy_as(
+            processor.plan_as(
                 real_dest_name,
                 destination_folder,
                 dest_is_parent_folder,
-                recursive,
-                mirror,
+                planner,
             )
         }
-        PathType::Invalid => {
-            return Err("invalid source path.".into());
+        PathType::Invalid => Err("invalid source path.".into()),
+    }
+}
+
+/// Plans copying each file or folder matched by the wildcard into the destination folder.
+/// With `preserve_structure`, the folders between the source root and the matched entry
+/// are also created in the destination folder.
+fn plan_from_matched_sources<D: DestinationFolder>(
+    manager: &dyn Manager,
+    storage_path: &DeviceStoragePath,
+    destination_folder: &mut PlannedFolder<D>,
+    planner: &mut Planner,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (device_info, device, storage_object) = match find_device_storage(manager, storage_path)? {
+        Some(device_object) => device_object,
+        None => return Err("failed to open source path.".into()),
+    };
+
+    let mut matches = Vec::<(ContentObjectInfo, String)>::new();
+    device_iterate_file_or_folder(
+        device.as_ref(),
+        &device_info,
+        &storage_object,
+        &storage_path.path,
+        false,
+        |object_info, path| {
+            let path_in_storage = DeviceStoragePath::from(path)?.path;
+            if !object_info.is_hidden && !object_info.is_system && path_in_storage != "\\" {
+                matches.push((object_info.clone(), path_in_storage));
+            }
+            Ok(true)
+        },
+    )?;
+    if matches.is_empty() {
+        return Err(format!(
+            "no file or folder was matched: {}",
+            storage_path.full_path()
+        )
+        .into());
+    }
+    // the parent folders come before their contents
+    matches.sort_by(|a, b| a.1.cmp(&b.1));
+
+    // the paths of the sources are written with the device name and the storage name
+    // as specified, like the source without wildcard characters
+    let full_path = |path: &str| {
+        format!(
+            "{}:{}:{}",
+            &storage_path.device_name, &storage_path.storage_name, path
+        )
+    };
+    let root = wildcard_root(&storage_path.path);
+    let preserve_structure = planner.options().preserve_structure;
+    let recursive = planner.options().recursive;
+    let mut copied_folders = Vec::<String>::new();
+    let mut dest_names = HashSet::<String>::new();
+    let mut planned_folders = HashSet::<String>::new();
+
+    for (object_info, path) in matches.iter() {
+        // the contents of a matched folder are copied with the folder
+        if recursive
+            && copied_folders
+                .iter()
+                .any(|folder| path.starts_with(&join_path(folder, "")))
+        {
+            continue;
         }
+        if object_info.is_folder() {
+            copied_folders.push(path.clone());
+        }
+        let processor =
+            DeviceCopyProcessor::new(device.as_ref(), object_info.clone(), &full_path(path));
+
+        if !preserve_structure {
+            if !dest_names.insert(object_info.name.clone()) {
+                return Err(format!(
+                    "multiple matched files or folders are named \"{}\". use --preserve-structure to copy them into separate folders.",
+                    &object_info.name
+                )
+                .into());
+            }
+            processor.plan_as(&object_info.name, destination_folder, true, planner)?;
+            continue;
+        }
+
+        let relative_path = path
+            .strip_prefix(root.as_str())
+            .unwrap_or(path)
+            .trim_start_matches(SEPARATORS);
+        let mut folder_names: Vec<&str> = relative_path.split(SEPARATORS).collect();
+        folder_names.pop();
+
+        let mut folder_path = root.clone();
+        let mut sub_folder: Option<PlannedFolder<D>> = None;
+        for name in folder_names {
+            let parent = match sub_folder.as_mut() {
+                Some(folder) => folder,
+                None => &mut *destination_folder,
+            };
+            folder_path = join_path(&folder_path, name);
+            let dest_path = parent.path_of(name);
+            // the folder shared by the matched entries is planned once
+            if planned_folders.insert(dest_path.clone()) {
+                let folder_info = match device_find_file_or_folder(
+                    device.as_ref(),
+                    &device_info,
+                    &storage_object,
+                    &folder_path,
+                )? {
+                    Some((folder_info, _)) => folder_info,
+                    None => return Err(format!("failed to open folder: {}", &folder_path).into()),
+                };
+                let src_file_info = FileInfo::from_content_object_info(&folder_info)?;
+                let dest_file_info = parent.get_file_info(name)?;
+                planner.plan_folder(
+                    &full_path(&folder_path),
+                    &dest_path,
+                    &src_file_info,
+                    dest_file_info.as_ref(),
+                );
+            }
+            let folder = parent.open_folder(name)?;
+            sub_folder = Some(folder);
+        }
+        let folder = match sub_folder.as_mut() {
+            Some(folder) => folder,
+            None => &mut *destination_folder,
+        };
+        processor.plan_as(&object_info.name, folder, true, planner)?;
     }
+    Ok(())
+}
+
+/// Returns the path the copy is relative to:
+/// the folder above the first component containing wildcard characters,
+/// or the source itself if it has no wildcard characters.
+fn source_root(src_path: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let path_type = get_path_type(src_path);
+    if path_type != PathType::DeviceStorage || !has_wildcard(src_path, path_type)? {
+        return Ok(normalize_path(src_path));
+    }
+    let mut storage_path = DeviceStoragePath::from(src_path)?;
+    storage_path.path = wildcard_root(&storage_path.path);
+    Ok(storage_path.full_path())
+}
+
+/// Returns the folder above the first component containing wildcard characters.
+fn wildcard_root(path: &str) -> String {
+    let folder_names: Vec<&str> = path
+        .split(SEPARATORS)
+        .filter(|name| !name.is_empty())
+        .take_while(|name| !name.contains(WILDCARD_CHARACTERS))
+        .collect();
+    format!("\\{}", folder_names.join("\\"))
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -223,7 +756,7 @@ struct TargetInspectionResult {
 }
 
 fn inspect_path(
-    manager: &Manager,
+    manager: &dyn Manager,
     path: &str,
     path_type: PathType,
 ) -> Result<TargetInspectionResult, Box<dyn std::error::Error>> {
@@ -239,7 +772,7 @@ fn inspect_local_path(path: &str) -> Result<TargetInspectionResult, Box<dyn std:
     let target_status = inspect_local_path_status(path_obj)?;
     let target_name: Option<String> = path_obj
         .file_name()
-        .and_then(|s| Some(String::from(s.to_str().unwrap())));
+        .map(|s| String::from(s.to_str().unwrap()));
     if target_status != TargetStatus::NotExist && target_name.is_none() {
         return Err("failed to get the file name of the destination path.".into());
     }
@@ -269,6 +802,7 @@ fn inspect_local_path_status(path_obj: &Path) -> Result<TargetStatus, Box<dyn st
     if !path_obj.exists() {
         Ok(TargetStatus::NotExist)
     } else {
+        // explicitly specified path is not treated as a dotfile
         let file_info = FileInfo::from_metadata(&path_obj.metadata()?, "")?;
         if file_info.is_hidden || file_info.is_system {
             Ok(TargetStatus::Hidden)
@@ -281,11 +815,11 @@ fn inspect_local_path_status(path_obj: &Path) -> Result<TargetStatus, Box<dyn st
 }
 
 fn inspect_device_path(
-    manager: &Manager,
+    manager: &dyn Manager,
     path: &str,
 ) -> Result<TargetInspectionResult, Box<dyn std::error::Error>> {
     let storage_path = DeviceStoragePath::from(path)?;
-    let target_name: Option<String> = storage_path.file_name().and_then(|v| Some(String::from(v)));
+    let target_name: Option<String> = storage_path.file_name().map(String::from);
     let target_status = inspect_device_path_status(manager, &storage_path)?;
 
     let parent_status: TargetStatus;
@@ -310,7 +844,7 @@ fn inspect_device_path(
 }
 
 fn inspect_device_path_status(
-    manager: &Manager,
+    manager: &dyn Manager,
     storage_path: &DeviceStoragePath,
 ) -> Result<TargetStatus, Box<dyn std::error::Error>> {
     match find_device_file_or_folder(manager, storage_path)? {
@@ -329,16 +863,19 @@ fn inspect_device_path_status(
     }
 }
 
+/// Device information, opened device, and the object found on the device
+type DeviceObject = (DeviceInfo, Box<dyn Device>, ContentObjectInfo);
+
 fn find_device_file_or_folder(
-    manager: &Manager,
+    manager: &dyn Manager,
     storage_path: &DeviceStoragePath,
-) -> Result<Option<(DeviceInfo, Device, ContentObjectInfo)>, Box<dyn std::error::Error>> {
+) -> Result<Option<DeviceObject>, Box<dyn std::error::Error>> {
     log::trace!("find_device_file_or_folder");
     if let Some((device_info, device, storage_object)) = find_device_storage(manager, storage_path)?
     {
         log::trace!("find_device_file_or_folder: storage found");
         match device_find_file_or_folder(
-            &device,
+            device.as_ref(),
             &device_info,
             &storage_object,
             &storage_path.path,
@@ -359,12 +896,12 @@ fn find_device_file_or_folder(
 }
 
 fn find_device_storage(
-    manager: &Manager,
+    manager: &dyn Manager,
     storage_path: &DeviceStoragePath,
-) -> Result<Option<(DeviceInfo, Device, ContentObjectInfo)>, Box<dyn std::error::Error>> {
+) -> Result<Option<DeviceObject>, Box<dyn std::error::Error>> {
     log::trace!("find_device_storage: storage_path = {:?}", storage_path);
     let mut device_vec = device_find_devices(manager, Some(&storage_path.device_name))?;
-    if device_vec.len() == 0 {
+    if device_vec.is_empty() {
         return Err(format!("device was not found: {}", &storage_path.device_name).into());
     }
     if device_vec.len() > 1 {
@@ -377,11 +914,34 @@ fn find_device_storage(
 
     let device_info = device_vec.pop().unwrap();
 
-    let device = Device::open(&device_info)?;
+    let device = manager.open_device(&device_info)?;
+    let storage_object = find_storage_object(device.as_ref(), storage_path)?;
+
+    log::trace!(
+        "find_device_storage: found {:?} {:?}",
+        &device_info,
+        &storage_object
+    );
+    Ok(Some((device_info, device, storage_object)))
+}
+
+/// Returns true if the device of the path is the device opened for the source.
+fn is_same_device(
+    manager: &dyn Manager,
+    device_info: &DeviceInfo,
+    storage_path: &DeviceStoragePath,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let device_vec = device_find_devices(manager, Some(&storage_path.device_name))?;
+    Ok(device_vec.len() == 1 && device_vec[0].id == device_info.id)
+}
 
+fn find_storage_object(
+    device: &dyn Device,
+    storage_path: &DeviceStoragePath,
+) -> Result<ContentObjectInfo, Box<dyn std::error::Error>> {
     let mut storage_object_vec =
-        device_find_storage_objects(&device, Some(&storage_path.storage_name))?;
-    if storage_object_vec.len() == 0 {
+        device_find_storage_objects(device, Some(&storage_path.storage_name))?;
+    if storage_object_vec.is_empty() {
         return Err(format!(
             "storage was not found: {}:{}",
             &storage_path.device_name, &storage_path.storage_name
@@ -396,12 +956,1035 @@ fn find_device_storage(
         .into());
     }
 
-    let storage_object = storage_object_vec.pop().unwrap();
+    Ok(storage_object_vec.pop().unwrap())
+}
 
-    log::trace!(
-        "find_device_storage: found {:?} {:?}",
-        &device_info,
-        &storage_object
-    );
-    Ok(Some((device_info, device, storage_object)))
+fn open_storage(
+    manager: &dyn Manager,
+    path: &str,
+) -> Result<DeviceObject, Box<dyn std::error::Error>> {
+    match find_device_storage(manager, &DeviceStoragePath::from(path)?)? {
+        Some(device_object) => Ok(device_object),
+        None => Err(format!("storage was not found: {}", path).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::copy::compare::CompareMode;
+    use crate::copy::plan::ActionKind;
+    use crate::memory::device::MemoryDevice;
+    use crate::memory::manager::MemoryManager;
+    use chrono::{NaiveDate, NaiveDateTime};
+
+    fn time(day: u32) -> Option<NaiveDateTime> {
+        Some(NaiveDate::from_ymd(2021, 4, day).and_hms(12, 34, 56))
+    }
+
+    fn setup() -> (MemoryManager, MemoryDevice, MemoryDevice) {
+        let mut manager = MemoryManager::new();
+        let src = manager.add_device("Src Phone");
+        let dest = manager.add_device("Dest Phone");
+
+        let storage = src.add_storage("Internal");
+        let music = src.add_folder(&storage, "Music");
+        let file_a = src.add_file(&music, "a.mp3", b"aaaa");
+        src.set_time_modified(&file_a, time(1));
+        let album = src.add_folder(&music, "Album");
+        let file_b = src.add_file(&album, "b.mp3", b"bbbbbb");
+        src.set_time_created(&file_b, time(2));
+        src.set_time_modified(&file_b, time(3));
+        let hidden = src.add_file(&music, "hidden.dat", b"h");
+        src.set_hidden(&hidden, true);
+        let system = src.add_file(&music, "system.dat", b"s");
+        src.set_system(&system, true);
+
+        dest.add_storage("SD Card");
+
+        (manager, src, dest)
+    }
+
+    fn copy(
+        manager: &MemoryManager,
+        src: &str,
+        dest: &str,
+        recursive: bool,
+        mirror: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let paths = Paths {
+            src: src.to_string(),
+            dest: dest.to_string(),
+        };
+        let journal_dir = tempfile::tempdir()?;
+        let options = CopyOptions {
+            recursive,
+            mirror,
+            journal_file: Some(
+                journal_dir
+                    .path()
+                    .join("journal")
+                    .to_str()
+                    .unwrap()
+                    .to_string(),
+            ),
+            ..Default::default()
+        };
+        command_copy(manager, &paths, &options)
+    }
+
+    /// Makes the state in which the copy was interrupted while "b.mp3" was being transferred.
+    fn interrupt_copy(
+        manager: &MemoryManager,
+        dest: &MemoryDevice,
+        journal_file: &Path,
+        data_b: &[u8],
+    ) -> Result<(Paths, CopyOptions), Box<dyn std::error::Error>> {
+        let paths = Paths {
+            src: "Src Phone:Internal:\\Music".to_string(),
+            dest: "Dest Phone:SD Card:\\Music".to_string(),
+        };
+        let options = CopyOptions {
+            recursive: true,
+            journal_file: Some(journal_file.to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+        let plan = plan_copy(manager, &paths, &options)?;
+        assert_eq!(
+            plan.actions[3].destination,
+            "Dest Phone:SD Card:\\Music\\Album\\b.mp3"
+        );
+
+        let storage = dest.find("SD Card").unwrap();
+        let music = dest.add_folder(&storage, "Music");
+        dest.add_file(&music, "a.mp3", b"AAAA");
+        let album = dest.add_folder(&music, "Album");
+        dest.add_file(&album, "b.mp3", data_b);
+
+        let mut journal = Journal::create(journal_file, &plan)?;
+        for index in 0..3 {
+            journal.start(index)?;
+            journal.complete(index)?;
+        }
+        journal.start(3)?;
+
+        let options = CopyOptions {
+            resume: true,
+            ..options
+        };
+        Ok((paths, options))
+    }
+
+    #[test]
+    fn test_copy_folder_recursively() -> Result<(), Box<dyn std::error::Error>> {
+        let (manager, _src, dest) = setup();
+
+        copy(
+            &manager,
+            "Src Phone:Internal:\\Music",
+            "Dest Phone:SD Card:\\Music",
+            true,
+            false,
+        )?;
+
+        let music = dest.find("SD Card\\Music").unwrap();
+        assert_eq!(dest.child_names(&music), vec!["a.mp3", "Album"]);
+        let file_b = dest.find("SD Card\\Music\\Album\\b.mp3").unwrap();
+        assert_eq!(dest.data(&file_b), b"bbbbbb");
+        let info_b = dest.get_object_info(file_b)?;
+        assert_eq!(info_b.time_created, time(2));
+        assert_eq!(info_b.time_modified, time(3));
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_folder_as_new_name() -> Result<(), Box<dyn std::error::Error>> {
+        let (manager, _src, dest) = setup();
+
+        copy(
+            &manager,
+            "Src Phone:Internal:\\Music",
+            "Dest Phone:SD Card:\\Songs",
+            true,
+            false,
+        )?;
+
+        let songs = dest.find("SD Card\\Songs").unwrap();
+        assert_eq!(dest.child_names(&songs), vec!["a.mp3", "Album"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_folder_not_recursively() -> Result<(), Box<dyn std::error::Error>> {
+        let (manager, _src, dest) = setup();
+
+        copy(
+            &manager,
+            "Src Phone:Internal:\\Music",
+            "Dest Phone:SD Card:\\Music",
+            false,
+            false,
+        )?;
+
+        let music = dest.find("SD Card\\Music").unwrap();
+        assert!(dest.child_names(&music).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_skips_unchanged_file() -> Result<(), Box<dyn std::error::Error>> {
+        let (manager, src, dest) = setup();
+        let src_path = "Src Phone:Internal:\\Music\\a.mp3";
+        let dest_path = "Dest Phone:SD Card:\\";
+
+        copy(&manager, src_path, dest_path, false, false)?;
+        let dest_file = dest.find("SD Card\\a.mp3").unwrap();
+        dest.set_data(&dest_file, b"xxxx");
+
+        // same size and same time
+        copy(&manager, src_path, dest_path, false, false)?;
+        let dest_file = dest.find("SD Card\\a.mp3").unwrap();
+        assert_eq!(dest.data(&dest_file), b"xxxx");
+
+        // source file was updated
+        let src_file = src.find("Internal\\Music\\a.mp3").unwrap();
+        src.set_time_modified(&src_file, time(10));
+        copy(&manager, src_path, dest_path, false, false)?;
+        let storage = dest.find("SD Card").unwrap();
+        assert_eq!(dest.child_names(&storage), vec!["a.mp3"]);
+        let dest_file = dest.find("SD Card\\a.mp3").unwrap();
+        assert_eq!(dest.data(&dest_file), b"aaaa");
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_with_compare_mode() -> Result<(), Box<dyn std::error::Error>> {
+        let (manager, src, dest) = setup();
+        let tempdir = tempfile::tempdir()?;
+        let paths = Paths {
+            src: "Src Phone:Internal:\\Music\\a.mp3".to_string(),
+            dest: "Dest Phone:SD Card:\\".to_string(),
+        };
+        let options = CopyOptions {
+            journal_file: Some(tempdir.path().join("journal").to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+        command_copy(&manager, &paths, &options)?;
+        let dest_file = dest.find("SD Card\\a.mp3").unwrap();
+        dest.set_data(&dest_file, b"xxxx");
+
+        // the time of the source was rounded by the destination
+        let src_file = src.find("Internal\\Music\\a.mp3").unwrap();
+        src.set_time_modified(
+            &src_file,
+            Some(time(1).unwrap() + chrono::Duration::seconds(2)),
+        );
+        let options = CopyOptions {
+            time_tolerance: 2,
+            ..options
+        };
+        let plan = plan_copy(&manager, &paths, &options)?;
+        assert_eq!(plan.actions[0].action, ActionKind::Skip);
+        assert_eq!(plan.actions[0].reason, "newer by 2s, within the tolerance");
+
+        // same size, different content
+        let options = CopyOptions {
+            compare: CompareMode::Checksum,
+            ..options
+        };
+        let plan = plan_copy(&manager, &paths, &options)?;
+        assert_eq!(plan.actions[0].action, ActionKind::Replace);
+        assert_eq!(plan.actions[0].reason, "content differs");
+        command_copy(&manager, &paths, &options)?;
+        let dest_file = dest.find("SD Card\\a.mp3").unwrap();
+        assert_eq!(dest.data(&dest_file), b"aaaa");
+
+        let plan = plan_copy(&manager, &paths, &options)?;
+        assert_eq!(plan.actions[0].action, ActionKind::Skip);
+        assert_eq!(plan.actions[0].reason, "same content");
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace_keeps_old_file_on_failure() -> Result<(), Box<dyn std::error::Error>> {
+        let (manager, _src, dest) = setup();
+        let tempdir = tempfile::tempdir()?;
+        let paths = Paths {
+            src: "Src Phone:Internal:\\Music\\a.mp3".to_string(),
+            dest: "Dest Phone:SD Card:\\".to_string(),
+        };
+        let options = CopyOptions {
+            journal_file: Some(tempdir.path().join("journal").to_str().unwrap().to_string()),
+            compare: CompareMode::Always,
+            ..Default::default()
+        };
+        let storage = dest.find("SD Card").unwrap();
+        dest.add_file(&storage, "a.mp3", b"xxxx");
+        dest.set_rename_supported(true);
+
+        // the upload of the temporary file failed
+        dest.fail_next_files(1);
+        assert!(command_copy(&manager, &paths, &options).is_err());
+        assert_eq!(dest.child_names(&storage), vec!["a.mp3"]);
+        let dest_file = dest.find("SD Card\\a.mp3").unwrap();
+        assert_eq!(dest.data(&dest_file), b"xxxx");
+
+        // the renaming of the temporary file failed
+        dest.fail_next_renames(1);
+        assert!(command_copy(&manager, &paths, &options).is_err());
+        assert_eq!(dest.child_names(&storage), vec!["a.mp3"]);
+        let dest_file = dest.find("SD Card\\a.mp3").unwrap();
+        assert_eq!(dest.data(&dest_file), b"xxxx");
+
+        command_copy(&manager, &paths, &options)?;
+        assert_eq!(dest.child_names(&storage), vec!["a.mp3"]);
+        let dest_file = dest.find("SD Card\\a.mp3").unwrap();
+        assert_eq!(dest.data(&dest_file), b"aaaa");
+
+        // the file is deleted first on the device which cannot rename
+        dest.set_data(&dest_file, b"xxxx");
+        dest.set_rename_supported(false);
+        dest.fail_next_files(1);
+        assert!(command_copy(&manager, &paths, &options).is_err());
+        assert!(dest.child_names(&storage).is_empty());
+
+        command_copy(&manager, &paths, &options)?;
+        let dest_file = dest.find("SD Card\\a.mp3").unwrap();
+        assert_eq!(dest.data(&dest_file), b"aaaa");
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_to_all_destinations() -> Result<(), Box<dyn std::error::Error>> {
+        let (mut manager, _src, dest) = setup();
+        let tablet = manager.add_device("Dest Tablet");
+        tablet.add_storage("Internal");
+        tablet.add_storage("SD Card");
+        let options = CopyOptions {
+            recursive: true,
+            ..Default::default()
+        };
+
+        command_copy_to_all(
+            &manager,
+            "Src Phone:Internal:\\Music\\Album",
+            &["Dest*:SD*:\\Album".to_string()],
+            &options,
+        )?;
+        let file_b = dest.find("SD Card\\Album\\b.mp3").unwrap();
+        assert_eq!(dest.data(&file_b), b"bbbbbb");
+        let file_b = tablet.find("SD Card\\Album\\b.mp3").unwrap();
+        assert_eq!(tablet.data(&file_b), b"bbbbbb");
+        assert!(tablet.find("Internal\\Album").is_none());
+
+        // the others are copied even if one of them failed
+        tablet.fail_next_files(1);
+        let result = command_copy_to_all(
+            &manager,
+            "Src Phone:Internal:\\Music\\a.mp3",
+            &[
+                "Dest Tablet:Internal:\\".to_string(),
+                "Dest Phone:SD Card:\\".to_string(),
+            ],
+            &options,
+        );
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "failed to copy to 1 of 2 destinations."
+        );
+        assert!(tablet.find("Internal\\a.mp3").is_none());
+        assert!(dest.find("SD Card\\a.mp3").is_some());
+        let failed_paths = Paths {
+            src: "Src Phone:Internal:\\Music\\a.mp3".to_string(),
+            dest: "Dest Tablet:Internal:\\".to_string(),
+        };
+        // the journal was left to resume
+        let failed_journal = journal_path(&failed_paths, &options);
+        assert!(failed_journal.exists());
+        std::fs::remove_file(failed_journal)?;
+
+        // the journal file cannot be shared
+        let options = CopyOptions {
+            journal_file: Some("journal".to_string()),
+            ..options
+        };
+        assert!(command_copy_to_all(
+            &manager,
+            "Src Phone:Internal:\\Music\\a.mp3",
+            &["Dest*:SD*:\\".to_string()],
+            &options,
+        )
+        .is_err());
+        assert!(command_copy_to_all(
+            &manager,
+            "Src Phone:Internal:\\Music\\a.mp3",
+            &["*:USB*:\\".to_string()],
+            &options,
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_on_same_device() -> Result<(), Box<dyn std::error::Error>> {
+        let (manager, src, _dest) = setup();
+        src.add_storage("SD Card");
+        src.set_rename_supported(true);
+
+        copy(
+            &manager,
+            "Src Phone:Internal:\\Music",
+            "Src Phone:SD Card:\\Music",
+            true,
+            false,
+        )?;
+        let file_b = src.find("SD Card\\Music\\Album\\b.mp3").unwrap();
+        assert_eq!(src.data(&file_b), b"bbbbbb");
+        let info_b = src.get_object_info(file_b)?;
+        assert_eq!(info_b.time_created, time(2));
+        assert_eq!(info_b.time_modified, time(3));
+        // the data was not transferred
+        assert_eq!(src.resources_opened(), 0);
+
+        // copied as the new name
+        copy(
+            &manager,
+            "Src Phone:Internal:\\Music\\a.mp3",
+            "Src Phone:SD Card:\\c.mp3",
+            false,
+            false,
+        )?;
+        let file_c = src.find("SD Card\\c.mp3").unwrap();
+        assert_eq!(src.data(&file_c), b"aaaa");
+        assert_eq!(src.resources_opened(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_on_same_device_falls_back_to_transfer() -> Result<(), Box<dyn std::error::Error>> {
+        let (manager, src, _dest) = setup();
+        src.add_storage("SD Card");
+        src.set_copy_supported(false);
+
+        copy(
+            &manager,
+            "Src Phone:Internal:\\Music",
+            "Src Phone:SD Card:\\Music",
+            true,
+            false,
+        )?;
+        let file_b = src.find("SD Card\\Music\\Album\\b.mp3").unwrap();
+        assert_eq!(src.data(&file_b), b"bbbbbb");
+        assert_eq!(src.resources_opened(), 2);
+
+        // the new name cannot be given without renaming
+        src.set_copy_supported(true);
+        src.set_rename_supported(false);
+        copy(
+            &manager,
+            "Src Phone:Internal:\\Music\\a.mp3",
+            "Src Phone:SD Card:\\c.mp3",
+            false,
+            false,
+        )?;
+        let file_c = src.find("SD Card\\c.mp3").unwrap();
+        assert_eq!(src.data(&file_c), b"aaaa");
+        assert_eq!(src.resources_opened(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_mirror_deletes_extra_entries() -> Result<(), Box<dyn std::error::Error>> {
+        let (manager, _src, dest) = setup();
+        let storage = dest.find("SD Card").unwrap();
+        let music = dest.add_folder(&storage, "Music");
+        dest.add_file(&music, "extra.mp3", b"x");
+        let extra_folder = dest.add_folder(&music, "Extra");
+        dest.add_file(&extra_folder, "c.mp3", b"c");
+        let album = dest.add_folder(&music, "Album");
+        dest.add_file(&album, "extra.mp3", b"x");
+
+        copy(
+            &manager,
+            "Src Phone:Internal:\\Music",
+            "Dest Phone:SD Card:\\Music",
+            true,
+            true,
+        )?;
+
+        let mut names = dest.child_names(&music);
+        names.sort();
+        assert_eq!(names, vec!["Album", "a.mp3"]);
+        assert_eq!(dest.child_names(&album), vec!["b.mp3"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_mirror_with_exclude_patterns() -> Result<(), Box<dyn std::error::Error>> {
+        let (manager, src, dest) = setup();
+        let src_music = src.find("Internal\\Music").unwrap();
+        src.add_file(&src_music, "a.tmp", b"t");
+        let src_album = src.find("Internal\\Music\\Album").unwrap();
+        let thumbnails = src.add_folder(&src_album, ".thumbnails");
+        src.add_file(&thumbnails, "b.jpg", b"j");
+
+        let storage = dest.find("SD Card").unwrap();
+        let music = dest.add_folder(&storage, "Music");
+        dest.add_file(&music, "extra.mp3", b"x");
+        dest.add_file(&music, "c.tmp", b"x");
+        dest.add_file(&music, "a.tmp", b"old");
+        let album = dest.add_folder(&music, "Album");
+        let dest_thumbnails = dest.add_folder(&album, ".thumbnails");
+        dest.add_file(&dest_thumbnails, "c.jpg", b"x");
+
+        let journal_dir = tempfile::tempdir()?;
+        let paths = Paths {
+            src: "Src Phone:Internal:\\Music".to_string(),
+            dest: "Dest Phone:SD Card:\\Music".to_string(),
+        };
+        let options = CopyOptions {
+            recursive: true,
+            mirror: true,
+            exclude: vec!["*.tmp".to_string(), "**\\.thumbnails".to_string()],
+            journal_file: Some(
+                journal_dir
+                    .path()
+                    .join("journal")
+                    .to_str()
+                    .unwrap()
+                    .to_string(),
+            ),
+            ..Default::default()
+        };
+        command_copy(&manager, &paths, &options)?;
+
+        // the excluded entries are neither copied nor deleted
+        let mut names = dest.child_names(&music);
+        names.sort();
+        assert_eq!(names, vec!["Album", "a.mp3", "a.tmp", "c.tmp"]);
+        let a_tmp = dest.find("SD Card\\Music\\a.tmp").unwrap();
+        assert_eq!(dest.data(&a_tmp), b"old");
+        let mut names = dest.child_names(&album);
+        names.sort();
+        assert_eq!(names, vec![".thumbnails", "b.mp3"]);
+        assert_eq!(dest.child_names(&dest_thumbnails), vec!["c.jpg"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_with_include_patterns() -> Result<(), Box<dyn std::error::Error>> {
+        let (manager, src, _dest) = setup();
+        let src_music = src.find("Internal\\Music").unwrap();
+        src.add_file(&src_music, "cover.jpg", b"j");
+
+        let paths = Paths {
+            src: "Src Phone:Internal:\\Music".to_string(),
+            dest: "Dest Phone:SD Card:\\Music".to_string(),
+        };
+        let options = CopyOptions {
+            recursive: true,
+            include: vec!["Album\\*.mp3".to_string()],
+            ..Default::default()
+        };
+        let plan = plan_copy(&manager, &paths, &options)?;
+        let destinations: Vec<&str> = plan
+            .actions
+            .iter()
+            .map(|action| action.destination.as_str())
+            .collect();
+        assert_eq!(
+            destinations,
+            vec![
+                "Dest Phone:SD Card:\\Music",
+                "Dest Phone:SD Card:\\Music\\Album",
+                "Dest Phone:SD Card:\\Music\\Album\\b.mp3",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_mirror_fails_on_undeletable_entry() {
+        let (manager, _src, dest) = setup();
+        let storage = dest.find("SD Card").unwrap();
+        let music = dest.add_folder(&storage, "Music");
+        let protected = dest.add_file(&music, "protected.mp3", b"x");
+        dest.set_can_delete(&protected, false);
+
+        let result = copy(
+            &manager,
+            "Src Phone:Internal:\\Music",
+            "Dest Phone:SD Card:\\Music",
+            true,
+            true,
+        );
+
+        assert!(result.is_err());
+        assert!(dest.find("SD Card\\Music\\protected.mp3").is_some());
+    }
+
+    #[test]
+    fn test_copy_fails_on_duplicated_source_names() {
+        let (manager, src, dest) = setup();
+        let music = src.find("Internal\\Music").unwrap();
+        src.add_file(&music, "a.mp3", b"another");
+
+        let result = copy(
+            &manager,
+            "Src Phone:Internal:\\Music",
+            "Dest Phone:SD Card:\\Music",
+            true,
+            false,
+        );
+
+        // either of the files cannot be chosen, so nothing is copied
+        assert!(result.is_err());
+        assert!(dest.find("SD Card\\Music").is_none());
+    }
+
+    #[test]
+    fn test_copy_fails_on_duplicated_destination_names() {
+        let (manager, _src, dest) = setup();
+        let storage = dest.find("SD Card").unwrap();
+        let music = dest.add_folder(&storage, "Music");
+        dest.add_file(&music, "a.mp3", b"x");
+        dest.add_file(&music, "a.mp3", b"y");
+
+        let result = copy(
+            &manager,
+            "Src Phone:Internal:\\Music",
+            "Dest Phone:SD Card:\\Music",
+            true,
+            false,
+        );
+
+        assert!(result.is_err());
+        assert_eq!(dest.child_names(&music), vec!["a.mp3", "a.mp3"]);
+    }
+
+    #[test]
+    fn test_dry_run_changes_nothing() -> Result<(), Box<dyn std::error::Error>> {
+        let (manager, src, dest) = setup();
+        let storage = dest.find("SD Card").unwrap();
+        let music = dest.add_folder(&storage, "Music");
+        dest.add_file(&music, "a.mp3", b"xx");
+        dest.add_file(&music, "extra.mp3", b"x");
+        let extra_folder = dest.add_folder(&music, "Extra");
+        dest.add_file(&extra_folder, "c.mp3", b"c");
+
+        let paths = Paths {
+            src: "Src Phone:Internal:\\Music".to_string(),
+            dest: "Dest Phone:SD Card:\\Music".to_string(),
+        };
+        let options = CopyOptions {
+            recursive: true,
+            mirror: true,
+            dry_run: true,
+            ..Default::default()
+        };
+        command_copy(&manager, &paths, &options)?;
+
+        let mut names = dest.child_names(&music);
+        names.sort();
+        assert_eq!(names, vec!["Extra", "a.mp3", "extra.mp3"]);
+        let file_a = dest.find("SD Card\\Music\\a.mp3").unwrap();
+        assert_eq!(dest.data(&file_a), b"xx");
+        assert_eq!(dest.child_names(&extra_folder), vec!["c.mp3"]);
+
+        // new folder
+        let paths = Paths {
+            src: "Src Phone:Internal:\\Music".to_string(),
+            dest: "Dest Phone:SD Card:\\Songs".to_string(),
+        };
+        command_copy(&manager, &paths, &options)?;
+        assert!(dest.find("SD Card\\Songs").is_none());
+
+        // local destination
+        let tempdir = tempfile::tempdir()?;
+        std::fs::write(tempdir.path().join("extra.txt"), b"x")?;
+        let paths = Paths {
+            src: "Src Phone:Internal:\\Music".to_string(),
+            dest: tempdir.path().to_str().unwrap().to_string(),
+        };
+        command_copy(&manager, &paths, &options)?;
+        let names: Vec<_> = std::fs::read_dir(tempdir.path())?
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, vec!["extra.txt"]);
+
+        let src_storage = src.find("Internal").unwrap();
+        assert_eq!(src.child_names(&src_storage), vec!["Music"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_copy() -> Result<(), Box<dyn std::error::Error>> {
+        let (manager, _src, dest) = setup();
+        let storage = dest.find("SD Card").unwrap();
+        let music = dest.add_folder(&storage, "Music");
+        dest.add_file(&music, "a.mp3", b"xx");
+        dest.add_file(&music, "extra.mp3", b"x");
+
+        let paths = Paths {
+            src: "Src Phone:Internal:Music".to_string(),
+            dest: "Dest Phone:SD Card:Music\\".to_string(),
+        };
+        let options = CopyOptions {
+            recursive: true,
+            mirror: true,
+            ..Default::default()
+        };
+        let plan = plan_copy(&manager, &paths, &options)?;
+
+        let actions: Vec<_> = plan
+            .actions
+            .iter()
+            .map(|action| {
+                (
+                    action.action,
+                    action.source.as_deref(),
+                    action.destination.as_str(),
+                    action.size,
+                )
+            })
+            .collect();
+        assert_eq!(
+            actions,
+            vec![
+                (
+                    ActionKind::Replace,
+                    Some("Src Phone:Internal:\\Music\\a.mp3"),
+                    "Dest Phone:SD Card:\\Music\\a.mp3",
+                    4
+                ),
+                (
+                    ActionKind::CreateFolder,
+                    Some("Src Phone:Internal:\\Music\\Album"),
+                    "Dest Phone:SD Card:\\Music\\Album",
+                    0
+                ),
+                (
+                    ActionKind::Upload,
+                    Some("Src Phone:Internal:\\Music\\Album\\b.mp3"),
+                    "Dest Phone:SD Card:\\Music\\Album\\b.mp3",
+                    6
+                ),
+                (
+                    ActionKind::Delete,
+                    None,
+                    "Dest Phone:SD Card:\\Music\\extra.mp3",
+                    1
+                ),
+            ]
+        );
+        assert_eq!(plan.actions[0].reason, "size differs");
+        assert_eq!(plan.transfer_size(), 10);
+
+        let json = serde_json::to_string(&plan)?;
+        assert!(json.contains("\"action\":\"create-folder\""));
+        let loaded: TransferPlan = serde_json::from_str(&json)?;
+        assert_eq!(loaded, plan);
+
+        // nothing was changed
+        let mut names = dest.child_names(&music);
+        names.sort();
+        assert_eq!(names, vec!["a.mp3", "extra.mp3"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_resume_interrupted_copy() -> Result<(), Box<dyn std::error::Error>> {
+        let (manager, _src, dest) = setup();
+        let tempdir = tempfile::tempdir()?;
+        let journal_file = tempdir.path().join("journal");
+        let (paths, options) = interrupt_copy(&manager, &dest, &journal_file, b"bbb")?;
+
+        // wrong paths
+        let other_paths = Paths {
+            src: paths.src.clone(),
+            dest: "Dest Phone:SD Card:\\Songs".to_string(),
+        };
+        assert!(command_copy(&manager, &other_paths, &options).is_err());
+
+        command_copy(&manager, &paths, &options)?;
+
+        // completed actions are not performed again
+        let file_a = dest.find("SD Card\\Music\\a.mp3").unwrap();
+        assert_eq!(dest.data(&file_a), b"AAAA");
+        // partial file is transferred again
+        let file_b = dest.find("SD Card\\Music\\Album\\b.mp3").unwrap();
+        assert_eq!(dest.data(&file_b), b"bbbbbb");
+
+        assert!(!journal_file.exists());
+        assert!(command_copy(&manager, &paths, &options).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_resume_verifies_last_item() -> Result<(), Box<dyn std::error::Error>> {
+        let (manager, _src, dest) = setup();
+        let tempdir = tempfile::tempdir()?;
+        let journal_file = tempdir.path().join("journal");
+
+        // same size, different content
+        let (paths, options) = interrupt_copy(&manager, &dest, &journal_file, b"bbbxxx")?;
+        command_copy(&manager, &paths, &options)?;
+        let file_b = dest.find("SD Card\\Music\\Album\\b.mp3").unwrap();
+        assert_eq!(dest.data(&file_b), b"bbbbbb");
+
+        // already completed
+        let music = dest.find("SD Card\\Music").unwrap();
+        dest.delete(&music)?;
+        let (paths, options) = interrupt_copy(&manager, &dest, &journal_file, b"bbbbbb")?;
+        let file_b = dest.find("SD Card\\Music\\Album\\b.mp3").unwrap();
+        command_copy(&manager, &paths, &options)?;
+        assert_eq!(dest.find("SD Card\\Music\\Album\\b.mp3").unwrap(), file_b);
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_verifies_checksum() -> Result<(), Box<dyn std::error::Error>> {
+        let (manager, _src, dest) = setup();
+        let tempdir = tempfile::tempdir()?;
+        let paths = Paths {
+            src: "Src Phone:Internal:\\Music\\Album".to_string(),
+            dest: "Dest Phone:SD Card:\\Album".to_string(),
+        };
+        let options = CopyOptions {
+            recursive: true,
+            journal_file: Some(tempdir.path().join("journal").to_str().unwrap().to_string()),
+            verify: true,
+            ..Default::default()
+        };
+
+        // truncated file is transferred again
+        dest.truncate_next_files(2);
+        command_copy(&manager, &paths, &options)?;
+        let file_b = dest.find("SD Card\\Album\\b.mp3").unwrap();
+        assert_eq!(dest.data(&file_b), b"bbbbbb");
+
+        // gives up
+        let album = dest.find("SD Card\\Album").unwrap();
+        dest.delete(&album)?;
+        dest.truncate_next_files(3);
+        assert!(command_copy(&manager, &paths, &options).is_err());
+        assert!(dest.find("SD Card\\Album\\b.mp3").is_none());
+
+        // not detected without verification
+        let album = dest.find("SD Card\\Album").unwrap();
+        dest.delete(&album)?;
+        dest.truncate_next_files(1);
+        let options = CopyOptions {
+            verify: false,
+            ..options
+        };
+        command_copy(&manager, &paths, &options)?;
+        let file_b = dest.find("SD Card\\Album\\b.mp3").unwrap();
+        assert_eq!(dest.data(&file_b), b"bbbbb");
+
+        // the existing file is kept if the new file does not match
+        dest.set_rename_supported(true);
+        dest.truncate_next_files(3);
+        let options = CopyOptions {
+            verify: true,
+            compare: CompareMode::Always,
+            ..options
+        };
+        assert!(command_copy(&manager, &paths, &options).is_err());
+        let album = dest.find("SD Card\\Album").unwrap();
+        assert_eq!(dest.child_names(&album), vec!["b.mp3"]);
+        let file_b = dest.find("SD Card\\Album\\b.mp3").unwrap();
+        assert_eq!(dest.data(&file_b), b"bbbbb");
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_matched_files() -> Result<(), Box<dyn std::error::Error>> {
+        let (manager, _src, dest) = setup();
+
+        copy(
+            &manager,
+            "Src Phone:Internal:\\Music\\**\\*.mp3",
+            "Dest Phone:SD Card:\\",
+            false,
+            false,
+        )?;
+
+        let storage = dest.find("SD Card").unwrap();
+        assert_eq!(dest.child_names(&storage), vec!["b.mp3", "a.mp3"]);
+        let file_b = dest.find("SD Card\\b.mp3").unwrap();
+        assert_eq!(dest.data(&file_b), b"bbbbbb");
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_matched_files_with_structure() -> Result<(), Box<dyn std::error::Error>> {
+        let (manager, src, dest) = setup();
+        let album = src.find("Internal\\Music\\Album").unwrap();
+        src.add_file(&album, "a.mp3", b"AAAA");
+        let paths = Paths {
+            src: "Src Phone:Internal:\\Music\\**\\*.mp3".to_string(),
+            dest: "Dest Phone:SD Card:\\".to_string(),
+        };
+
+        // the files of the same name cannot be copied into one folder
+        let result = plan_copy(&manager, &paths, &CopyOptions::default());
+        assert!(result.is_err());
+
+        let options = CopyOptions {
+            preserve_structure: true,
+            ..Default::default()
+        };
+        let plan = plan_copy(&manager, &paths, &options)?;
+        assert_eq!(plan.source, "Src Phone:Internal:\\Music");
+        // the folder shared by the files is created once
+        assert_eq!(
+            plan.actions
+                .iter()
+                .filter(|action| action.action == ActionKind::CreateFolder)
+                .count(),
+            1
+        );
+
+        let tempdir = tempfile::tempdir()?;
+        let options = CopyOptions {
+            journal_file: Some(tempdir.path().join("journal").to_str().unwrap().to_string()),
+            ..options
+        };
+        command_copy(&manager, &paths, &options)?;
+        let storage = dest.find("SD Card").unwrap();
+        assert_eq!(dest.child_names(&storage), vec!["Album", "a.mp3"]);
+        let album = dest.find("SD Card\\Album").unwrap();
+        assert_eq!(dest.child_names(&album), vec!["a.mp3", "b.mp3"]);
+        let file_a = dest.find("SD Card\\Album\\a.mp3").unwrap();
+        assert_eq!(dest.data(&file_a), b"AAAA");
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_matched_files_from_all_storages() -> Result<(), Box<dyn std::error::Error>> {
+        let (manager, src, dest) = setup();
+        let storage = src.add_storage("SD Card");
+        let music = src.add_folder(&storage, "Music");
+        src.add_file(&music, "c.mp3", b"cc");
+
+        command_copy_to_all(
+            &manager,
+            "Src Phone:*:\\Music\\*.mp3",
+            &["Dest Phone:SD Card:\\".to_string()],
+            &CopyOptions::default(),
+        )?;
+
+        let storage = dest.find("SD Card").unwrap();
+        assert_eq!(dest.child_names(&storage), vec!["a.mp3", "c.mp3"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_wildcard_errors() {
+        let (manager, _src, _dest) = setup();
+        let tempdir = tempfile::tempdir().unwrap();
+        let plan = |src: &str, dest: &str, options: &CopyOptions| {
+            let paths = Paths {
+                src: src.to_string(),
+                dest: dest.to_string(),
+            };
+            plan_copy(&manager, &paths, options)
+        };
+        let options = CopyOptions::default();
+
+        // nothing matched
+        assert!(plan(
+            "Src Phone:Internal:\\Music\\*.jpg",
+            "Dest Phone:SD Card:\\",
+            &options
+        )
+        .is_err());
+        // the destination folder does not exist
+        assert!(plan(
+            "Src Phone:Internal:\\Music\\*.mp3",
+            "Dest Phone:SD Card:\\Music",
+            &options
+        )
+        .is_err());
+        assert!(plan(
+            "Src Phone:Internal:\\Music\\a.mp3",
+            "Dest Phone:SD Card:\\*",
+            &options
+        )
+        .is_err());
+        assert!(plan(
+            tempdir.path().join("*.txt").to_str().unwrap(),
+            "Dest Phone:SD Card:\\",
+            &options
+        )
+        .is_err());
+        let options = CopyOptions {
+            move_source: true,
+            ..options
+        };
+        assert!(plan(
+            "Src Phone:Internal:\\Music\\*.mp3",
+            "Dest Phone:SD Card:\\",
+            &options
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_copy_from_device_to_local() -> Result<(), Box<dyn std::error::Error>> {
+        let (manager, _src, _dest) = setup();
+        let tempdir = tempfile::tempdir()?;
+
+        copy(
+            &manager,
+            "Src Phone:Internal:\\Music",
+            tempdir.path().join("Music").to_str().unwrap(),
+            true,
+            false,
+        )?;
+
+        let path = tempdir.path().join("Music").join("Album").join("b.mp3");
+        assert_eq!(std::fs::read(&path)?, b"bbbbbb");
+        assert!(!tempdir.path().join("Music").join("hidden.dat").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_from_local_to_device() -> Result<(), Box<dyn std::error::Error>> {
+        let (manager, _src, dest) = setup();
+        let tempdir = tempfile::tempdir()?;
+        let folder = tempdir.path().join("Docs");
+        std::fs::create_dir_all(folder.join("Sub"))?;
+        std::fs::write(folder.join("a.txt"), b"aaa")?;
+        std::fs::write(folder.join("Sub").join("b.txt"), b"bb")?;
+
+        copy(
+            &manager,
+            folder.to_str().unwrap(),
+            "Dest Phone:SD Card:\\Docs",
+            true,
+            false,
+        )?;
+
+        let file_b = dest.find("SD Card\\Docs\\Sub\\b.txt").unwrap();
+        assert_eq!(dest.data(&file_b), b"bb");
+
+        // unchanged files are skipped
+        let paths = Paths {
+            src: folder.to_str().unwrap().to_string(),
+            dest: "Dest Phone:SD Card:\\Docs".to_string(),
+        };
+        let options = CopyOptions {
+            recursive: true,
+            ..Default::default()
+        };
+        let plan = plan_copy(&manager, &paths, &options)?;
+        assert!(plan
+            .actions
+            .iter()
+            .all(|action| action.action == ActionKind::Skip));
+        assert_eq!(plan.actions.len(), 2);
+        Ok(())
+    }
 }
@@ -1,13 +1,13 @@
 use chrono::NaiveDateTime;
 
-use crate::wpd::device::ContentObjectInfo;
-use crate::wpd::device::Device;
-use crate::wpd::manager::Manager;
+use crate::backend::content_object::ContentObjectInfo;
+use crate::backend::manager::Manager;
 
 use crate::finders::*;
 use crate::path::DeviceStoragePath;
 
 pub fn command_list_files(
+    manager: &dyn Manager,
     path: String,
     recursive: bool,
     verbose: u32,
@@ -16,17 +16,16 @@ pub fn command_list_files(
 
     let storage_path = DeviceStoragePath::from(&path)?;
 
-    let manager = Manager::get_portable_device_manager()?;
-    let device_info_vec = device_find_devices(&manager, Some(&storage_path.device_name))?;
+    let device_info_vec = device_find_devices(manager, Some(&storage_path.device_name))?;
 
     if device_info_vec.len() == 0 {
         return Err("No device matched.".into());
     }
 
     for device_info in device_info_vec {
-        let device = Device::open(&device_info)?;
+        let device = manager.open_device(&device_info)?;
         let storage_object_vec =
-            device_find_storage_objects(&device, Some(&storage_path.storage_name))?;
+            device_find_storage_objects(device.as_ref(), Some(&storage_path.storage_name))?;
 
         let callback = if verbose > 0 {
             show_file_or_folder_with_details
@@ -36,7 +35,7 @@ pub fn command_list_files(
 
         for storage_object_info in storage_object_vec {
             device_iterate_file_or_folder(
-                &device,
+                device.as_ref(),
                 &device_info,
                 &storage_object_info,
                 &storage_path.path,
@@ -84,3 +83,25 @@ fn format_datetime_opt(opt: &Option<NaiveDateTime>) -> String {
         Some(dt) => dt.to_string(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::manager::MemoryManager;
+
+    #[test]
+    fn test_command_list_files() {
+        let mut manager = MemoryManager::new();
+        let phone = manager.add_device("My Phone");
+        let storage = phone.add_storage("SD Card");
+        let folder = phone.add_folder(&storage, "Pictures");
+        phone.add_file(&folder, "a.jpg", b"a");
+
+        assert!(
+            command_list_files(&manager, "*:SD*:\\Pictures\\*.jpg".to_string(), false, 1).is_ok()
+        );
+        assert!(command_list_files(&manager, "My Phone:SD Card:".to_string(), true, 0).is_ok());
+        assert!(command_list_files(&manager, "Tablet:SD Card:".to_string(), false, 0).is_err());
+        assert!(command_list_files(&manager, "My Phone".to_string(), false, 0).is_err());
+    }
+}
@@ -1,22 +1,20 @@
-use crate::wpd::device::Device;
-use crate::wpd::manager::Manager;
+use crate::backend::manager::Manager;
 
 use crate::finders::*;
 
-pub fn command_list_storages() -> Result<(), Box<dyn std::error::Error>> {
+pub fn command_list_storages(manager: &dyn Manager) -> Result<(), Box<dyn std::error::Error>> {
     log::trace!("COMMAND list-storages");
 
-    let manager = Manager::get_portable_device_manager()?;
-    let device_info_vec = device_find_devices(&manager, None)?;
+    let device_info_vec = device_find_devices(manager, None)?;
 
     let mut count = 0;
     for device_info in device_info_vec {
-        match Device::open(&device_info) {
+        match manager.open_device(&device_info) {
             Err(err) => {
                 log::debug!("{}", err);
                 log::warn!("failed to open \"{}\" (skipped)", device_info.name);
             }
-            Ok(device) => match device_find_storage_objects(&device, None) {
+            Ok(device) => match device_find_storage_objects(device.as_ref(), None) {
                 Err(err) => {
                     log::debug!("{}", err);
                     log::warn!(
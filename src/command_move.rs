@@ -0,0 +1,241 @@
+use crate::backend::manager::Manager;
+use crate::command_copy::command_copy;
+use crate::copy::copy_processor::CopyOptions;
+use crate::Paths;
+
+/// Copies the source to the destination, then deletes each source file
+/// after it was copied (and verified, if requested).
+/// The source folders are deleted if they became empty.
+pub fn command_move(
+    manager: &dyn Manager,
+    paths: &Paths,
+    options: &CopyOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    log::trace!("COMMAND move");
+    log::trace!("  paths = {:?}", paths);
+    log::trace!("  options = {:?}", options);
+
+    let options = CopyOptions {
+        move_source: true,
+        ..options.clone()
+    };
+    command_copy(manager, paths, &options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::device::MemoryDevice;
+    use crate::memory::manager::MemoryManager;
+
+    fn setup() -> (MemoryManager, MemoryDevice, MemoryDevice) {
+        let mut manager = MemoryManager::new();
+        let src = manager.add_device("Camera");
+        let dest = manager.add_device("Phone");
+        let storage = src.add_storage("SD Card");
+        let dcim = src.add_folder(&storage, "DCIM");
+        src.add_file(&dcim, "a.jpg", b"aaaa");
+        let folder = src.add_folder(&dcim, "100CANON");
+        src.add_file(&folder, "b.jpg", b"bbbbbb");
+        src.add_folder(&dcim, "Empty");
+        dest.add_storage("Internal");
+        (manager, src, dest)
+    }
+
+    fn move_dcim(
+        manager: &MemoryManager,
+        options: CopyOptions,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let paths = Paths {
+            src: "Camera:SD Card:\\DCIM".to_string(),
+            dest: "Phone:Internal:\\DCIM".to_string(),
+        };
+        let journal_dir = tempfile::tempdir()?;
+        let options = CopyOptions {
+            recursive: true,
+            journal_file: Some(
+                journal_dir
+                    .path()
+                    .join("journal")
+                    .to_str()
+                    .unwrap()
+                    .to_string(),
+            ),
+            ..options
+        };
+        command_move(manager, &paths, &options)
+    }
+
+    #[test]
+    fn test_move_folder() -> Result<(), Box<dyn std::error::Error>> {
+        let (manager, src, dest) = setup();
+
+        move_dcim(&manager, CopyOptions::default())?;
+
+        let file_b = dest.find("Internal\\DCIM\\100CANON\\b.jpg").unwrap();
+        assert_eq!(dest.data(&file_b), b"bbbbbb");
+        assert!(dest.find("Internal\\DCIM\\Empty").is_some());
+        let storage = src.find("SD Card").unwrap();
+        assert!(src.child_names(&storage).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_move_keeps_undeletable_sources() -> Result<(), Box<dyn std::error::Error>> {
+        let (manager, src, dest) = setup();
+        let file_b = src.find("SD Card\\DCIM\\100CANON\\b.jpg").unwrap();
+        src.set_can_delete(&file_b, false);
+
+        let result = move_dcim(&manager, CopyOptions::default());
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "1 source files or folders could not be removed."
+        );
+
+        // b.jpg is left, and the folders containing it are kept without being reported
+        assert!(dest.find("Internal\\DCIM\\100CANON\\b.jpg").is_some());
+        let dcim = src.find("SD Card\\DCIM").unwrap();
+        assert_eq!(src.child_names(&dcim), vec!["100CANON"]);
+        assert!(src.find("SD Card\\DCIM\\100CANON\\b.jpg").is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_move_skipped_files() -> Result<(), Box<dyn std::error::Error>> {
+        let (manager, src, dest) = setup();
+        let storage = dest.find("Internal").unwrap();
+        let dcim = dest.add_folder(&storage, "DCIM");
+        // same file
+        dest.add_file(&dcim, "a.jpg", b"aaaa");
+        // skipped by the size, but the content differs
+        let folder = dest.add_folder(&dcim, "100CANON");
+        dest.add_file(&folder, "b.jpg", b"BBBBBB");
+
+        let options = CopyOptions {
+            compare: crate::copy::compare::CompareMode::Size,
+            ..Default::default()
+        };
+        assert!(move_dcim(&manager, options).is_err());
+
+        assert!(src.find("SD Card\\DCIM\\a.jpg").is_none());
+        assert!(src.find("SD Card\\DCIM\\100CANON\\b.jpg").is_some());
+        let file_b = dest.find("Internal\\DCIM\\100CANON\\b.jpg").unwrap();
+        assert_eq!(dest.data(&file_b), b"BBBBBB");
+        Ok(())
+    }
+
+    #[test]
+    fn test_move_keeps_source_on_verify_failure() {
+        let (manager, src, dest) = setup();
+        dest.truncate_next_files(3);
+
+        let options = CopyOptions {
+            verify: true,
+            ..Default::default()
+        };
+        assert!(move_dcim(&manager, options).is_err());
+
+        // a.jpg was copied first, and failed
+        assert!(src.find("SD Card\\DCIM\\a.jpg").is_some());
+        assert!(src.find("SD Card\\DCIM\\100CANON\\b.jpg").is_some());
+    }
+
+    #[test]
+    fn test_move_with_exclude_patterns() -> Result<(), Box<dyn std::error::Error>> {
+        let (manager, src, dest) = setup();
+        let dcim = src.find("SD Card\\DCIM").unwrap();
+        src.add_file(&dcim, "c.tmp", b"t");
+        let hidden = src.add_file(&dcim, "hidden.dat", b"h");
+        src.set_hidden(&hidden, true);
+
+        let options = CopyOptions {
+            exclude: vec!["*.tmp".to_string()],
+            ..Default::default()
+        };
+        move_dcim(&manager, options)?;
+
+        assert!(dest.find("Internal\\DCIM\\a.jpg").is_some());
+        assert!(dest.find("Internal\\DCIM\\100CANON\\b.jpg").is_some());
+        assert!(dest.find("Internal\\DCIM\\c.tmp").is_none());
+        // the folder containing the entries left out is kept
+        assert_eq!(src.child_names(&dcim), vec!["c.tmp", "hidden.dat"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_move_folder_without_recursive() {
+        let (manager, src, dest) = setup();
+        let paths = Paths {
+            src: "Camera:SD Card:\\DCIM".to_string(),
+            dest: "Phone:Internal:\\DCIM".to_string(),
+        };
+
+        assert!(command_move(&manager, &paths, &CopyOptions::default()).is_err());
+
+        assert!(dest.find("Internal\\DCIM").is_none());
+        assert!(src.find("SD Card\\DCIM\\a.jpg").is_some());
+    }
+
+    #[test]
+    fn test_move_on_same_device() -> Result<(), Box<dyn std::error::Error>> {
+        let (manager, src, _dest) = setup();
+        src.add_storage("Internal");
+        let file_a = src.find("SD Card\\DCIM\\a.jpg").unwrap();
+
+        let paths = Paths {
+            src: "Camera:SD Card:\\DCIM".to_string(),
+            dest: "Camera:Internal:\\DCIM".to_string(),
+        };
+        let journal_dir = tempfile::tempdir()?;
+        let options = CopyOptions {
+            recursive: true,
+            journal_file: Some(
+                journal_dir
+                    .path()
+                    .join("journal")
+                    .to_str()
+                    .unwrap()
+                    .to_string(),
+            ),
+            ..Default::default()
+        };
+        command_move(&manager, &paths, &options)?;
+
+        // the files were moved by the device, without transferring the data
+        assert_eq!(src.resources_opened(), 0);
+        let moved_a = src.find("Internal\\DCIM\\a.jpg").unwrap();
+        assert_eq!(moved_a.id, file_a.id);
+        assert_eq!(src.data(&moved_a), b"aaaa");
+        let file_b = src.find("Internal\\DCIM\\100CANON\\b.jpg").unwrap();
+        assert_eq!(src.data(&file_b), b"bbbbbb");
+        let storage = src.find("SD Card").unwrap();
+        assert!(src.child_names(&storage).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_move_local_folder() -> Result<(), Box<dyn std::error::Error>> {
+        let (manager, _src, dest) = setup();
+        let tempdir = tempfile::tempdir()?;
+        let folder = tempdir.path().join("Docs");
+        std::fs::create_dir_all(folder.join("Sub"))?;
+        std::fs::write(folder.join("a.txt"), b"aaa")?;
+        std::fs::write(folder.join("Sub").join("b.txt"), b"bb")?;
+
+        let paths = Paths {
+            src: folder.to_str().unwrap().to_string(),
+            dest: "Phone:Internal:\\Docs".to_string(),
+        };
+        let options = CopyOptions {
+            recursive: true,
+            journal_file: Some(tempdir.path().join("journal").to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+        command_move(&manager, &paths, &options)?;
+
+        let file_b = dest.find("Internal\\Docs\\Sub\\b.txt").unwrap();
+        assert_eq!(dest.data(&file_b), b"bb");
+        assert!(!folder.exists());
+        Ok(())
+    }
+}
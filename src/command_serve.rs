@@ -0,0 +1,187 @@
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+
+use crate::localdir::config::{DeviceConfig, StorageConfig};
+use crate::localdir::device::LocalDirDevice;
+use crate::mtp::ptpip::{with_default_port, PtpIpTransport};
+use crate::mtp::responder::Responder;
+use crate::mtp::transport::Transport;
+
+/// Where the responder waits for the initiators.
+#[derive(Debug)]
+pub enum ServeEndpoint {
+    /// PTP/IP on the TCP address
+    PtpIp(String),
+    /// MTP containers on the Unix domain socket
+    #[cfg(unix)]
+    UnixSocket(PathBuf),
+}
+
+pub fn command_serve(
+    dir: &str,
+    device_name: &str,
+    endpoint: &ServeEndpoint,
+    once: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    log::trace!("COMMAND serve");
+    log::trace!("  dir = {}", dir);
+    log::trace!("  device_name = {}", device_name);
+    log::trace!("  endpoint = {:?}", endpoint);
+
+    let config = device_config(Path::new(dir), device_name)?;
+
+    match endpoint {
+        ServeEndpoint::PtpIp(address) => {
+            let listener = TcpListener::bind(with_default_port(address))?;
+            println!(
+                "serving \"{}\" as \"{}:{}:\" on {} (PTP/IP)",
+                dir,
+                &config.name,
+                &config.storages[0].name,
+                listener.local_addr()?
+            );
+            serve_ptpip(&listener, &config, once)
+        }
+        #[cfg(unix)]
+        ServeEndpoint::UnixSocket(path) => {
+            let listener = std::os::unix::net::UnixListener::bind(path)?;
+            println!(
+                "serving \"{}\" as \"{}:{}:\" on {}",
+                dir,
+                &config.name,
+                &config.storages[0].name,
+                path.to_string_lossy()
+            );
+            loop {
+                let (stream, _) = listener.accept()?;
+                log::info!("connected");
+                let mut transport = crate::mtp::transport::StreamTransport::new(stream);
+                serve_connection(&config, &mut transport);
+                if once {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Makes a virtual device that has the folder as its only storage.
+fn device_config(
+    dir: &Path,
+    device_name: &str,
+) -> Result<DeviceConfig, Box<dyn std::error::Error>> {
+    if !dir.is_dir() {
+        return Err(format!("not a folder: {}", dir.to_string_lossy()).into());
+    }
+    let storage_name = match dir.canonicalize()?.file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => String::from("Storage"),
+    };
+    Ok(DeviceConfig {
+        name: device_name.to_string(),
+        storages: vec![StorageConfig {
+            name: storage_name,
+            path: PathBuf::from(dir),
+        }],
+    })
+}
+
+/// Serves the initiators connecting one after another.
+fn serve_ptpip(
+    listener: &TcpListener,
+    config: &DeviceConfig,
+    once: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    loop {
+        match PtpIpTransport::accept(listener, &config.name) {
+            Err(err) => {
+                log::warn!("failed to accept the initiator: {}", err);
+            }
+            Ok(mut transport) => {
+                log::info!("connected from \"{}\"", transport.peer_name());
+                serve_connection(config, &mut transport);
+            }
+        }
+        if once {
+            return Ok(());
+        }
+    }
+}
+
+/// Serves a connection.
+/// Errors are only logged so that the next initiator can be served.
+fn serve_connection(config: &DeviceConfig, transport: &mut dyn Transport) {
+    let device = LocalDirDevice::new(config.clone());
+    let result =
+        Responder::new(Box::new(device)).and_then(|mut responder| responder.serve(transport));
+    match result {
+        Ok(_) => log::info!("disconnected"),
+        Err(err) => log::warn!("connection aborted: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::content_object::ContentObjectInfo;
+    use crate::backend::device::Device;
+    use crate::finders::device_find_storage_objects;
+    use crate::mtp::device::MtpDevice;
+
+    fn children(device: &dyn Device, parent: &ContentObjectInfo) -> Vec<ContentObjectInfo> {
+        let mut iter = device.get_object_iterator(&parent.content_object).unwrap();
+        let mut infos = Vec::<ContentObjectInfo>::new();
+        while let Some(object) = iter.next().unwrap() {
+            infos.push(device.get_object_info(object).unwrap());
+        }
+        infos
+    }
+
+    #[test]
+    fn test_serve_local_folder() -> Result<(), Box<dyn std::error::Error>> {
+        let tempdir = tempfile::tempdir()?;
+        let dir = tempdir.path().join("share");
+        std::fs::create_dir_all(dir.join("Music"))?;
+        std::fs::write(dir.join("Music").join("a.mp3"), b"aaaa")?;
+
+        let config = device_config(&dir, "Served")?;
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?;
+        let handle = std::thread::spawn(move || serve_ptpip(&listener, &config, true).unwrap());
+
+        {
+            let transport = PtpIpTransport::connect(address, "test initiator")?;
+            let device = MtpDevice::open(Box::new(transport), None)?;
+            assert_eq!(device.name(), "Served");
+
+            let storage = device_find_storage_objects(&device, None)?.pop().unwrap();
+            assert_eq!(storage.name, "share");
+            let music = children(&device, &storage).pop().unwrap();
+            assert_eq!(music.name, "Music");
+            let file = children(&device, &music).pop().unwrap();
+            assert_eq!(file.name, "a.mp3");
+            assert_eq!(file.data_size, 4);
+
+            let mut reader = device.get_resoure(&file.content_object)?;
+            assert_eq!(reader.next(100)?.unwrap(), b"aaaa");
+            drop(reader);
+
+            let mut writer = device.create_file(&music.content_object, "b.mp3", 6, &None, &None)?;
+            writer.write(b"bbbbbb")?;
+            writer.commit()?;
+        }
+
+        handle.join().unwrap();
+        assert_eq!(std::fs::read(dir.join("Music").join("b.mp3"))?, b"bbbbbb");
+        Ok(())
+    }
+
+    #[test]
+    fn test_serve_non_folder() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let file = tempdir.path().join("a.txt");
+        std::fs::write(&file, b"a").unwrap();
+        assert!(device_config(&file, "Served").is_err());
+        assert!(device_config(&tempdir.path().join("none"), "Served").is_err());
+    }
+}
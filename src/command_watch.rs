@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::backend::device::{Device, DeviceEvent};
+use crate::backend::manager::{DeviceInfo, Manager};
+use crate::glob::filename::FileNamePattern;
+
+use crate::finders::*;
+use crate::path::DeviceStoragePath;
+
+pub fn command_watch(
+    manager: &dyn Manager,
+    path: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    log::trace!("COMMAND watch");
+
+    let storage_path = DeviceStoragePath::from(&path)?;
+
+    let mut device_info_vec = device_find_devices(manager, Some(&storage_path.device_name))?;
+    if device_info_vec.is_empty() {
+        return Err("No device matched.".into());
+    }
+    if device_info_vec.len() > 1 {
+        return Err("Multiple devices matched.".into());
+    }
+    let device_info = device_info_vec.pop().unwrap();
+    let device = manager.open_device(&device_info)?;
+
+    // subscribe before collecting the objects so that no changes are missed
+    let mut events = device.subscribe_events()?;
+    let mut watcher = Watcher::new(device.as_ref(), &device_info, &storage_path)?;
+    if watcher.storages.is_empty() {
+        return Err("No storage matched.".into());
+    }
+    println!("watching {}", &path);
+
+    loop {
+        if let Some(event) = events.next_event(Duration::from_secs(1))? {
+            let device_removed = event == DeviceEvent::DeviceRemoved;
+            if let Some(line) = watcher.handle_event(event) {
+                println!("{}", line);
+            }
+            if device_removed {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Keeps the paths of the watched objects to render the events.
+struct Watcher<'a> {
+    device: &'a dyn Device,
+    device_info: &'a DeviceInfo,
+    storage_pattern: FileNamePattern,
+    watch_storage_root: bool,
+    /// Storage object ID -> "<device>:<storage>:"
+    storages: HashMap<String, String>,
+    /// Object ID -> path of the watched objects
+    paths: HashMap<String, String>,
+}
+
+impl<'a> Watcher<'a> {
+    fn new(
+        device: &'a dyn Device,
+        device_info: &'a DeviceInfo,
+        storage_path: &DeviceStoragePath,
+    ) -> Result<Watcher<'a>, Box<dyn std::error::Error>> {
+        let mut watcher = Watcher {
+            device,
+            device_info,
+            storage_pattern: FileNamePattern::new(&storage_path.storage_name),
+            watch_storage_root: storage_path.path == "\\",
+            storages: HashMap::new(),
+            paths: HashMap::new(),
+        };
+        let storage_object_vec =
+            device_find_storage_objects(device, Some(&storage_path.storage_name))?;
+        for storage_object_info in storage_object_vec {
+            watcher.storages.insert(
+                storage_object_info.content_object.id.clone(),
+                format!("{}:{}:", &device_info.name, &storage_object_info.name),
+            );
+            let paths = &mut watcher.paths;
+            device_iterate_file_or_folder(
+                device,
+                device_info,
+                &storage_object_info,
+                &storage_path.path,
+                true,
+                |info, path| {
+                    paths.insert(info.content_object.id.clone(), path.to_string());
+                    Ok(true)
+                },
+            )?;
+        }
+        Ok(watcher)
+    }
+
+    /// Updates the paths, and returns the line to show.
+    /// Returns None if the event is not related to the watched objects.
+    fn handle_event(&mut self, event: DeviceEvent) -> Option<String> {
+        log::trace!("event {:?}", &event);
+        match event {
+            DeviceEvent::ObjectAdded {
+                object,
+                parent: Some(parent),
+            } => {
+                let parent_path = self.paths.get(&parent.id)?;
+                let info = self.device.get_object_info(object).ok()?;
+                if !info.is_file() && !info.is_folder() {
+                    return None;
+                }
+                let path = join_path(parent_path, &info.name);
+                self.paths
+                    .insert(info.content_object.id.clone(), path.clone());
+                Some(format!("added: {}", path))
+            }
+            DeviceEvent::ObjectAdded { parent: None, .. } => None,
+            DeviceEvent::ObjectRemoved(object) => {
+                let path = self.paths.remove(&object.id)?;
+                let prefix = join_path(&path, "");
+                self.paths.retain(|_, p| !p.starts_with(&prefix));
+                Some(format!("removed: {}", path))
+            }
+            DeviceEvent::ObjectInfoChanged(object) => {
+                let path = self.paths.get(&object.id)?;
+                Some(format!("changed: {}", path))
+            }
+            DeviceEvent::StorageAdded(storage) => {
+                let info = self.device.get_object_info(storage).ok()?;
+                if !info.is_storage() || !self.storage_pattern.matches(&info.name) {
+                    return None;
+                }
+                let storage_path = format!("{}:{}:", &self.device_info.name, &info.name);
+                self.storages
+                    .insert(info.content_object.id.clone(), storage_path.clone());
+                if self.watch_storage_root {
+                    self.paths
+                        .insert(info.content_object.id.clone(), join_path(&storage_path, ""));
+                }
+                Some(format!("storage added: {}", storage_path))
+            }
+            DeviceEvent::StorageRemoved(storage) => {
+                let storage_path = self.storages.remove(&storage.id)?;
+                self.paths.retain(|_, p| !p.starts_with(&storage_path));
+                Some(format!("storage removed: {}", storage_path))
+            }
+            DeviceEvent::DeviceRemoved => Some("device removed".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::manager::MemoryManager;
+
+    #[test]
+    fn test_watcher() -> Result<(), Box<dyn std::error::Error>> {
+        let mut manager = MemoryManager::new();
+        let phone = manager.add_device("My Phone");
+        let storage = phone.add_storage("SD Card");
+        let pictures = phone.add_folder(&storage, "Pictures");
+        let folder_2021 = phone.add_folder(&pictures, "2021");
+        let music = phone.add_folder(&storage, "Music");
+
+        let device_info = device_find_devices(&manager, Some("My Phone"))?
+            .pop()
+            .unwrap();
+        let device = manager.open_device(&device_info)?;
+        let mut events = device.subscribe_events()?;
+        let storage_path = DeviceStoragePath::from("My Phone:SD*:\\Pictures")?;
+        let mut watcher = Watcher::new(device.as_ref(), &device_info, &storage_path)?;
+
+        let mut lines = Vec::<String>::new();
+        let mut take_lines = |watcher: &mut Watcher| -> Result<(), Box<dyn std::error::Error>> {
+            while let Some(event) = events.next_event(Duration::from_millis(0))? {
+                if let Some(line) = watcher.handle_event(event) {
+                    lines.push(line);
+                }
+            }
+            Ok(())
+        };
+
+        let file_a = phone.add_file(&folder_2021, "a.jpg", b"a");
+        phone.set_time_modified(&file_a, None);
+        phone.add_file(&music, "b.mp3", b"b");
+        let folder_2022 = phone.add_folder(&pictures, "2022");
+        take_lines(&mut watcher)?;
+        phone.add_file(&folder_2022, "c.jpg", b"c");
+        device.delete(&folder_2021)?;
+        let sd_card_2 = phone.add_storage("SD Card 2");
+        phone.add_storage("USB");
+        take_lines(&mut watcher)?;
+        device.delete(&sd_card_2)?;
+        take_lines(&mut watcher)?;
+
+        assert_eq!(
+            lines,
+            vec![
+                "added: My Phone:SD Card:\\Pictures\\2021\\a.jpg",
+                "changed: My Phone:SD Card:\\Pictures\\2021\\a.jpg",
+                "added: My Phone:SD Card:\\Pictures\\2022",
+                "added: My Phone:SD Card:\\Pictures\\2022\\c.jpg",
+                "removed: My Phone:SD Card:\\Pictures\\2021",
+                "storage added: My Phone:SD Card 2:",
+                "storage removed: My Phone:SD Card 2:",
+            ]
+        );
+        assert!(!watcher.paths.values().any(|path| path.contains("a.jpg")));
+
+        assert_eq!(
+            watcher.handle_event(DeviceEvent::DeviceRemoved),
+            Some("device removed".to_string())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_command_watch_errors() {
+        let mut manager = MemoryManager::new();
+        manager.add_device("My Phone").add_storage("SD Card");
+        manager.add_device("My Tablet");
+
+        assert!(command_watch(&manager, "Camera:SD Card:".to_string()).is_err());
+        assert!(command_watch(&manager, "My*:SD Card:".to_string()).is_err());
+        assert!(command_watch(&manager, "My Phone:Internal:".to_string()).is_err());
+        assert!(command_watch(&manager, "My Phone".to_string()).is_err());
+    }
+}
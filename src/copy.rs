@@ -1,10 +1,24 @@
+pub mod checksum;
+pub mod compare;
 pub mod copy_processor;
 pub mod destination_folder;
 pub mod device_copy_processor;
 pub mod device_destination_folder;
 pub mod device_file_reader;
+pub mod device_plan_endpoint;
+pub mod executor;
 pub mod file_info;
 pub mod file_reader;
+pub mod filter;
+pub mod journal;
 pub mod local_copy_processor;
 pub mod local_destination_folder;
 pub mod local_file_reader;
+pub mod local_plan_endpoint;
+pub mod pipeline;
+pub mod plan;
+pub mod planned_folder;
+pub mod progress;
+pub mod rate_limit;
+pub mod reporter;
+pub mod source_cache;
@@ -0,0 +1,97 @@
+use sha2::{Digest, Sha256};
+
+use super::file_reader::FileReader;
+
+pub type Checksum = [u8; 32];
+
+/// Computes the checksum of the bytes while they are read through.
+pub struct ChecksumFileReader<R: FileReader> {
+    reader: R,
+    hasher: Sha256,
+}
+
+impl<R: FileReader> ChecksumFileReader<R> {
+    pub fn new(reader: R) -> ChecksumFileReader<R> {
+        ChecksumFileReader {
+            reader,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// Returns the checksum of the bytes which have been read.
+    pub fn checksum(self) -> Checksum {
+        self.hasher.finalize().into()
+    }
+}
+
+impl<R: FileReader> FileReader for ChecksumFileReader<R> {
+    fn get_optimized_buffer_size(&self) -> u32 {
+        self.reader.get_optimized_buffer_size()
+    }
+
+    fn next(&mut self, max_size: u32) -> Result<Option<&[u8]>, Box<dyn std::error::Error>> {
+        let result = self.reader.next(max_size)?;
+        if let Some(bytes) = result {
+            self.hasher.update(bytes);
+        }
+        Ok(result)
+    }
+}
+
+/// Reads all bytes from the reader and returns the checksum.
+pub fn checksum_of(reader: &mut dyn FileReader) -> Result<Checksum, Box<dyn std::error::Error>> {
+    let mut hasher = Sha256::new();
+    while let Some(bytes) = reader.next(reader.get_optimized_buffer_size())? {
+        hasher.update(bytes);
+    }
+    Ok(hasher.finalize().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct BytesReader {
+        data: Vec<u8>,
+        offset: usize,
+    }
+
+    impl FileReader for BytesReader {
+        fn get_optimized_buffer_size(&self) -> u32 {
+            3
+        }
+
+        fn next(&mut self, max_size: u32) -> Result<Option<&[u8]>, Box<dyn std::error::Error>> {
+            if self.offset >= self.data.len() {
+                return Ok(None);
+            }
+            let end = std::cmp::min(self.data.len(), self.offset + max_size as usize);
+            let chunk = &self.data[self.offset..end];
+            self.offset = end;
+            Ok(Some(chunk))
+        }
+    }
+
+    fn reader(data: &[u8]) -> BytesReader {
+        BytesReader {
+            data: data.to_vec(),
+            offset: 0,
+        }
+    }
+
+    #[test]
+    fn test_checksum() -> Result<(), Box<dyn std::error::Error>> {
+        let mut checksum_reader = ChecksumFileReader::new(reader(b"abcdefgh"));
+        let mut data = Vec::<u8>::new();
+        while let Some(bytes) = checksum_reader.next(5)? {
+            data.extend_from_slice(bytes);
+        }
+        assert_eq!(data, b"abcdefgh");
+        let checksum = checksum_reader.checksum();
+
+        assert_eq!(checksum, checksum_of(&mut reader(b"abcdefgh"))?);
+        assert_ne!(checksum, checksum_of(&mut reader(b"abcdefg"))?);
+        assert_ne!(checksum, checksum_of(&mut reader(b"abcdefgi"))?);
+        Ok(())
+    }
+}
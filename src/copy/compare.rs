@@ -0,0 +1,234 @@
+use chrono::NaiveDateTime;
+
+use super::file_info::FileInfo;
+
+/// How to decide whether the existing destination file is replaced
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CompareMode {
+    /// Skip if the sizes are the same
+    Size,
+    /// Skip if the source is not newer
+    Mtime,
+    /// Skip if the sizes are the same and the source is not newer
+    #[default]
+    SizeAndMtime,
+    /// Skip if the contents are the same
+    Checksum,
+    /// Always replace the existing file
+    Always,
+    /// Never replace the existing file
+    Never,
+}
+
+impl std::str::FromStr for CompareMode {
+    type Err = Box<dyn std::error::Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "size" => Ok(CompareMode::Size),
+            "mtime" => Ok(CompareMode::Mtime),
+            "size+mtime" => Ok(CompareMode::SizeAndMtime),
+            "checksum" => Ok(CompareMode::Checksum),
+            "always" => Ok(CompareMode::Always),
+            "never" => Ok(CompareMode::Never),
+            _ => Err(format!("invalid compare mode: {}", s).into()),
+        }
+    }
+}
+
+/// What to do with the existing destination file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    Skip(String),
+    Replace(String),
+}
+
+/// Compares the source file and the existing destination file.
+///
+/// `time_tolerance` is the number of seconds by which the source may be newer
+/// than the destination without being copied.
+/// `same_content` is called only if the contents need to be compared.
+pub fn compare_files<F>(
+    src_file_info: &FileInfo,
+    dest_file_info: &FileInfo,
+    mode: CompareMode,
+    time_tolerance: u32,
+    same_content: F,
+) -> Result<Decision, Box<dyn std::error::Error>>
+where
+    F: FnOnce() -> Result<bool, Box<dyn std::error::Error>>,
+{
+    match mode {
+        CompareMode::Always => return Ok(replace("always replaced")),
+        CompareMode::Never => return Ok(skip("never replaced")),
+        _ => {}
+    }
+    if dest_file_info.is_folder {
+        return Ok(replace("a folder exists"));
+    }
+    let same_size = src_file_info.data_size == dest_file_info.data_size;
+    let decision = match mode {
+        CompareMode::Size => {
+            if same_size {
+                skip("same size")
+            } else {
+                replace("size differs")
+            }
+        }
+        CompareMode::Mtime => compare_times(src_file_info, dest_file_info, time_tolerance),
+        CompareMode::SizeAndMtime => {
+            if same_size {
+                compare_times(src_file_info, dest_file_info, time_tolerance)
+            } else {
+                replace("size differs")
+            }
+        }
+        CompareMode::Checksum => {
+            if !same_size {
+                replace("size differs")
+            } else if same_content()? {
+                skip("same content")
+            } else {
+                replace("content differs")
+            }
+        }
+        CompareMode::Always | CompareMode::Never => unreachable!(),
+    };
+    Ok(decision)
+}
+
+fn compare_times(
+    src_file_info: &FileInfo,
+    dest_file_info: &FileInfo,
+    time_tolerance: u32,
+) -> Decision {
+    let (src_time, dest_time) = match (get_file_time(src_file_info), get_file_time(dest_file_info))
+    {
+        (Some(src_time), Some(dest_time)) => (src_time, dest_time),
+        _ => return replace("time unknown"),
+    };
+    // use .timestamp() value to ignore the subsecond
+    let src_ts = src_time.timestamp();
+    let dest_ts = dest_time.timestamp();
+    log::debug!(
+        "src_time = {:?} ({:?})  dest_time = {:?} ({:?})",
+        &src_time,
+        &src_ts,
+        &dest_time,
+        &dest_ts
+    );
+    let newer_by = src_ts - dest_ts;
+    if newer_by <= 0 {
+        skip("not changed")
+    } else if newer_by <= time_tolerance as i64 {
+        Decision::Skip(format!("newer by {}s, within the tolerance", newer_by))
+    } else {
+        Decision::Replace(format!("source is newer by {}s", newer_by))
+    }
+}
+
+fn get_file_time(file_info: &FileInfo) -> Option<NaiveDateTime> {
+    if let Some(time_created) = file_info.time_created {
+        if let Some(time_modified) = file_info.time_modified {
+            Some(std::cmp::max(time_created, time_modified))
+        } else {
+            Some(time_created)
+        }
+    } else {
+        file_info.time_modified
+    }
+}
+
+fn skip(reason: &str) -> Decision {
+    Decision::Skip(reason.to_string())
+}
+
+fn replace(reason: &str) -> Decision {
+    Decision::Replace(reason.to_string())
+}
+
+#[cfg(test)]
+#[allow(clippy::unused_unit)] // expanded from test_case
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use test_case::test_case;
+
+    fn file(size: u64, second: u32) -> FileInfo {
+        FileInfo {
+            name: "a.mp3".to_string(),
+            is_folder: false,
+            is_hidden: false,
+            is_system: false,
+            can_delete: true,
+            time_created: None,
+            time_modified: Some(NaiveDate::from_ymd(2021, 4, 1).and_hms(12, 0, second)),
+            data_size: size,
+        }
+    }
+
+    #[test_case(CompareMode::SizeAndMtime, 0, 10, 10 => Decision::Skip("not changed".to_string()))]
+    #[test_case(CompareMode::SizeAndMtime, 0, 11, 10 => Decision::Replace("source is newer by 1s".to_string()))]
+    #[test_case(CompareMode::SizeAndMtime, 2, 12, 10 => Decision::Skip("newer by 2s, within the tolerance".to_string()))]
+    #[test_case(CompareMode::SizeAndMtime, 2, 13, 10 => Decision::Replace("source is newer by 3s".to_string()))]
+    #[test_case(CompareMode::Size, 0, 13, 10 => Decision::Skip("same size".to_string()))]
+    #[test_case(CompareMode::Mtime, 0, 9, 10 => Decision::Skip("not changed".to_string()))]
+    #[test_case(CompareMode::Always, 0, 10, 10 => Decision::Replace("always replaced".to_string()))]
+    #[test_case(CompareMode::Never, 0, 13, 10 => Decision::Skip("never replaced".to_string()))]
+    fn test_compare_times(
+        mode: CompareMode,
+        time_tolerance: u32,
+        src_second: u32,
+        dest_second: u32,
+    ) -> Decision {
+        compare_files(
+            &file(4, src_second),
+            &file(4, dest_second),
+            mode,
+            time_tolerance,
+            || panic!("contents are not compared"),
+        )
+        .unwrap()
+    }
+
+    #[test_case(CompareMode::Size, 4, 5, true => Decision::Replace("size differs".to_string()))]
+    #[test_case(CompareMode::Mtime, 4, 5, true => Decision::Skip("not changed".to_string()))]
+    #[test_case(CompareMode::SizeAndMtime, 4, 5, true => Decision::Replace("size differs".to_string()))]
+    #[test_case(CompareMode::Checksum, 4, 5, true => Decision::Replace("size differs".to_string()))]
+    #[test_case(CompareMode::Checksum, 4, 4, true => Decision::Skip("same content".to_string()))]
+    #[test_case(CompareMode::Checksum, 4, 4, false => Decision::Replace("content differs".to_string()))]
+    fn test_compare_sizes_and_contents(
+        mode: CompareMode,
+        src_size: u64,
+        dest_size: u64,
+        same_content: bool,
+    ) -> Decision {
+        compare_files(&file(src_size, 10), &file(dest_size, 10), mode, 0, || {
+            Ok(same_content)
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_existing_folder_is_replaced() {
+        let mut folder = file(0, 10);
+        folder.is_folder = true;
+        assert_eq!(
+            compare_files(&file(4, 10), &folder, CompareMode::Size, 0, || Ok(true)).unwrap(),
+            Decision::Replace("a folder exists".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_compare_mode() {
+        assert_eq!(
+            "size+mtime".parse::<CompareMode>().unwrap(),
+            CompareMode::SizeAndMtime
+        );
+        assert_eq!(
+            "checksum".parse::<CompareMode>().unwrap(),
+            CompareMode::Checksum
+        );
+        assert!("content".parse::<CompareMode>().is_err());
+    }
+}
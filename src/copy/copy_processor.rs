@@ -1,74 +1,52 @@
-use std::io::{stdout, Write};
-
-use chrono::NaiveDateTime;
-
-use super::{destination_folder::DestinationFolder, file_info::FileInfo};
+use super::compare::CompareMode;
+use super::destination_folder::DestinationFolder;
+use super::plan::Planner;
+use super::planned_folder::PlannedFolder;
+use super::reporter::OutputFormat;
+
+#[derive(Debug, Default, Clone)]
+pub struct CopyOptions {
+    /// Copy the contents of the folders
+    pub recursive: bool,
+    /// Delete files and folders that do not exist in the source
+    pub mirror: bool,
+    /// Only report what would be done
+    pub dry_run: bool,
+    /// Save the plan to the file instead of copying
+    pub plan_file: Option<String>,
+    /// Continue the interrupted copy recorded in the journal
+    pub resume: bool,
+    /// Journal file (or None to use the default location)
+    pub journal_file: Option<String>,
+    /// Read back the copied files and compare the checksums
+    pub verify: bool,
+    /// How to decide whether the existing file is replaced
+    pub compare: CompareMode,
+    /// Seconds by which the source may be newer than the existing file without being copied
+    pub time_tolerance: u32,
+    /// Explain why each file is skipped or replaced
+    pub verbose: bool,
+    /// Delete the source files and folders after they were copied
+    pub move_source: bool,
+    /// Bytes per second to read the source files (None for unlimited)
+    pub bwlimit: Option<u64>,
+    /// Format of the messages
+    pub output: OutputFormat,
+    /// Patterns of the files to copy (all files if empty)
+    pub include: Vec<String>,
+    /// Patterns of the files and folders not to copy
+    pub exclude: Vec<String>,
+    /// Copy the files matched by the wildcard with the folders below the first wildcard component
+    pub preserve_structure: bool,
+}
 
 pub trait CopyProcessor {
-    fn copy_as(
+    /// Plans the actions to copy the source as `name` in the destination folder.
+    fn plan_as<D: DestinationFolder>(
         &self,
         name: &str,
-        dest: &mut impl DestinationFolder,
+        dest: &mut PlannedFolder<D>,
         dest_is_parent_folder: bool,
-        recursive: bool,
-        mirror: bool,
+        planner: &mut Planner,
     ) -> Result<(), Box<dyn std::error::Error>>;
 }
-
-pub fn can_skip_copying(src_file_info: &FileInfo, dest_file_info: &FileInfo) -> bool {
-    if src_file_info.data_size == dest_file_info.data_size {
-        if let Some(src_time) = get_file_time(src_file_info) {
-            if let Some(dest_time) = get_file_time(dest_file_info) {
-                // use .timestamp() value to ignore the subsecond
-                let src_ts = src_time.timestamp();
-                let dest_ts = dest_time.timestamp();
-                log::debug!(
-                    "src_time = {:?} ({:?})  dest_time = {:?} ({:?})",
-                    &src_time,
-                    &src_ts,
-                    &dest_time,
-                    &dest_ts
-                );
-                return src_ts <= dest_ts;
-            }
-        }
-    }
-    false
-}
-
-fn get_file_time(file_info: &FileInfo) -> Option<NaiveDateTime> {
-    if let Some(time_created) = file_info.time_created {
-        if let Some(time_modified) = file_info.time_modified {
-            Some(std::cmp::max(time_created, time_modified))
-        } else {
-            Some(time_created)
-        }
-    } else {
-        if let Some(time_modified) = file_info.time_modified {
-            Some(time_modified)
-        } else {
-            None
-        }
-    }
-}
-
-pub fn report_copying_start(src_file_info: &FileInfo) {
-    print!("copying \"{}\" ...", src_file_info.name);
-    stdout().flush().unwrap();
-}
-
-pub fn report_copying_end() {
-    println!("");
-}
-
-pub fn report_creating_new_folder(name: &str) {
-    println!("create folder \"{}\"", name);
-}
-
-pub fn report_delete_file(name: &str) {
-    println!("delete file \"{}\"", name);
-}
-
-pub fn report_delete_folder(name: &str) {
-    println!("delete folder \"{}\"", name);
-}
@@ -1,5 +1,7 @@
 use chrono::NaiveDateTime;
 
+use crate::backend::content_object::ContentObject;
+
 use super::file_info::FileInfo;
 use super::file_reader::FileReader;
 
@@ -7,6 +9,9 @@ pub trait DestinationFolder {
     fn get_file_info(&mut self, name: &str)
         -> Result<Option<FileInfo>, Box<dyn std::error::Error>>;
 
+    /// Creates the file.
+    /// If `verify` is true, the file is read back and its checksum is compared with the data read.
+    /// Returns false if they differ, then the file is not left.
     fn create_file(
         &mut self,
         name: &str,
@@ -14,7 +19,39 @@ pub trait DestinationFolder {
         size: u64,
         created: &Option<NaiveDateTime>,
         modified: &Option<NaiveDateTime>,
-    ) -> Result<(), Box<dyn std::error::Error>>;
+        verify: bool,
+    ) -> Result<bool, Box<dyn std::error::Error>>;
+
+    /// Replaces the existing file or folder with the new file.
+    /// If `verify` is true, the new file is checked as `create_file` does before it replaces the existing one.
+    fn replace_file(
+        &mut self,
+        name: &str,
+        reader: &mut impl FileReader,
+        size: u64,
+        created: &Option<NaiveDateTime>,
+        modified: &Option<NaiveDateTime>,
+        verify: bool,
+    ) -> Result<bool, Box<dyn std::error::Error>>;
+
+    /// Copies the file on the same device into this folder, without transferring the data.
+    /// Returns false if the device cannot copy the file by itself.
+    fn copy_on_device(
+        &mut self,
+        name: &str,
+        object: &ContentObject,
+    ) -> Result<bool, Box<dyn std::error::Error>>;
+
+    /// Moves the file on the same device into this folder, without transferring the data.
+    /// Returns false if the device cannot move the file by itself.
+    fn move_on_device(
+        &mut self,
+        name: &str,
+        object: &ContentObject,
+    ) -> Result<bool, Box<dyn std::error::Error>>;
+
+    /// Opens the file in this folder to read it back.
+    fn open_file(&mut self, name: &str) -> Result<Box<dyn FileReader>, Box<dyn std::error::Error>>;
 
     fn open_or_create_folder<FBeforeOpen, FBeforeCreate>(
         &mut self,
@@ -30,12 +67,11 @@ pub trait DestinationFolder {
 
     fn retain(&mut self, name: &str);
 
-    fn delete_unretained<FBeforeDeleteFile, FBeforeDeleteFolder>(
-        &mut self,
-        before_delete_file: FBeforeDeleteFile,
-        before_delete_folder: FBeforeDeleteFolder,
-    ) -> Result<(), Box<dyn std::error::Error>>
-    where
-        FBeforeDeleteFile: Fn(&str),
-        FBeforeDeleteFolder: Fn(&str);
+    /// Returns files and folders in this folder which were not retained.
+    fn get_unretained(&mut self) -> Result<Vec<FileInfo>, Box<dyn std::error::Error>>;
+}
+
+/// Returns the name of the temporary file used while the file is being replaced.
+pub fn temporary_name(name: &str) -> String {
+    format!(".{}.mtpcopy-tmp", name)
 }
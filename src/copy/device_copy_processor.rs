@@ -1,90 +1,92 @@
-use crate::wpd::device::{ContentObjectInfo, Device};
+use crate::backend::content_object::ContentObjectInfo;
+use crate::backend::device::Device;
+use crate::finders::join_path;
 
 use super::destination_folder::DestinationFolder;
 use super::device_file_reader::DeviceFileReader;
 use super::file_info::FileInfo;
+use super::file_reader::same_content;
+use super::plan::{normalize_path, Planner};
+use super::planned_folder::PlannedFolder;
 
-use super::copy_processor::{
-    can_skip_copying, report_copying_end, report_copying_start, report_creating_new_folder,
-    report_delete_file, report_delete_folder, CopyProcessor,
-};
+use super::copy_processor::CopyProcessor;
 
 pub struct DeviceCopyProcessor<'d> {
-    device: &'d Device,
+    device: &'d dyn Device,
     source_root_object_info: ContentObjectInfo,
+    source_root_path: String,
 }
 
 impl<'d> DeviceCopyProcessor<'d> {
-    pub fn new(device: &'d Device, source_root_object_info: ContentObjectInfo) -> Self {
+    pub fn new(
+        device: &'d dyn Device,
+        source_root_object_info: ContentObjectInfo,
+        source_root_path: &str,
+    ) -> Self {
         Self {
             device,
             source_root_object_info,
+            source_root_path: normalize_path(source_root_path),
         }
     }
 }
 
 impl<'d> CopyProcessor for DeviceCopyProcessor<'d> {
-    fn copy_as(
+    fn plan_as<D: DestinationFolder>(
         &self,
         name: &str,
-        dest: &mut impl DestinationFolder,
+        dest: &mut PlannedFolder<D>,
         dest_is_parent_folder: bool,
-        recursive: bool,
-        mirror: bool,
+        planner: &mut Planner,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        copy_hierarchy(
+        plan_hierarchy(
             self.device,
             dest,
             dest_is_parent_folder,
             &self.source_root_object_info,
+            &self.source_root_path,
             name,
-            recursive,
-            mirror,
+            planner,
         )
     }
 }
 
-fn copy_hierarchy(
-    device: &Device,
-    dest: &mut impl DestinationFolder,
+fn plan_hierarchy<D: DestinationFolder>(
+    device: &dyn Device,
+    dest: &mut PlannedFolder<D>,
     dest_is_parent_folder: bool,
     target_object_info: &ContentObjectInfo,
+    src_path: &str,
     dest_name: &str,
-    recursive: bool,
-    mirror: bool,
+    planner: &mut Planner,
 ) -> Result<(), Box<dyn std::error::Error>> {
     if target_object_info.is_system || target_object_info.is_hidden {
         return Ok(());
     }
 
+    if planner.is_excluded(src_path, target_object_info.is_folder()) {
+        // the existing destination is not deleted by mirroring
+        dest.retain(dest_name);
+        return Ok(());
+    }
+
     if target_object_info.is_file() {
-        let src_file_info = FileInfo::from_content_object_info(&target_object_info)?;
+        let src_file_info = FileInfo::from_content_object_info(target_object_info)?;
         let dest_file_info = dest.get_file_info(dest_name)?;
-
-        if let Some(dest_file_info_ref) = dest_file_info.as_ref() {
-            if can_skip_copying(&src_file_info, dest_file_info_ref) {
-                dest.retain(dest_name);
-                return Ok(());
-            }
-        }
-
-        if dest_file_info.is_some() {
-            dest.delete_file_or_folder(dest_name)?;
-        }
-
-        let res_reader = device.get_resoure(&target_object_info.content_object)?;
-        let mut dev_reader = DeviceFileReader::new(res_reader);
-        report_copying_start(&src_file_info);
-        dest.create_file(
-            dest_name,
-            &mut dev_reader,
-            src_file_info.data_size,
-            &target_object_info.time_created,
-            &target_object_info.time_modified,
+        let dest_path = dest.path_of(dest_name);
+        planner.plan_file(
+            src_path,
+            &dest_path,
+            &src_file_info,
+            dest_file_info.as_ref(),
+            || {
+                let reader = device.get_resoure(&target_object_info.content_object)?;
+                let mut src_reader = DeviceFileReader::new(reader);
+                let mut dest_reader = dest.open_file(dest_name)?;
+                same_content(&mut src_reader, dest_reader.as_mut())
+            },
         )?;
         dest.retain(dest_name);
-        report_copying_end();
-
         return Ok(());
     }
 
@@ -93,32 +95,40 @@ fn copy_hierarchy(
         let new_dest_ref;
 
         if dest_is_parent_folder {
-            new_dest = dest.open_or_create_folder(dest_name, |_| {}, report_creating_new_folder)?;
+            let src_file_info = FileInfo::from_content_object_info(target_object_info)?;
+            let dest_file_info = dest.get_file_info(dest_name)?;
+            planner.plan_folder(
+                src_path,
+                &dest.path_of(dest_name),
+                &src_file_info,
+                dest_file_info.as_ref(),
+            );
+            new_dest = dest.open_folder(dest_name)?;
             dest.retain(dest_name);
-            new_dest_ref = new_dest.as_mut();
+            new_dest_ref = &mut new_dest;
         } else {
             // if the source object was a folder, and the specified destination
             // was an existing folder, use the destination folder as it is.
             new_dest_ref = dest;
         }
 
-        if recursive {
+        if planner.options().recursive {
             let mut iter = device.get_object_iterator(&target_object_info.content_object)?;
             while let Some(content_object) = iter.next()? {
                 let content_object_info = device.get_object_info(content_object)?;
-                copy_hierarchy(
+                plan_hierarchy(
                     device,
                     new_dest_ref,
                     true, // dest_is_parent_folder
                     &content_object_info,
+                    &join_path(src_path, &content_object_info.name),
                     &content_object_info.name,
-                    recursive,
-                    mirror,
+                    planner,
                 )?;
             }
 
-            if mirror {
-                new_dest_ref.delete_unretained(report_delete_file, report_delete_folder)?;
+            if planner.options().mirror {
+                planner.plan_deletion(src_path, new_dest_ref)?;
             }
         }
     }
@@ -1,30 +1,37 @@
 use chrono::NaiveDateTime;
 use std::collections::{HashMap, HashSet};
 
-use crate::wpd::device::{ContentObjectInfo, Device};
+use crate::backend::content_object::{ContentObject, ContentObjectInfo};
+use crate::backend::device::Device;
 
+use super::checksum::{checksum_of, ChecksumFileReader};
+use super::device_file_reader::DeviceFileReader;
 use super::file_info::FileInfo;
 use super::file_reader::FileReader;
 
-use super::destination_folder::DestinationFolder;
+use super::destination_folder::{temporary_name, DestinationFolder};
 
 pub struct DeviceDestinationFolder<'d> {
-    device: &'d Device,
+    device: &'d dyn Device,
     folder_object_info: ContentObjectInfo,
-    entry_map: HashMap<String, ContentObjectInfo>,
+    /// The device may have several objects of the same name
+    entry_map: HashMap<String, Vec<ContentObjectInfo>>,
     retained: HashSet<String>,
 }
 
 impl<'d> DeviceDestinationFolder<'d> {
     pub fn new(
-        device: &'d Device,
+        device: &'d dyn Device,
         folder_object_info: ContentObjectInfo,
     ) -> Result<DeviceDestinationFolder<'d>, Box<dyn std::error::Error>> {
         let mut iter = device.get_object_iterator(&folder_object_info.content_object)?;
-        let mut entry_map = HashMap::<String, ContentObjectInfo>::new();
+        let mut entry_map = HashMap::<String, Vec<ContentObjectInfo>>::new();
         while let Some(object) = iter.next()? {
             let object_info = device.get_object_info(object)?;
-            entry_map.insert(object_info.name.clone(), object_info);
+            entry_map
+                .entry(object_info.name.clone())
+                .or_default()
+                .push(object_info);
         }
         let retained = HashSet::<String>::new();
 
@@ -35,27 +42,28 @@ impl<'d> DeviceDestinationFolder<'d> {
             retained,
         })
     }
-}
 
-impl<'d> DestinationFolder for DeviceDestinationFolder<'d> {
-    fn get_file_info(
-        &mut self,
-        name: &str,
-    ) -> Result<Option<FileInfo>, Box<dyn std::error::Error>> {
+    /// Finds the object by the name.
+    /// It is an error if several objects have the name, because either of them cannot be chosen.
+    fn find(&self, name: &str) -> Result<Option<&ContentObjectInfo>, Box<dyn std::error::Error>> {
         match self.entry_map.get(name) {
+            Some(entries) if entries.len() > 1 => {
+                Err(format!("several objects have the same name: {}", name).into())
+            }
+            Some(entries) => Ok(entries.first()),
             None => Ok(None),
-            Some(object_info) => Ok(Some(FileInfo::from_content_object_info(object_info)?)),
         }
     }
 
-    fn create_file(
-        &mut self,
+    /// Uploads the data as a new file.
+    fn upload(
+        &self,
         name: &str,
         reader: &mut impl FileReader,
         size: u64,
         created: &Option<NaiveDateTime>,
         modified: &Option<NaiveDateTime>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    ) -> Result<ContentObject, Box<dyn std::error::Error>> {
         let mut resource_writer = self.device.create_file(
             &self.folder_object_info.content_object,
             name,
@@ -67,12 +75,179 @@ impl<'d> DestinationFolder for DeviceDestinationFolder<'d> {
         while let Some(bytes) = reader.next(resource_writer.get_buffer_size())? {
             resource_writer.write(bytes)?;
         }
-        let content_object = resource_writer.commit()?;
+        resource_writer.commit()
+    }
+
+    fn add_entry(&mut self, object_info: ContentObjectInfo) {
+        self.entry_map
+            .entry(object_info.name.clone())
+            .or_default()
+            .push(object_info);
+    }
+}
+
+impl<'d> DestinationFolder for DeviceDestinationFolder<'d> {
+    fn get_file_info(
+        &mut self,
+        name: &str,
+    ) -> Result<Option<FileInfo>, Box<dyn std::error::Error>> {
+        match self.find(name)? {
+            None => Ok(None),
+            Some(object_info) => Ok(Some(FileInfo::from_content_object_info(object_info)?)),
+        }
+    }
+
+    fn create_file(
+        &mut self,
+        name: &str,
+        reader: &mut impl FileReader,
+        size: u64,
+        created: &Option<NaiveDateTime>,
+        modified: &Option<NaiveDateTime>,
+        verify: bool,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let content_object = if verify {
+            let mut checksum_reader = ChecksumFileReader::new(reader);
+            let content_object =
+                self.upload(name, &mut checksum_reader, size, created, modified)?;
+            // some devices truncate the file silently
+            let resource_reader = self.device.get_resoure(&content_object)?;
+            let checksum = checksum_of(&mut DeviceFileReader::new(resource_reader))?;
+            if checksum != checksum_reader.checksum() {
+                self.device.delete(&content_object)?;
+                return Ok(false);
+            }
+            content_object
+        } else {
+            self.upload(name, reader, size, created, modified)?
+        };
 
         let object_info = self.device.get_object_info(content_object)?;
-        self.entry_map.insert(object_info.name.clone(), object_info);
+        self.add_entry(object_info);
 
-        Ok(())
+        Ok(true)
+    }
+
+    /// Uploads the new file under a temporary name, renames it, then deletes the old file,
+    /// so that the old file remains if the upload, the verification or the renaming failed.
+    /// If the device cannot rename the file, the old file is deleted before the upload.
+    fn replace_file(
+        &mut self,
+        name: &str,
+        reader: &mut impl FileReader,
+        size: u64,
+        created: &Option<NaiveDateTime>,
+        modified: &Option<NaiveDateTime>,
+        verify: bool,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let old_object = match self.find(name)? {
+            Some(object_info)
+                if object_info.is_file() && self.device.can_rename(&object_info.content_object) =>
+            {
+                object_info.content_object.clone()
+            }
+            _ => {
+                self.delete_file_or_folder(name)?;
+                return self.create_file(name, reader, size, created, modified, verify);
+            }
+        };
+
+        let temp_name = temporary_name(name);
+        // may be left by the interrupted copy
+        self.delete_file_or_folder(&temp_name)?;
+        if !self.create_file(&temp_name, reader, size, created, modified, verify)? {
+            return Ok(false);
+        }
+
+        let temp_object = self.entry_map[&temp_name][0].content_object.clone();
+        let renamed = match self.device.rename(&temp_object, name) {
+            Ok(renamed) => renamed,
+            Err(err) => {
+                let _ = self.delete_file_or_folder(&temp_name);
+                return Err(
+                    format!("cannot rename \"{}\" to \"{}\": {}", &temp_name, name, err).into(),
+                );
+            }
+        };
+        self.entry_map.remove(&temp_name);
+        // both files have the name until the old one is deleted
+        let object_info = self.device.get_object_info(renamed)?;
+        self.add_entry(object_info);
+        self.device.delete(&old_object)?;
+        self.entry_map
+            .get_mut(name)
+            .unwrap()
+            .retain(|object_info| object_info.content_object.id != old_object.id);
+        Ok(true)
+    }
+
+    /// Copies the file on the device, then renames it if the name differs from the source.
+    fn copy_on_device(
+        &mut self,
+        name: &str,
+        object: &ContentObject,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        if self.entry_map.contains_key(name) || !self.device.can_copy_on_device(object) {
+            return Ok(false);
+        }
+        let src_name = self.device.get_object_info(object.clone())?.name;
+        if src_name != name
+            && (self.entry_map.contains_key(&src_name) || !self.device.can_rename(object))
+        {
+            return Ok(false);
+        }
+
+        let mut copied = self
+            .device
+            .copy_object(object, &self.folder_object_info.content_object)?;
+        if src_name != name {
+            copied = match self.device.rename(&copied, name) {
+                Ok(renamed) => renamed,
+                Err(err) => {
+                    let _ = self.device.delete(&copied);
+                    return Err(format!(
+                        "cannot rename \"{}\" to \"{}\": {}",
+                        &src_name, name, err
+                    )
+                    .into());
+                }
+            };
+        }
+        let object_info = self.device.get_object_info(copied)?;
+        self.add_entry(object_info);
+        Ok(true)
+    }
+
+    /// Moves the file on the device only if the name is not changed,
+    /// because the moved file could not be put back if renaming it failed.
+    fn move_on_device(
+        &mut self,
+        name: &str,
+        object: &ContentObject,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        if self.entry_map.contains_key(name) || !self.device.can_move_on_device(object) {
+            return Ok(false);
+        }
+        if self.device.get_object_info(object.clone())?.name != name {
+            return Ok(false);
+        }
+
+        let moved = self
+            .device
+            .move_object(object, &self.folder_object_info.content_object)?;
+        let object_info = self.device.get_object_info(moved)?;
+        self.add_entry(object_info);
+        Ok(true)
+    }
+
+    fn open_file(&mut self, name: &str) -> Result<Box<dyn FileReader>, Box<dyn std::error::Error>> {
+        match self.find(name)? {
+            Some(object_info) if object_info.is_file() => {
+                let reader = self.device.get_resoure(&object_info.content_object)?;
+                Ok(Box::new(DeviceFileReader::new(reader)))
+            }
+            _ => Err(format!("file was not found: {}", name).into()),
+        }
     }
 
     fn open_or_create_folder<FBeforeOpen, FBeforeCreate>(
@@ -85,7 +260,7 @@ impl<'d> DestinationFolder for DeviceDestinationFolder<'d> {
         FBeforeOpen: FnOnce(&str),
         FBeforeCreate: FnOnce(&str),
     {
-        match self.entry_map.get(name) {
+        match self.find(name)? {
             None => {
                 // create
                 before_create(name);
@@ -93,8 +268,7 @@ impl<'d> DestinationFolder for DeviceDestinationFolder<'d> {
                     .device
                     .create_folder(&self.folder_object_info.content_object, name)?;
                 let object_info = self.device.get_object_info(content_object)?;
-                self.entry_map
-                    .insert(object_info.name.clone(), object_info.clone());
+                self.add_entry(object_info.clone());
                 Ok(Box::new(DeviceDestinationFolder::new(
                     self.device,
                     object_info,
@@ -111,9 +285,13 @@ impl<'d> DestinationFolder for DeviceDestinationFolder<'d> {
         }
     }
 
+    /// Deletes all objects of the name.
     fn delete_file_or_folder(&mut self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
-        if let Some(object_info) = self.entry_map.get(name) {
-            self.device.delete(&object_info.content_object)?;
+        if let Some(entries) = self.entry_map.get_mut(name) {
+            while let Some(object_info) = entries.last() {
+                self.device.delete(&object_info.content_object)?;
+                entries.pop();
+            }
             self.entry_map.remove(name);
         }
         Ok(())
@@ -123,45 +301,18 @@ impl<'d> DestinationFolder for DeviceDestinationFolder<'d> {
         self.retained.insert(String::from(name));
     }
 
-    fn delete_unretained<FBeforeDeleteFile, FBeforeDeleteFolder>(
-        &mut self,
-        before_delete_file: FBeforeDeleteFile,
-        before_delete_folder: FBeforeDeleteFolder,
-    ) -> Result<(), Box<dyn std::error::Error>>
-    where
-        FBeforeDeleteFile: Fn(&str),
-        FBeforeDeleteFolder: Fn(&str),
-    {
-        let mut delete_error: Option<windows::Error> = None;
-        let mut names_to_delete = Vec::<String>::new();
-        for (name, object_info) in self.entry_map.iter() {
-            if object_info.is_file() || object_info.is_folder() {
-                if !self.retained.contains(name) {
-                    if object_info.is_file() {
-                        before_delete_file(name);
-                    } else if object_info.is_folder() {
-                        before_delete_folder(name);
-                    }
-
-                    if let Err(err) = self.device.delete(&object_info.content_object) {
-                        delete_error = Some(err);
-                        break;
-                    }
-                    // entry_map.remove() is not allowed here because entry_map must be immutable
-                    // as long as `name` and `object_info` refer internal data.
-                    names_to_delete.push(String::from(name));
+    fn get_unretained(&mut self) -> Result<Vec<FileInfo>, Box<dyn std::error::Error>> {
+        let mut unretained = Vec::<FileInfo>::new();
+        for (name, entries) in self.entry_map.iter() {
+            if self.retained.contains(name) {
+                continue;
+            }
+            for object_info in entries {
+                if object_info.is_file() || object_info.is_folder() {
+                    unretained.push(FileInfo::from_content_object_info(object_info)?);
                 }
             }
         }
-
-        for name in names_to_delete.iter() {
-            self.entry_map.remove(name);
-        }
-
-        if delete_error.is_some() {
-            Err(delete_error.unwrap().into())
-        } else {
-            Ok(())
-        }
+        Ok(unretained)
     }
 }
@@ -1,13 +1,13 @@
-use crate::wpd::resource_stream::ResourceReader;
+use crate::backend::resource::ResourceReader;
 
 use super::file_reader::FileReader;
 
 pub struct DeviceFileReader {
-    reader: ResourceReader,
+    reader: Box<dyn ResourceReader>,
 }
 
 impl DeviceFileReader {
-    pub fn new(reader: ResourceReader) -> DeviceFileReader {
+    pub fn new(reader: Box<dyn ResourceReader>) -> DeviceFileReader {
         DeviceFileReader { reader }
     }
 }
@@ -18,6 +18,6 @@ impl FileReader for DeviceFileReader {
     }
 
     fn next(&mut self, max_size: u32) -> Result<Option<&[u8]>, Box<dyn std::error::Error>> {
-        Ok(self.reader.next(max_size)?)
+        self.reader.next(max_size)
     }
 }
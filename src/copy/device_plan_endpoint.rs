@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+
+use crate::backend::content_object::{ContentObject, ContentObjectInfo};
+use crate::backend::device::Device;
+use crate::path::DeviceStoragePath;
+
+use super::device_destination_folder::DeviceDestinationFolder;
+use super::device_file_reader::DeviceFileReader;
+use super::executor::{PlanDestination, PlanSource};
+use super::file_info::FileInfo;
+use super::file_reader::FileReader;
+
+/// Files and folders on the storage referred by the plan
+pub struct DevicePlanEndpoint<'d> {
+    device: &'d dyn Device,
+    storage_object_info: ContentObjectInfo,
+    /// Folder path in the storage -> (name -> objects)
+    folders: HashMap<String, HashMap<String, Vec<ContentObjectInfo>>>,
+}
+
+impl<'d> DevicePlanEndpoint<'d> {
+    pub fn new(device: &'d dyn Device, storage_object_info: ContentObjectInfo) -> Self {
+        Self {
+            device,
+            storage_object_info,
+            folders: HashMap::new(),
+        }
+    }
+
+    /// Finds the object by the path.
+    /// The device name and the storage name in the path are not checked.
+    /// It is an error if the path is ambiguous, because the device may have several objects of the same name.
+    fn find(
+        &mut self,
+        path: &str,
+    ) -> Result<Option<ContentObjectInfo>, Box<dyn std::error::Error>> {
+        let storage_path = DeviceStoragePath::from(path)?;
+        let mut object_info = self.storage_object_info.clone();
+        let mut folder_path = String::new();
+        for name in storage_path.path.split('\\').filter(|s| !s.is_empty()) {
+            if !self.folders.contains_key(&folder_path) {
+                let entries = self.read_folder(&object_info)?;
+                self.folders.insert(folder_path.clone(), entries);
+            }
+            match self.folders[&folder_path].get(name) {
+                Some(children) if children.len() > 1 => {
+                    return Err(format!("several objects have the same name: {}", path).into())
+                }
+                Some(children) => object_info = children[0].clone(),
+                None => return Ok(None),
+            }
+            folder_path.push('\\');
+            folder_path.push_str(name);
+        }
+        Ok(Some(object_info))
+    }
+
+    fn read_folder(
+        &self,
+        folder_object_info: &ContentObjectInfo,
+    ) -> Result<HashMap<String, Vec<ContentObjectInfo>>, Box<dyn std::error::Error>> {
+        let mut entries = HashMap::<String, Vec<ContentObjectInfo>>::new();
+        let mut iter = self
+            .device
+            .get_object_iterator(&folder_object_info.content_object)?;
+        while let Some(object) = iter.next()? {
+            let object_info = self.device.get_object_info(object)?;
+            entries
+                .entry(object_info.name.clone())
+                .or_default()
+                .push(object_info);
+        }
+        Ok(entries)
+    }
+}
+
+impl<'d> PlanSource for DevicePlanEndpoint<'d> {
+    fn get_file_info(
+        &mut self,
+        path: &str,
+    ) -> Result<Option<FileInfo>, Box<dyn std::error::Error>> {
+        match self.find(path)? {
+            Some(object_info) => Ok(Some(FileInfo::from_content_object_info(&object_info)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn open_file(&mut self, path: &str) -> Result<Box<dyn FileReader>, Box<dyn std::error::Error>> {
+        match self.find(path)? {
+            Some(object_info) if object_info.is_file() => {
+                let reader = self.device.get_resoure(&object_info.content_object)?;
+                Ok(Box::new(DeviceFileReader::new(reader)))
+            }
+            _ => Err(format!("file was not found: {}", path).into()),
+        }
+    }
+
+    fn delete(&mut self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let object_info = match self.find(path)? {
+            Some(object_info) if object_info.is_file() || object_info.is_folder() => object_info,
+            _ => return Err(format!("file was not found: {}", path).into()),
+        };
+        // the device may delete the folder with its contents
+        if object_info.is_folder() && !self.read_folder(&object_info)?.is_empty() {
+            return Err("folder is not empty".into());
+        }
+        self.device.delete(&object_info.content_object)?;
+
+        // the keys of the cached folders are made as `find()` does
+        let storage_path = DeviceStoragePath::from(path)?;
+        let mut parent_path = String::new();
+        let names: Vec<&str> = storage_path
+            .path
+            .split('\\')
+            .filter(|s| !s.is_empty())
+            .collect();
+        for name in names.iter().take(names.len() - 1) {
+            parent_path.push('\\');
+            parent_path.push_str(name);
+        }
+        if let Some(entries) = self.folders.get_mut(&parent_path) {
+            entries.remove(&object_info.name);
+        }
+        self.folders
+            .remove(&format!("{}\\{}", &parent_path, &object_info.name));
+        Ok(())
+    }
+
+    fn is_empty_folder(&mut self, path: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        match self.find(path)? {
+            // the cached entries may have been moved or deleted
+            Some(object_info) if object_info.is_folder() => {
+                Ok(self.read_folder(&object_info)?.is_empty())
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn device_object(
+        &mut self,
+        path: &str,
+    ) -> Result<Option<ContentObject>, Box<dyn std::error::Error>> {
+        match self.find(path)? {
+            Some(object_info) if object_info.is_file() => Ok(Some(object_info.content_object)),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl<'d> PlanDestination for DevicePlanEndpoint<'d> {
+    type Folder = DeviceDestinationFolder<'d>;
+
+    fn open_folder(
+        &mut self,
+        path: &str,
+    ) -> Result<Option<DeviceDestinationFolder<'d>>, Box<dyn std::error::Error>> {
+        match self.find(path)? {
+            Some(object_info) if object_info.is_folder() || object_info.is_storage() => Ok(Some(
+                DeviceDestinationFolder::new(self.device, object_info)?,
+            )),
+            _ => Ok(None),
+        }
+    }
+}
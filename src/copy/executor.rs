@@ -0,0 +1,672 @@
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
+use std::rc::Rc;
+
+use crate::backend::content_object::ContentObject;
+use crate::path::split_any_path;
+
+use super::checksum::checksum_of;
+use super::destination_folder::DestinationFolder;
+use super::file_info::FileInfo;
+use super::file_reader::{same_content, FileReader};
+use super::journal::Journal;
+use super::plan::{ActionKind, EntryState, PlannedAction, TransferPlan};
+use super::progress::{Progress, ProgressFileReader};
+use super::rate_limit::{RateLimitedFileReader, SystemClock, TokenBucket};
+use super::reporter::{Event, Reporter, Summary};
+
+/// Source files and folders referred by the plan.
+pub trait PlanSource {
+    fn get_file_info(&mut self, path: &str)
+        -> Result<Option<FileInfo>, Box<dyn std::error::Error>>;
+
+    fn open_file(&mut self, path: &str) -> Result<Box<dyn FileReader>, Box<dyn std::error::Error>>;
+
+    /// Deletes the file, or the folder if it is empty.
+    fn delete(&mut self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        Err(format!("cannot delete: {}", path).into())
+    }
+
+    /// Returns whether the folder contains no files nor folders, including the hidden ones.
+    fn is_empty_folder(&mut self, _path: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        Ok(false)
+    }
+
+    /// Returns the object of the file if the source is on a device.
+    fn device_object(
+        &mut self,
+        _path: &str,
+    ) -> Result<Option<ContentObject>, Box<dyn std::error::Error>> {
+        Ok(None)
+    }
+}
+
+/// Destination folders referred by the plan.
+pub trait PlanDestination {
+    type Folder: DestinationFolder;
+
+    /// Opens the existing folder. Returns None if the folder was not found.
+    fn open_folder(
+        &mut self,
+        path: &str,
+    ) -> Result<Option<Self::Folder>, Box<dyn std::error::Error>>;
+}
+
+/// Number of times a file is transferred until its checksum matches
+const VERIFY_ATTEMPTS: u32 = 3;
+
+/// Carries out the transfer plan.
+pub struct Executor<'a, D: PlanDestination> {
+    source: &'a mut dyn PlanSource,
+    destination: &'a mut D,
+    /// Destination folders which have been opened, by their paths
+    folders: HashMap<String, Box<D::Folder>>,
+    journal: Option<Journal>,
+    /// Read back the transferred files and compare the checksums
+    verify: bool,
+    reporter: &'a dyn Reporter,
+    /// The source and the destination are on the same device
+    on_device: bool,
+    /// Delete the sources after they were copied
+    move_source: bool,
+    /// Limits the transfer rate of all files
+    bucket: Option<Rc<RefCell<TokenBucket>>>,
+    /// Reports the bytes transferred
+    progress: Option<Rc<RefCell<Progress>>>,
+    summary: Summary,
+}
+
+impl<'a, D: PlanDestination> Executor<'a, D> {
+    pub fn new(
+        source: &'a mut dyn PlanSource,
+        destination: &'a mut D,
+        reporter: &'a dyn Reporter,
+    ) -> Executor<'a, D> {
+        Executor {
+            source,
+            destination,
+            folders: HashMap::new(),
+            journal: None,
+            verify: false,
+            reporter,
+            on_device: false,
+            move_source: false,
+            bucket: None,
+            progress: reporter
+                .progress()
+                .map(|progress| Rc::new(RefCell::new(progress))),
+            summary: Summary::default(),
+        }
+    }
+
+    /// Records the progress to the journal.
+    /// The actions completed in the journal are not performed again.
+    pub fn set_journal(&mut self, journal: Journal) {
+        self.journal = Some(journal);
+    }
+
+    /// Reads back each transferred file and compares its checksum with the source.
+    /// The file is transferred again if the checksums differ.
+    pub fn set_verify(&mut self, verify: bool) {
+        self.verify = verify;
+    }
+
+    /// Lets the device copy the new files by itself, without transferring the data.
+    /// It must be set only if the source and the destination are on the same device.
+    pub fn set_on_device(&mut self, on_device: bool) {
+        self.on_device = on_device;
+    }
+
+    /// Deletes each source file after it was copied (and verified, if requested),
+    /// and the source folders which became empty at the end.
+    /// The sources which cannot be deleted are reported, and make the run fail.
+    /// On the same device, the files which are not verified are moved by the device if it can.
+    pub fn set_move_source(&mut self, move_source: bool) {
+        self.move_source = move_source;
+    }
+
+    /// Limits the bytes per second read from the source files while they are transferred.
+    pub fn set_bwlimit(&mut self, bwlimit: Option<u64>) {
+        self.bucket = bwlimit.map(|rate| {
+            Rc::new(RefCell::new(TokenBucket::new(
+                rate,
+                Box::new(SystemClock::new()),
+            )))
+        });
+    }
+
+    /// Checks the sources and the destinations, then performs the actions.
+    /// The journal is removed when all actions were completed.
+    pub fn run(&mut self, plan: &TransferPlan) -> Result<(), Box<dyn std::error::Error>> {
+        let in_flight = self
+            .journal
+            .as_ref()
+            .and_then(|journal| journal.in_flight());
+        let (total_files, total_bytes) = plan
+            .actions
+            .iter()
+            .enumerate()
+            .filter(|(index, action)| action.is_transfer() && !self.is_completed(*index))
+            .fold((0, 0), |(files, bytes), (_, action)| {
+                (files + 1, bytes + action.size)
+            });
+        update_progress(&self.progress, |progress| {
+            progress.set_totals(total_files, total_bytes)
+        });
+        for (index, action) in plan.actions.iter().enumerate() {
+            if self.is_completed(index) {
+                continue;
+            }
+            // the destination of the interrupted action is checked later
+            self.check_unchanged(action, Some(index) != in_flight)?;
+        }
+        for (index, action) in plan.actions.iter().enumerate() {
+            if self.is_completed(index) {
+                continue;
+            }
+            // skipping the file changes nothing, so it is not recorded as started
+            if action.action != ActionKind::Skip || self.move_source {
+                if let Some(journal) = self.journal.as_mut() {
+                    journal.start(index)?;
+                }
+            }
+            if Some(index) == in_flight {
+                self.recover(action)?;
+            } else {
+                self.execute(action)?;
+            }
+            if let Some(journal) = self.journal.as_mut() {
+                journal.complete(index)?;
+            }
+        }
+        if self.move_source {
+            self.remove_source_folders(plan)?;
+        }
+        if let Some(journal) = self.journal.take() {
+            journal.remove()?;
+        }
+        self.reporter.report(&Event::Summary(&self.summary));
+        if self.summary.not_removed > 0 {
+            return Err(format!(
+                "{} source files or folders could not be removed.",
+                self.summary.not_removed
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    fn is_completed(&self, index: usize) -> bool {
+        match self.journal.as_ref() {
+            Some(journal) => journal.is_completed(index),
+            None => false,
+        }
+    }
+
+    /// Checks that the source and the destination have not been changed since planning.
+    fn check_unchanged(
+        &mut self,
+        action: &PlannedAction,
+        check_destination: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(src_path) = action.source.as_ref() {
+            let state = self
+                .source
+                .get_file_info(src_path)?
+                .as_ref()
+                .map(EntryState::from);
+            // the interrupted action may have removed the source after copying it
+            let removed = self.move_source && !check_destination && state.is_none();
+            if state != action.source_state && !removed {
+                return Err(format!("source was changed since planning: {}", src_path).into());
+            }
+        }
+        if !check_destination {
+            return Ok(());
+        }
+
+        let (folder_path, name) = split_destination_path(&action.destination)?;
+        let state = match self.folder(&folder_path)? {
+            Some(folder) => folder.get_file_info(&name)?.as_ref().map(EntryState::from),
+            None => None,
+        };
+        if state != action.destination_state {
+            return Err(format!(
+                "destination was changed since planning: {}",
+                &action.destination
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, action: &PlannedAction) -> Result<(), Box<dyn std::error::Error>> {
+        log::trace!("execute {:?}", action);
+        let (folder_path, name) = split_destination_path(&action.destination)?;
+        match action.action {
+            ActionKind::Skip => {
+                self.summary.files_skipped += 1;
+                self.reporter.report(&Event::Skipped {
+                    name: &name,
+                    reason: &action.reason,
+                });
+                if self.move_source {
+                    // the source is removed only if the existing file has the same content
+                    if self.is_same_file(action, &folder_path, &name)? {
+                        self.remove_source(action)?;
+                    } else {
+                        self.report_not_removed(
+                            action.source.as_ref().unwrap(),
+                            "differs from the destination",
+                        );
+                    }
+                }
+            }
+            ActionKind::CreateFolder => {
+                let reporter = self.reporter;
+                let mut created = false;
+                let folder = self.existing_folder(&folder_path)?;
+                let new_folder = folder.open_or_create_folder(
+                    &name,
+                    |_| {},
+                    |name| {
+                        reporter.report(&Event::FolderCreated { name });
+                        created = true;
+                    },
+                )?;
+                self.folders.insert(action.destination.clone(), new_folder);
+                if created {
+                    self.summary.folders_created += 1;
+                }
+            }
+            ActionKind::Upload | ActionKind::Download | ActionKind::Replace => {
+                let src_file_info = self.source_file_info(action)?;
+                if action.action == ActionKind::Replace {
+                    self.reporter.report(&Event::Replacing {
+                        name: &name,
+                        reason: &action.reason,
+                    });
+                }
+                let replace = action.action == ActionKind::Replace;
+                if !self.move_on_device(action, &src_file_info, &folder_path, &name, replace)? {
+                    self.transfer(action, &src_file_info, &folder_path, &name, replace)?;
+                    self.remove_source(action)?;
+                }
+            }
+            ActionKind::Delete => {
+                let is_folder = matches!(&action.destination_state, Some(state) if state.is_folder);
+                let reporter = self.reporter;
+                let folder = self.existing_folder(&folder_path)?;
+                reporter.report(&Event::Deleted {
+                    name: &name,
+                    is_folder,
+                });
+                folder.delete_file_or_folder(&name)?;
+                self.summary.deleted += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Performs the action which was interrupted.
+    /// A file which was being transferred is checked, and transferred again if it is not complete.
+    fn recover(&mut self, action: &PlannedAction) -> Result<(), Box<dyn std::error::Error>> {
+        if !action.is_transfer() {
+            // other actions can be performed again
+            return self.execute(action);
+        }
+
+        let (folder_path, name) = split_destination_path(&action.destination)?;
+        let src_path = action.source.as_ref().unwrap();
+        if self.move_source && self.source.get_file_info(src_path)?.is_none() {
+            // the source was removed after it was copied
+            return Ok(());
+        }
+        let src_file_info = self.source_file_info(action)?;
+        let dest_file_info = match self.folder(&folder_path)? {
+            Some(folder) => folder.get_file_info(&name)?,
+            None => None,
+        };
+        if let Some(dest_file_info) = dest_file_info.as_ref() {
+            if !dest_file_info.is_folder
+                && dest_file_info.data_size == src_file_info.data_size
+                && self.is_same_file(action, &folder_path, &name)?
+            {
+                self.reporter.report(&Event::Verified { name: &name });
+                update_progress(&self.progress, |progress| {
+                    progress.add_file(src_file_info.data_size)
+                });
+                self.summary.files_copied += 1;
+                self.summary.bytes_copied += src_file_info.data_size;
+                return self.remove_source(action);
+            }
+        }
+        let replace = dest_file_info.is_some();
+        if self.move_on_device(action, &src_file_info, &folder_path, &name, replace)? {
+            return Ok(());
+        }
+        self.transfer(action, &src_file_info, &folder_path, &name, replace)?;
+        self.remove_source(action)
+    }
+
+    /// Deletes the source file of the action if the sources are moved.
+    fn remove_source(&mut self, action: &PlannedAction) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.move_source {
+            return Ok(());
+        }
+        let src_path = action.source.as_ref().unwrap();
+        let src_file_info = match self.source.get_file_info(src_path)? {
+            Some(src_file_info) => src_file_info,
+            // moved by the device
+            None => return Ok(()),
+        };
+        if !src_file_info.can_delete {
+            self.report_not_removed(src_path, "cannot be deleted");
+            return Ok(());
+        }
+        match self.source.delete(src_path) {
+            Ok(()) => self.report_source_removed(&src_file_info.name),
+            Err(err) => self.report_not_removed(src_path, &err.to_string()),
+        }
+        Ok(())
+    }
+
+    /// Deletes the source folders which became empty, from the deepest one.
+    /// The folders which still contain files are left without being reported,
+    /// because they contain the entries left out of the plan (excluded, hidden, or not walked),
+    /// or the entries which were reported as not removed.
+    fn remove_source_folders(
+        &mut self,
+        plan: &TransferPlan,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut folders = BTreeSet::<String>::new();
+        folders.insert(plan.source.clone());
+        for action in plan.actions.iter() {
+            let mut path = match action.source.as_ref() {
+                Some(src_path) => src_path.clone(),
+                None => continue,
+            };
+            if matches!(&action.source_state, Some(state) if state.is_folder) {
+                folders.insert(path.clone());
+            }
+            while let Some((parent, _)) = split_any_path(&path) {
+                if parent.len() <= plan.source.len() || !parent.starts_with(&plan.source) {
+                    break;
+                }
+                folders.insert(parent.clone());
+                path = parent;
+            }
+        }
+
+        let mut folders: Vec<String> = folders.into_iter().collect();
+        folders.sort_by_key(|path| std::cmp::Reverse(path.len()));
+        for path in folders {
+            let folder_info = match self.source.get_file_info(&path)? {
+                Some(folder_info) if folder_info.is_folder => folder_info,
+                _ => continue,
+            };
+            if !self.source.is_empty_folder(&path)? {
+                continue;
+            }
+            if !folder_info.can_delete {
+                self.report_not_removed(&path, "cannot be deleted");
+                continue;
+            }
+            match self.source.delete(&path) {
+                Ok(()) => self.report_source_removed(&folder_info.name),
+                Err(err) => self.report_not_removed(&path, &err.to_string()),
+            }
+        }
+        Ok(())
+    }
+
+    fn report_source_removed(&mut self, name: &str) {
+        self.summary.sources_removed += 1;
+        self.reporter.report(&Event::SourceRemoved { name });
+    }
+
+    fn report_not_removed(&mut self, path: &str, reason: &str) {
+        self.summary.not_removed += 1;
+        self.reporter.report(&Event::NotRemoved { path, reason });
+    }
+
+    fn report_copied(&mut self, name: &str, src_file_info: &FileInfo, on_device: bool) {
+        self.summary.files_copied += 1;
+        self.summary.bytes_copied += src_file_info.data_size;
+        self.reporter.report(&Event::FileCopied {
+            name,
+            size: src_file_info.data_size,
+            on_device,
+        });
+    }
+
+    /// Compares the contents of the source and the destination.
+    fn is_same_file(
+        &mut self,
+        action: &PlannedAction,
+        folder_path: &str,
+        name: &str,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let src_path = action.source.as_ref().unwrap();
+        let mut src_reader = self.source.open_file(src_path)?;
+        let mut dest_reader = self.existing_folder(folder_path)?.open_file(name)?;
+        same_content(src_reader.as_mut(), dest_reader.as_mut())
+    }
+
+    fn source_file_info(
+        &mut self,
+        action: &PlannedAction,
+    ) -> Result<FileInfo, Box<dyn std::error::Error>> {
+        let src_path = match action.source.as_ref() {
+            Some(src_path) => src_path,
+            None => return Err(format!("no source: {}", &action.destination).into()),
+        };
+        match self.source.get_file_info(src_path)? {
+            Some(src_file_info) => Ok(src_file_info),
+            None => Err(format!("source was not found: {}", src_path).into()),
+        }
+    }
+
+    fn transfer(
+        &mut self,
+        action: &PlannedAction,
+        src_file_info: &FileInfo,
+        folder_path: &str,
+        name: &str,
+        replace: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // the existing file is replaced by the upload, so that it remains if the copy failed
+        if self.on_device
+            && !replace
+            && self.copy_on_device(action, src_file_info, folder_path, name)?
+        {
+            return Ok(());
+        }
+
+        let src_path = action.source.as_ref().unwrap();
+        let verify = self.verify;
+        let progress = self.progress.clone();
+        let reporter = self.reporter;
+        let mut attempt = 1;
+        loop {
+            let mut src_reader = self.source.open_file(src_path)?;
+            if let Some(bucket) = self.bucket.as_ref() {
+                src_reader = Box::new(RateLimitedFileReader::new(src_reader, bucket.clone()));
+            }
+            if let Some(progress) = progress.as_ref() {
+                src_reader = Box::new(ProgressFileReader::new(src_reader, progress.clone()));
+            }
+            let folder = self.existing_folder(folder_path)?;
+            update_progress(&progress, |progress| progress.start_file(src_file_info));
+            // the mismatched file is not left, and the existing file is kept
+            let verified = create_file(
+                folder,
+                name,
+                &mut src_reader,
+                src_file_info,
+                replace,
+                verify,
+            )?;
+            update_progress(&progress, |progress| progress.end_file());
+            if verified {
+                break;
+            }
+            reporter.report(&Event::ChecksumMismatch {
+                name,
+                attempt,
+                max_attempts: VERIFY_ATTEMPTS,
+            });
+            update_progress(&progress, |progress| progress.discard_file());
+            if attempt >= VERIFY_ATTEMPTS {
+                return Err(format!("checksum mismatch: {}", &action.destination).into());
+            }
+            attempt += 1;
+        }
+        self.report_copied(name, src_file_info, false);
+        Ok(())
+    }
+
+    /// Moves the source file on the device by itself, if the sources are moved.
+    /// The file is not moved if it is verified, because the source is needed to compare with.
+    /// Returns false if the device cannot move it, then the file is transferred.
+    fn move_on_device(
+        &mut self,
+        action: &PlannedAction,
+        src_file_info: &FileInfo,
+        folder_path: &str,
+        name: &str,
+        replace: bool,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        // the device does not move the file over the existing one
+        if !self.on_device
+            || !self.move_source
+            || self.verify
+            || replace
+            || !src_file_info.can_delete
+        {
+            return Ok(false);
+        }
+        let src_path = action.source.as_ref().unwrap();
+        let object = match self.source.device_object(src_path)? {
+            Some(object) => object,
+            None => return Ok(false),
+        };
+        if !self
+            .existing_folder(folder_path)?
+            .move_on_device(name, &object)?
+        {
+            return Ok(false);
+        }
+        update_progress(&self.progress, |progress| {
+            progress.add_file(src_file_info.data_size)
+        });
+        self.report_copied(name, src_file_info, true);
+        self.report_source_removed(&src_file_info.name);
+        Ok(true)
+    }
+
+    /// Copies the file on the device by itself.
+    /// Returns false if the device cannot copy it, or the copied file did not match the source.
+    fn copy_on_device(
+        &mut self,
+        action: &PlannedAction,
+        src_file_info: &FileInfo,
+        folder_path: &str,
+        name: &str,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let src_path = action.source.as_ref().unwrap();
+        let object = match self.source.device_object(src_path)? {
+            Some(object) => object,
+            None => return Ok(false),
+        };
+        let verify = self.verify;
+        let folder = self.existing_folder(folder_path)?;
+        if !folder.copy_on_device(name, &object)? {
+            return Ok(false);
+        }
+        if verify {
+            let checksum = checksum_of(folder.open_file(name)?.as_mut())?;
+            if checksum != checksum_of(self.source.open_file(src_path)?.as_mut())? {
+                self.reporter.report(&Event::OnDeviceMismatch { name });
+                self.existing_folder(folder_path)?
+                    .delete_file_or_folder(name)?;
+                return Ok(false);
+            }
+        }
+        update_progress(&self.progress, |progress| {
+            progress.add_file(src_file_info.data_size)
+        });
+        self.report_copied(name, src_file_info, true);
+        Ok(true)
+    }
+
+    /// Returns the destination folder, or None if it does not exist.
+    fn folder(&mut self, path: &str) -> Result<Option<&mut D::Folder>, Box<dyn std::error::Error>> {
+        if !self.folders.contains_key(path) {
+            match self.destination.open_folder(path)? {
+                Some(folder) => {
+                    self.folders.insert(path.to_string(), Box::new(folder));
+                }
+                None => return Ok(None),
+            }
+        }
+        Ok(self.folders.get_mut(path).map(|folder| folder.as_mut()))
+    }
+
+    fn existing_folder(
+        &mut self,
+        path: &str,
+    ) -> Result<&mut D::Folder, Box<dyn std::error::Error>> {
+        match self.folder(path)? {
+            Some(folder) => Ok(folder),
+            None => Err(format!("folder was not found: {}", path).into()),
+        }
+    }
+}
+
+fn update_progress<F>(progress: &Option<Rc<RefCell<Progress>>>, f: F)
+where
+    F: FnOnce(&mut Progress),
+{
+    if let Some(progress) = progress.as_ref() {
+        f(&mut progress.borrow_mut());
+    }
+}
+
+fn split_destination_path(path: &str) -> Result<(String, String), Box<dyn std::error::Error>> {
+    match split_any_path(path) {
+        Some(folder_and_name) => Ok(folder_and_name),
+        None => Err(format!("invalid destination path: {}", path).into()),
+    }
+}
+
+/// Creates the file, or replaces the existing file if `replace` is true.
+/// Returns false if the file was verified and did not match.
+fn create_file(
+    folder: &mut impl DestinationFolder,
+    name: &str,
+    reader: &mut impl FileReader,
+    src_file_info: &FileInfo,
+    replace: bool,
+    verify: bool,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    if replace {
+        folder.replace_file(
+            name,
+            reader,
+            src_file_info.data_size,
+            &src_file_info.time_created,
+            &src_file_info.time_modified,
+            verify,
+        )
+    } else {
+        folder.create_file(
+            name,
+            reader,
+            src_file_info.data_size,
+            &src_file_info.time_created,
+            &src_file_info.time_modified,
+            verify,
+        )
+    }
+}
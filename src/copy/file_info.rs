@@ -1,9 +1,8 @@
-use crate::wpd::device::ContentObjectInfo;
+use crate::backend::content_object::ContentObjectInfo;
 use chrono::{DateTime, Local, NaiveDateTime};
-use std::{
-    fs::Metadata,
-    os::windows::prelude::MetadataExt,
-};
+use std::fs::Metadata;
+#[cfg(windows)]
+use std::os::windows::prelude::MetadataExt;
 
 pub struct FileInfo {
     /// Name to display
@@ -17,6 +16,7 @@ pub struct FileInfo {
     /// System flag
     pub is_system: bool,
     /// Whether the object can be deleted
+    #[allow(dead_code)]
     pub can_delete: bool,
     /// Time created (or None if not provided)
     pub time_created: Option<NaiveDateTime>,
@@ -35,8 +35,8 @@ impl FileInfo {
             is_hidden: info.is_hidden,
             is_system: info.is_system,
             can_delete: info.can_delete,
-            time_created: info.time_created.clone(),
-            time_modified: info.time_modified.clone(),
+            time_created: info.time_created,
+            time_modified: info.time_modified,
         })
     }
 
@@ -44,23 +44,40 @@ impl FileInfo {
         metadata: &Metadata,
         name: &str,
     ) -> Result<Self, Box<dyn std::error::Error>> {
-        let created_date_time  = DateTime::<Local>::from(metadata.created()?);
-        let modified_date_time = DateTime::<Local>::from(metadata.modified()?);
-        let file_attr = metadata.file_attributes();
-        let data_size = if metadata.is_dir() {
-            0
-        } else {
-            metadata.file_size()
+        // creation time is not supported on some platforms and filesystems
+        let time_created = match metadata.created() {
+            Ok(created) => Some(DateTime::<Local>::from(created).naive_local()),
+            Err(_) => None,
         };
+        let modified_date_time = DateTime::<Local>::from(metadata.modified()?);
+        let (is_hidden, is_system) = file_attributes(metadata, name);
+        let data_size = if metadata.is_dir() { 0 } else { metadata.len() };
         Ok(FileInfo {
             name: name.to_string(),
             data_size,
             is_folder: metadata.is_dir(),
-            is_hidden: (file_attr & 2/* FILE_ATTRIBUTE_HIDDEN */) != 0,
-            is_system: (file_attr & 4/* FILE_ATTRIBUTE_SYSTEM */) != 0,
+            is_hidden,
+            is_system,
             can_delete: true,
-            time_created: Some(created_date_time.naive_local()),
+            time_created,
             time_modified: Some(modified_date_time.naive_local()),
         })
     }
 }
+
+/// Returns the hidden flag and the system flag of the local file.
+#[cfg(windows)]
+pub fn file_attributes(metadata: &Metadata, _name: &str) -> (bool, bool) {
+    let file_attr = metadata.file_attributes();
+    (
+        (file_attr & 2/* FILE_ATTRIBUTE_HIDDEN */) != 0,
+        (file_attr & 4/* FILE_ATTRIBUTE_SYSTEM */) != 0,
+    )
+}
+
+/// Returns the hidden flag and the system flag of the local file.
+/// Dotfiles are treated as hidden files.
+#[cfg(not(windows))]
+pub fn file_attributes(_metadata: &Metadata, name: &str) -> (bool, bool) {
+    (name.starts_with('.'), false)
+}
@@ -2,3 +2,49 @@ pub trait FileReader {
     fn get_optimized_buffer_size(&self) -> u32;
     fn next(&mut self, max_size: u32) -> Result<Option<&[u8]>, Box<dyn std::error::Error>>;
 }
+
+impl<R: FileReader + ?Sized> FileReader for Box<R> {
+    fn get_optimized_buffer_size(&self) -> u32 {
+        self.as_ref().get_optimized_buffer_size()
+    }
+
+    fn next(&mut self, max_size: u32) -> Result<Option<&[u8]>, Box<dyn std::error::Error>> {
+        self.as_mut().next(max_size)
+    }
+}
+
+impl<R: FileReader + ?Sized> FileReader for &mut R {
+    fn get_optimized_buffer_size(&self) -> u32 {
+        (**self).get_optimized_buffer_size()
+    }
+
+    fn next(&mut self, max_size: u32) -> Result<Option<&[u8]>, Box<dyn std::error::Error>> {
+        (**self).next(max_size)
+    }
+}
+
+/// Returns whether the two readers give the same bytes.
+pub fn same_content(
+    reader1: &mut dyn FileReader,
+    reader2: &mut dyn FileReader,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    // bytes read from reader1 and not compared yet
+    let mut pending = Vec::<u8>::new();
+    loop {
+        if pending.is_empty() {
+            match reader1.next(reader1.get_optimized_buffer_size())? {
+                Some(bytes) => pending.extend_from_slice(bytes),
+                None => return Ok(reader2.next(1)?.is_none()),
+            }
+        }
+        match reader2.next(pending.len() as u32)? {
+            Some(bytes) => {
+                if bytes.len() > pending.len() || bytes != &pending[..bytes.len()] {
+                    return Ok(false);
+                }
+                pending.drain(..bytes.len());
+            }
+            None => return Ok(false),
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use crate::glob::path::{create_path_pattern_matcher, PathMatchingState, RootPathMatcher};
+use crate::path::SEPARATORS;
+
+/// Selects the entries to copy by their paths relative to the copy root.
+///
+/// * the files and the folders matching an exclude pattern are not copied,
+///   and the files in the excluded folders are not visited.
+/// * if any include pattern is given, only the files matching one of them are copied.
+///   the folders are visited regardless of the include patterns.
+///
+/// A pattern without separators matches the name in any folder, e.g. `*.tmp`.
+/// Other patterns are matched from the copy root, e.g. `Album\*.mp3` or `**\.thumbnails`.
+pub struct PathFilter {
+    include: Vec<RootPathMatcher>,
+    exclude: Vec<RootPathMatcher>,
+}
+
+impl PathFilter {
+    pub fn new(
+        include: &[String],
+        exclude: &[String],
+    ) -> Result<PathFilter, Box<dyn std::error::Error>> {
+        Ok(PathFilter {
+            include: create_matchers(include)?,
+            exclude: create_matchers(exclude)?,
+        })
+    }
+
+    /// Checks whether the entry is filtered out.
+    /// The copy root itself (an empty path) is never excluded.
+    pub fn is_excluded(&self, relative_path: &str, is_folder: bool) -> bool {
+        let components: Vec<&str> = relative_path
+            .split(SEPARATORS)
+            .filter(|compo| !compo.is_empty())
+            .collect();
+        if components.is_empty() {
+            return false;
+        }
+        if self
+            .exclude
+            .iter()
+            .any(|matcher| matches_path(matcher, &components, is_folder))
+        {
+            return true;
+        }
+        !is_folder
+            && !self.include.is_empty()
+            && !self
+                .include
+                .iter()
+                .any(|matcher| matches_path(matcher, &components, is_folder))
+    }
+}
+
+fn create_matchers(
+    patterns: &[String],
+) -> Result<Vec<RootPathMatcher>, Box<dyn std::error::Error>> {
+    let mut matchers = Vec::<RootPathMatcher>::new();
+    for pattern in patterns {
+        let matcher = if pattern.contains(SEPARATORS) {
+            create_path_pattern_matcher(pattern)
+        } else {
+            create_path_pattern_matcher(&format!("**\\{}", pattern))
+        };
+        match matcher {
+            Ok(matcher) => matchers.push(matcher),
+            Err(err) => return Err(format!("invalid pattern \"{}\": {}", pattern, err).into()),
+        }
+    }
+    Ok(matchers)
+}
+
+/// Checks whether the path, or one of its parent folders, matches the pattern.
+fn matches_path(root_matcher: &RootPathMatcher, components: &[&str], is_folder: bool) -> bool {
+    let mut matcher = match root_matcher.matches_root() {
+        (PathMatchingState::Accepted, Some(matcher)) => matcher,
+        _ => return true,
+    };
+    for (index, name) in components.iter().enumerate() {
+        let is_dir = index + 1 < components.len() || is_folder;
+        match matcher.matches(name, is_dir) {
+            (PathMatchingState::Accepted, Some(next)) => matcher = next,
+            (PathMatchingState::Completed, _) => return true,
+            _ => return false,
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+#[allow(clippy::unused_unit)] // expanded from test_case
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    fn filter(include: &[&str], exclude: &[&str]) -> PathFilter {
+        let to_strings =
+            |patterns: &[&str]| patterns.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        PathFilter::new(&to_strings(include), &to_strings(exclude)).unwrap()
+    }
+
+    #[test_case("a.tmp", false => true ; "name in the root")]
+    #[test_case("Album\\a.tmp", false => true ; "name in a folder")]
+    #[test_case("Album\\a.mp3", false => false ; "other name")]
+    #[test_case("Album\\.thumbnails", true => true ; "folder in a folder")]
+    #[test_case(".thumbnails", true => true ; "folder in the root")]
+    #[test_case("Album\\.thumbnails\\x.jpg", false => true ; "file in the excluded folder")]
+    #[test_case("", true => false ; "copy root")]
+    fn test_exclude(path: &str, is_folder: bool) -> bool {
+        filter(&[], &["*.tmp", "**\\.thumbnails"]).is_excluded(path, is_folder)
+    }
+
+    #[test_case("a.mp3", false => false ; "included file")]
+    #[test_case("Album\\b.mp3", false => false ; "included file in a folder")]
+    #[test_case("a.jpg", false => true ; "other file")]
+    #[test_case("Album", true => false ; "folders are visited")]
+    #[test_case("Podcasts\\c.m4a", false => false ; "file in the included folder")]
+    #[test_case("Album\\c.m4a", false => true ; "file in other folder")]
+    #[test_case("Album\\d.mp3", false => true ; "excluded file")]
+    fn test_include(path: &str, is_folder: bool) -> bool {
+        filter(&["*.mp3", "Podcasts\\"], &["Album\\d.mp3"]).is_excluded(path, is_folder)
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        let result = PathFilter::new(&[], &["Music\\**".to_string()]);
+        assert!(result.is_err());
+    }
+}
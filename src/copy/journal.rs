@@ -0,0 +1,233 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::plan::TransferPlan;
+
+/// A line of the journal file
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Record {
+    /// The plan being performed (the first line)
+    Plan(TransferPlan),
+    /// Index of the action which was started
+    Started(usize),
+    /// Index of the action which was completed
+    Completed(usize),
+}
+
+/// Records the progress of the transfer plan,
+/// so that the interrupted copy can be resumed.
+///
+/// The file is synced to the disk once for each started action, not for each record.
+/// The completed records are synced together with the next started one,
+/// so an action whose completed record was lost is performed or checked again after resuming.
+pub struct Journal {
+    path: PathBuf,
+    file: File,
+    completed: HashSet<usize>,
+    in_flight: Option<usize>,
+}
+
+impl Journal {
+    /// Starts a new journal for the plan.
+    pub fn create(path: &Path, plan: &TransferPlan) -> Result<Journal, Box<dyn std::error::Error>> {
+        let file = File::create(path)?;
+        let mut journal = Journal {
+            path: PathBuf::from(path),
+            file,
+            completed: HashSet::new(),
+            in_flight: None,
+        };
+        journal.write(&Record::Plan(plan.clone()))?;
+        journal.file.sync_data()?;
+        Ok(journal)
+    }
+
+    /// Opens the journal left by the interrupted copy.
+    /// Returns the journal to continue and the plan in it.
+    pub fn open(path: &Path) -> Result<(Journal, TransferPlan), Box<dyn std::error::Error>> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(_) => {
+                return Err(format!("no journal to resume: {}", path.to_string_lossy()).into());
+            }
+        };
+        let mut plan: Option<TransferPlan> = None;
+        let mut completed = HashSet::<usize>::new();
+        let mut in_flight: Option<usize> = None;
+        for line in BufReader::new(file).lines() {
+            // the last line may be broken if the process was killed while writing it
+            let record = match serde_json::from_str::<Record>(&line?) {
+                Ok(record) => record,
+                Err(_) => break,
+            };
+            match record {
+                Record::Plan(p) => plan = Some(p),
+                Record::Started(index) => in_flight = Some(index),
+                Record::Completed(index) => {
+                    completed.insert(index);
+                    if in_flight == Some(index) {
+                        in_flight = None;
+                    }
+                }
+            }
+        }
+        let plan = match plan {
+            Some(plan) => plan,
+            None => return Err(format!("invalid journal: {}", path.to_string_lossy()).into()),
+        };
+
+        // rewrite the records to drop the broken line
+        let tmp_path = path.with_extension("tmp");
+        let mut journal = Journal::create(&tmp_path, &plan)?;
+        let mut indexes: Vec<usize> = completed.iter().copied().collect();
+        indexes.sort_unstable();
+        for index in indexes {
+            journal.complete(index)?;
+        }
+        if let Some(index) = in_flight {
+            journal.start(index)?;
+        }
+        journal.file.sync_data()?;
+        std::fs::rename(&tmp_path, path)?;
+        journal.path = PathBuf::from(path);
+        Ok((journal, plan))
+    }
+
+    /// Returns the path of the journal file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn is_completed(&self, index: usize) -> bool {
+        self.completed.contains(&index)
+    }
+
+    /// Returns the index of the action which was started but not completed.
+    pub fn in_flight(&self) -> Option<usize> {
+        self.in_flight
+    }
+
+    pub fn completed_count(&self) -> usize {
+        self.completed.len()
+    }
+
+    /// Records the action to be started.
+    /// The record must survive a sudden power loss before the action changes anything.
+    pub fn start(&mut self, index: usize) -> Result<(), Box<dyn std::error::Error>> {
+        self.write(&Record::Started(index))?;
+        self.file.sync_data()?;
+        self.in_flight = Some(index);
+        Ok(())
+    }
+
+    /// Records the action completed. The record is not synced until the next action is started.
+    pub fn complete(&mut self, index: usize) -> Result<(), Box<dyn std::error::Error>> {
+        self.write(&Record::Completed(index))?;
+        self.completed.insert(index);
+        self.in_flight = None;
+        Ok(())
+    }
+
+    /// Removes the journal file after all actions were completed.
+    pub fn remove(self) -> Result<(), Box<dyn std::error::Error>> {
+        drop(self.file);
+        std::fs::remove_file(&self.path)?;
+        Ok(())
+    }
+
+    fn write(&mut self, record: &Record) -> Result<(), Box<dyn std::error::Error>> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Returns the journal file used when no file was specified.
+/// The name is derived from the source and the destination.
+pub fn default_journal_path(source: &str, destination: &str) -> PathBuf {
+    // FNV-1a, which is stable across the builds
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in source
+        .bytes()
+        .chain(b"\n".iter().copied())
+        .chain(destination.bytes())
+    {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    std::env::temp_dir().join(format!("mtpcopy-{:016x}.journal", hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::OpenOptions;
+
+    fn plan() -> TransferPlan {
+        TransferPlan {
+            source: "a".to_string(),
+            destination: "b".to_string(),
+            recursive: true,
+            mirror: false,
+            move_source: false,
+            actions: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_reopen_journal() -> Result<(), Box<dyn std::error::Error>> {
+        let tempdir = tempfile::tempdir()?;
+        let path = tempdir.path().join("journal");
+
+        let mut journal = Journal::create(&path, &plan())?;
+        journal.start(0)?;
+        journal.complete(0)?;
+        journal.start(1)?;
+        journal.complete(1)?;
+        journal.start(2)?;
+        drop(journal);
+        // broken record
+        OpenOptions::new()
+            .append(true)
+            .open(&path)?
+            .write_all(b"{\"comp")?;
+
+        let (mut journal, loaded_plan) = Journal::open(&path)?;
+        assert_eq!(loaded_plan, plan());
+        assert!(journal.is_completed(0));
+        assert!(journal.is_completed(1));
+        assert!(!journal.is_completed(2));
+        assert_eq!(journal.in_flight(), Some(2));
+        assert_eq!(journal.completed_count(), 2);
+
+        // the broken record was dropped
+        journal.complete(2)?;
+        drop(journal);
+        let (journal, _) = Journal::open(&path)?;
+        assert!(journal.is_completed(2));
+        assert_eq!(journal.in_flight(), None);
+
+        journal.remove()?;
+        assert!(!path.exists());
+        assert!(Journal::open(&path).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_default_journal_path() {
+        assert_eq!(
+            default_journal_path("a:b:\\c", "d"),
+            default_journal_path("a:b:\\c", "d")
+        );
+        assert_ne!(
+            default_journal_path("a:b:\\c", "d"),
+            default_journal_path("a:b:\\c", "e")
+        );
+    }
+}
@@ -1,16 +1,14 @@
+use std::fs::File;
 use std::path::PathBuf;
-use std::{fs::File, os::windows::prelude::MetadataExt};
-
-use chrono::{DateTime, Local};
 
 use super::destination_folder::DestinationFolder;
-use super::file_info::FileInfo;
+use super::file_info::{file_attributes, FileInfo};
+use super::file_reader::same_content;
 use super::local_file_reader::LocalFileReader;
+use super::plan::Planner;
+use super::planned_folder::PlannedFolder;
 
-use super::copy_processor::{
-    can_skip_copying, report_copying_end, report_copying_start, report_creating_new_folder,
-    report_delete_file, report_delete_folder, CopyProcessor,
-};
+use super::copy_processor::CopyProcessor;
 
 pub struct LocalCopyProcessor {
     path: PathBuf,
@@ -25,73 +23,55 @@ impl LocalCopyProcessor {
 }
 
 impl CopyProcessor for LocalCopyProcessor {
-    fn copy_as(
+    fn plan_as<D: DestinationFolder>(
         &self,
         name: &str,
-        dest: &mut impl DestinationFolder,
+        dest: &mut PlannedFolder<D>,
         dest_is_parent_folder: bool,
-        recursive: bool,
-        mirror: bool,
+        planner: &mut Planner,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        copy_hierarchy(
-            &self.path,
-            dest,
-            dest_is_parent_folder,
-            name,
-            recursive,
-            mirror,
-        )
+        plan_hierarchy(&self.path, dest, dest_is_parent_folder, name, planner)
     }
 }
 
-fn copy_hierarchy(
+fn plan_hierarchy<D: DestinationFolder>(
     path: &PathBuf,
-    dest: &mut impl DestinationFolder,
+    dest: &mut PlannedFolder<D>,
     dest_is_parent_folder: bool,
     dest_name: &str,
-    recursive: bool,
-    mirror: bool,
+    planner: &mut Planner,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let metadata = path.metadata()?;
-    let file_attr = metadata.file_attributes();
-    let is_hidden = (file_attr & 2/* FILE_ATTRIBUTE_HIDDEN */) != 0;
-    let is_system = (file_attr & 4/* FILE_ATTRIBUTE_SYSTEM */) != 0;
+    let src_path = path.to_str().unwrap();
+    let src_file_name = path.file_name().unwrap().to_str().unwrap();
+    let (is_hidden, is_system) = file_attributes(&metadata, src_file_name);
 
     if is_hidden || is_system {
         return Ok(());
     }
 
-    let src_file_name = path.file_name().unwrap().to_str().unwrap();
+    if planner.is_excluded(src_path, metadata.is_dir()) {
+        // the existing destination is not deleted by mirroring
+        dest.retain(dest_name);
+        return Ok(());
+    }
 
     if metadata.is_file() {
         let src_file_info = FileInfo::from_metadata(&metadata, src_file_name)?;
         let dest_file_info = dest.get_file_info(dest_name)?;
-
-        if let Some(dest_file_info_ref) = dest_file_info.as_ref() {
-            if can_skip_copying(&src_file_info, dest_file_info_ref) {
-                dest.retain(dest_name);
-                return Ok(());
-            }
-        }
-
-        if dest_file_info.is_some() {
-            dest.delete_file_or_folder(dest_name)?;
-        }
-
-        let file = File::open(path)?;
-        let mut reader = LocalFileReader::new(file);
-        let created_date_time = DateTime::<Local>::from(metadata.created()?);
-        let modified_date_time = DateTime::<Local>::from(metadata.modified()?);
-        report_copying_start(&src_file_info);
-        dest.create_file(
-            dest_name,
-            &mut reader,
-            src_file_info.data_size,
-            &Some(created_date_time.naive_local()),
-            &Some(modified_date_time.naive_local()),
+        let dest_path = dest.path_of(dest_name);
+        planner.plan_file(
+            src_path,
+            &dest_path,
+            &src_file_info,
+            dest_file_info.as_ref(),
+            || {
+                let mut src_reader = LocalFileReader::new(File::open(path)?);
+                let mut dest_reader = dest.open_file(dest_name)?;
+                same_content(&mut src_reader, dest_reader.as_mut())
+            },
         )?;
         dest.retain(dest_name);
-        report_copying_end();
         return Ok(());
     }
 
@@ -100,32 +80,41 @@ fn copy_hierarchy(
         let new_dest_ref;
 
         if dest_is_parent_folder {
-            new_dest = dest.open_or_create_folder(dest_name, |_| {}, report_creating_new_folder)?;
+            let src_file_info = FileInfo::from_metadata(&metadata, src_file_name)?;
+            let dest_file_info = dest.get_file_info(dest_name)?;
+            planner.plan_folder(
+                src_path,
+                &dest.path_of(dest_name),
+                &src_file_info,
+                dest_file_info.as_ref(),
+            );
+            new_dest = dest.open_folder(dest_name)?;
             dest.retain(dest_name);
-            new_dest_ref = new_dest.as_mut();
+            new_dest_ref = &mut new_dest;
         } else {
             // if the source object was a folder, and the specified destination
             // was an existing folder, use the destination folder as it is.
             new_dest_ref = dest;
         }
 
-        if recursive {
-            for result in std::fs::read_dir(path)? {
-                let entry = result?;
+        if planner.options().recursive {
+            // sorted so that the plan is stable
+            let mut entries = std::fs::read_dir(path)?.collect::<Result<Vec<_>, _>>()?;
+            entries.sort_by_key(|entry| entry.file_name());
+            for entry in entries {
                 let new_path = entry.path();
                 let dest_file_name = new_path.file_name().unwrap().to_str().unwrap();
-                copy_hierarchy(
+                plan_hierarchy(
                     &new_path,
                     new_dest_ref,
                     true, // dest_is_parent_folder
                     dest_file_name,
-                    recursive,
-                    mirror,
+                    planner,
                 )?;
             }
 
-            if mirror {
-                new_dest_ref.delete_unretained(report_delete_file, report_delete_folder)?;
+            if planner.options().mirror {
+                planner.plan_deletion(src_path, new_dest_ref)?;
             }
         }
     }
@@ -1,21 +1,32 @@
+#[cfg(windows)]
 use bindings::Windows::Win32::Foundation::{CloseHandle, FILETIME, HANDLE, PWSTR, SYSTEMTIME};
+#[cfg(windows)]
 use bindings::Windows::Win32::Storage::FileSystem::SetFileTime;
+#[cfg(windows)]
 use bindings::Windows::Win32::Storage::FileSystem::{
     CreateFileW, FILE_ATTRIBUTE_NORMAL, FILE_GENERIC_READ, FILE_GENERIC_WRITE, FILE_SHARE_NONE,
     OPEN_EXISTING,
 };
+#[cfg(windows)]
 use bindings::Windows::Win32::System::Time::SystemTimeToFileTime;
-use chrono::{Datelike, Local, NaiveDateTime, TimeZone, Timelike, Utc};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+#[cfg(windows)]
+use chrono::{Datelike, Timelike, Utc};
 use std::collections::HashSet;
 use std::fs::{File, OpenOptions};
-use std::io::Write;
+#[cfg(windows)]
 use std::os::windows::ffi::OsStrExt;
 use std::path::{Path, PathBuf};
 
+use crate::backend::content_object::ContentObject;
+
+use super::checksum::{checksum_of, ChecksumFileReader};
 use super::file_info::FileInfo;
 use super::file_reader::FileReader;
+use super::local_file_reader::LocalFileReader;
+use super::pipeline::write_behind;
 
-use super::destination_folder::DestinationFolder;
+use super::destination_folder::{temporary_name, DestinationFolder};
 
 pub struct LocalDestinationFolder {
     folder_path: PathBuf,
@@ -52,29 +63,68 @@ impl DestinationFolder for LocalDestinationFolder {
         #[allow(unused_variables)] size: u64,
         created: &Option<NaiveDateTime>,
         modified: &Option<NaiveDateTime>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+        verify: bool,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
         let path_buf = Path::new(&self.folder_path).join(name);
+        // the existing file is kept until the new content is complete
+        let tmp_path_buf = Path::new(&self.folder_path).join(temporary_name(name));
+
+        match write_verified_file(&tmp_path_buf, reader, created, modified, verify) {
+            Ok(true) => {}
+            Ok(false) => {
+                std::fs::remove_file(&tmp_path_buf)?;
+                return Ok(false);
+            }
+            Err(err) => {
+                let _ = std::fs::remove_file(&tmp_path_buf);
+                return Err(err);
+            }
+        }
 
-        let copy_result;
-        {
-            // a scope in which a File object lives
-            let mut file = OpenOptions::new()
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .open(&path_buf)?;
-
-            copy_result = copy_to_file(reader, &mut file);
+        if let Err(err) = std::fs::rename(&tmp_path_buf, &path_buf) {
+            let _ = std::fs::remove_file(&tmp_path_buf);
+            return Err(err.into());
         }
 
-        if let Err(err) = copy_result {
-            let _ = std::fs::remove_file(&path_buf);
-            return Err(err);
+        Ok(true)
+    }
+
+    fn replace_file(
+        &mut self,
+        name: &str,
+        reader: &mut impl FileReader,
+        size: u64,
+        created: &Option<NaiveDateTime>,
+        modified: &Option<NaiveDateTime>,
+        verify: bool,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        // a file is replaced by renaming, but a folder cannot be
+        if Path::new(&self.folder_path).join(name).is_dir() {
+            self.delete_file_or_folder(name)?;
         }
+        self.create_file(name, reader, size, created, modified, verify)
+    }
 
-        set_file_time(&path_buf, created, modified)?;
+    fn copy_on_device(
+        &mut self,
+        _name: &str,
+        _object: &ContentObject,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        Ok(false)
+    }
 
-        Ok(())
+    fn move_on_device(
+        &mut self,
+        _name: &str,
+        _object: &ContentObject,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        Ok(false)
+    }
+
+    fn open_file(&mut self, name: &str) -> Result<Box<dyn FileReader>, Box<dyn std::error::Error>> {
+        let path_buf = Path::new(&self.folder_path).join(name);
+        let file = File::open(&path_buf)?;
+        Ok(Box::new(LocalFileReader::new(file)))
     }
 
     fn open_or_create_folder<FBeforeOpen, FBeforeCreate>(
@@ -116,49 +166,64 @@ impl DestinationFolder for LocalDestinationFolder {
         self.retained.insert(String::from(name));
     }
 
-    fn delete_unretained<FBeforeDeleteFile, FBeforeDeleteFolder>(
-        &mut self,
-        before_delete_file: FBeforeDeleteFile,
-        before_delete_folder: FBeforeDeleteFolder,
-    ) -> Result<(), Box<dyn std::error::Error>>
-    where
-        FBeforeDeleteFile: Fn(&str),
-        FBeforeDeleteFolder: Fn(&str),
-    {
+    fn get_unretained(&mut self) -> Result<Vec<FileInfo>, Box<dyn std::error::Error>> {
+        let mut unretained = Vec::<FileInfo>::new();
         for entry_result in self.folder_path.read_dir()? {
             let entry = entry_result?;
             if let Some(name) = entry.file_name().to_str() {
                 let metadata = entry.metadata()?;
                 let file_info = FileInfo::from_metadata(&metadata, name)?;
-
-                if !file_info.is_hidden && !file_info.is_system {
-                    if !self.retained.contains(name) {
-                        if file_info.is_folder {
-                            before_delete_folder(name);
-                        } else {
-                            before_delete_file(name);
-                        }
-
-                        self.delete_file_or_folder(name)?;
-                    }
+                if !file_info.is_hidden && !file_info.is_system && !self.retained.contains(name) {
+                    unretained.push(file_info);
                 }
             }
         }
-        Ok(())
+        Ok(unretained)
     }
 }
 
-fn copy_to_file(
+/// Writes the file, flushes it to the disk and sets its times.
+/// Writes the file, then reads it back to compare the checksum if `verify` is true.
+/// Returns false if the checksums differ.
+fn write_verified_file(
+    path: &Path,
     reader: &mut impl FileReader,
-    file: &mut File,
+    created: &Option<NaiveDateTime>,
+    modified: &Option<NaiveDateTime>,
+    verify: bool,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    if !verify {
+        write_file(path, reader, created, modified)?;
+        return Ok(true);
+    }
+    let mut checksum_reader = ChecksumFileReader::new(reader);
+    write_file(path, &mut checksum_reader, created, modified)?;
+    let checksum = checksum_of(&mut LocalFileReader::new(File::open(path)?))?;
+    Ok(checksum == checksum_reader.checksum())
+}
+
+fn write_file(
+    path: &Path,
+    reader: &mut impl FileReader,
+    created: &Option<NaiveDateTime>,
+    modified: &Option<NaiveDateTime>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    while let Some(bytes) = reader.next(reader.get_optimized_buffer_size())? {
-        file.write_all(bytes)?;
+    {
+        // a scope in which a File object lives
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+
+        write_behind(reader, &mut file)?;
+        file.sync_all()?;
     }
-    Ok(())
+
+    set_file_time(path, created, modified)
 }
 
-fn set_file_time(
+pub fn set_file_time(
     path: &Path,
     created: &Option<NaiveDateTime>,
     modified: &Option<NaiveDateTime>,
@@ -167,6 +232,15 @@ fn set_file_time(
         return Ok(());
     }
 
+    platform_set_file_time(path, created, modified)
+}
+
+#[cfg(windows)]
+fn platform_set_file_time(
+    path: &Path,
+    created: &Option<NaiveDateTime>,
+    modified: &Option<NaiveDateTime>,
+) -> Result<(), Box<dyn std::error::Error>> {
     let created_ft = naive_date_time_to_file_time(created)?;
     let modified_ft = naive_date_time_to_file_time(modified)?;
 
@@ -174,10 +248,42 @@ fn set_file_time(
     win_set_file_time.set_file_time(&created_ft, &modified_ft)
 }
 
+/// Sets the access time and the modification time.
+/// The creation time cannot be changed on this platform, so it is ignored.
+#[cfg(not(windows))]
+fn platform_set_file_time(
+    path: &Path,
+    _created: &Option<NaiveDateTime>,
+    modified: &Option<NaiveDateTime>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let modified_time = match modified {
+        Some(dt) => std::time::SystemTime::from(naive_date_time_to_local(dt)?),
+        None => return Ok(()),
+    };
+    let file = OpenOptions::new().write(true).open(path)?;
+    file.set_times(
+        std::fs::FileTimes::new()
+            .set_accessed(modified_time)
+            .set_modified(modified_time),
+    )?;
+    Ok(())
+}
+
+fn naive_date_time_to_local(
+    dt: &NaiveDateTime,
+) -> Result<DateTime<Local>, Box<dyn std::error::Error>> {
+    match Local.from_local_datetime(dt).latest() {
+        Some(dt_local) => Ok(dt_local),
+        None => Err(format!("Cannot convert to a local time. : {}", dt).into()),
+    }
+}
+
+#[cfg(windows)]
 struct WindowsSetFileTime {
     handle: HANDLE,
 }
 
+#[cfg(windows)]
 impl WindowsSetFileTime {
     fn open(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
         let mut path_w: Vec<u16> = path.as_os_str().encode_wide().collect();
@@ -224,6 +330,7 @@ impl WindowsSetFileTime {
     }
 }
 
+#[cfg(windows)]
 impl Drop for WindowsSetFileTime {
     fn drop(&mut self) {
         unsafe {
@@ -232,6 +339,7 @@ impl Drop for WindowsSetFileTime {
     }
 }
 
+#[cfg(windows)]
 fn naive_date_time_to_file_time(
     dt_opt: &Option<NaiveDateTime>,
 ) -> Result<Option<FILETIME>, Box<dyn std::error::Error>> {
@@ -240,11 +348,7 @@ fn naive_date_time_to_file_time(
     }
 
     let dt = dt_opt.unwrap();
-    let dt_local = Local.from_local_datetime(&dt).latest();
-    if dt_local.is_none() {
-        return Err(format!("Cannot convert to a local time. : {}", dt.to_string()).into());
-    }
-    let dt_utc = dt_local.unwrap().with_timezone(&Utc);
+    let dt_utc = naive_date_time_to_local(&dt)?.with_timezone(&Utc);
 
     let st = SYSTEMTIME {
         wYear: dt_utc.year() as u16,
@@ -271,6 +375,7 @@ fn naive_date_time_to_file_time(
 }
 
 #[cfg(test)]
+#[allow(clippy::unused_unit)] // expanded from test_case
 mod local_destination_folder_tests {
     use super::*;
     use chrono::{DateTime, Local, NaiveDate, NaiveTime};
@@ -283,16 +388,16 @@ mod local_destination_folder_tests {
         std::fs::create_dir(path)?;
 
         let mut ldf = LocalDestinationFolder::new(PathBuf::from(tempdir.path()));
-        let file_info_opt = ldf.get_file_info(&"foo bar".to_string())?;
+        let file_info_opt = ldf.get_file_info("foo bar")?;
 
         assert!(file_info_opt.is_some());
         let file_info = file_info_opt.unwrap();
         assert_eq!(file_info.name, "foo bar");
         assert_eq!(file_info.data_size, 0u64);
-        assert_eq!(file_info.is_folder, true);
-        assert_eq!(file_info.is_hidden, false);
-        assert_eq!(file_info.is_system, false);
-        assert_eq!(file_info.can_delete, true);
+        assert!(file_info.is_folder);
+        assert!(!file_info.is_hidden);
+        assert!(!file_info.is_system);
+        assert!(file_info.can_delete);
         assert!(file_info.time_created.is_some());
         assert!(file_info.time_modified.is_some());
         let now = Local::now().naive_local();
@@ -317,16 +422,16 @@ mod local_destination_folder_tests {
         std::fs::write(&path, "abc")?;
 
         let mut ldf = LocalDestinationFolder::new(PathBuf::from(tempdir.path()));
-        let file_info_opt = ldf.get_file_info(&"foo bar".to_string())?;
+        let file_info_opt = ldf.get_file_info("foo bar")?;
 
         assert!(file_info_opt.is_some());
         let file_info = file_info_opt.unwrap();
         assert_eq!(file_info.name, "foo bar");
         assert_eq!(file_info.data_size, 3u64);
-        assert_eq!(file_info.is_folder, false);
-        assert_eq!(file_info.is_hidden, false);
-        assert_eq!(file_info.is_system, false);
-        assert_eq!(file_info.can_delete, true);
+        assert!(!file_info.is_folder);
+        assert!(!file_info.is_hidden);
+        assert!(!file_info.is_system);
+        assert!(file_info.can_delete);
         assert!(file_info.time_created.is_some());
         assert!(file_info.time_modified.is_some());
         let now = Local::now().naive_local();
@@ -344,6 +449,20 @@ mod local_destination_folder_tests {
         Ok(())
     }
 
+    #[cfg(not(windows))]
+    #[test]
+    fn test_get_file_info_dotfile() -> Result<(), Box<dyn std::error::Error>> {
+        let tempdir = tempfile::tempdir()?;
+        std::fs::write(tempdir.path().join(".foo"), "abc")?;
+
+        let mut ldf = LocalDestinationFolder::new(PathBuf::from(tempdir.path()));
+        let file_info = ldf.get_file_info(".foo")?.unwrap();
+        assert!(file_info.is_hidden);
+        assert!(!file_info.is_system);
+
+        Ok(())
+    }
+
     struct TestingFileReader {
         n: u8,
         buf: [u8; 10],
@@ -402,19 +521,23 @@ mod local_destination_folder_tests {
         let mut reader = TestingFileReader::new();
         let mut ldf = LocalDestinationFolder::new(PathBuf::from(tempdir.path()));
         ldf.create_file(
-            &"foo bar".to_string(),
+            "foo bar",
             &mut reader,
             file_size,
             &created,
             &modified,
+            false,
         )?;
 
         let metadata = path.metadata()?;
         assert!(metadata.is_file());
-        let file_created_dt = DateTime::<Local>::from(metadata.created()?).naive_local();
+        #[cfg(windows)]
+        {
+            let file_created_dt = DateTime::<Local>::from(metadata.created()?).naive_local();
+            assert_eq!(file_created_dt, created.unwrap());
+        }
         let file_modified_dt = DateTime::<Local>::from(metadata.modified()?).naive_local();
-        assert_eq!(file_created_dt, created.unwrap());
-        assert_eq!(file_modified_dt, modified.unwrap());
+        assert_eq!(Some(file_modified_dt), modified);
 
         let actual_content = std::fs::read(&path)?;
         let expected_content_array: [u8; 30] = [
@@ -428,6 +551,59 @@ mod local_destination_folder_tests {
         Ok(())
     }
 
+    struct FailingFileReader {
+        buf: [u8; 10],
+        count: u32,
+    }
+
+    impl FileReader for FailingFileReader {
+        fn get_optimized_buffer_size(&self) -> u32 {
+            self.buf.len() as u32
+        }
+
+        fn next(&mut self, _max_size: u32) -> Result<Option<&[u8]>, Box<dyn std::error::Error>> {
+            if self.count >= 1 {
+                Err("device was disconnected".into())
+            } else {
+                self.count += 1;
+                Ok(Some(&self.buf))
+            }
+        }
+    }
+
+    #[test_case(false; "new file")]
+    #[test_case(true; "existing file")]
+    fn test_create_file_fails(overwrite: bool) -> Result<(), Box<dyn std::error::Error>> {
+        let tempdir = tempfile::tempdir()?;
+        let path = tempdir.path().join("foo bar");
+
+        if overwrite {
+            std::fs::write(&path, "xxx")?;
+        }
+
+        let mut reader = FailingFileReader {
+            buf: [1; 10],
+            count: 0,
+        };
+        let mut ldf = LocalDestinationFolder::new(PathBuf::from(tempdir.path()));
+        assert!(ldf
+            .create_file("foo bar", &mut reader, 20, &None, &None, false)
+            .is_err());
+
+        // the existing file is intact, and the temporary file was removed
+        if overwrite {
+            assert_eq!(std::fs::read(&path)?, b"xxx");
+        } else {
+            assert!(!path.exists());
+        }
+        assert_eq!(
+            tempdir.path().read_dir()?.count(),
+            if overwrite { 1 } else { 0 }
+        );
+
+        Ok(())
+    }
+
     #[test_case(false; "create new folder")]
     #[test_case(true; "open existing folder")]
     fn test_open_or_create_folder(open_existing: bool) -> Result<(), Box<dyn std::error::Error>> {
@@ -442,7 +618,7 @@ mod local_destination_folder_tests {
         let mut before_open_called = false;
         let mut before_create_called = false;
         let ldf2 = ldf.open_or_create_folder(
-            &"foo bar".to_string(),
+            "foo bar",
             |_name| before_open_called = true,
             |_name| before_create_called = true,
         )?;
@@ -10,8 +10,7 @@ pub struct LocalFileReader {
 
 impl LocalFileReader {
     pub fn new(file: File) -> LocalFileReader {
-        let mut buf = Vec::<u8>::new();
-        buf.resize(32768, 0);
+        let buf = vec![0; 32768];
         LocalFileReader { file, buf }
     }
 }
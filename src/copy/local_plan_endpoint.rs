@@ -0,0 +1,65 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use super::executor::{PlanDestination, PlanSource};
+use super::file_info::FileInfo;
+use super::file_reader::FileReader;
+use super::local_destination_folder::LocalDestinationFolder;
+use super::local_file_reader::LocalFileReader;
+use super::pipeline::ReadAheadFileReader;
+
+/// Local files and folders referred by the plan
+pub struct LocalPlanEndpoint;
+
+impl PlanSource for LocalPlanEndpoint {
+    fn get_file_info(
+        &mut self,
+        path: &str,
+    ) -> Result<Option<FileInfo>, Box<dyn std::error::Error>> {
+        let path = Path::new(path);
+        let name = match path.file_name() {
+            Some(name) => name.to_str().unwrap(),
+            None => "",
+        };
+        match path.metadata() {
+            Ok(metadata) => Ok(Some(FileInfo::from_metadata(&metadata, name)?)),
+            Err(_) => Ok(None),
+        }
+    }
+
+    fn open_file(&mut self, path: &str) -> Result<Box<dyn FileReader>, Box<dyn std::error::Error>> {
+        let file = File::open(path)?;
+        Ok(Box::new(ReadAheadFileReader::new(LocalFileReader::new(
+            file,
+        ))))
+    }
+
+    fn delete(&mut self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if Path::new(path).is_dir() {
+            std::fs::remove_dir(path)?;
+        } else {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    fn is_empty_folder(&mut self, path: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        Ok(Path::new(path).read_dir()?.next().is_none())
+    }
+}
+
+impl PlanDestination for LocalPlanEndpoint {
+    type Folder = LocalDestinationFolder;
+
+    fn open_folder(
+        &mut self,
+        path: &str,
+    ) -> Result<Option<LocalDestinationFolder>, Box<dyn std::error::Error>> {
+        let path_buf = PathBuf::from(path);
+        if path_buf.is_dir() {
+            Ok(Some(LocalDestinationFolder::new(path_buf)))
+        } else {
+            Ok(None)
+        }
+    }
+}
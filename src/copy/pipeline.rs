@@ -0,0 +1,275 @@
+use std::io::Write;
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::thread::JoinHandle;
+
+use super::file_reader::FileReader;
+
+/// Number of the buffers which are read ahead or waiting to be written
+const PIPELINE_DEPTH: usize = 4;
+
+/// Reads the file in a worker thread, so that the next bytes are read
+/// while the previous bytes are being written.
+pub struct ReadAheadFileReader {
+    buffer_size: u32,
+    // the error is passed as a message because the error type is not Send
+    receiver: Option<Receiver<Result<Vec<u8>, String>>>,
+    current: Vec<u8>,
+    offset: usize,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl ReadAheadFileReader {
+    pub fn new<R: FileReader + Send + 'static>(mut reader: R) -> ReadAheadFileReader {
+        let buffer_size = reader.get_optimized_buffer_size();
+        let (sender, receiver) = sync_channel::<Result<Vec<u8>, String>>(PIPELINE_DEPTH);
+        let worker = std::thread::spawn(move || loop {
+            let result = match reader.next(buffer_size) {
+                Ok(Some(bytes)) => Ok(bytes.to_vec()),
+                Ok(None) => return,
+                Err(err) => Err(err.to_string()),
+            };
+            let is_err = result.is_err();
+            // the receiver was dropped, or the reader failed
+            if sender.send(result).is_err() || is_err {
+                return;
+            }
+        });
+        ReadAheadFileReader {
+            buffer_size,
+            receiver: Some(receiver),
+            current: Vec::new(),
+            offset: 0,
+            worker: Some(worker),
+        }
+    }
+}
+
+impl FileReader for ReadAheadFileReader {
+    fn get_optimized_buffer_size(&self) -> u32 {
+        self.buffer_size
+    }
+
+    fn next(&mut self, max_size: u32) -> Result<Option<&[u8]>, Box<dyn std::error::Error>> {
+        while self.offset >= self.current.len() {
+            let received = match self.receiver.as_ref() {
+                Some(receiver) => receiver.recv(),
+                None => return Ok(None),
+            };
+            match received {
+                Ok(Ok(bytes)) => {
+                    self.current = bytes;
+                    self.offset = 0;
+                }
+                Ok(Err(message)) => return Err(message.into()),
+                // the worker reached the end of the file
+                Err(_) => return Ok(None),
+            }
+        }
+        let end = std::cmp::min(self.current.len(), self.offset + max_size as usize);
+        let chunk = &self.current[self.offset..end];
+        self.offset = end;
+        Ok(Some(chunk))
+    }
+}
+
+impl Drop for ReadAheadFileReader {
+    fn drop(&mut self) {
+        // stops the worker waiting for the buffer to be received
+        drop(self.receiver.take());
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Copies the bytes from the reader to the writer.
+/// The writer runs in a worker thread, so that the next bytes are read
+/// while the previous bytes are being written.
+pub fn write_behind<W: Write + Send>(
+    reader: &mut impl FileReader,
+    writer: &mut W,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (sender, receiver) = sync_channel::<Vec<u8>>(PIPELINE_DEPTH);
+    std::thread::scope(|scope| {
+        let worker = scope.spawn(move || -> std::io::Result<()> {
+            for bytes in receiver {
+                writer.write_all(&bytes)?;
+            }
+            Ok(())
+        });
+
+        let mut read_result: Result<(), Box<dyn std::error::Error>> = Ok(());
+        loop {
+            match reader.next(reader.get_optimized_buffer_size()) {
+                Ok(Some(bytes)) => {
+                    // the worker failed in writing
+                    if sender.send(bytes.to_vec()).is_err() {
+                        break;
+                    }
+                }
+                Ok(None) => break,
+                Err(err) => {
+                    read_result = Err(err);
+                    break;
+                }
+            }
+        }
+        drop(sender);
+
+        match worker.join() {
+            Ok(write_result) => write_result?,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+        read_result
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::device::Device;
+    use crate::copy::destination_folder::DestinationFolder;
+    use crate::copy::device_destination_folder::DeviceDestinationFolder;
+    use crate::memory::device::MemoryDevice;
+    use std::time::{Duration, Instant};
+
+    /// Gives the bytes 0, 1, 2, ... in the chunks of the given size
+    struct SlowReader {
+        size: usize,
+        chunk_size: usize,
+        offset: usize,
+        latency: Duration,
+        fail_at: Option<usize>,
+        buf: Vec<u8>,
+    }
+
+    impl SlowReader {
+        fn new(size: usize, chunk_size: usize) -> SlowReader {
+            SlowReader {
+                size,
+                chunk_size,
+                offset: 0,
+                latency: Duration::from_millis(0),
+                fail_at: None,
+                buf: Vec::new(),
+            }
+        }
+    }
+
+    impl FileReader for SlowReader {
+        fn get_optimized_buffer_size(&self) -> u32 {
+            self.chunk_size as u32
+        }
+
+        fn next(&mut self, max_size: u32) -> Result<Option<&[u8]>, Box<dyn std::error::Error>> {
+            if let Some(fail_at) = self.fail_at {
+                if self.offset >= fail_at {
+                    return Err("device was disconnected".into());
+                }
+            }
+            if self.offset >= self.size {
+                return Ok(None);
+            }
+            std::thread::sleep(self.latency);
+            let end = std::cmp::min(self.size, self.offset + max_size as usize);
+            self.buf = (self.offset..end).map(|n| n as u8).collect();
+            self.offset = end;
+            Ok(Some(&self.buf))
+        }
+    }
+
+    fn read_all(reader: &mut impl FileReader, max_size: u32) -> Vec<u8> {
+        let mut data = Vec::<u8>::new();
+        while let Some(bytes) = reader.next(max_size).unwrap() {
+            data.extend_from_slice(bytes);
+        }
+        data
+    }
+
+    #[test]
+    fn test_read_ahead() {
+        let expected = read_all(&mut SlowReader::new(1000, 64), 64);
+        for max_size in [1, 30, 64, 100] {
+            let mut reader = ReadAheadFileReader::new(SlowReader::new(1000, 64));
+            assert_eq!(reader.get_optimized_buffer_size(), 64);
+            assert_eq!(read_all(&mut reader, max_size), expected);
+            assert!(reader.next(max_size).unwrap().is_none());
+        }
+
+        // empty file
+        let mut reader = ReadAheadFileReader::new(SlowReader::new(0, 64));
+        assert!(reader.next(64).unwrap().is_none());
+
+        // dropped in the middle of the file
+        let mut reader = ReadAheadFileReader::new(SlowReader::new(100_000, 64));
+        reader.next(64).unwrap();
+        drop(reader);
+    }
+
+    #[test]
+    fn test_read_ahead_error() {
+        let mut slow_reader = SlowReader::new(1000, 64);
+        slow_reader.fail_at = Some(128);
+        let mut reader = ReadAheadFileReader::new(slow_reader);
+        assert_eq!(reader.next(100).unwrap().unwrap().len(), 64);
+        assert_eq!(reader.next(100).unwrap().unwrap().len(), 64);
+        let err = reader.next(100).err().unwrap();
+        assert_eq!(err.to_string(), "device was disconnected");
+    }
+
+    #[test]
+    fn test_write_behind() {
+        let mut data = Vec::<u8>::new();
+        write_behind(&mut SlowReader::new(1000, 64), &mut data).unwrap();
+        assert_eq!(data, read_all(&mut SlowReader::new(1000, 64), 64));
+
+        let mut slow_reader = SlowReader::new(1000, 64);
+        slow_reader.fail_at = Some(128);
+        let mut data = Vec::<u8>::new();
+        assert!(write_behind(&mut slow_reader, &mut data).is_err());
+    }
+
+    /// Uploads a file from the slow local disk to the slow device.
+    /// `cargo test bench_read_ahead -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_read_ahead() {
+        const SIZE: usize = 4 * 1024 * 1024;
+        const CHUNK_SIZE: usize = 32768;
+        let latency = Duration::from_millis(2);
+
+        let device = MemoryDevice::new("Phone");
+        let storage = device.add_storage("Internal");
+        device.set_latency(latency);
+        let upload = |name: &str, mut reader: Box<dyn FileReader>| -> Duration {
+            let storage_info = device.get_object_info(storage.clone()).unwrap();
+            let mut folder = DeviceDestinationFolder::new(&device, storage_info).unwrap();
+            let start = Instant::now();
+            folder
+                .create_file(name, &mut reader, SIZE as u64, &None, &None, false)
+                .unwrap();
+            start.elapsed()
+        };
+
+        let mut slow_reader = SlowReader::new(SIZE, CHUNK_SIZE);
+        slow_reader.latency = latency;
+        let sequential = upload("a.bin", Box::new(slow_reader));
+
+        let mut slow_reader = SlowReader::new(SIZE, CHUNK_SIZE);
+        slow_reader.latency = latency;
+        let pipelined = upload("b.bin", Box::new(ReadAheadFileReader::new(slow_reader)));
+
+        let throughput = |elapsed: Duration| SIZE as f64 / elapsed.as_secs_f64() / 1048576.0;
+        println!(
+            "sequential: {:?} ({:.1} MB/s)",
+            sequential,
+            throughput(sequential)
+        );
+        println!(
+            "read-ahead: {:?} ({:.1} MB/s)",
+            pipelined,
+            throughput(pipelined)
+        );
+        assert!(pipelined < sequential);
+    }
+}
@@ -0,0 +1,292 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::path::{get_path_type, join_any_path, DeviceStoragePath, PathType, SEPARATORS};
+
+use super::compare::{compare_files, Decision};
+use super::copy_processor::CopyOptions;
+use super::destination_folder::DestinationFolder;
+use super::file_info::FileInfo;
+use super::filter::PathFilter;
+use super::planned_folder::PlannedFolder;
+
+/// Kind of the planned action
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ActionKind {
+    /// Create a new folder
+    CreateFolder,
+    /// Copy a new file to the portable device
+    Upload,
+    /// Copy a new file to the local computer
+    Download,
+    /// Overwrite the existing file
+    Replace,
+    /// Leave the existing file as it is
+    Skip,
+    /// Delete the file or folder that does not exist in the source
+    Delete,
+}
+
+impl ActionKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ActionKind::CreateFolder => "create-folder",
+            ActionKind::Upload => "upload",
+            ActionKind::Download => "download",
+            ActionKind::Replace => "replace",
+            ActionKind::Skip => "skip",
+            ActionKind::Delete => "delete",
+        }
+    }
+}
+
+/// State of a file or folder at the planning.
+/// The plan is not applied if the state was changed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EntryState {
+    pub is_folder: bool,
+    pub size: u64,
+    pub time_created: Option<NaiveDateTime>,
+    pub time_modified: Option<NaiveDateTime>,
+}
+
+impl From<&FileInfo> for EntryState {
+    fn from(file_info: &FileInfo) -> Self {
+        EntryState {
+            is_folder: file_info.is_folder,
+            size: file_info.data_size,
+            time_created: file_info.time_created,
+            time_modified: file_info.time_modified,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedAction {
+    pub action: ActionKind,
+    /// Path of the source file or folder (None for the deletion)
+    pub source: Option<String>,
+    /// Path of the destination file or folder
+    pub destination: String,
+    /// Size of the data to be transferred (or to be deleted)
+    pub size: u64,
+    pub reason: String,
+    /// State of the source (None if not exist)
+    pub source_state: Option<EntryState>,
+    /// State of the destination (None if not exist)
+    pub destination_state: Option<EntryState>,
+}
+
+/// Actions to copy the source to the destination, in the order to be performed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransferPlan {
+    /// Source path
+    pub source: String,
+    /// Destination path
+    pub destination: String,
+    pub recursive: bool,
+    pub mirror: bool,
+    /// Delete the sources after they were copied
+    #[serde(default)]
+    pub move_source: bool,
+    pub actions: Vec<PlannedAction>,
+}
+
+impl TransferPlan {
+    pub fn load(path: &Path) -> Result<TransferPlan, Box<dyn std::error::Error>> {
+        let reader = BufReader::new(File::open(path)?);
+        match serde_json::from_reader(reader) {
+            Ok(plan) => Ok(plan),
+            Err(err) => Err(format!("invalid plan: {}: {}", path.to_string_lossy(), err).into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+
+    /// Returns the total size of the files to be transferred.
+    pub fn transfer_size(&self) -> u64 {
+        self.actions
+            .iter()
+            .filter(|action| action.is_transfer())
+            .map(|action| action.size)
+            .sum()
+    }
+}
+
+impl PlannedAction {
+    pub fn is_transfer(&self) -> bool {
+        matches!(
+            self.action,
+            ActionKind::Upload | ActionKind::Download | ActionKind::Replace
+        )
+    }
+}
+
+/// Decides the actions while the copy processor walks the source and the destination.
+pub struct Planner {
+    options: CopyOptions,
+    filter: PathFilter,
+    plan: TransferPlan,
+}
+
+impl Planner {
+    pub fn new(
+        source: &str,
+        destination: &str,
+        options: &CopyOptions,
+    ) -> Result<Planner, Box<dyn std::error::Error>> {
+        Ok(Planner {
+            options: options.clone(),
+            filter: PathFilter::new(&options.include, &options.exclude)?,
+            plan: TransferPlan {
+                source: normalize_path(source),
+                destination: normalize_path(destination),
+                recursive: options.recursive,
+                mirror: options.mirror,
+                move_source: options.move_source,
+                actions: Vec::new(),
+            },
+        })
+    }
+
+    pub fn options(&self) -> &CopyOptions {
+        &self.options
+    }
+
+    pub fn into_plan(self) -> TransferPlan {
+        self.plan
+    }
+
+    /// Checks whether the source entry is filtered out by the include and exclude patterns.
+    pub fn is_excluded(&self, src_path: &str, is_folder: bool) -> bool {
+        self.filter
+            .is_excluded(self.relative_path(src_path), is_folder)
+    }
+
+    /// Returns the path relative to the source of the copy.
+    fn relative_path<'p>(&self, src_path: &'p str) -> &'p str {
+        src_path
+            .strip_prefix(self.plan.source.as_str())
+            .unwrap_or(src_path)
+            .trim_start_matches(SEPARATORS)
+    }
+
+    /// Plans copying the file.
+    /// `same_content` compares the contents of the source and the existing destination file,
+    /// which is called only if the comparison needs it.
+    pub fn plan_file<F>(
+        &mut self,
+        src_path: &str,
+        dest_path: &str,
+        src_file_info: &FileInfo,
+        dest_file_info: Option<&FileInfo>,
+        same_content: F,
+    ) -> Result<(), Box<dyn std::error::Error>>
+    where
+        F: FnOnce() -> Result<bool, Box<dyn std::error::Error>>,
+    {
+        let (action, reason) = match dest_file_info {
+            None => {
+                let action = if get_path_type(dest_path) == PathType::DeviceStorage {
+                    ActionKind::Upload
+                } else {
+                    ActionKind::Download
+                };
+                (action, "new file".to_string())
+            }
+            Some(dest_file_info) => match compare_files(
+                src_file_info,
+                dest_file_info,
+                self.options.compare,
+                self.options.time_tolerance,
+                same_content,
+            )? {
+                Decision::Skip(reason) => (ActionKind::Skip, reason),
+                Decision::Replace(reason) => (ActionKind::Replace, reason),
+            },
+        };
+        let size = if action == ActionKind::Skip {
+            0
+        } else {
+            src_file_info.data_size
+        };
+        self.plan.actions.push(PlannedAction {
+            action,
+            source: Some(src_path.to_string()),
+            destination: dest_path.to_string(),
+            size,
+            reason,
+            source_state: Some(EntryState::from(src_file_info)),
+            destination_state: dest_file_info.map(EntryState::from),
+        });
+        Ok(())
+    }
+
+    pub fn plan_folder(
+        &mut self,
+        src_path: &str,
+        dest_path: &str,
+        src_file_info: &FileInfo,
+        dest_file_info: Option<&FileInfo>,
+    ) {
+        // an existing folder is used as it is
+        if dest_file_info.is_none() {
+            self.plan.actions.push(PlannedAction {
+                action: ActionKind::CreateFolder,
+                source: Some(src_path.to_string()),
+                destination: dest_path.to_string(),
+                size: 0,
+                reason: "new folder".to_string(),
+                source_state: Some(EntryState::from(src_file_info)),
+                destination_state: None,
+            });
+        }
+    }
+
+    /// Plans deletion of the entries which were not retained in the folder.
+    /// `src_path` is the source folder copied to the folder.
+    /// The entries filtered out by the include and exclude patterns are not deleted.
+    pub fn plan_deletion<D: DestinationFolder>(
+        &mut self,
+        src_path: &str,
+        dest: &mut PlannedFolder<D>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut unretained = dest.get_unretained()?;
+        unretained.sort_by(|a, b| a.name.cmp(&b.name));
+        let relative_folder = self.relative_path(src_path).to_string();
+        for file_info in unretained {
+            let relative_path = join_any_path(&relative_folder, &file_info.name);
+            if self.filter.is_excluded(&relative_path, file_info.is_folder) {
+                continue;
+            }
+            self.plan.actions.push(PlannedAction {
+                action: ActionKind::Delete,
+                source: None,
+                destination: dest.path_of(&file_info.name),
+                size: file_info.data_size,
+                reason: "not in the source".to_string(),
+                source_state: None,
+                destination_state: Some(EntryState::from(&file_info)),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Returns the path on the device in the normalized form,
+/// so that the paths of the actions can be split into the folder and the name.
+pub fn normalize_path(path: &str) -> String {
+    match DeviceStoragePath::from(path) {
+        Ok(storage_path) => storage_path.full_path(),
+        Err(_) => path.to_string(),
+    }
+}
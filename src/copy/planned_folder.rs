@@ -0,0 +1,84 @@
+use crate::path::join_any_path;
+
+use super::destination_folder::DestinationFolder;
+use super::file_info::FileInfo;
+use super::file_reader::FileReader;
+
+/// A destination folder seen by the planner.
+///
+/// Nothing is changed on the destination.
+/// The folders that would be created are represented without the actual folder,
+/// so the files in them are all treated as new files.
+pub struct PlannedFolder<D: DestinationFolder> {
+    folder: Option<Box<D>>,
+    path: String,
+}
+
+impl<D: DestinationFolder> PlannedFolder<D> {
+    pub fn new(folder: D, path: &str) -> PlannedFolder<D> {
+        PlannedFolder {
+            folder: Some(Box::new(folder)),
+            path: path.to_string(),
+        }
+    }
+
+    /// Returns the path of the entry in this folder.
+    pub fn path_of(&self, name: &str) -> String {
+        join_any_path(&self.path, name)
+    }
+
+    pub fn get_file_info(
+        &mut self,
+        name: &str,
+    ) -> Result<Option<FileInfo>, Box<dyn std::error::Error>> {
+        match self.folder.as_mut() {
+            Some(folder) => folder.get_file_info(name),
+            None => Ok(None),
+        }
+    }
+
+    /// Opens the existing file to compare the contents.
+    pub fn open_file(
+        &mut self,
+        name: &str,
+    ) -> Result<Box<dyn FileReader>, Box<dyn std::error::Error>> {
+        match self.folder.as_mut() {
+            Some(folder) => folder.open_file(name),
+            None => Err(format!("file was not found: {}", self.path_of(name)).into()),
+        }
+    }
+
+    /// Opens the sub folder, or represents the folder which would be created.
+    pub fn open_folder(
+        &mut self,
+        name: &str,
+    ) -> Result<PlannedFolder<D>, Box<dyn std::error::Error>> {
+        let sub_folder = match self.get_file_info(name)? {
+            Some(file_info) => {
+                if !file_info.is_folder {
+                    return Err(format!("cannot open a folder: {}", self.path_of(name)).into());
+                }
+                let folder = self.folder.as_mut().unwrap();
+                Some(folder.open_or_create_folder(name, |_| {}, |_| {})?)
+            }
+            None => None,
+        };
+        Ok(PlannedFolder {
+            folder: sub_folder,
+            path: self.path_of(name),
+        })
+    }
+
+    pub fn retain(&mut self, name: &str) {
+        if let Some(folder) = self.folder.as_mut() {
+            folder.retain(name);
+        }
+    }
+
+    pub fn get_unretained(&mut self) -> Result<Vec<FileInfo>, Box<dyn std::error::Error>> {
+        match self.folder.as_mut() {
+            Some(folder) => folder.get_unretained(),
+            None => Ok(Vec::new()),
+        }
+    }
+}
@@ -0,0 +1,385 @@
+use std::cell::RefCell;
+use std::io::{stdout, IsTerminal, Write};
+use std::rc::Rc;
+use std::time::Duration;
+
+use super::file_info::FileInfo;
+use super::file_reader::FileReader;
+use super::rate_limit::{Clock, SystemClock};
+
+/// Interval to redraw the live line on the terminal
+const LIVE_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Interval to print the progress lines when the output is not a terminal
+const LINE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Reports the bytes transferred in the current file and in the whole job.
+///
+/// On a terminal, the progress is redrawn on a single line while the file is transferred.
+/// Otherwise, a progress line is printed periodically, only for the files taking a long time.
+pub struct Progress {
+    out: Box<dyn Write>,
+    clock: Box<dyn Clock>,
+    /// Redraw a single line instead of printing lines
+    live: bool,
+    total_files: usize,
+    total_bytes: u64,
+    done_files: usize,
+    /// Bytes of the completed files
+    done_bytes: u64,
+    /// Bytes actually read, to calculate the rate
+    transferred: u64,
+    start: Duration,
+    file_name: String,
+    file_size: u64,
+    file_done: u64,
+    /// Bytes of the last completed file, which are discarded if it is transferred again
+    last_file_bytes: u64,
+    last_render: Duration,
+    /// The line of the current file has not been ended
+    line_open: bool,
+    /// Length of the last live line, to erase the rest of it
+    last_width: usize,
+}
+
+impl Progress {
+    pub fn new(live: bool, out: Box<dyn Write>, clock: Box<dyn Clock>) -> Progress {
+        let start = clock.now();
+        Progress {
+            out,
+            clock,
+            live,
+            total_files: 0,
+            total_bytes: 0,
+            done_files: 0,
+            done_bytes: 0,
+            transferred: 0,
+            start,
+            file_name: String::new(),
+            file_size: 0,
+            file_done: 0,
+            last_file_bytes: 0,
+            last_render: start,
+            line_open: false,
+            last_width: 0,
+        }
+    }
+
+    /// Reports to the standard output, as a live line if it is a terminal.
+    pub fn to_stdout() -> Progress {
+        Progress::new(
+            stdout().is_terminal(),
+            Box::new(stdout()),
+            Box::new(SystemClock::new()),
+        )
+    }
+
+    /// Sets the files and the bytes to be transferred, and restarts measuring the rate.
+    pub fn set_totals(&mut self, total_files: usize, total_bytes: u64) {
+        self.total_files = total_files;
+        self.total_bytes = total_bytes;
+        self.done_files = 0;
+        self.done_bytes = 0;
+        self.transferred = 0;
+        self.start = self.clock.now();
+    }
+
+    pub fn start_file(&mut self, src_file_info: &FileInfo) {
+        self.file_name = src_file_info.name.clone();
+        self.file_size = src_file_info.data_size;
+        self.file_done = 0;
+        self.last_render = self.clock.now();
+        if self.live {
+            self.last_width = 0;
+            self.render_live();
+        } else {
+            let _ = write!(self.out, "copying \"{}\" ...", &self.file_name);
+            self.line_open = true;
+        }
+        let _ = self.out.flush();
+    }
+
+    /// Counts the bytes read from the current file.
+    pub fn advance(&mut self, bytes: usize) {
+        self.file_done += bytes as u64;
+        self.transferred += bytes as u64;
+        let now = self.clock.now();
+        let elapsed = now.saturating_sub(self.last_render);
+        if self.live && elapsed >= LIVE_INTERVAL {
+            self.last_render = now;
+            self.render_live();
+            let _ = self.out.flush();
+        } else if !self.live && elapsed >= LINE_INTERVAL {
+            self.last_render = now;
+            if self.line_open {
+                let _ = writeln!(self.out);
+                self.line_open = false;
+            }
+            let status = self.status();
+            let _ = writeln!(self.out, "  {}", status);
+        }
+    }
+
+    pub fn end_file(&mut self) {
+        self.done_files += 1;
+        // the file is complete even if the reader returned less bytes than expected
+        self.file_size = self.file_done;
+        if self.live {
+            self.render_live();
+            let _ = writeln!(self.out);
+        } else if self.line_open {
+            let _ = writeln!(self.out);
+            self.line_open = false;
+        } else {
+            let status = self.status();
+            let _ = writeln!(self.out, "  {}", status);
+        }
+        let _ = self.out.flush();
+        self.done_bytes += self.file_done;
+        self.last_file_bytes = self.file_done;
+        self.file_done = 0;
+    }
+
+    /// Uncounts the last file, which will be transferred again.
+    pub fn discard_file(&mut self) {
+        self.done_files -= 1;
+        self.done_bytes -= self.last_file_bytes;
+        self.last_file_bytes = 0;
+    }
+
+    /// Counts the file which was completed without being transferred.
+    pub fn add_file(&mut self, size: u64) {
+        self.done_files += 1;
+        self.done_bytes += size;
+    }
+
+    fn render_live(&mut self) {
+        let line = format!("\rcopying \"{}\" {}", &self.file_name, self.status());
+        let width = line.chars().count();
+        let padding = self.last_width.saturating_sub(width);
+        let _ = write!(self.out, "{}{}", line, " ".repeat(padding));
+        self.last_width = width;
+    }
+
+    fn status(&self) -> String {
+        let done = self.done_bytes + self.file_done;
+        let elapsed = self.clock.now().saturating_sub(self.start).as_secs_f64();
+        let (rate, eta) = if self.transferred > 0 && elapsed > 0.0 {
+            let rate = self.transferred as f64 / elapsed;
+            let remaining = self.total_bytes.saturating_sub(done) as f64;
+            (
+                format!("{}/s", format_bytes(rate as u64)),
+                format_duration(Duration::from_secs_f64(remaining / rate)),
+            )
+        } else {
+            (String::from("-"), String::from("-"))
+        };
+        format!(
+            "{} / {} ({}%), total {} / {} ({}%, {} of {} files), {}, ETA {}",
+            format_bytes(self.file_done),
+            format_bytes(self.file_size),
+            percent(self.file_done, self.file_size),
+            format_bytes(done),
+            format_bytes(self.total_bytes),
+            percent(done, self.total_bytes),
+            self.done_files,
+            self.total_files,
+            rate,
+            eta
+        )
+    }
+}
+
+/// Counts the bytes read from the file in the progress.
+pub struct ProgressFileReader<R: FileReader> {
+    reader: R,
+    progress: Rc<RefCell<Progress>>,
+}
+
+impl<R: FileReader> ProgressFileReader<R> {
+    pub fn new(reader: R, progress: Rc<RefCell<Progress>>) -> ProgressFileReader<R> {
+        ProgressFileReader { reader, progress }
+    }
+}
+
+impl<R: FileReader> FileReader for ProgressFileReader<R> {
+    fn get_optimized_buffer_size(&self) -> u32 {
+        self.reader.get_optimized_buffer_size()
+    }
+
+    fn next(&mut self, max_size: u32) -> Result<Option<&[u8]>, Box<dyn std::error::Error>> {
+        let bytes = self.reader.next(max_size)?;
+        if let Some(bytes) = bytes {
+            self.progress.borrow_mut().advance(bytes.len());
+        }
+        Ok(bytes)
+    }
+}
+
+fn percent(done: u64, total: u64) -> u64 {
+    match (done * 100).checked_div(total) {
+        Some(percent) => std::cmp::min(100, percent),
+        None => 100,
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs < 60 {
+        format!("{}s", secs)
+    } else if secs < 3600 {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h {:02}m", secs / 3600, secs % 3600 / 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::copy::rate_limit::FakeClock;
+
+    #[derive(Clone)]
+    struct SharedOutput {
+        buf: Rc<RefCell<Vec<u8>>>,
+    }
+
+    impl SharedOutput {
+        fn text(&self) -> String {
+            String::from_utf8(self.buf.borrow().clone()).unwrap()
+        }
+    }
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.buf.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn setup(live: bool) -> (Progress, FakeClock, SharedOutput) {
+        let clock = FakeClock::new();
+        let output = SharedOutput {
+            buf: Rc::new(RefCell::new(Vec::new())),
+        };
+        let progress = Progress::new(live, Box::new(output.clone()), Box::new(clock.clone()));
+        (progress, clock, output)
+    }
+
+    fn file_info(name: &str, size: u64) -> FileInfo {
+        FileInfo {
+            name: name.to_string(),
+            is_folder: false,
+            is_system: false,
+            is_hidden: false,
+            can_delete: true,
+            time_created: None,
+            time_modified: None,
+            data_size: size,
+        }
+    }
+
+    #[test]
+    fn test_live() {
+        let (mut progress, clock, output) = setup(true);
+        progress.set_totals(2, 4096);
+
+        progress.start_file(&file_info("a.mp4", 2048));
+        clock.advance(Duration::from_secs(1));
+        progress.advance(1024);
+        clock.advance(Duration::from_millis(100));
+        // not redrawn within the interval
+        progress.advance(512);
+        clock.advance(Duration::from_millis(900));
+        progress.advance(512);
+        progress.end_file();
+
+        assert_eq!(
+            output.text(),
+            concat!(
+                "\rcopying \"a.mp4\" 0 B / 2.0 KiB (0%), total 0 B / 4.0 KiB (0%, 0 of 2 files), -, ETA -",
+                "\rcopying \"a.mp4\" 1.0 KiB / 2.0 KiB (50%), total 1.0 KiB / 4.0 KiB (25%, 0 of 2 files), 1.0 KiB/s, ETA 3s",
+                "\rcopying \"a.mp4\" 2.0 KiB / 2.0 KiB (100%), total 2.0 KiB / 4.0 KiB (50%, 0 of 2 files), 1.0 KiB/s, ETA 2s",
+                "\rcopying \"a.mp4\" 2.0 KiB / 2.0 KiB (100%), total 2.0 KiB / 4.0 KiB (50%, 1 of 2 files), 1.0 KiB/s, ETA 2s\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_lines() {
+        let (mut progress, clock, output) = setup(false);
+        progress.set_totals(3, 1024 * 1024 * 3);
+
+        // short file
+        progress.start_file(&file_info("a.jpg", 1024 * 1024));
+        clock.advance(Duration::from_secs(1));
+        progress.advance(1024 * 1024);
+        progress.end_file();
+
+        // long file
+        progress.start_file(&file_info("b.mp4", 1024 * 1024 * 2));
+        clock.advance(Duration::from_secs(4));
+        progress.advance(1024 * 1024);
+        clock.advance(Duration::from_secs(5));
+        progress.advance(1024 * 1024);
+        progress.end_file();
+
+        assert_eq!(
+            output.text(),
+            concat!(
+                "copying \"a.jpg\" ...\n",
+                "copying \"b.mp4\" ...\n",
+                "  2.0 MiB / 2.0 MiB (100%), total 3.0 MiB / 3.0 MiB (100%, 1 of 3 files), 307.2 KiB/s, ETA 0s\n",
+                "  2.0 MiB / 2.0 MiB (100%), total 3.0 MiB / 3.0 MiB (100%, 2 of 3 files), 307.2 KiB/s, ETA 0s\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_discard_and_add_file() {
+        let (mut progress, _clock, _output) = setup(false);
+        progress.set_totals(2, 300);
+
+        progress.start_file(&file_info("a", 100));
+        progress.advance(100);
+        progress.end_file();
+        progress.discard_file();
+        assert_eq!((progress.done_files, progress.done_bytes), (0, 0));
+
+        progress.start_file(&file_info("a", 100));
+        progress.advance(100);
+        progress.end_file();
+        progress.add_file(200);
+        assert_eq!((progress.done_files, progress.done_bytes), (2, 300));
+        assert_eq!(progress.transferred, 200);
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(4 * 1024 * 1024 * 1024), "4.0 GiB");
+        assert_eq!(format_duration(Duration::from_secs(59)), "59s");
+        assert_eq!(format_duration(Duration::from_secs(192)), "3m 12s");
+        assert_eq!(format_duration(Duration::from_secs(3720)), "1h 02m");
+    }
+}
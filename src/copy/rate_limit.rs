@@ -0,0 +1,227 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use super::file_reader::FileReader;
+
+/// Source of the time, which can be replaced in the tests.
+pub trait Clock {
+    /// Returns the time elapsed since an arbitrary origin.
+    fn now(&self) -> Duration;
+
+    fn sleep(&self, duration: Duration);
+}
+
+pub struct SystemClock {
+    origin: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> SystemClock {
+        SystemClock {
+            origin: Instant::now(),
+        }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.origin.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// A clock which advances only by sleeping or by the test
+#[cfg(test)]
+#[derive(Clone)]
+pub struct FakeClock {
+    now: Rc<std::cell::Cell<Duration>>,
+}
+
+#[cfg(test)]
+impl FakeClock {
+    pub fn new() -> FakeClock {
+        FakeClock {
+            now: Rc::new(std::cell::Cell::new(Duration::from_secs(100))),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.now.set(self.now.get() + duration);
+    }
+}
+
+#[cfg(test)]
+impl Clock for FakeClock {
+    fn now(&self) -> Duration {
+        self.now.get()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+/// Limits the number of bytes per second.
+///
+/// The bucket holds the bytes which can be read without waiting, up to the bytes of one second.
+/// Reading more bytes than the bucket holds waits until they are refilled.
+pub struct TokenBucket {
+    /// Bytes per second
+    rate: u64,
+    /// Bytes which can be read without waiting (negative while waiting)
+    tokens: f64,
+    /// Time when the tokens were refilled
+    last: Duration,
+    clock: Box<dyn Clock>,
+}
+
+impl TokenBucket {
+    pub fn new(rate: u64, clock: Box<dyn Clock>) -> TokenBucket {
+        let last = clock.now();
+        TokenBucket {
+            rate,
+            tokens: rate as f64,
+            last,
+            clock,
+        }
+    }
+
+    /// Takes the bytes from the bucket, and waits if they exceeded the rate.
+    pub fn consume(&mut self, bytes: usize) {
+        let now = self.clock.now();
+        let elapsed = now.saturating_sub(self.last).as_secs_f64();
+        self.tokens = f64::min(self.rate as f64, self.tokens + elapsed * self.rate as f64);
+        self.last = now;
+
+        self.tokens -= bytes as f64;
+        if self.tokens < 0.0 {
+            self.clock
+                .sleep(Duration::from_secs_f64(-self.tokens / self.rate as f64));
+        }
+    }
+}
+
+/// Reads the file at the rate limited by the bucket.
+/// The bucket can be shared by the readers, so that the limit applies to the whole job.
+pub struct RateLimitedFileReader<R: FileReader> {
+    reader: R,
+    bucket: Rc<RefCell<TokenBucket>>,
+}
+
+impl<R: FileReader> RateLimitedFileReader<R> {
+    pub fn new(reader: R, bucket: Rc<RefCell<TokenBucket>>) -> RateLimitedFileReader<R> {
+        RateLimitedFileReader { reader, bucket }
+    }
+}
+
+impl<R: FileReader> FileReader for RateLimitedFileReader<R> {
+    fn get_optimized_buffer_size(&self) -> u32 {
+        self.reader.get_optimized_buffer_size()
+    }
+
+    fn next(&mut self, max_size: u32) -> Result<Option<&[u8]>, Box<dyn std::error::Error>> {
+        let bytes = self.reader.next(max_size)?;
+        if let Some(bytes) = bytes {
+            self.bucket.borrow_mut().consume(bytes.len());
+        }
+        Ok(bytes)
+    }
+}
+
+/// Parses the rate in bytes per second, e.g. `500k`, `10M` or `1.5M`.
+/// The suffixes `k`, `M` and `G` are the powers of 1024.
+pub fn parse_rate(s: &str) -> Result<u64, Box<dyn std::error::Error>> {
+    let (number, unit) = match s.char_indices().last() {
+        Some((pos, 'k')) | Some((pos, 'K')) => (&s[..pos], 1024.0),
+        Some((pos, 'm')) | Some((pos, 'M')) => (&s[..pos], 1024.0 * 1024.0),
+        Some((pos, 'g')) | Some((pos, 'G')) => (&s[..pos], 1024.0 * 1024.0 * 1024.0),
+        _ => (s, 1.0),
+    };
+    match number.parse::<f64>() {
+        Ok(value) if value.is_finite() && value * unit >= 1.0 => Ok((value * unit) as u64),
+        _ => Err(format!("invalid rate: {}", s).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct ZeroReader {
+        remaining: usize,
+        buf: Vec<u8>,
+    }
+
+    impl FileReader for ZeroReader {
+        fn get_optimized_buffer_size(&self) -> u32 {
+            500
+        }
+
+        fn next(&mut self, max_size: u32) -> Result<Option<&[u8]>, Box<dyn std::error::Error>> {
+            if self.remaining == 0 {
+                return Ok(None);
+            }
+            let len = std::cmp::min(self.remaining, max_size as usize);
+            self.remaining -= len;
+            self.buf = vec![0; len];
+            Ok(Some(&self.buf))
+        }
+    }
+
+    fn read_all(reader: &mut impl FileReader) -> usize {
+        let mut total = 0;
+        while let Some(bytes) = reader.next(reader.get_optimized_buffer_size()).unwrap() {
+            total += bytes.len();
+        }
+        total
+    }
+
+    fn zeros(size: usize) -> ZeroReader {
+        ZeroReader {
+            remaining: size,
+            buf: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_rate_limited_reader() {
+        let clock = FakeClock::new();
+        let start = clock.now();
+        let bucket = Rc::new(RefCell::new(TokenBucket::new(
+            1000,
+            Box::new(clock.clone()),
+        )));
+
+        // the first second is read without waiting
+        let mut reader = RateLimitedFileReader::new(zeros(5000), bucket.clone());
+        assert_eq!(read_all(&mut reader), 5000);
+        assert_eq!(clock.now() - start, Duration::from_secs(4));
+
+        // the limit is shared by the next file
+        let mut reader = RateLimitedFileReader::new(zeros(2000), bucket.clone());
+        assert_eq!(read_all(&mut reader), 2000);
+        assert_eq!(clock.now() - start, Duration::from_secs(6));
+
+        // the idle time refills the bucket up to one second
+        clock.advance(Duration::from_secs(10));
+        let mut reader = RateLimitedFileReader::new(zeros(3000), bucket);
+        assert_eq!(read_all(&mut reader), 3000);
+        assert_eq!(clock.now() - start, Duration::from_secs(18));
+    }
+
+    #[test]
+    fn test_parse_rate() {
+        assert_eq!(parse_rate("1000").unwrap(), 1000);
+        assert_eq!(parse_rate("500k").unwrap(), 500 * 1024);
+        assert_eq!(parse_rate("1.5M").unwrap(), 1536 * 1024);
+        assert_eq!(parse_rate("2G").unwrap(), 2 * 1024 * 1024 * 1024);
+        assert!(parse_rate("").is_err());
+        assert!(parse_rate("0").is_err());
+        assert!(parse_rate("fast").is_err());
+        assert!(parse_rate("-1M").is_err());
+    }
+}
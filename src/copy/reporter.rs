@@ -0,0 +1,349 @@
+use serde::Serialize;
+
+use super::copy_processor::CopyOptions;
+use super::plan::TransferPlan;
+use super::progress::{format_bytes, Progress};
+
+/// Format of the messages reported while copying
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human-readable text
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = Box<dyn std::error::Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!("invalid output format: {}", s).into()),
+        }
+    }
+}
+
+/// Numbers of the files and folders processed by a copy
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct Summary {
+    pub files_copied: usize,
+    pub bytes_copied: u64,
+    pub files_skipped: usize,
+    pub folders_created: usize,
+    pub deleted: usize,
+    pub sources_removed: usize,
+    pub not_removed: usize,
+}
+
+/// Something happened while copying.
+/// In the JSON output, the name of the variant is written as `"event"`.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// Informational message
+    Message {
+        text: &'a str,
+    },
+    /// Copying to the destination is started
+    Destination {
+        destination: &'a str,
+    },
+    /// An action of the plan (dry run or saving the plan)
+    Planned {
+        action: &'a str,
+        destination: &'a str,
+        reason: &'a str,
+    },
+    /// End of the plan
+    PlanSummary {
+        actions: usize,
+        transfer_size: u64,
+    },
+    FolderCreated {
+        name: &'a str,
+    },
+    FileCopied {
+        name: &'a str,
+        size: u64,
+        on_device: bool,
+    },
+    /// The existing file is replaced by the file being copied
+    Replacing {
+        name: &'a str,
+        reason: &'a str,
+    },
+    Skipped {
+        name: &'a str,
+        reason: &'a str,
+    },
+    /// The copied file was found complete after the interruption
+    Verified {
+        name: &'a str,
+    },
+    ChecksumMismatch {
+        name: &'a str,
+        attempt: u32,
+        max_attempts: u32,
+    },
+    /// The file copied on the device did not match the source, and it is transferred instead
+    OnDeviceMismatch {
+        name: &'a str,
+    },
+    Deleted {
+        name: &'a str,
+        is_folder: bool,
+    },
+    SourceRemoved {
+        name: &'a str,
+    },
+    NotRemoved {
+        path: &'a str,
+        reason: &'a str,
+    },
+    /// The copy failed (destination is None if the whole command failed)
+    Error {
+        destination: Option<&'a str>,
+        message: &'a str,
+    },
+    Summary(&'a Summary),
+}
+
+/// Reports the events of the copy.
+pub trait Reporter {
+    fn report(&self, event: &Event);
+
+    /// Returns the progress to show while the files are transferred, if any.
+    fn progress(&self) -> Option<Progress> {
+        None
+    }
+}
+
+pub fn create_reporter(options: &CopyOptions) -> Box<dyn Reporter> {
+    match options.output {
+        OutputFormat::Text => Box::new(TextReporter::new(options.verbose)),
+        OutputFormat::Json => Box::new(JsonReporter),
+    }
+}
+
+pub fn report_plan(plan: &TransferPlan, reporter: &dyn Reporter) {
+    for action in plan.actions.iter() {
+        reporter.report(&Event::Planned {
+            action: action.action.name(),
+            destination: &action.destination,
+            reason: &action.reason,
+        });
+    }
+    reporter.report(&Event::PlanSummary {
+        actions: plan.actions.len(),
+        transfer_size: plan.transfer_size(),
+    });
+}
+
+/// Prints the events as text.
+pub struct TextReporter {
+    /// Report why each file is skipped or replaced
+    verbose: bool,
+}
+
+impl TextReporter {
+    pub fn new(verbose: bool) -> TextReporter {
+        TextReporter { verbose }
+    }
+
+    fn text_of(&self, event: &Event) -> Option<String> {
+        match *event {
+            Event::Message { text } => Some(text.to_string()),
+            Event::Destination { destination } => Some(format!("destination: \"{}\"", destination)),
+            Event::Planned {
+                action,
+                destination,
+                reason,
+            } => Some(format!("{:<13} \"{}\" ({})", action, destination, reason)),
+            Event::PlanSummary {
+                actions,
+                transfer_size,
+            } => Some(format!(
+                "{} actions, {} bytes to transfer.",
+                actions, transfer_size
+            )),
+            Event::FolderCreated { name } => Some(format!("create folder \"{}\"", name)),
+            // the transferred files are shown by the progress
+            Event::FileCopied {
+                name,
+                on_device: true,
+                ..
+            } => Some(format!("copying \"{}\" on the device", name)),
+            Event::FileCopied { .. } => None,
+            Event::Replacing { name, reason } if self.verbose => {
+                Some(format!("replace \"{}\" ({})", name, reason))
+            }
+            Event::Skipped { name, reason } if self.verbose => {
+                Some(format!("skip \"{}\" ({})", name, reason))
+            }
+            Event::Replacing { .. } | Event::Skipped { .. } => None,
+            Event::Verified { name } => Some(format!("verified \"{}\"", name)),
+            Event::ChecksumMismatch {
+                name,
+                attempt,
+                max_attempts,
+            } => {
+                if attempt < max_attempts {
+                    Some(format!("checksum mismatch \"{}\" (retrying)", name))
+                } else {
+                    Some(format!("checksum mismatch \"{}\" (gave up)", name))
+                }
+            }
+            Event::OnDeviceMismatch { name } => Some(format!(
+                "checksum mismatch \"{}\" on the device (transferring)",
+                name
+            )),
+            Event::Deleted {
+                name,
+                is_folder: true,
+            } => Some(format!("delete folder \"{}\"", name)),
+            Event::Deleted { name, .. } => Some(format!("delete file \"{}\"", name)),
+            Event::SourceRemoved { name } => Some(format!("remove source \"{}\"", name)),
+            Event::NotRemoved { path, reason } => {
+                Some(format!("not removed \"{}\" ({})", path, reason))
+            }
+            Event::Error {
+                destination: Some(destination),
+                message,
+            } => Some(format!(
+                "failed to copy to \"{}\": {}",
+                destination, message
+            )),
+            // the error of the command is logged by main()
+            Event::Error { .. } => None,
+            Event::Summary(summary) => {
+                let mut text = format!(
+                    "{} files copied ({}), {} skipped, {} folders created, {} deleted",
+                    summary.files_copied,
+                    format_bytes(summary.bytes_copied),
+                    summary.files_skipped,
+                    summary.folders_created,
+                    summary.deleted
+                );
+                if summary.sources_removed > 0 || summary.not_removed > 0 {
+                    text += &format!(
+                        ", {} sources removed, {} not removed",
+                        summary.sources_removed, summary.not_removed
+                    );
+                }
+                text += ".";
+                Some(text)
+            }
+        }
+    }
+}
+
+impl Reporter for TextReporter {
+    fn report(&self, event: &Event) {
+        if let Some(text) = self.text_of(event) {
+            println!("{}", text);
+        }
+    }
+
+    fn progress(&self) -> Option<Progress> {
+        Some(Progress::to_stdout())
+    }
+}
+
+/// Prints each event as a JSON object on a line.
+pub struct JsonReporter;
+
+impl Reporter for JsonReporter {
+    fn report(&self, event: &Event) {
+        match serde_json::to_string(event) {
+            Ok(json) => println!("{}", json),
+            Err(err) => log::error!("cannot serialize {:?}: {}", event, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json() {
+        let json = |event: &Event| serde_json::to_string(event).unwrap();
+        assert_eq!(
+            json(&Event::FileCopied {
+                name: "a.mp3",
+                size: 4,
+                on_device: false
+            }),
+            r#"{"event":"file_copied","name":"a.mp3","size":4,"on_device":false}"#
+        );
+        assert_eq!(
+            json(&Event::Skipped {
+                name: "b.mp3",
+                reason: "same size"
+            }),
+            r#"{"event":"skipped","name":"b.mp3","reason":"same size"}"#
+        );
+        assert_eq!(
+            json(&Event::Error {
+                destination: None,
+                message: "failed"
+            }),
+            r#"{"event":"error","destination":null,"message":"failed"}"#
+        );
+        let summary = Summary {
+            files_copied: 2,
+            bytes_copied: 10,
+            ..Default::default()
+        };
+        assert_eq!(
+            json(&Event::Summary(&summary)),
+            concat!(
+                r#"{"event":"summary","files_copied":2,"bytes_copied":10,"files_skipped":0,"#,
+                r#""folders_created":0,"deleted":0,"sources_removed":0,"not_removed":0}"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_text() {
+        let reporter = TextReporter::new(false);
+        let skipped = Event::Skipped {
+            name: "b.mp3",
+            reason: "same size",
+        };
+        assert_eq!(reporter.text_of(&skipped), None);
+        assert_eq!(
+            TextReporter::new(true).text_of(&skipped).unwrap(),
+            "skip \"b.mp3\" (same size)"
+        );
+        assert_eq!(
+            reporter
+                .text_of(&Event::Deleted {
+                    name: "Album",
+                    is_folder: true
+                })
+                .unwrap(),
+            "delete folder \"Album\""
+        );
+        let summary = Summary {
+            files_copied: 2,
+            bytes_copied: 2048,
+            files_skipped: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            reporter.text_of(&Event::Summary(&summary)).unwrap(),
+            "2 files copied (2.0 KiB), 1 skipped, 0 folders created, 0 deleted."
+        );
+    }
+
+    #[test]
+    fn test_parse_output_format() {
+        assert_eq!("json".parse::<OutputFormat>().unwrap(), OutputFormat::Json);
+        assert_eq!("text".parse::<OutputFormat>().unwrap(), OutputFormat::Text);
+        assert!("xml".parse::<OutputFormat>().is_err());
+    }
+}
@@ -0,0 +1,237 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::backend::content_object::ContentObject;
+
+use super::executor::PlanSource;
+use super::file_info::FileInfo;
+use super::file_reader::FileReader;
+use super::local_file_reader::LocalFileReader;
+use super::pipeline::ReadAheadFileReader;
+
+/// Number of the caches created in this process, to name the cache folders
+static CACHE_COUNT: AtomicU32 = AtomicU32::new(0);
+
+/// Source path -> cached file
+type CachedFiles = Rc<RefCell<HashMap<String, PathBuf>>>;
+
+/// Keeps the files read from the source in a temporary folder,
+/// so that the source is read only once when it is copied to several destinations.
+pub struct CachedPlanSource<'a> {
+    source: &'a mut dyn PlanSource,
+    folder: PathBuf,
+    cached: CachedFiles,
+    next_id: u32,
+}
+
+impl<'a> CachedPlanSource<'a> {
+    pub fn new(
+        source: &'a mut dyn PlanSource,
+    ) -> Result<CachedPlanSource<'a>, Box<dyn std::error::Error>> {
+        let folder = std::env::temp_dir().join(format!(
+            "mtpcopy-{}-{}.cache",
+            std::process::id(),
+            CACHE_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&folder)?;
+        Ok(CachedPlanSource {
+            source,
+            folder,
+            cached: Rc::new(RefCell::new(HashMap::new())),
+            next_id: 0,
+        })
+    }
+}
+
+impl<'a> PlanSource for CachedPlanSource<'a> {
+    fn get_file_info(
+        &mut self,
+        path: &str,
+    ) -> Result<Option<FileInfo>, Box<dyn std::error::Error>> {
+        self.source.get_file_info(path)
+    }
+
+    fn open_file(&mut self, path: &str) -> Result<Box<dyn FileReader>, Box<dyn std::error::Error>> {
+        if let Some(cache_path) = self.cached.borrow().get(path) {
+            let file = File::open(cache_path)?;
+            return Ok(Box::new(ReadAheadFileReader::new(LocalFileReader::new(
+                file,
+            ))));
+        }
+
+        let reader = self.source.open_file(path)?;
+        self.next_id += 1;
+        let cache_path = self.folder.join(format!("{}.bin", self.next_id));
+        // the file is read without the cache if the cache cannot be written
+        let file = File::create(&cache_path).ok();
+        Ok(Box::new(CachingFileReader {
+            reader,
+            file,
+            cache_path,
+            source_path: path.to_string(),
+            cached: self.cached.clone(),
+        }))
+    }
+
+    fn device_object(
+        &mut self,
+        path: &str,
+    ) -> Result<Option<ContentObject>, Box<dyn std::error::Error>> {
+        self.source.device_object(path)
+    }
+
+    fn delete(&mut self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.source.delete(path)
+    }
+
+    fn is_empty_folder(&mut self, path: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        self.source.is_empty_folder(path)
+    }
+}
+
+impl<'a> Drop for CachedPlanSource<'a> {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.folder);
+    }
+}
+
+/// Writes the bytes to the cache file while they are read.
+/// The cache file is used after the whole file was read.
+struct CachingFileReader {
+    reader: Box<dyn FileReader>,
+    file: Option<File>,
+    cache_path: PathBuf,
+    source_path: String,
+    cached: CachedFiles,
+}
+
+impl FileReader for CachingFileReader {
+    fn get_optimized_buffer_size(&self) -> u32 {
+        self.reader.get_optimized_buffer_size()
+    }
+
+    fn next(&mut self, max_size: u32) -> Result<Option<&[u8]>, Box<dyn std::error::Error>> {
+        match self.reader.next(max_size)? {
+            Some(bytes) => {
+                if let Some(file) = self.file.as_mut() {
+                    if file.write_all(bytes).is_err() {
+                        self.file = None;
+                        let _ = std::fs::remove_file(&self.cache_path);
+                    }
+                }
+                Ok(Some(bytes))
+            }
+            None => {
+                if self.file.take().is_some() {
+                    self.cached
+                        .borrow_mut()
+                        .insert(self.source_path.clone(), self.cache_path.clone());
+                }
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl Drop for CachingFileReader {
+    fn drop(&mut self) {
+        // the file was not read to the end
+        if self.file.take().is_some() {
+            let _ = std::fs::remove_file(&self.cache_path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Counts how many times the files are opened
+    struct CountingSource {
+        opened: u32,
+    }
+
+    struct BytesReader {
+        data: Vec<u8>,
+        offset: usize,
+    }
+
+    impl FileReader for BytesReader {
+        fn get_optimized_buffer_size(&self) -> u32 {
+            3
+        }
+
+        fn next(&mut self, max_size: u32) -> Result<Option<&[u8]>, Box<dyn std::error::Error>> {
+            if self.offset >= self.data.len() {
+                return Ok(None);
+            }
+            let end = std::cmp::min(self.data.len(), self.offset + max_size as usize);
+            let chunk = &self.data[self.offset..end];
+            self.offset = end;
+            Ok(Some(chunk))
+        }
+    }
+
+    impl PlanSource for CountingSource {
+        fn get_file_info(
+            &mut self,
+            _path: &str,
+        ) -> Result<Option<FileInfo>, Box<dyn std::error::Error>> {
+            Ok(None)
+        }
+
+        fn open_file(
+            &mut self,
+            path: &str,
+        ) -> Result<Box<dyn FileReader>, Box<dyn std::error::Error>> {
+            self.opened += 1;
+            Ok(Box::new(BytesReader {
+                data: path.as_bytes().to_vec(),
+                offset: 0,
+            }))
+        }
+    }
+
+    fn read_all(reader: &mut dyn FileReader) -> Vec<u8> {
+        let mut data = Vec::<u8>::new();
+        while let Some(bytes) = reader.next(reader.get_optimized_buffer_size()).unwrap() {
+            data.extend_from_slice(bytes);
+        }
+        data
+    }
+
+    #[test]
+    fn test_cached_plan_source() -> Result<(), Box<dyn std::error::Error>> {
+        let mut source = CountingSource { opened: 0 };
+        let folder;
+        {
+            let mut cached_source = CachedPlanSource::new(&mut source)?;
+            folder = cached_source.folder.clone();
+
+            // not cached until the file was read to the end
+            let mut reader = cached_source.open_file("a:b:\\abcdefg")?;
+            reader.next(3)?;
+            drop(reader);
+            let mut reader = cached_source.open_file("a:b:\\abcdefg")?;
+            assert_eq!(read_all(reader.as_mut()), b"a:b:\\abcdefg");
+            drop(reader);
+
+            for _ in 0..2 {
+                let mut reader = cached_source.open_file("a:b:\\abcdefg")?;
+                assert_eq!(read_all(reader.as_mut()), b"a:b:\\abcdefg");
+            }
+            let mut reader = cached_source.open_file("a:b:\\hij")?;
+            assert_eq!(read_all(reader.as_mut()), b"a:b:\\hij");
+            assert!(folder.exists());
+        }
+        assert_eq!(source.opened, 3);
+        // the cache is removed
+        assert!(!folder.exists());
+        Ok(())
+    }
+}
@@ -1,18 +1,17 @@
+use crate::backend::content_object::ContentObjectInfo;
+use crate::backend::device::{ContentObjectIterator, Device};
+use crate::backend::manager::{DeviceInfo, Manager};
 use crate::path::SEPARATORS;
-use crate::wpd::device::ContentObjectInfo;
-use crate::wpd::device::{ContentObjectIterator, Device};
-use crate::wpd::manager::DeviceInfo;
-use crate::wpd::manager::Manager;
 
 use crate::glob::filename::FileNamePattern;
 use crate::glob::path::create_path_pattern_matcher;
 use crate::glob::path::PathMatcher;
 use crate::glob::path::PathMatchingState;
 
-/// Returns WPD devices whose name is matching the specified pattern, or
+/// Returns devices whose name is matching the specified pattern, or
 /// returns all devices if the pattern was None.
 pub fn device_find_devices(
-    manager: &Manager,
+    manager: &dyn Manager,
     pattern: Option<&str>,
 ) -> Result<Vec<DeviceInfo>, Box<dyn std::error::Error>> {
     log::trace!("device_find_devices pattern={:?}", &pattern);
@@ -35,7 +34,7 @@ pub fn device_find_devices(
 /// Returns storage objects whose name is matching the specified pattern, or
 /// returns all storage objects if the pattern was None.
 pub fn device_find_storage_objects(
-    device: &Device,
+    device: &dyn Device,
     pattern: Option<&str>,
 ) -> Result<Vec<ContentObjectInfo>, Box<dyn std::error::Error>> {
     log::trace!("device_find_storage_objects pattern={:?}", &pattern);
@@ -70,13 +69,13 @@ pub fn device_find_storage_objects(
 }
 
 fn device_find_device_object(
-    device: &Device,
+    device: &dyn Device,
 ) -> Result<Option<ContentObjectInfo>, Box<dyn std::error::Error>> {
     let root = device.get_root_object();
     match device.get_object_iterator(&root) {
         Err(err) => {
             log::debug!("{}", err);
-            log::warn!("failed to get the device object: {}", &device.name);
+            log::warn!("failed to get the device object: {}", device.name());
         }
         Ok(mut iter) => {
             while let Some(obj) = iter.next()? {
@@ -95,7 +94,7 @@ fn device_find_device_object(
 /// Returns the first matched object which is matching the specified path.
 /// Path can be the glob pattern.
 pub fn device_find_file_or_folder(
-    device: &Device,
+    device: &dyn Device,
     device_info: &DeviceInfo,
     storage_object: &ContentObjectInfo,
     path: &str,
@@ -119,7 +118,7 @@ pub fn device_find_file_or_folder(
 /// and calls given callback for each file or folders.
 /// Path can be the glob pattern.
 pub fn device_iterate_file_or_folder<F>(
-    device: &Device,
+    device: &dyn Device,
     device_info: &DeviceInfo,
     storage_object: &ContentObjectInfo,
     path: &str,
@@ -186,8 +185,8 @@ where
 }
 
 fn device_iterate_file_or_folder_core<F>(
-    device: &Device,
-    mut content_object_iterator: ContentObjectIterator,
+    device: &dyn Device,
+    mut content_object_iterator: Box<dyn ContentObjectIterator>,
     path_matcher: &PathMatcher,
     base_path: String,
     recursive: bool,
@@ -277,7 +276,7 @@ where
     Ok(continued)
 }
 
-fn join_path(base_path: &str, sub_path: &str) -> String {
+pub fn join_path(base_path: &str, sub_path: &str) -> String {
     let mut s = String::from(base_path);
     if !s.ends_with(SEPARATORS) {
         s.push('\\');
@@ -285,3 +284,145 @@ fn join_path(base_path: &str, sub_path: &str) -> String {
     s.push_str(sub_path);
     s
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::manager::MemoryManager;
+
+    fn setup() -> MemoryManager {
+        let mut manager = MemoryManager::new();
+        let phone = manager.add_device("My Phone");
+        let storage = phone.add_storage("SD Card");
+        let pictures = phone.add_folder(&storage, "Pictures");
+        let folder_2020 = phone.add_folder(&pictures, "2020");
+        phone.add_file(&folder_2020, "a.jpg", b"a");
+        phone.add_file(&folder_2020, "b.png", b"b");
+        let folder_2021 = phone.add_folder(&pictures, "2021");
+        let april = phone.add_folder(&folder_2021, "April");
+        phone.add_file(&april, "c.jpg", b"c");
+        phone.add_storage("Internal");
+        manager.add_device("My Camera");
+        manager
+    }
+
+    fn collect_paths(
+        manager: &MemoryManager,
+        path: &str,
+        recursive: bool,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let device_info = device_find_devices(manager, Some("My Phone"))?
+            .pop()
+            .unwrap();
+        let device = manager.open_device(&device_info)?;
+        let storage_object = device_find_storage_objects(device.as_ref(), Some("SD*"))?
+            .pop()
+            .unwrap();
+        let mut paths = Vec::<String>::new();
+        device_iterate_file_or_folder(
+            device.as_ref(),
+            &device_info,
+            &storage_object,
+            path,
+            recursive,
+            |_, path| {
+                paths.push(path.to_string());
+                Ok(true)
+            },
+        )?;
+        Ok(paths)
+    }
+
+    #[test]
+    fn test_device_find_devices() -> Result<(), Box<dyn std::error::Error>> {
+        let manager = setup();
+        assert_eq!(device_find_devices(&manager, None)?.len(), 2);
+        assert_eq!(device_find_devices(&manager, Some("My *"))?.len(), 2);
+        let device_info_vec = device_find_devices(&manager, Some("*Phone"))?;
+        assert_eq!(device_info_vec.len(), 1);
+        assert_eq!(device_info_vec[0].name, "My Phone");
+        assert!(device_find_devices(&manager, Some("Tablet"))?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_device_find_storage_objects() -> Result<(), Box<dyn std::error::Error>> {
+        let manager = setup();
+        let device_info = device_find_devices(&manager, Some("My Phone"))?
+            .pop()
+            .unwrap();
+        let device = manager.open_device(&device_info)?;
+        let names: Vec<String> = device_find_storage_objects(device.as_ref(), None)?
+            .into_iter()
+            .map(|info| info.name)
+            .collect();
+        assert_eq!(names, vec!["SD Card", "Internal"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_device_iterate_file_or_folder_with_pattern() -> Result<(), Box<dyn std::error::Error>> {
+        let manager = setup();
+        assert_eq!(
+            collect_paths(&manager, "\\Pictures\\**\\*.jpg", false)?,
+            vec![
+                "My Phone:SD Card:\\Pictures\\2020\\a.jpg",
+                "My Phone:SD Card:\\Pictures\\2021\\April\\c.jpg",
+            ]
+        );
+        assert_eq!(
+            collect_paths(&manager, "\\Pictures\\202?", false)?,
+            vec![
+                "My Phone:SD Card:\\Pictures\\2020",
+                "My Phone:SD Card:\\Pictures\\2021",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_device_iterate_file_or_folder_recursively() -> Result<(), Box<dyn std::error::Error>> {
+        let manager = setup();
+        assert_eq!(
+            collect_paths(&manager, "\\Pictures\\2021", true)?,
+            vec![
+                "My Phone:SD Card:\\Pictures\\2021",
+                "My Phone:SD Card:\\Pictures\\2021\\April",
+                "My Phone:SD Card:\\Pictures\\2021\\April\\c.jpg",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_device_find_file_or_folder() -> Result<(), Box<dyn std::error::Error>> {
+        let manager = setup();
+        let device_info = device_find_devices(&manager, Some("My Phone"))?
+            .pop()
+            .unwrap();
+        let device = manager.open_device(&device_info)?;
+        let storage_object = device_find_storage_objects(device.as_ref(), Some("SD Card"))?
+            .pop()
+            .unwrap();
+
+        let (info, path) = device_find_file_or_folder(
+            device.as_ref(),
+            &device_info,
+            &storage_object,
+            "\\Pictures\\2020\\b.png",
+        )?
+        .unwrap();
+        assert!(info.is_file());
+        assert_eq!(info.data_size, 1);
+        assert_eq!(path, "My Phone:SD Card:\\Pictures\\2020\\b.png");
+
+        assert!(device_find_file_or_folder(
+            device.as_ref(),
+            &device_info,
+            &storage_object,
+            "\\Pictures\\2022",
+        )?
+        .is_none());
+        Ok(())
+    }
+}
@@ -95,6 +95,7 @@ impl RootPathMatcher {
 
 /// Other matchers
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum PathMatcher {
     ExactNameMatcher {
         name: String,
@@ -196,6 +197,7 @@ impl PathMatcher {
 }
 
 #[cfg(test)]
+#[allow(clippy::unused_unit)] // expanded from test_case
 mod tests {
     use super::*;
     use test_case::test_case;
@@ -0,0 +1,182 @@
+use std::path::PathBuf;
+
+/// Environment variable that specifies the path of the configuration file.
+pub const CONFIG_FILE_ENV: &str = "MTPCOPY_CONFIG";
+
+/// Environment variable that specifies the configuration inline.
+/// Entries are separated by `;` or newline.
+pub const CONFIG_INLINE_ENV: &str = "MTPCOPY_VIRTUAL_DEVICES";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageConfig {
+    /// Storage name
+    pub name: String,
+    /// Local directory exposed as the storage
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceConfig {
+    /// Device name
+    pub name: String,
+    pub storages: Vec<StorageConfig>,
+}
+
+/// Configuration of the virtual devices backed by local directories.
+///
+/// Each entry maps a storage of a virtual device to a local directory:
+///
+/// ```text
+/// # comment
+/// Fake Phone:Internal=/tmp/phone
+/// Fake Phone:SD Card=/tmp/phone-sd
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VirtualDeviceConfig {
+    pub devices: Vec<DeviceConfig>,
+}
+
+impl VirtualDeviceConfig {
+    pub fn new() -> VirtualDeviceConfig {
+        VirtualDeviceConfig {
+            devices: Vec::new(),
+        }
+    }
+
+    /// Loads the configuration from the configuration file and the environment
+    /// variable. Returns None if neither of them is specified.
+    pub fn load_from_env() -> Result<Option<VirtualDeviceConfig>, Box<dyn std::error::Error>> {
+        let mut config: Option<VirtualDeviceConfig> = None;
+
+        if let Some(config_path) = std::env::var_os(CONFIG_FILE_ENV) {
+            let text = std::fs::read_to_string(&config_path).map_err(|err| {
+                format!(
+                    "failed to read {}: {}",
+                    PathBuf::from(&config_path).to_string_lossy(),
+                    err
+                )
+            })?;
+            config
+                .get_or_insert_with(VirtualDeviceConfig::new)
+                .parse(&text)?;
+        }
+
+        if let Ok(text) = std::env::var(CONFIG_INLINE_ENV) {
+            config
+                .get_or_insert_with(VirtualDeviceConfig::new)
+                .parse(&text)?;
+        }
+
+        Ok(config)
+    }
+
+    /// Parses entries and adds them to this configuration.
+    pub fn parse(&mut self, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+        for line in text.split(['\n', ';']) {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            self.parse_entry(line)
+                .map_err(|err| format!("invalid virtual device entry \"{}\": {}", line, err))?;
+        }
+        Ok(())
+    }
+
+    fn parse_entry(&mut self, entry: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let (name_part, path) = match entry.find('=') {
+            Some(pos) => (&entry[..pos], entry[pos + 1..].trim()),
+            None => return Err("'=' is missing.".into()),
+        };
+        let (device_name, storage_name) = match name_part.find(':') {
+            Some(pos) => (name_part[..pos].trim(), name_part[pos + 1..].trim()),
+            None => return Err("the name must be in the form <device>:<storage>.".into()),
+        };
+        if device_name.is_empty() {
+            return Err("device name is empty.".into());
+        }
+        if storage_name.is_empty() || storage_name.contains(':') {
+            return Err("invalid storage name.".into());
+        }
+        if path.is_empty() {
+            return Err("path is empty.".into());
+        }
+
+        let device = match self.devices.iter_mut().position(|d| d.name == device_name) {
+            Some(index) => &mut self.devices[index],
+            None => {
+                self.devices.push(DeviceConfig {
+                    name: device_name.to_string(),
+                    storages: Vec::new(),
+                });
+                self.devices.last_mut().unwrap()
+            }
+        };
+        if device.storages.iter().any(|s| s.name == storage_name) {
+            return Err("the storage is already defined.".into());
+        }
+        device.storages.push(StorageConfig {
+            name: storage_name.to_string(),
+            path: PathBuf::from(path),
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn storage(name: &str, path: &str) -> StorageConfig {
+        StorageConfig {
+            name: name.to_string(),
+            path: PathBuf::from(path),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let mut config = VirtualDeviceConfig::new();
+        config
+            .parse(
+                "# test devices\n\
+                 Fake Phone:Internal=/tmp/phone\n\
+                 \n\
+                 Fake Camera : SD Card = C:\\camera ; Fake Phone:SD Card=/tmp/phone-sd\r\n",
+            )
+            .unwrap();
+
+        assert_eq!(
+            config.devices,
+            vec![
+                DeviceConfig {
+                    name: "Fake Phone".to_string(),
+                    storages: vec![
+                        storage("Internal", "/tmp/phone"),
+                        storage("SD Card", "/tmp/phone-sd"),
+                    ],
+                },
+                DeviceConfig {
+                    name: "Fake Camera".to_string(),
+                    storages: vec![storage("SD Card", "C:\\camera")],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_entries() {
+        for entry in &[
+            "Fake Phone:Internal",
+            "Fake Phone=/tmp/phone",
+            ":Internal=/tmp/phone",
+            "Fake Phone:=/tmp/phone",
+            "Fake Phone:Internal=",
+            "Fake Phone:Internal:Music=/tmp/phone",
+            "Fake Phone:Internal=/tmp/a;Fake Phone:Internal=/tmp/b",
+        ] {
+            let mut config = VirtualDeviceConfig::new();
+            assert!(config.parse(entry).is_err(), "{}", entry);
+        }
+    }
+}
@@ -0,0 +1,626 @@
+use chrono::NaiveDateTime;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+use crate::backend::content_object::{ContentObject, ContentObjectInfo, ContentObjectKind};
+use crate::backend::device::{ContentObjectIterator, Device};
+use crate::backend::resource::{ResourceReader, ResourceWriter};
+use crate::copy::file_info::FileInfo;
+use crate::copy::local_destination_folder::set_file_time;
+
+use super::config::DeviceConfig;
+
+const ROOT_OBJECT_ID: &str = "";
+const DEVICE_OBJECT_ID: &str = "DEVICE";
+const BUFFER_SIZE: u32 = 32768;
+
+/// A virtual device that exposes local directories as its storages.
+///
+/// Object IDs of the files and folders are in the form `<storage-index>:/<path>`,
+/// where `<path>` is the `/`-separated path from the storage directory.
+pub struct LocalDirDevice {
+    config: DeviceConfig,
+}
+
+enum ObjectLocation {
+    Root,
+    Device,
+    Storage(usize),
+    Entry(PathBuf),
+}
+
+impl LocalDirDevice {
+    pub fn new(config: DeviceConfig) -> LocalDirDevice {
+        LocalDirDevice { config }
+    }
+
+    fn locate(&self, object: &ContentObject) -> Result<ObjectLocation, Box<dyn std::error::Error>> {
+        match object.id.as_str() {
+            ROOT_OBJECT_ID => return Ok(ObjectLocation::Root),
+            DEVICE_OBJECT_ID => return Ok(ObjectLocation::Device),
+            _ => {}
+        }
+        let (index_str, sub_path) = match object.id.find(':') {
+            Some(pos) => (&object.id[..pos], &object.id[pos + 1..]),
+            None => return Err(format!("invalid object id: {}", &object.id).into()),
+        };
+        let storage_index = match index_str.parse::<usize>() {
+            Ok(index) if index < self.config.storages.len() => index,
+            _ => return Err(format!("invalid object id: {}", &object.id).into()),
+        };
+        if sub_path.is_empty() {
+            return Ok(ObjectLocation::Storage(storage_index));
+        }
+        let mut path = self.config.storages[storage_index].path.clone();
+        for name in sub_path.split('/').filter(|s| !s.is_empty()) {
+            path.push(name);
+        }
+        Ok(ObjectLocation::Entry(path))
+    }
+
+    fn folder_path(&self, object: &ContentObject) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        match self.locate(object)? {
+            ObjectLocation::Storage(storage_index) => {
+                Ok(self.config.storages[storage_index].path.clone())
+            }
+            ObjectLocation::Entry(path) if path.is_dir() => Ok(path),
+            _ => Err(format!("not a folder: {}", &object.id).into()),
+        }
+    }
+}
+
+impl LocalDirDevice {
+    /// Returns the path of the file, the path in the folder and the ID in the folder.
+    fn copy_destination(
+        &self,
+        object: &ContentObject,
+        parent: &ContentObject,
+    ) -> Result<(PathBuf, PathBuf, String), Box<dyn std::error::Error>> {
+        let path = match self.locate(object)? {
+            ObjectLocation::Entry(path) if path.is_file() => path,
+            _ => return Err(format!("not a file: {}", &object.id).into()),
+        };
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default()
+            .to_string();
+        let id = child_id(parent, &name)?;
+        let new_path = self.folder_path(parent)?.join(&name);
+        if new_path.exists() {
+            return Err(format!("already exists: {}", name).into());
+        }
+        Ok((path, new_path, id))
+    }
+}
+
+fn child_id(parent: &ContentObject, name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(&['/', '\\'][..]) {
+        return Err(format!("invalid name: {}", name).into());
+    }
+    Ok(format!("{}/{}", &parent.id, name))
+}
+
+impl Device for LocalDirDevice {
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn get_root_object(&self) -> ContentObject {
+        ContentObject::new(ROOT_OBJECT_ID.to_string())
+    }
+
+    fn get_object_iterator(
+        &self,
+        parent: &ContentObject,
+    ) -> Result<Box<dyn ContentObjectIterator>, Box<dyn std::error::Error>> {
+        let mut children: Vec<String> = match self.locate(parent)? {
+            ObjectLocation::Root => vec![DEVICE_OBJECT_ID.to_string()],
+            ObjectLocation::Device => (0..self.config.storages.len())
+                .map(|index| format!("{}:", index))
+                .collect(),
+            ObjectLocation::Storage(_) | ObjectLocation::Entry(_) => {
+                let mut names = Vec::<String>::new();
+                for entry_result in self.folder_path(parent)?.read_dir()? {
+                    let entry = entry_result?;
+                    match entry.file_name().to_str() {
+                        Some(name) => names.push(name.to_string()),
+                        None => log::warn!("unsupported file name: {:?}", entry.file_name()),
+                    }
+                }
+                names.sort();
+                names
+                    .iter()
+                    .map(|name| child_id(parent, name))
+                    .collect::<Result<Vec<String>, _>>()?
+            }
+        };
+        children.reverse(); // for moving item out by pop()
+        Ok(Box::new(LocalDirObjectIterator { children }))
+    }
+
+    fn get_object_info(
+        &self,
+        object: ContentObject,
+    ) -> Result<ContentObjectInfo, Box<dyn std::error::Error>> {
+        let (name, kind) = match self.locate(&object)? {
+            ObjectLocation::Root => (String::new(), ContentObjectKind::FunctionalObject),
+            ObjectLocation::Device => (self.config.name.clone(), ContentObjectKind::Device),
+            ObjectLocation::Storage(storage_index) => (
+                self.config.storages[storage_index].name.clone(),
+                ContentObjectKind::Storage,
+            ),
+            ObjectLocation::Entry(path) => {
+                let name = path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .unwrap_or_default()
+                    .to_string();
+                let metadata = path.metadata()?;
+                let file_info = FileInfo::from_metadata(&metadata, &name)?;
+                return Ok(ContentObjectInfo {
+                    content_object: object,
+                    name,
+                    kind: if file_info.is_folder {
+                        ContentObjectKind::Folder
+                    } else {
+                        ContentObjectKind::File
+                    },
+                    data_size: file_info.data_size,
+                    is_hidden: file_info.is_hidden,
+                    is_system: file_info.is_system,
+                    can_delete: !metadata.permissions().readonly(),
+                    time_created: file_info.time_created,
+                    time_modified: file_info.time_modified,
+                });
+            }
+        };
+        Ok(ContentObjectInfo {
+            content_object: object,
+            name,
+            kind,
+            data_size: 0,
+            is_hidden: false,
+            is_system: false,
+            can_delete: false,
+            time_created: None,
+            time_modified: None,
+        })
+    }
+
+    fn get_resoure(
+        &self,
+        object: &ContentObject,
+    ) -> Result<Box<dyn ResourceReader>, Box<dyn std::error::Error>> {
+        match self.locate(object)? {
+            ObjectLocation::Entry(path) if path.is_file() => {
+                let buf = vec![0; BUFFER_SIZE as usize];
+                Ok(Box::new(LocalDirResourceReader {
+                    file: File::open(&path)?,
+                    buf,
+                }))
+            }
+            _ => Err(format!("not a file: {}", &object.id).into()),
+        }
+    }
+
+    fn create_file(
+        &self,
+        parent: &ContentObject,
+        name: &str,
+        size: u64,
+        created: &Option<NaiveDateTime>,
+        modified: &Option<NaiveDateTime>,
+    ) -> Result<Box<dyn ResourceWriter>, Box<dyn std::error::Error>> {
+        let id = child_id(parent, name)?;
+        let path = self.folder_path(parent)?.join(name);
+        if path.exists() {
+            return Err(format!("already exists: {}", name).into());
+        }
+        let file = File::create(&path)?;
+        Ok(Box::new(LocalDirResourceWriter {
+            id,
+            path,
+            file: Some(file),
+            size,
+            written: 0,
+            created: *created,
+            modified: *modified,
+        }))
+    }
+
+    fn create_folder(
+        &self,
+        parent: &ContentObject,
+        name: &str,
+    ) -> Result<ContentObject, Box<dyn std::error::Error>> {
+        let id = child_id(parent, name)?;
+        std::fs::create_dir(self.folder_path(parent)?.join(name))?;
+        Ok(ContentObject::new(id))
+    }
+
+    fn delete(&self, object: &ContentObject) -> Result<(), Box<dyn std::error::Error>> {
+        match self.locate(object)? {
+            ObjectLocation::Entry(path) => {
+                if path.is_dir() {
+                    std::fs::remove_dir_all(path)?;
+                } else {
+                    std::fs::remove_file(path)?;
+                }
+                Ok(())
+            }
+            _ => Err(format!("cannot delete: {}", &object.id).into()),
+        }
+    }
+
+    fn can_rename(&self, object: &ContentObject) -> bool {
+        matches!(self.locate(object), Ok(ObjectLocation::Entry(_)))
+    }
+
+    fn rename(
+        &self,
+        object: &ContentObject,
+        name: &str,
+    ) -> Result<ContentObject, Box<dyn std::error::Error>> {
+        let path = match self.locate(object)? {
+            ObjectLocation::Entry(path) => path,
+            _ => return Err(format!("cannot rename: {}", &object.id).into()),
+        };
+        let parent_id = &object.id[..object.id.rfind('/').unwrap()];
+        let id = child_id(&ContentObject::new(parent_id.to_string()), name)?;
+        let new_path = path.with_file_name(name);
+        if new_path.exists() {
+            return Err(format!("already exists: {}", name).into());
+        }
+        std::fs::rename(&path, &new_path)?;
+        Ok(ContentObject::new(id))
+    }
+
+    fn can_copy_on_device(&self, object: &ContentObject) -> bool {
+        matches!(self.locate(object), Ok(ObjectLocation::Entry(path)) if path.is_file())
+    }
+
+    fn can_move_on_device(&self, object: &ContentObject) -> bool {
+        self.can_copy_on_device(object)
+    }
+
+    fn copy_object(
+        &self,
+        object: &ContentObject,
+        parent: &ContentObject,
+    ) -> Result<ContentObject, Box<dyn std::error::Error>> {
+        let (path, new_path, id) = self.copy_destination(object, parent)?;
+        std::fs::copy(&path, &new_path)?;
+        // the modified time is kept as the file uploaded to the device
+        let name = new_path.file_name().unwrap().to_string_lossy();
+        let file_info = FileInfo::from_metadata(&path.metadata()?, &name)?;
+        if let Err(err) = set_file_time(&new_path, &None, &file_info.time_modified) {
+            let _ = std::fs::remove_file(&new_path);
+            return Err(err);
+        }
+        Ok(ContentObject::new(id))
+    }
+
+    fn move_object(
+        &self,
+        object: &ContentObject,
+        parent: &ContentObject,
+    ) -> Result<ContentObject, Box<dyn std::error::Error>> {
+        let (path, new_path, id) = self.copy_destination(object, parent)?;
+        std::fs::rename(&path, &new_path)?;
+        Ok(ContentObject::new(id))
+    }
+}
+
+struct LocalDirObjectIterator {
+    children: Vec<String>,
+}
+
+impl ContentObjectIterator for LocalDirObjectIterator {
+    fn next(&mut self) -> Result<Option<ContentObject>, Box<dyn std::error::Error>> {
+        Ok(self.children.pop().map(ContentObject::new))
+    }
+}
+
+struct LocalDirResourceReader {
+    file: File,
+    buf: Vec<u8>,
+}
+
+impl ResourceReader for LocalDirResourceReader {
+    fn get_optimized_buffer_size(&self) -> u32 {
+        BUFFER_SIZE
+    }
+
+    fn next(&mut self, max_size: u32) -> Result<Option<&[u8]>, Box<dyn std::error::Error>> {
+        self.buf.resize(max_size as usize, 0);
+        let len = self.file.read(self.buf.as_mut_slice())?;
+        if len > 0 {
+            Ok(Some(&self.buf[..len]))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Writes a new file.
+/// The file is removed if it was not committed.
+struct LocalDirResourceWriter {
+    id: String,
+    path: PathBuf,
+    file: Option<File>,
+    size: u64,
+    written: u64,
+    created: Option<NaiveDateTime>,
+    modified: Option<NaiveDateTime>,
+}
+
+impl ResourceWriter for LocalDirResourceWriter {
+    fn get_buffer_size(&self) -> u32 {
+        BUFFER_SIZE
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        match self.file.as_mut() {
+            Some(file) => {
+                file.write_all(data)?;
+                self.written += data.len() as u64;
+                Ok(())
+            }
+            None => Err("the object was already committed.".into()),
+        }
+    }
+
+    fn commit(&mut self) -> Result<ContentObject, Box<dyn std::error::Error>> {
+        let file = match self.file.take() {
+            Some(file) => file,
+            None => return Err("the object was already committed.".into()),
+        };
+        let result = finish_file(file, self.size, self.written)
+            .and_then(|_| set_file_time(&self.path, &self.created, &self.modified));
+        match result {
+            Ok(_) => Ok(ContentObject::new(self.id.clone())),
+            Err(err) => {
+                let _ = std::fs::remove_file(&self.path);
+                Err(err)
+            }
+        }
+    }
+}
+
+fn finish_file(file: File, size: u64, written: u64) -> Result<(), Box<dyn std::error::Error>> {
+    if written != size {
+        return Err(format!(
+            "size mismatch: expected {} bytes, but {} bytes were written.",
+            size, written
+        )
+        .into());
+    }
+    file.sync_all()?;
+    Ok(())
+}
+
+impl Drop for LocalDirResourceWriter {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            // not committed
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::localdir::config::StorageConfig;
+    use chrono::NaiveDate;
+    use std::path::Path;
+
+    fn create_device(dir: &Path) -> LocalDirDevice {
+        std::fs::create_dir_all(dir.join("internal").join("Music").join("Album")).unwrap();
+        std::fs::write(dir.join("internal").join("Music").join("a.mp3"), b"aaaa").unwrap();
+        std::fs::write(
+            dir.join("internal")
+                .join("Music")
+                .join("Album")
+                .join("b.mp3"),
+            b"bbbbbb",
+        )
+        .unwrap();
+        std::fs::create_dir_all(dir.join("sd")).unwrap();
+        LocalDirDevice::new(DeviceConfig {
+            name: "Fake Phone".to_string(),
+            storages: vec![
+                StorageConfig {
+                    name: "Internal".to_string(),
+                    path: dir.join("internal"),
+                },
+                StorageConfig {
+                    name: "SD Card".to_string(),
+                    path: dir.join("sd"),
+                },
+            ],
+        })
+    }
+
+    fn children(device: &LocalDirDevice, parent: &ContentObject) -> Vec<ContentObjectInfo> {
+        let mut iter = device.get_object_iterator(parent).unwrap();
+        let mut infos = Vec::<ContentObjectInfo>::new();
+        while let Some(object) = iter.next().unwrap() {
+            infos.push(device.get_object_info(object).unwrap());
+        }
+        infos
+    }
+
+    fn find(device: &LocalDirDevice, path: &[&str]) -> ContentObjectInfo {
+        let mut parent = device.get_root_object();
+        let mut info: Option<ContentObjectInfo> = None;
+        for name in [DEVICE_OBJECT_ID].iter().chain(path.iter()) {
+            let child = children(device, &parent)
+                .into_iter()
+                .find(|info| info.name == *name || info.content_object.id == *name)
+                .unwrap();
+            parent = child.content_object.clone();
+            info = Some(child);
+        }
+        info.unwrap()
+    }
+
+    #[test]
+    fn test_object_hierarchy() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let device = create_device(tempdir.path());
+
+        let device_objects = children(&device, &device.get_root_object());
+        assert_eq!(device_objects.len(), 1);
+        assert!(device_objects[0].is_device());
+        assert_eq!(device_objects[0].name, "Fake Phone");
+
+        let storages = children(&device, &device_objects[0].content_object);
+        let storage_names: Vec<&str> = storages.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(storage_names, vec!["Internal", "SD Card"]);
+        assert!(storages.iter().all(|s| s.is_storage()));
+
+        let music = find(&device, &["Internal", "Music"]);
+        assert!(music.is_folder());
+        let entries = children(&device, &music.content_object);
+        let entry_names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(entry_names, vec!["Album", "a.mp3"]);
+        assert!(entries[0].is_folder());
+        assert!(entries[1].is_file());
+        assert_eq!(entries[1].data_size, 4);
+        assert!(entries[1].time_modified.is_some());
+
+        let file_b = find(&device, &["Internal", "Music", "Album", "b.mp3"]);
+        let mut reader = device.get_resoure(&file_b.content_object).unwrap();
+        let mut data = Vec::<u8>::new();
+        while let Some(bytes) = reader.next(4).unwrap() {
+            data.extend_from_slice(bytes);
+        }
+        assert_eq!(data, b"bbbbbb");
+    }
+
+    #[test]
+    fn test_create_and_delete() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let device = create_device(tempdir.path());
+        let sd_card = find(&device, &["SD Card"]);
+
+        let folder = device
+            .create_folder(&sd_card.content_object, "Data")
+            .unwrap();
+        let mut writer = device
+            .create_file(&folder, "c.txt", 3, &None, &None)
+            .unwrap();
+        writer.write(b"ccc").unwrap();
+        let file = writer.commit().unwrap();
+        assert_eq!(device.get_object_info(file).unwrap().name, "c.txt");
+        assert_eq!(
+            std::fs::read(tempdir.path().join("sd").join("Data").join("c.txt")).unwrap(),
+            b"ccc"
+        );
+
+        device.delete(&folder).unwrap();
+        assert!(!tempdir.path().join("sd").join("Data").exists());
+
+        assert!(device.delete(&sd_card.content_object).is_err());
+        assert!(device.create_folder(&sd_card.content_object, "..").is_err());
+    }
+
+    #[test]
+    fn test_create_file_with_time() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let device = create_device(tempdir.path());
+        let sd_card = find(&device, &["SD Card"]);
+        let time = Some(NaiveDate::from_ymd(2021, 4, 1).and_hms(12, 34, 56));
+
+        let mut writer = device
+            .create_file(&sd_card.content_object, "d.txt", 1, &None, &time)
+            .unwrap();
+        writer.write(b"d").unwrap();
+        let file = writer.commit().unwrap();
+
+        assert_eq!(device.get_object_info(file).unwrap().time_modified, time);
+    }
+
+    #[test]
+    fn test_uncommitted_file_is_removed() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let device = create_device(tempdir.path());
+        let sd_card = find(&device, &["SD Card"]);
+        let path = tempdir.path().join("sd").join("e.txt");
+
+        let mut writer = device
+            .create_file(&sd_card.content_object, "e.txt", 10, &None, &None)
+            .unwrap();
+        writer.write(b"eee").unwrap();
+        assert!(writer.commit().is_err());
+        assert!(!path.exists());
+
+        let mut writer = device
+            .create_file(&sd_card.content_object, "e.txt", 3, &None, &None)
+            .unwrap();
+        writer.write(b"eee").unwrap();
+        drop(writer);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_rename() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let device = create_device(tempdir.path());
+        let file_a = find(&device, &["Internal", "Music", "a.mp3"]);
+        let storage = find(&device, &["Internal"]);
+        assert!(device.can_rename(&file_a.content_object));
+        assert!(!device.can_rename(&storage.content_object));
+
+        let renamed = device.rename(&file_a.content_object, "c.mp3").unwrap();
+        assert_eq!(device.get_object_info(renamed).unwrap().name, "c.mp3");
+        let music = tempdir.path().join("internal").join("Music");
+        assert!(!music.join("a.mp3").exists());
+        assert_eq!(std::fs::read(music.join("c.mp3")).unwrap(), b"aaaa");
+
+        let renamed = find(&device, &["Internal", "Music", "c.mp3"]);
+        assert!(device.rename(&renamed.content_object, "Album").is_err());
+        assert!(device.rename(&renamed.content_object, "x/y").is_err());
+    }
+
+    #[test]
+    fn test_copy_and_move() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let device = create_device(tempdir.path());
+        let file_a = find(&device, &["Internal", "Music", "a.mp3"]);
+        let album = find(&device, &["Internal", "Music", "Album"]);
+        let sd_card = find(&device, &["SD Card"]);
+        assert!(device.can_copy_on_device(&file_a.content_object));
+        assert!(device.can_move_on_device(&file_a.content_object));
+        assert!(!device.can_copy_on_device(&album.content_object));
+
+        let copied = device
+            .copy_object(&file_a.content_object, &sd_card.content_object)
+            .unwrap();
+        let copied = device.get_object_info(copied).unwrap();
+        assert_eq!(copied.name, "a.mp3");
+        assert_eq!(copied.time_modified, file_a.time_modified);
+        assert_eq!(
+            std::fs::read(tempdir.path().join("sd").join("a.mp3")).unwrap(),
+            b"aaaa"
+        );
+        // the name is already used
+        assert!(device
+            .copy_object(&file_a.content_object, &sd_card.content_object)
+            .is_err());
+
+        let moved = device
+            .move_object(&file_a.content_object, &album.content_object)
+            .unwrap();
+        assert_eq!(device.get_object_info(moved).unwrap().name, "a.mp3");
+        let music = tempdir.path().join("internal").join("Music");
+        assert!(!music.join("a.mp3").exists());
+        assert_eq!(
+            std::fs::read(music.join("Album").join("a.mp3")).unwrap(),
+            b"aaaa"
+        );
+    }
+}
@@ -0,0 +1,73 @@
+use crate::backend::device::Device;
+use crate::backend::manager::{DeviceInfo, DeviceInfoIterator, Manager};
+
+use super::config::VirtualDeviceConfig;
+use super::device::LocalDirDevice;
+
+/// A manager of the virtual devices backed by local directories.
+pub struct LocalDirManager {
+    config: VirtualDeviceConfig,
+}
+
+impl LocalDirManager {
+    pub fn new(config: VirtualDeviceConfig) -> LocalDirManager {
+        LocalDirManager { config }
+    }
+}
+
+impl Manager for LocalDirManager {
+    fn get_device_iterator(
+        &self,
+    ) -> Result<Box<dyn DeviceInfoIterator + '_>, Box<dyn std::error::Error>> {
+        let mut device_infos: Vec<DeviceInfo> = self
+            .config
+            .devices
+            .iter()
+            .enumerate()
+            .map(|(index, device)| DeviceInfo {
+                id: index.to_string(),
+                name: device.name.clone(),
+            })
+            .collect();
+        device_infos.reverse(); // for moving item out by pop()
+        Ok(Box::new(LocalDirDeviceInfoIterator { device_infos }))
+    }
+
+    fn open_device(
+        &self,
+        info: &DeviceInfo,
+    ) -> Result<Box<dyn Device>, Box<dyn std::error::Error>> {
+        match info
+            .id
+            .parse::<usize>()
+            .ok()
+            .and_then(|i| self.config.devices.get(i))
+        {
+            Some(device_config) => {
+                for storage in device_config.storages.iter() {
+                    if !storage.path.is_dir() {
+                        return Err(format!(
+                            "\"{}:{}:\" is not a directory: {}",
+                            &device_config.name,
+                            &storage.name,
+                            storage.path.to_string_lossy()
+                        )
+                        .into());
+                    }
+                }
+                Ok(Box::new(LocalDirDevice::new(device_config.clone())))
+            }
+            None => Err(format!("no such device: {}", &info.name).into()),
+        }
+    }
+}
+
+struct LocalDirDeviceInfoIterator {
+    device_infos: Vec<DeviceInfo>,
+}
+
+impl DeviceInfoIterator for LocalDirDeviceInfoIterator {
+    fn next(&mut self) -> Result<Option<DeviceInfo>, Box<dyn std::error::Error>> {
+        Ok(self.device_infos.pop())
+    }
+}
@@ -1,20 +1,43 @@
+mod backend;
+mod command_apply;
 mod command_copy;
 mod command_list_files;
 mod command_list_storages;
+mod command_move;
+mod command_serve;
+mod command_watch;
 mod copy;
 mod finders;
 mod glob;
+mod localdir;
+mod memory;
+mod mtp;
 mod path;
+#[cfg(windows)]
 mod wpd;
 
 use std::fmt::Write;
 
+use backend::composite::CompositeManager;
+use command_serve::ServeEndpoint;
+use copy::compare::CompareMode;
+use copy::copy_processor::CopyOptions;
+use copy::reporter::{create_reporter, Event, OutputFormat};
+use localdir::config::VirtualDeviceConfig;
+use localdir::manager::LocalDirManager;
+use memory::manager::MemoryManager;
+use mtp::manager::PtpIpManager;
+
 #[derive(Debug, Eq, PartialEq, Clone, Copy)]
 enum Command {
     None,
     ListStorages,
     ListFiles,
     Copy,
+    Move,
+    Apply,
+    Serve,
+    Watch,
 }
 
 #[derive(Debug)]
@@ -27,46 +50,154 @@ pub struct Paths {
 struct Args {
     command: Command,
     paths: Option<Paths>,
+    /// Destination paths of "copy" command
+    destinations: Vec<String>,
     recursive: bool,
     mirror: bool,
+    dry_run: bool,
+    plan_file: Option<String>,
+    resume: bool,
+    journal_file: Option<String>,
+    verify: bool,
+    compare: CompareMode,
+    time_tolerance: u32,
+    bwlimit: Option<u64>,
+    output: OutputFormat,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    preserve_structure: bool,
     verbose: u32,
+    device_name: String,
+    endpoint: Option<ServeEndpoint>,
+    once: bool,
 }
 
 fn main() {
     pretty_env_logger::init();
     let result = run();
     if let Err(err) = result {
+        #[cfg(windows)]
         if let Some(winerr) = err.downcast_ref::<windows::Error>() {
             let hresult = winerr.code();
             log::error!("{} (HRESULT=0x{:08x})", err, hresult.0);
-        } else {
-            log::error!("{}", err);
+            std::process::exit(1);
         }
+        log::error!("{}", err);
         std::process::exit(1);
     }
 }
 
 fn run() -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(windows)]
     windows::initialize_mta()?;
 
     let args = parse_args()?;
+    if args.command == Command::None {
+        return Ok(());
+    }
+
+    if args.command == Command::Serve {
+        return command_serve::command_serve(
+            &args.paths.unwrap().src,
+            &args.device_name,
+            &args.endpoint.unwrap(),
+            args.once,
+        );
+    }
+
+    let manager = create_manager()?;
     match args.command {
-        Command::ListStorages => command_list_storages::command_list_storages()?,
+        Command::ListStorages => command_list_storages::command_list_storages(&manager)?,
 
         Command::ListFiles => command_list_files::command_list_files(
+            &manager,
             args.paths.unwrap().src,
             args.recursive,
             args.verbose,
         )?,
 
-        Command::Copy => {
-            command_copy::command_copy(&args.paths.unwrap(), args.recursive, args.mirror)?
+        Command::Copy | Command::Move => {
+            let options = CopyOptions {
+                recursive: args.recursive,
+                mirror: args.mirror,
+                dry_run: args.dry_run,
+                plan_file: args.plan_file,
+                resume: args.resume,
+                journal_file: args.journal_file,
+                verify: args.verify,
+                compare: args.compare,
+                time_tolerance: args.time_tolerance,
+                verbose: args.verbose > 0,
+                move_source: args.command == Command::Move,
+                bwlimit: args.bwlimit,
+                output: args.output,
+                include: args.include,
+                exclude: args.exclude,
+                preserve_structure: args.preserve_structure,
+            };
+            let result = if args.command == Command::Move {
+                command_move::command_move(&manager, &args.paths.unwrap(), &options)
+            } else {
+                command_copy::command_copy_to_all(
+                    &manager,
+                    &args.paths.unwrap().src,
+                    &args.destinations,
+                    &options,
+                )
+            };
+            report_failure(&options, result)?
+        }
+
+        Command::Apply => {
+            let options = CopyOptions {
+                verify: args.verify,
+                verbose: args.verbose > 0,
+                bwlimit: args.bwlimit,
+                output: args.output,
+                ..Default::default()
+            };
+            let result = command_apply::command_apply(&manager, &args.paths.unwrap().src, &options);
+            report_failure(&options, result)?
         }
+
+        Command::Watch => command_watch::command_watch(&manager, args.paths.unwrap().src)?,
         _ => {}
     };
     Ok(())
 }
 
+/// Reports the error of the copy also in the output, so that it can be parsed with the other events.
+fn report_failure(
+    options: &CopyOptions,
+    result: Result<(), Box<dyn std::error::Error>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Err(err) = result.as_ref() {
+        create_reporter(options).report(&Event::Error {
+            destination: None,
+            message: &err.to_string(),
+        });
+    }
+    result
+}
+
+fn create_manager() -> Result<CompositeManager, Box<dyn std::error::Error>> {
+    let mut manager = CompositeManager::new();
+    #[cfg(windows)]
+    manager.add(Box::new(
+        wpd::manager::Manager::get_portable_device_manager()?,
+    ));
+    if let Some(config) = VirtualDeviceConfig::load_from_env()? {
+        manager.add(Box::new(LocalDirManager::new(config)));
+    }
+    if let Some(ptpip_manager) = PtpIpManager::from_env()? {
+        manager.add(Box::new(ptpip_manager));
+    }
+    if let Some(memory_manager) = MemoryManager::from_env()? {
+        manager.add(Box::new(memory_manager));
+    }
+    Ok(manager)
+}
+
 fn parse_args() -> Result<Args, Box<dyn std::error::Error>> {
     let mut options = getopts::Options::new();
     options
@@ -75,14 +206,105 @@ fn parse_args() -> Result<Args, Box<dyn std::error::Error>> {
         .optflag(
             "R",
             "recursive",
-            "(with \"list\" command or \"copy\" command) process recursively",
+            "(with \"list\", \"copy\" or \"move\" command) process recursively",
         )
         .optflag(
             "M",
             "mirror",
-            "(with \"copy\" command) delete files that do not exist in the source. implies -R.",
+            "(with \"copy\" or \"move\" command) delete files that do not exist in the source. implies -R.",
+        )
+        .optflag(
+            "",
+            "dry-run",
+            "(with \"copy\" or \"move\" command) show what would be done without changing anything.",
+        )
+        .optopt(
+            "",
+            "plan",
+            "(with \"copy\" or \"move\" command) save the plan to the file without changing anything. the plan can be performed by \"apply\" command.",
+            "FILE",
+        )
+        .optflag(
+            "",
+            "resume",
+            "(with \"copy\" or \"move\" command) continue the interrupted copy recorded in the journal.",
+        )
+        .optopt(
+            "",
+            "journal",
+            "(with \"copy\" or \"move\" command) file to record the progress of the copy. (default: a file in the temporary folder)",
+            "FILE",
+        )
+        .optflag(
+            "",
+            "verify",
+            "(with \"copy\", \"move\" or \"apply\" command) read back each copied file and compare the checksum. the file is copied again if it differs.",
         )
-        .optflagmulti("v", "verbose", "verbose output.");
+        .optopt(
+            "",
+            "compare",
+            "(with \"copy\" or \"move\" command) how to decide whether the existing file is replaced. size, mtime, size+mtime, checksum, always or never. (default: size+mtime)",
+            "MODE",
+        )
+        .optopt(
+            "",
+            "time-tolerance",
+            "(with \"copy\" or \"move\" command) seconds by which the source file may be newer than the existing file without being copied. (default: 0)",
+            "SECONDS",
+        )
+        .optopt(
+            "",
+            "bwlimit",
+            "(with \"copy\", \"move\" or \"apply\" command) maximum bytes per second to read the source files. the suffixes k, M and G can be used. (default: unlimited)",
+            "RATE",
+        )
+        .optopt(
+            "",
+            "output",
+            "(with \"copy\", \"move\" or \"apply\" command) format of the messages. text, or json to print one JSON object per line. (default: text)",
+            "FORMAT",
+        )
+        .optmulti(
+            "",
+            "include",
+            "(with \"copy\" or \"move\" command) copy only the files matching the pattern. can be repeated.",
+            "PATTERN",
+        )
+        .optmulti(
+            "",
+            "exclude",
+            "(with \"copy\" or \"move\" command) don't copy the files and folders matching the pattern, and don't delete them by --mirror. can be repeated.",
+            "PATTERN",
+        )
+        .optflag(
+            "",
+            "preserve-structure",
+            "(with \"copy\" command) copy the files matching the wildcard of the source with the folders below the first wildcard component.",
+        )
+        .optflagmulti("v", "verbose", "verbose output.")
+        .optopt(
+            "n",
+            "name",
+            "(with \"serve\" command) device name. (default: mtpcopy)",
+            "NAME",
+        )
+        .optopt(
+            "l",
+            "listen",
+            "(with \"serve\" command) address to accept PTP/IP connections. (default: 127.0.0.1:15740)",
+            "ADDRESS",
+        )
+        .optopt(
+            "",
+            "socket",
+            "(with \"serve\" command) path of the Unix domain socket used instead of PTP/IP.",
+            "PATH",
+        )
+        .optflag(
+            "",
+            "once",
+            "(with \"serve\" command) exit when the first connection is closed.",
+        );
 
     let matches = options.parse(std::env::args().skip(1))?;
 
@@ -90,9 +312,42 @@ fn parse_args() -> Result<Args, Box<dyn std::error::Error>> {
     let version = matches.opt_present("version");
     let mirror = matches.opt_present("mirror");
     let recursive = matches.opt_present("recursive") | mirror;
+    let dry_run = matches.opt_present("dry-run");
+    let plan_file = matches.opt_str("plan");
+    let resume = matches.opt_present("resume");
+    let journal_file = matches.opt_str("journal");
+    let verify = matches.opt_present("verify");
+    let compare = match matches.opt_str("compare") {
+        Some(mode) => mode.parse::<CompareMode>()?,
+        None => CompareMode::default(),
+    };
+    let time_tolerance = match matches.opt_str("time-tolerance") {
+        Some(seconds) => match seconds.parse::<u32>() {
+            Ok(seconds) => seconds,
+            Err(_) => return Err(format!("invalid time tolerance: {}", seconds).into()),
+        },
+        None => 0,
+    };
+    let bwlimit = match matches.opt_str("bwlimit") {
+        Some(rate) => Some(copy::rate_limit::parse_rate(&rate)?),
+        None => None,
+    };
+    let output = match matches.opt_str("output") {
+        Some(format) => format.parse::<OutputFormat>()?,
+        None => OutputFormat::default(),
+    };
+    let include = matches.opt_strs("include");
+    let exclude = matches.opt_strs("exclude");
+    let preserve_structure = matches.opt_present("preserve-structure");
     let verbose = matches.opt_count("verbose") as u32;
+    let device_name = matches
+        .opt_str("name")
+        .unwrap_or_else(|| String::from("mtpcopy"));
+    let once = matches.opt_present("once");
+    let mut endpoint: Option<ServeEndpoint> = None;
 
     let mut paths: Option<Paths> = None;
+    let mut destinations = Vec::<String>::new();
     let command: Command;
 
     if help || version {
@@ -112,6 +367,15 @@ fn parse_args() -> Result<Args, Box<dyn std::error::Error>> {
                     paths = Some(Paths { src, dest });
                     command = cmd;
                 }
+                Command::Watch => {
+                    if matches.free.len() < 2 {
+                        return Err("(command \"watch\") path is not specified".into());
+                    }
+                    let src = String::from(&matches.free[1]);
+                    let dest = String::new();
+                    paths = Some(Paths { src, dest });
+                    command = cmd;
+                }
                 Command::Copy => {
                     if matches.free.len() < 2 {
                         return Err("(command \"copy\") source path is not specified".into());
@@ -122,6 +386,43 @@ fn parse_args() -> Result<Args, Box<dyn std::error::Error>> {
                     let src = String::from(&matches.free[1]);
                     let dest = String::from(&matches.free[2]);
                     paths = Some(Paths { src, dest });
+                    destinations = matches.free[2..].to_vec();
+                    command = cmd;
+                }
+                Command::Move => {
+                    if matches.free.len() < 2 {
+                        return Err("(command \"move\") source path is not specified".into());
+                    }
+                    if matches.free.len() < 3 {
+                        return Err("(command \"move\") destination path is not specified".into());
+                    }
+                    if matches.free.len() > 3 {
+                        return Err(
+                            "(command \"move\") only one destination path can be specified".into(),
+                        );
+                    }
+                    let src = String::from(&matches.free[1]);
+                    let dest = String::from(&matches.free[2]);
+                    paths = Some(Paths { src, dest });
+                    command = cmd;
+                }
+                Command::Apply => {
+                    if matches.free.len() < 2 {
+                        return Err("(command \"apply\") plan file is not specified".into());
+                    }
+                    let src = String::from(&matches.free[1]);
+                    let dest = String::new();
+                    paths = Some(Paths { src, dest });
+                    command = cmd;
+                }
+                Command::Serve => {
+                    if matches.free.len() < 2 {
+                        return Err("(command \"serve\") folder is not specified".into());
+                    }
+                    let src = String::from(&matches.free[1]);
+                    let dest = String::new();
+                    paths = Some(Paths { src, dest });
+                    endpoint = Some(serve_endpoint(&matches)?);
                     command = cmd;
                 }
                 cmd => {
@@ -145,29 +446,85 @@ fn parse_args() -> Result<Args, Box<dyn std::error::Error>> {
     Ok(Args {
         command,
         paths,
+        destinations,
         recursive,
         mirror,
+        dry_run,
+        plan_file,
+        resume,
+        journal_file,
+        verify,
+        compare,
+        time_tolerance,
+        bwlimit,
+        output,
+        include,
+        exclude,
+        preserve_structure,
         verbose,
+        device_name,
+        endpoint,
+        once,
     })
 }
 
+fn serve_endpoint(matches: &getopts::Matches) -> Result<ServeEndpoint, Box<dyn std::error::Error>> {
+    if let Some(socket) = matches.opt_str("socket") {
+        if matches.opt_present("listen") {
+            return Err("(command \"serve\") --listen and --socket cannot be used together".into());
+        }
+        #[cfg(unix)]
+        return Ok(ServeEndpoint::UnixSocket(std::path::PathBuf::from(socket)));
+        #[cfg(not(unix))]
+        return Err(format!(
+            "(command \"serve\") Unix domain socket is not supported: {}",
+            socket
+        )
+        .into());
+    }
+    let address = matches
+        .opt_str("listen")
+        .unwrap_or_else(|| String::from("127.0.0.1"));
+    Ok(ServeEndpoint::PtpIp(address))
+}
+
 fn usage_brief() -> Result<String, std::fmt::Error> {
     let bin_name = env!("CARGO_BIN_NAME");
     let mut s = String::new();
     write!(&mut s, "Usage: {} [-hV]\n", bin_name)?;
     write!(
         &mut s,
-        "       {} copy [-RM] <source-path> <dest-path>\n",
+        "       {} copy [-RMv] [--dry-run | --plan <file> | --resume] [--journal <file>] [--verify] [--compare <mode>] [--time-tolerance <seconds>] [--bwlimit <rate>] [--output <format>] [--include <pattern>]... [--exclude <pattern>]... [--preserve-structure] <source-path> <dest-path>...\n",
+        bin_name
+    )?;
+    write!(
+        &mut s,
+        "       {} move [-RMv] [--dry-run | --plan <file> | --resume] [--journal <file>] [--verify] [--compare <mode>] [--time-tolerance <seconds>] [--bwlimit <rate>] [--output <format>] [--include <pattern>]... [--exclude <pattern>]... <source-path> <dest-path>\n",
+        bin_name
+    )?;
+    write!(
+        &mut s,
+        "       {} apply [--verify] [--bwlimit <rate>] [--output <format>] <plan-file>\n",
         bin_name
     )?;
     write!(&mut s, "       {} storages\n", bin_name)?;
     write!(&mut s, "       {} list [-Rv] <path>\n", bin_name)?;
+    write!(&mut s, "       {} watch <path>\n", bin_name)?;
+    write!(
+        &mut s,
+        "       {} serve [-n <name>] [-l <address> | --socket <path>] [--once] <folder>\n",
+        bin_name
+    )?;
     s.push_str("\n");
     s.push_str("Commands:\n");
     s.push_str("    copy       copy files or folders.\n");
+    s.push_str("    move       copy files or folders, then delete the copied sources.\n");
+    s.push_str("    apply      perform the plan saved by \"copy --plan\".\n");
     s.push_str("    storages   list all storages for the all connecting portable devices.\n");
     s.push_str("    list       list all file or folders matching the path.\n");
     s.push_str("               <path> can contains wildcard (see below.)\n");
+    s.push_str("    watch      show changes of files or folders on the device as they happen.\n");
+    s.push_str("    serve      act as an MTP device that has the local folder as its storage.\n");
     s.push_str("\n");
     s.push_str("About Path:\n");
     s.push_str("    A path on the portable device must be specified in this form:\n");
@@ -177,7 +534,33 @@ fn usage_brief() -> Result<String, std::fmt::Error> {
     s.push_str("    In \"list\" command, the path can contain wildcard characters:\n");
     s.push_str("        e.g. \"My*:SD*:**\\2021\\**\\*.jpg\"\n");
     s.push_str("\n");
-    s.push_str("    The other form will be used as the local path on your computer.");
+    s.push_str("    In \"copy\" command, the device name and the storage name\n");
+    s.push_str("    of the destination can contain wildcard characters\n");
+    s.push_str("    to copy to all matched storages:\n");
+    s.push_str("        e.g. \"*:SD*:\\Music\"\n");
+    s.push_str("    The source on the device can contain wildcard characters like \"list\".\n");
+    s.push_str("    The matched files and folders are copied into the destination folder:\n");
+    s.push_str("        e.g. \"Phone:SD*:\\Pictures\\202?\\**\\*.jpg\"\n");
+    s.push_str("\n");
+    s.push_str("    The other form will be used as the local path on your computer.\n");
+    s.push_str("\n");
+    s.push_str("Virtual Devices:\n");
+    s.push_str("    Local folders can be used as storages of virtual devices.\n");
+    s.push_str("    Specify entries in the file set to MTPCOPY_CONFIG,\n");
+    s.push_str("    or in MTPCOPY_VIRTUAL_DEVICES (separated by \";\"):\n");
+    s.push_str("        e.g. \"Fake Phone:Internal=C:\\Temp\\phone\"\n");
+    s.push_str("\n");
+    s.push_str("Simulated Devices:\n");
+    s.push_str("    Devices held in memory can be listed in MTPCOPY_MEMORY_DEVICES.\n");
+    s.push_str("    A storage starts empty, or with a copy of the local folder,\n");
+    s.push_str("    and the changes are discarded when the command exits:\n");
+    s.push_str("        e.g. \"Test Phone:Internal\" or \"Test Phone:SD Card=C:\\Temp\\sd\"\n");
+    s.push_str("\n");
+    s.push_str("PTP/IP Devices:\n");
+    s.push_str(
+        "    Network devices can be listed in MTPCOPY_PTPIP_DEVICES (separated by \";\"):\n",
+    );
+    s.push_str("        e.g. \"My Camera=192.168.1.10\" or \"My Camera=camera.local:15740\"");
     Ok(s)
 }
 
@@ -198,8 +581,12 @@ fn show_version() {
 fn find_command(s: &str) -> Option<Command> {
     let commands = [
         ("copy", Command::Copy),
+        ("move", Command::Move),
+        ("apply", Command::Apply),
         ("list", Command::ListFiles),
         ("storages", Command::ListStorages),
+        ("serve", Command::Serve),
+        ("watch", Command::Watch),
     ];
     let mut matched: Vec<Command> = commands
         .iter()
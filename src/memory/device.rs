@@ -0,0 +1,740 @@
+use chrono::NaiveDateTime;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::rc::{Rc, Weak};
+use std::time::Duration;
+
+use crate::backend::content_object::{ContentObject, ContentObjectInfo, ContentObjectKind};
+use crate::backend::device::{ContentObjectIterator, Device, DeviceEvent, EventSubscription};
+use crate::backend::resource::{ResourceReader, ResourceWriter};
+use crate::copy::file_info::FileInfo;
+
+const ROOT_OBJECT_ID: &str = "";
+const DEVICE_OBJECT_ID: &str = "DEVICE";
+const BUFFER_SIZE: u32 = 4096;
+
+struct MemoryObject {
+    parent: String,
+    children: Vec<String>,
+    name: String,
+    kind: ContentObjectKind,
+    data: Vec<u8>,
+    /// Size reported instead of the size of the data
+    reported_size: Option<u64>,
+    is_hidden: bool,
+    is_system: bool,
+    can_delete: bool,
+    time_created: Option<NaiveDateTime>,
+    time_modified: Option<NaiveDateTime>,
+}
+
+impl MemoryObject {
+    fn new(parent: &str, name: &str, kind: ContentObjectKind) -> MemoryObject {
+        MemoryObject {
+            parent: parent.to_string(),
+            children: Vec::new(),
+            name: name.to_string(),
+            kind,
+            data: Vec::new(),
+            reported_size: None,
+            is_hidden: false,
+            is_system: false,
+            can_delete: true,
+            time_created: None,
+            time_modified: None,
+        }
+    }
+}
+
+type EventQueue = RefCell<VecDeque<DeviceEvent>>;
+
+struct ObjectTree {
+    objects: HashMap<String, MemoryObject>,
+    next_id: u32,
+    /// Event queues of the subscriptions
+    subscribers: Vec<Weak<EventQueue>>,
+    /// Number of the next files to be truncated silently
+    files_to_truncate: u32,
+    /// Number of the next files to fail in committing
+    files_to_fail: u32,
+    /// Number of the next renamings to fail
+    renames_to_fail: u32,
+    /// Many devices cannot rename the files
+    rename_supported: bool,
+    copy_supported: bool,
+    /// Number of times the data of the files were opened
+    resources_opened: u32,
+    /// Time taken by each read or write of the data
+    latency: Duration,
+}
+
+impl ObjectTree {
+    fn get(&self, id: &str) -> Result<&MemoryObject, Box<dyn std::error::Error>> {
+        self.objects
+            .get(id)
+            .ok_or_else(|| format!("no such object: {:?}", id).into())
+    }
+
+    fn get_mut(&mut self, id: &str) -> Result<&mut MemoryObject, Box<dyn std::error::Error>> {
+        self.objects
+            .get_mut(id)
+            .ok_or_else(|| format!("no such object: {:?}", id).into())
+    }
+
+    fn insert(&mut self, object: MemoryObject) -> Result<String, Box<dyn std::error::Error>> {
+        let parent = self.get(&object.parent)?;
+        if parent.kind == ContentObjectKind::File {
+            return Err(format!("cannot create an object in a file: {}", &parent.name).into());
+        }
+        self.next_id += 1;
+        let id = format!("o{}", self.next_id);
+        self.get_mut(&object.parent)?.children.push(id.clone());
+        let event = if object.kind == ContentObjectKind::Storage {
+            DeviceEvent::StorageAdded(ContentObject::new(id.clone()))
+        } else {
+            DeviceEvent::ObjectAdded {
+                object: ContentObject::new(id.clone()),
+                parent: Some(ContentObject::new(object.parent.clone())),
+            }
+        };
+        self.objects.insert(id.clone(), object);
+        self.notify(event);
+        Ok(id)
+    }
+
+    fn notify(&mut self, event: DeviceEvent) {
+        self.subscribers.retain(|queue| match queue.upgrade() {
+            Some(queue) => {
+                queue.borrow_mut().push_back(event.clone());
+                true
+            }
+            None => false,
+        });
+    }
+
+    fn remove(&mut self, id: &str) {
+        if let Some(object) = self.objects.remove(id) {
+            for child in object.children.iter() {
+                self.remove(child);
+            }
+        }
+    }
+}
+
+/// A simulated portable device whose objects live in memory.
+///
+/// Objects behave like objects on a WPD device: names don't have to be unique
+/// in a folder. Renaming and copying on the device can be disabled
+/// to simulate the devices which don't support them.
+///
+/// Cloned instances share the same objects, so a test can keep a clone to
+/// inspect the device after it was handed to the code under test.
+#[derive(Clone)]
+pub struct MemoryDevice {
+    name: String,
+    tree: Rc<RefCell<ObjectTree>>,
+}
+
+impl MemoryDevice {
+    pub fn new(name: &str) -> MemoryDevice {
+        let mut objects = HashMap::<String, MemoryObject>::new();
+        let mut root = MemoryObject::new("", "", ContentObjectKind::FunctionalObject);
+        root.children.push(DEVICE_OBJECT_ID.to_string());
+        objects.insert(ROOT_OBJECT_ID.to_string(), root);
+        objects.insert(
+            DEVICE_OBJECT_ID.to_string(),
+            MemoryObject::new(ROOT_OBJECT_ID, name, ContentObjectKind::Device),
+        );
+        MemoryDevice {
+            name: name.to_string(),
+            tree: Rc::new(RefCell::new(ObjectTree {
+                objects,
+                next_id: 0,
+                subscribers: Vec::new(),
+                files_to_truncate: 0,
+                files_to_fail: 0,
+                renames_to_fail: 0,
+                rename_supported: false,
+                copy_supported: true,
+                resources_opened: 0,
+                latency: Duration::from_millis(0),
+            })),
+        }
+    }
+
+    pub fn add_storage(&self, name: &str) -> ContentObject {
+        self.add_object(MemoryObject::new(
+            DEVICE_OBJECT_ID,
+            name,
+            ContentObjectKind::Storage,
+        ))
+    }
+
+    pub fn add_folder(&self, parent: &ContentObject, name: &str) -> ContentObject {
+        self.add_object(MemoryObject::new(
+            &parent.id,
+            name,
+            ContentObjectKind::Folder,
+        ))
+    }
+
+    pub fn add_file(&self, parent: &ContentObject, name: &str, data: &[u8]) -> ContentObject {
+        let mut object = MemoryObject::new(&parent.id, name, ContentObjectKind::File);
+        object.data = data.to_vec();
+        self.add_object(object)
+    }
+
+    /// Adds the files and the folders in the local folder to the parent object.
+    /// The data are read at once; nothing is written back to the local folder.
+    pub fn load_folder(
+        &self,
+        parent: &ContentObject,
+        path: &Path,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut entries = std::fs::read_dir(path)
+            .map_err(|err| format!("failed to read {}: {}", path.to_string_lossy(), err))?
+            .collect::<Result<Vec<_>, _>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let metadata = entry.metadata()?;
+            let file_info = FileInfo::from_metadata(&metadata, &name)?;
+            let object = if metadata.is_dir() {
+                let folder = self.add_folder(parent, &name);
+                self.load_folder(&folder, &entry.path())?;
+                folder
+            } else {
+                self.add_file(parent, &name, &std::fs::read(entry.path())?)
+            };
+            self.set_hidden(&object, file_info.is_hidden);
+            self.set_system(&object, file_info.is_system);
+            self.set_time_created(&object, file_info.time_created);
+            self.set_time_modified(&object, file_info.time_modified);
+        }
+        Ok(())
+    }
+
+    fn add_object(&self, object: MemoryObject) -> ContentObject {
+        ContentObject::new(self.tree.borrow_mut().insert(object).unwrap())
+    }
+
+    pub fn set_hidden(&self, object: &ContentObject, is_hidden: bool) {
+        self.modify(object, |o| o.is_hidden = is_hidden);
+    }
+
+    pub fn set_system(&self, object: &ContentObject, is_system: bool) {
+        self.modify(object, |o| o.is_system = is_system);
+    }
+
+    pub fn set_time_created(&self, object: &ContentObject, time: Option<NaiveDateTime>) {
+        self.modify(object, |o| o.time_created = time);
+    }
+
+    pub fn set_time_modified(&self, object: &ContentObject, time: Option<NaiveDateTime>) {
+        self.modify(object, |o| o.time_modified = time);
+    }
+
+    fn modify<F: FnOnce(&mut MemoryObject)>(&self, object: &ContentObject, f: F) {
+        let mut tree = self.tree.borrow_mut();
+        f(tree.get_mut(&object.id).unwrap());
+        tree.notify(DeviceEvent::ObjectInfoChanged(object.clone()));
+    }
+}
+
+/// Inspection and simulated behaviors of the devices for the tests
+#[cfg(test)]
+impl MemoryDevice {
+    /// Finds an object by the path like `Storage\Folder\File`.
+    /// Returns the first one if the names were duplicated.
+    pub fn find(&self, path: &str) -> Option<ContentObject> {
+        let tree = self.tree.borrow();
+        let mut id = DEVICE_OBJECT_ID.to_string();
+        for name in path.split('\\').filter(|s| !s.is_empty()) {
+            let object = tree.objects.get(&id)?;
+            id = object
+                .children
+                .iter()
+                .find(|child| tree.objects[child.as_str()].name == name)?
+                .clone();
+        }
+        Some(ContentObject::new(id))
+    }
+
+    /// Returns names of the child objects.
+    pub fn child_names(&self, object: &ContentObject) -> Vec<String> {
+        let tree = self.tree.borrow();
+        tree.objects[&object.id]
+            .children
+            .iter()
+            .map(|child| tree.objects[child].name.clone())
+            .collect()
+    }
+
+    pub fn data(&self, object: &ContentObject) -> Vec<u8> {
+        self.tree.borrow().objects[&object.id].data.clone()
+    }
+    pub fn set_can_delete(&self, object: &ContentObject, can_delete: bool) {
+        self.modify(object, |o| o.can_delete = can_delete);
+    }
+
+    pub fn set_data(&self, object: &ContentObject, data: &[u8]) {
+        self.modify(object, |o| o.data = data.to_vec());
+    }
+
+    /// Makes the file report the size without having the data, to simulate large files.
+    pub fn set_reported_size(&self, object: &ContentObject, size: u64) {
+        self.modify(object, |o| o.reported_size = Some(size));
+    }
+
+    /// Makes the next `count` files lose their last byte after they were committed,
+    /// as some MTP stacks do.
+    pub fn truncate_next_files(&self, count: u32) {
+        self.tree.borrow_mut().files_to_truncate = count;
+    }
+
+    /// Makes the next `count` files fail when they are committed.
+    pub fn fail_next_files(&self, count: u32) {
+        self.tree.borrow_mut().files_to_fail = count;
+    }
+
+    /// Makes the next `count` renamings fail, after the device accepted them.
+    pub fn fail_next_renames(&self, count: u32) {
+        self.tree.borrow_mut().renames_to_fail = count;
+    }
+
+    /// Makes the device accept renaming, which is refused by default like many devices do.
+    pub fn set_rename_supported(&self, rename_supported: bool) {
+        self.tree.borrow_mut().rename_supported = rename_supported;
+    }
+
+    /// Makes the device refuse copying and moving the files on the device.
+    pub fn set_copy_supported(&self, copy_supported: bool) {
+        self.tree.borrow_mut().copy_supported = copy_supported;
+    }
+
+    /// Returns how many times the data of the files were opened.
+    pub fn resources_opened(&self) -> u32 {
+        self.tree.borrow().resources_opened
+    }
+
+    /// Makes each read or write of the data take the given time, like the devices connected by USB.
+    pub fn set_latency(&self, latency: Duration) {
+        self.tree.borrow_mut().latency = latency;
+    }
+}
+
+impl Device for MemoryDevice {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn get_root_object(&self) -> ContentObject {
+        ContentObject::new(ROOT_OBJECT_ID.to_string())
+    }
+
+    fn get_object_iterator(
+        &self,
+        parent: &ContentObject,
+    ) -> Result<Box<dyn ContentObjectIterator>, Box<dyn std::error::Error>> {
+        let tree = self.tree.borrow();
+        let mut children = tree.get(&parent.id)?.children.clone();
+        children.reverse(); // for moving item out by pop()
+        Ok(Box::new(MemoryObjectIterator { children }))
+    }
+
+    fn get_object_info(
+        &self,
+        object: ContentObject,
+    ) -> Result<ContentObjectInfo, Box<dyn std::error::Error>> {
+        let tree = self.tree.borrow();
+        let memory_object = tree.get(&object.id)?;
+        Ok(ContentObjectInfo {
+            content_object: object,
+            name: memory_object.name.clone(),
+            kind: memory_object.kind,
+            data_size: memory_object
+                .reported_size
+                .unwrap_or(memory_object.data.len() as u64),
+            is_hidden: memory_object.is_hidden,
+            is_system: memory_object.is_system,
+            can_delete: memory_object.can_delete,
+            time_created: memory_object.time_created,
+            time_modified: memory_object.time_modified,
+        })
+    }
+
+    fn get_resoure(
+        &self,
+        object: &ContentObject,
+    ) -> Result<Box<dyn ResourceReader>, Box<dyn std::error::Error>> {
+        let mut tree = self.tree.borrow_mut();
+        tree.resources_opened += 1;
+        let memory_object = tree.get(&object.id)?;
+        if memory_object.kind != ContentObjectKind::File {
+            return Err(format!("not a file: {}", &memory_object.name).into());
+        }
+        Ok(Box::new(MemoryResourceReader {
+            data: memory_object.data.clone(),
+            offset: 0,
+            latency: tree.latency,
+        }))
+    }
+
+    fn create_file(
+        &self,
+        parent: &ContentObject,
+        name: &str,
+        size: u64,
+        created: &Option<NaiveDateTime>,
+        modified: &Option<NaiveDateTime>,
+    ) -> Result<Box<dyn ResourceWriter>, Box<dyn std::error::Error>> {
+        self.tree.borrow().get(&parent.id)?;
+        let mut object = MemoryObject::new(&parent.id, name, ContentObjectKind::File);
+        object.time_created = *created;
+        object.time_modified = *modified;
+        Ok(Box::new(MemoryResourceWriter {
+            tree: self.tree.clone(),
+            object: Some(object),
+            size,
+        }))
+    }
+
+    fn create_folder(
+        &self,
+        parent: &ContentObject,
+        name: &str,
+    ) -> Result<ContentObject, Box<dyn std::error::Error>> {
+        let object = MemoryObject::new(&parent.id, name, ContentObjectKind::Folder);
+        Ok(ContentObject::new(self.tree.borrow_mut().insert(object)?))
+    }
+
+    fn delete(&self, object: &ContentObject) -> Result<(), Box<dyn std::error::Error>> {
+        let mut tree = self.tree.borrow_mut();
+        let memory_object = tree.get(&object.id)?;
+        if !memory_object.can_delete {
+            return Err(format!("cannot delete: {}", &memory_object.name).into());
+        }
+        let event = if memory_object.kind == ContentObjectKind::Storage {
+            DeviceEvent::StorageRemoved(object.clone())
+        } else {
+            DeviceEvent::ObjectRemoved(object.clone())
+        };
+        let parent_id = memory_object.parent.clone();
+        tree.get_mut(&parent_id)?
+            .children
+            .retain(|child| child != &object.id);
+        tree.remove(&object.id);
+        tree.notify(event);
+        Ok(())
+    }
+
+    fn can_rename(&self, object: &ContentObject) -> bool {
+        let tree = self.tree.borrow();
+        match tree.get(&object.id) {
+            Ok(memory_object) => {
+                tree.rename_supported
+                    && matches!(
+                        memory_object.kind,
+                        ContentObjectKind::File | ContentObjectKind::Folder
+                    )
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn rename(
+        &self,
+        object: &ContentObject,
+        name: &str,
+    ) -> Result<ContentObject, Box<dyn std::error::Error>> {
+        if !self.can_rename(object) {
+            return Err(format!("cannot rename: {}", &object.id).into());
+        }
+        {
+            let mut tree = self.tree.borrow_mut();
+            if tree.renames_to_fail > 0 {
+                tree.renames_to_fail -= 1;
+                return Err(format!("failed to rename: {}", &object.id).into());
+            }
+        }
+        self.modify(object, |o| o.name = name.to_string());
+        Ok(object.clone())
+    }
+
+    fn can_copy_on_device(&self, object: &ContentObject) -> bool {
+        let tree = self.tree.borrow();
+        match tree.get(&object.id) {
+            Ok(memory_object) => {
+                tree.copy_supported && memory_object.kind == ContentObjectKind::File
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn can_move_on_device(&self, object: &ContentObject) -> bool {
+        // supported together with copying
+        self.can_copy_on_device(object)
+    }
+
+    fn copy_object(
+        &self,
+        object: &ContentObject,
+        parent: &ContentObject,
+    ) -> Result<ContentObject, Box<dyn std::error::Error>> {
+        if !self.can_copy_on_device(object) {
+            return Err(format!("cannot copy: {}", &object.id).into());
+        }
+        let mut tree = self.tree.borrow_mut();
+        let source = tree.get(&object.id)?;
+        let mut copied = MemoryObject::new(&parent.id, &source.name, ContentObjectKind::File);
+        copied.data = source.data.clone();
+        copied.is_hidden = source.is_hidden;
+        copied.is_system = source.is_system;
+        copied.time_created = source.time_created;
+        copied.time_modified = source.time_modified;
+        Ok(ContentObject::new(tree.insert(copied)?))
+    }
+
+    fn move_object(
+        &self,
+        object: &ContentObject,
+        parent: &ContentObject,
+    ) -> Result<ContentObject, Box<dyn std::error::Error>> {
+        if !self.can_move_on_device(object) {
+            return Err(format!("cannot move: {}", &object.id).into());
+        }
+        let mut tree = self.tree.borrow_mut();
+        if tree.get(&parent.id)?.kind == ContentObjectKind::File {
+            return Err(format!("cannot move an object into a file: {}", &parent.id).into());
+        }
+        let old_parent_id = tree.get(&object.id)?.parent.clone();
+        tree.get_mut(&old_parent_id)?
+            .children
+            .retain(|child| child != &object.id);
+        tree.get_mut(&parent.id)?.children.push(object.id.clone());
+        tree.get_mut(&object.id)?.parent = parent.id.clone();
+        tree.notify(DeviceEvent::ObjectRemoved(object.clone()));
+        tree.notify(DeviceEvent::ObjectAdded {
+            object: object.clone(),
+            parent: Some(parent.clone()),
+        });
+        Ok(object.clone())
+    }
+
+    fn subscribe_events(&self) -> Result<Box<dyn EventSubscription>, Box<dyn std::error::Error>> {
+        let queue = Rc::new(RefCell::new(VecDeque::<DeviceEvent>::new()));
+        self.tree
+            .borrow_mut()
+            .subscribers
+            .push(Rc::downgrade(&queue));
+        Ok(Box::new(MemoryEventSubscription { queue }))
+    }
+}
+
+/// Events are queued when the device is modified, so the subscription never waits.
+struct MemoryEventSubscription {
+    queue: Rc<EventQueue>,
+}
+
+impl EventSubscription for MemoryEventSubscription {
+    fn next_event(
+        &mut self,
+        _timeout: Duration,
+    ) -> Result<Option<DeviceEvent>, Box<dyn std::error::Error>> {
+        Ok(self.queue.borrow_mut().pop_front())
+    }
+}
+
+struct MemoryObjectIterator {
+    children: Vec<String>,
+}
+
+impl ContentObjectIterator for MemoryObjectIterator {
+    fn next(&mut self) -> Result<Option<ContentObject>, Box<dyn std::error::Error>> {
+        Ok(self.children.pop().map(ContentObject::new))
+    }
+}
+
+struct MemoryResourceReader {
+    data: Vec<u8>,
+    offset: usize,
+    latency: Duration,
+}
+
+impl ResourceReader for MemoryResourceReader {
+    fn get_optimized_buffer_size(&self) -> u32 {
+        BUFFER_SIZE
+    }
+
+    fn next(&mut self, max_size: u32) -> Result<Option<&[u8]>, Box<dyn std::error::Error>> {
+        if self.offset >= self.data.len() {
+            return Ok(None);
+        }
+        std::thread::sleep(self.latency);
+        let end = std::cmp::min(self.data.len(), self.offset + max_size as usize);
+        let chunk = &self.data[self.offset..end];
+        self.offset = end;
+        Ok(Some(chunk))
+    }
+}
+
+struct MemoryResourceWriter {
+    tree: Rc<RefCell<ObjectTree>>,
+    object: Option<MemoryObject>,
+    size: u64,
+}
+
+impl ResourceWriter for MemoryResourceWriter {
+    fn get_buffer_size(&self) -> u32 {
+        BUFFER_SIZE
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        match self.object.as_mut() {
+            Some(object) => {
+                std::thread::sleep(self.tree.borrow().latency);
+                object.data.extend_from_slice(data);
+                Ok(())
+            }
+            None => Err("the object was already committed.".into()),
+        }
+    }
+
+    fn commit(&mut self) -> Result<ContentObject, Box<dyn std::error::Error>> {
+        let mut object = match self.object.take() {
+            Some(object) => object,
+            None => return Err("the object was already committed.".into()),
+        };
+        if object.data.len() as u64 != self.size {
+            return Err(format!(
+                "size mismatch: expected {} bytes, but {} bytes were written.",
+                self.size,
+                object.data.len()
+            )
+            .into());
+        }
+        let mut tree = self.tree.borrow_mut();
+        if tree.files_to_fail > 0 {
+            tree.files_to_fail -= 1;
+            return Err(format!("failed to write: {}", &object.name).into());
+        }
+        if tree.files_to_truncate > 0 {
+            tree.files_to_truncate -= 1;
+            object.data.pop();
+        }
+        Ok(ContentObject::new(tree.insert(object)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events() {
+        let device = MemoryDevice::new("Phone");
+        let storage = device.add_storage("Internal");
+        let mut subscription = device.subscribe_events().unwrap();
+        let mut next = || subscription.next_event(Duration::from_secs(0)).unwrap();
+
+        let folder = device.add_folder(&storage, "DCIM");
+        assert_eq!(
+            next(),
+            Some(DeviceEvent::ObjectAdded {
+                object: folder.clone(),
+                parent: Some(storage.clone()),
+            })
+        );
+        device.set_hidden(&folder, true);
+        assert_eq!(next(), Some(DeviceEvent::ObjectInfoChanged(folder.clone())));
+        device.delete(&folder).unwrap();
+        assert_eq!(next(), Some(DeviceEvent::ObjectRemoved(folder)));
+        let sd_card = device.add_storage("SD Card");
+        assert_eq!(next(), Some(DeviceEvent::StorageAdded(sd_card)));
+        assert_eq!(next(), None);
+    }
+
+    fn read_all(device: &MemoryDevice, object: &ContentObject) -> Vec<u8> {
+        let mut reader = device.get_resoure(object).unwrap();
+        let mut data = Vec::<u8>::new();
+        while let Some(bytes) = reader.next(3).unwrap() {
+            data.extend_from_slice(bytes);
+        }
+        data
+    }
+
+    #[test]
+    fn test_object_hierarchy() {
+        let device = MemoryDevice::new("Phone");
+        let storage = device.add_storage("Internal");
+        let folder = device.add_folder(&storage, "Music");
+        device.add_file(&folder, "a.mp3", b"abc");
+
+        let root = device.get_root_object();
+        let mut iter = device.get_object_iterator(&root).unwrap();
+        let device_object = iter.next().unwrap().unwrap();
+        assert!(iter.next().unwrap().is_none());
+        let device_info = device.get_object_info(device_object.clone()).unwrap();
+        assert!(device_info.is_device());
+        assert_eq!(device_info.name, "Phone");
+
+        let mut iter = device.get_object_iterator(&device_object).unwrap();
+        let storage_info = device
+            .get_object_info(iter.next().unwrap().unwrap())
+            .unwrap();
+        assert!(storage_info.is_storage());
+        assert_eq!(storage_info.name, "Internal");
+
+        let file = device.find("Internal\\Music\\a.mp3").unwrap();
+        let file_info = device.get_object_info(file.clone()).unwrap();
+        assert!(file_info.is_file());
+        assert_eq!(file_info.data_size, 3);
+        assert_eq!(read_all(&device, &file), b"abc");
+
+        assert!(device.find("Internal\\Music\\b.mp3").is_none());
+    }
+
+    #[test]
+    fn test_create_file_allows_duplicated_names() {
+        let device = MemoryDevice::new("Phone");
+        let storage = device.add_storage("Internal");
+        for data in [&b"first"[..], &b"second"[..]].iter() {
+            let mut writer = device
+                .create_file(&storage, "a.txt", data.len() as u64, &None, &None)
+                .unwrap();
+            writer.write(data).unwrap();
+            writer.commit().unwrap();
+        }
+        assert_eq!(device.child_names(&storage), vec!["a.txt", "a.txt"]);
+        let first = device.find("Internal\\a.txt").unwrap();
+        assert_eq!(device.data(&first), b"first");
+    }
+
+    #[test]
+    fn test_create_file_size_mismatch() {
+        let device = MemoryDevice::new("Phone");
+        let storage = device.add_storage("Internal");
+        let mut writer = device
+            .create_file(&storage, "a.txt", 10, &None, &None)
+            .unwrap();
+        writer.write(b"abc").unwrap();
+        assert!(writer.commit().is_err());
+        assert!(device.child_names(&storage).is_empty());
+    }
+
+    #[test]
+    fn test_delete() {
+        let device = MemoryDevice::new("Phone");
+        let storage = device.add_storage("Internal");
+        let folder = device.add_folder(&storage, "DCIM");
+        let file = device.add_file(&folder, "a.jpg", b"abc");
+        let protected = device.add_file(&storage, "b.jpg", b"abc");
+        device.set_can_delete(&protected, false);
+
+        assert!(device.delete(&protected).is_err());
+        device.delete(&folder).unwrap();
+        assert_eq!(device.child_names(&storage), vec!["b.jpg"]);
+        assert!(device.get_object_info(file).is_err());
+    }
+}
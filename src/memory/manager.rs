@@ -0,0 +1,157 @@
+use std::path::Path;
+
+use crate::backend::device::Device;
+use crate::backend::manager::{DeviceInfo, DeviceInfoIterator, Manager};
+
+use super::device::MemoryDevice;
+
+/// Environment variable that specifies simulated devices.
+/// Entries are in the form `<device-name>:<storage-name>[=<local folder>]`,
+/// and separated by `;` or newline.
+pub const MEMORY_DEVICES_ENV: &str = "MTPCOPY_MEMORY_DEVICES";
+
+/// A manager of simulated devices.
+///
+/// The devices can be specified by `MTPCOPY_MEMORY_DEVICES` for scripting.
+/// A storage starts with a copy of the local folder if it is given, and the changes
+/// are discarded when the command exits.
+pub struct MemoryManager {
+    devices: Vec<MemoryDevice>,
+}
+
+impl MemoryManager {
+    pub fn new() -> MemoryManager {
+        MemoryManager {
+            devices: Vec::new(),
+        }
+    }
+
+    /// Adds a new device, and returns the device that shares objects with
+    /// the one the manager opens.
+    pub fn add_device(&mut self, name: &str) -> MemoryDevice {
+        let device = MemoryDevice::new(name);
+        self.devices.push(device.clone());
+        device
+    }
+
+    /// Creates a manager from the environment variable.
+    /// Returns None if no devices are specified.
+    pub fn from_env() -> Result<Option<MemoryManager>, Box<dyn std::error::Error>> {
+        match std::env::var(MEMORY_DEVICES_ENV) {
+            Ok(text) => Ok(Some(MemoryManager::parse(&text)?)),
+            Err(_) => Ok(None),
+        }
+    }
+
+    fn parse(text: &str) -> Result<MemoryManager, Box<dyn std::error::Error>> {
+        let mut manager = MemoryManager::new();
+        for entry in text.split(['\n', ';']) {
+            let entry = entry.trim();
+            if entry.is_empty() || entry.starts_with('#') {
+                continue;
+            }
+            let (storage_path, folder) = match entry.find('=') {
+                Some(pos) => (&entry[..pos], Some(entry[pos + 1..].trim())),
+                None => (entry, None),
+            };
+            let (device_name, storage_name) = match storage_path.split_once(':') {
+                Some((device_name, storage_name)) => (device_name.trim(), storage_name.trim()),
+                None => return Err(format!("invalid memory device entry: {}", entry).into()),
+            };
+            if device_name.is_empty()
+                || storage_name.is_empty()
+                || storage_name.contains(':')
+                || folder == Some("")
+            {
+                return Err(format!("invalid memory device entry: {}", entry).into());
+            }
+
+            let device = match manager
+                .devices
+                .iter()
+                .find(|device| device.name() == device_name)
+            {
+                Some(device) => device.clone(),
+                None => manager.add_device(device_name),
+            };
+            let storage = device.add_storage(storage_name);
+            if let Some(folder) = folder {
+                device.load_folder(&storage, Path::new(folder))?;
+            }
+        }
+        Ok(manager)
+    }
+}
+
+impl Manager for MemoryManager {
+    fn get_device_iterator(
+        &self,
+    ) -> Result<Box<dyn DeviceInfoIterator + '_>, Box<dyn std::error::Error>> {
+        let mut device_infos: Vec<DeviceInfo> = self
+            .devices
+            .iter()
+            .enumerate()
+            .map(|(index, device)| DeviceInfo {
+                id: index.to_string(),
+                name: device.name().to_string(),
+            })
+            .collect();
+        device_infos.reverse(); // for moving item out by pop()
+        Ok(Box::new(MemoryDeviceInfoIterator { device_infos }))
+    }
+
+    fn open_device(
+        &self,
+        info: &DeviceInfo,
+    ) -> Result<Box<dyn Device>, Box<dyn std::error::Error>> {
+        match info
+            .id
+            .parse::<usize>()
+            .ok()
+            .and_then(|i| self.devices.get(i))
+        {
+            Some(device) => Ok(Box::new(device.clone())),
+            None => Err(format!("no such device: {}", &info.name).into()),
+        }
+    }
+}
+
+struct MemoryDeviceInfoIterator {
+    device_infos: Vec<DeviceInfo>,
+}
+
+impl DeviceInfoIterator for MemoryDeviceInfoIterator {
+    fn next(&mut self) -> Result<Option<DeviceInfo>, Box<dyn std::error::Error>> {
+        Ok(self.device_infos.pop())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tempdir.path().join("Music")).unwrap();
+        std::fs::write(tempdir.path().join("Music").join("a.mp3"), b"aaaa").unwrap();
+        let text = format!(
+            "Phone:Internal; Phone:SD Card={}\n# comment\nTablet:Internal",
+            tempdir.path().to_str().unwrap()
+        );
+        let manager = MemoryManager::parse(&text).unwrap();
+        let names: Vec<&str> = manager.devices.iter().map(|d| d.name()).collect();
+        assert_eq!(names, vec!["Phone", "Tablet"]);
+        let phone = &manager.devices[0];
+        let root = phone.find("").unwrap();
+        assert_eq!(phone.child_names(&root), vec!["Internal", "SD Card"]);
+        let file_a = phone.find("SD Card\\Music\\a.mp3").unwrap();
+        assert_eq!(phone.data(&file_a), b"aaaa");
+
+        assert!(MemoryManager::parse("Phone").is_err());
+        assert!(MemoryManager::parse(":Internal").is_err());
+        assert!(MemoryManager::parse("Phone:").is_err());
+        assert!(MemoryManager::parse("Phone:Internal=").is_err());
+        assert!(MemoryManager::parse("Phone:Internal:Music").is_err());
+    }
+}
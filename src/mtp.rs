@@ -0,0 +1,13 @@
+//! Protocol implementation of PTP/MTP.
+
+pub mod codes;
+pub mod container;
+pub mod dataset;
+pub mod device;
+#[cfg(test)]
+pub mod loopback;
+pub mod manager;
+pub mod ptpip;
+pub mod responder;
+pub mod session;
+pub mod transport;
@@ -0,0 +1,229 @@
+//! Code tables defined in the PTP (ISO 15740) and MTP specifications.
+
+macro_rules! code_table {
+    ($(#[$attr:meta])* $mod_name:ident { $($name:ident = $value:literal,)* }) => {
+        $(#[$attr])*
+        #[allow(dead_code)]
+        pub mod $mod_name {
+            $(pub const $name: u16 = $value;)*
+
+            /// Returns the name of the code.
+            pub fn name(code: u16) -> Option<&'static str> {
+                match code {
+                    $($value => Some(stringify!($name)),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+code_table!(
+    /// Operation codes
+    operation {
+        GET_DEVICE_INFO = 0x1001,
+        OPEN_SESSION = 0x1002,
+        CLOSE_SESSION = 0x1003,
+        GET_STORAGE_IDS = 0x1004,
+        GET_STORAGE_INFO = 0x1005,
+        GET_NUM_OBJECTS = 0x1006,
+        GET_OBJECT_HANDLES = 0x1007,
+        GET_OBJECT_INFO = 0x1008,
+        GET_OBJECT = 0x1009,
+        GET_THUMB = 0x100A,
+        DELETE_OBJECT = 0x100B,
+        SEND_OBJECT_INFO = 0x100C,
+        SEND_OBJECT = 0x100D,
+        FORMAT_STORE = 0x100F,
+        RESET_DEVICE = 0x1010,
+        GET_DEVICE_PROP_DESC = 0x1014,
+        GET_DEVICE_PROP_VALUE = 0x1015,
+        SET_DEVICE_PROP_VALUE = 0x1016,
+        MOVE_OBJECT = 0x1019,
+        COPY_OBJECT = 0x101A,
+        GET_PARTIAL_OBJECT = 0x101B,
+        GET_OBJECT_PROPS_SUPPORTED = 0x9801,
+        GET_OBJECT_PROP_DESC = 0x9802,
+        GET_OBJECT_PROP_VALUE = 0x9803,
+        SET_OBJECT_PROP_VALUE = 0x9804,
+        GET_OBJECT_PROP_LIST = 0x9805,
+        SET_OBJECT_PROP_LIST = 0x9806,
+        SEND_OBJECT_PROP_LIST = 0x9808,
+        GET_OBJECT_REFERENCES = 0x9810,
+        SET_OBJECT_REFERENCES = 0x9811,
+    }
+);
+
+code_table!(
+    /// Response codes
+    response {
+        UNDEFINED = 0x2000,
+        OK = 0x2001,
+        GENERAL_ERROR = 0x2002,
+        SESSION_NOT_OPEN = 0x2003,
+        INVALID_TRANSACTION_ID = 0x2004,
+        OPERATION_NOT_SUPPORTED = 0x2005,
+        PARAMETER_NOT_SUPPORTED = 0x2006,
+        INCOMPLETE_TRANSFER = 0x2007,
+        INVALID_STORAGE_ID = 0x2008,
+        INVALID_OBJECT_HANDLE = 0x2009,
+        DEVICE_PROP_NOT_SUPPORTED = 0x200A,
+        INVALID_OBJECT_FORMAT_CODE = 0x200B,
+        STORE_FULL = 0x200C,
+        OBJECT_WRITE_PROTECTED = 0x200D,
+        STORE_READ_ONLY = 0x200E,
+        ACCESS_DENIED = 0x200F,
+        NO_THUMBNAIL_PRESENT = 0x2010,
+        PARTIAL_DELETION = 0x2012,
+        STORE_NOT_AVAILABLE = 0x2013,
+        SPECIFICATION_BY_FORMAT_UNSUPPORTED = 0x2014,
+        NO_VALID_OBJECT_INFO = 0x2015,
+        DEVICE_BUSY = 0x2019,
+        INVALID_PARENT_OBJECT = 0x201A,
+        INVALID_PARAMETER = 0x201D,
+        SESSION_ALREADY_OPEN = 0x201E,
+        TRANSACTION_CANCELLED = 0x201F,
+        INVALID_OBJECT_PROP_CODE = 0xA801,
+        INVALID_OBJECT_PROP_FORMAT = 0xA802,
+        INVALID_OBJECT_PROP_VALUE = 0xA803,
+        INVALID_DATASET = 0xA806,
+        SPECIFICATION_BY_GROUP_UNSUPPORTED = 0xA807,
+        OBJECT_TOO_LARGE = 0xA809,
+    }
+);
+
+code_table!(
+    /// Event codes
+    event {
+        CANCEL_TRANSACTION = 0x4001,
+        OBJECT_ADDED = 0x4002,
+        OBJECT_REMOVED = 0x4003,
+        STORE_ADDED = 0x4004,
+        STORE_REMOVED = 0x4005,
+        DEVICE_PROP_CHANGED = 0x4006,
+        OBJECT_INFO_CHANGED = 0x4007,
+        DEVICE_INFO_CHANGED = 0x4008,
+        STORE_FULL = 0x400A,
+        STORAGE_INFO_CHANGED = 0x400C,
+        OBJECT_PROP_CHANGED = 0xC801,
+    }
+);
+
+code_table!(
+    /// Object format codes
+    format {
+        UNDEFINED = 0x3000,
+        ASSOCIATION = 0x3001,
+        SCRIPT = 0x3002,
+        EXECUTABLE = 0x3003,
+        TEXT = 0x3004,
+        HTML = 0x3005,
+        AIFF = 0x3007,
+        WAV = 0x3008,
+        MP3 = 0x3009,
+        AVI = 0x300A,
+        MPEG = 0x300B,
+        ASF = 0x300C,
+        EXIF_JPEG = 0x3801,
+        BMP = 0x3804,
+        GIF = 0x3807,
+        PNG = 0x380B,
+        TIFF = 0x380D,
+        WMA = 0xB901,
+        OGG = 0xB902,
+        AAC = 0xB903,
+        FLAC = 0xB906,
+        WMV = 0xB981,
+        MP4_CONTAINER = 0xB982,
+        ABSTRACT_AUDIO_ALBUM = 0xBA03,
+        ABSTRACT_AV_PLAYLIST = 0xBA05,
+    }
+);
+
+code_table!(
+    /// Object property codes
+    object_property {
+        STORAGE_ID = 0xDC01,
+        OBJECT_FORMAT = 0xDC02,
+        PROTECTION_STATUS = 0xDC03,
+        OBJECT_SIZE = 0xDC04,
+        ASSOCIATION_TYPE = 0xDC05,
+        OBJECT_FILE_NAME = 0xDC07,
+        DATE_CREATED = 0xDC08,
+        DATE_MODIFIED = 0xDC09,
+        PARENT_OBJECT = 0xDC0B,
+        PERSISTENT_UID = 0xDC41,
+        NAME = 0xDC44,
+        DATE_ADDED = 0xDC4E,
+        HIDDEN = 0xDC8D,
+    }
+);
+
+code_table!(
+    /// Data type codes
+    datatype {
+        UNDEFINED = 0x0000,
+        INT8 = 0x0001,
+        UINT8 = 0x0002,
+        INT16 = 0x0003,
+        UINT16 = 0x0004,
+        INT32 = 0x0005,
+        UINT32 = 0x0006,
+        INT64 = 0x0007,
+        UINT64 = 0x0008,
+        INT128 = 0x0009,
+        UINT128 = 0x000A,
+        STR = 0xFFFF,
+    }
+);
+
+/// Whether the operation has the data phase from the initiator to the responder.
+pub fn has_data_out(operation_code: u16) -> bool {
+    matches!(
+        operation_code,
+        operation::SEND_OBJECT_INFO
+            | operation::SEND_OBJECT
+            | operation::SET_DEVICE_PROP_VALUE
+            | operation::SET_OBJECT_PROP_VALUE
+            | operation::SET_OBJECT_PROP_LIST
+            | operation::SEND_OBJECT_PROP_LIST
+            | operation::SET_OBJECT_REFERENCES
+    )
+}
+
+/// Association type of a generic folder
+pub const ASSOCIATION_TYPE_GENERIC_FOLDER: u16 = 0x0001;
+
+/// Protection status: no protection
+pub const PROTECTION_STATUS_NONE: u16 = 0x0000;
+/// Protection status: read-only
+pub const PROTECTION_STATUS_READ_ONLY: u16 = 0x0001;
+
+/// Storage type: fixed RAM
+pub const STORAGE_TYPE_FIXED_RAM: u16 = 0x0003;
+
+/// Filesystem type: generic hierarchical
+pub const FILESYSTEM_TYPE_GENERIC_HIERARCHICAL: u16 = 0x0002;
+
+/// Access capability: read-write
+pub const ACCESS_CAPABILITY_READ_WRITE: u16 = 0x0000;
+
+/// Object handle / storage id that means "all" or "root" depending on the operation
+pub const ALL: u32 = 0xFFFF_FFFF;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_name() {
+        assert_eq!(operation::name(0x1001), Some("GET_DEVICE_INFO"));
+        assert_eq!(
+            operation::name(operation::GET_OBJECT_PROP_LIST),
+            Some("GET_OBJECT_PROP_LIST")
+        );
+        assert_eq!(response::name(0x2001), Some("OK"));
+        assert_eq!(response::name(0x2fff), None);
+        assert_eq!(format::name(format::ASSOCIATION), Some("ASSOCIATION"));
+    }
+}
@@ -0,0 +1,282 @@
+//! Generic containers of PTP/MTP.
+//!
+//! A container consists of a 12-byte header and the payload:
+//!
+//! | offset | size | field          |
+//! |--------|------|----------------|
+//! | 0      | 4    | container length (including the header) |
+//! | 4      | 2    | container type |
+//! | 6      | 2    | operation / response / event code |
+//! | 8      | 4    | transaction ID |
+//! | 12     | -    | payload        |
+//!
+//! All values are little-endian.
+
+use std::convert::TryInto;
+
+pub const HEADER_SIZE: usize = 12;
+
+/// Max number of the parameters of the command, response and event containers.
+pub const MAX_PARAMS: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContainerType {
+    Command = 1,
+    Data = 2,
+    Response = 3,
+    Event = 4,
+}
+
+impl ContainerType {
+    fn from_u16(value: u16) -> Option<ContainerType> {
+        match value {
+            1 => Some(ContainerType::Command),
+            2 => Some(ContainerType::Data),
+            3 => Some(ContainerType::Response),
+            4 => Some(ContainerType::Event),
+            _ => None,
+        }
+    }
+}
+
+/// Header of a container.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerHeader {
+    /// Container length including the header
+    pub length: u32,
+    pub container_type: ContainerType,
+    pub code: u16,
+    pub transaction_id: u32,
+}
+
+impl ContainerHeader {
+    pub fn decode(bytes: &[u8]) -> Result<ContainerHeader, Box<dyn std::error::Error>> {
+        if bytes.len() < HEADER_SIZE {
+            return Err(format!("container header is too short: {} bytes", bytes.len()).into());
+        }
+        let length = u32::from_le_bytes(bytes[0..4].try_into()?);
+        let type_value = u16::from_le_bytes(bytes[4..6].try_into()?);
+        let code = u16::from_le_bytes(bytes[6..8].try_into()?);
+        let transaction_id = u32::from_le_bytes(bytes[8..12].try_into()?);
+        let container_type = match ContainerType::from_u16(type_value) {
+            Some(t) => t,
+            None => return Err(format!("unknown container type: {}", type_value).into()),
+        };
+        if (length as usize) < HEADER_SIZE {
+            return Err(format!("invalid container length: {}", length).into());
+        }
+        Ok(ContainerHeader {
+            length,
+            container_type,
+            code,
+            transaction_id,
+        })
+    }
+
+    pub fn encode(&self) -> [u8; HEADER_SIZE] {
+        let mut bytes = [0u8; HEADER_SIZE];
+        bytes[0..4].copy_from_slice(&self.length.to_le_bytes());
+        bytes[4..6].copy_from_slice(&(self.container_type as u16).to_le_bytes());
+        bytes[6..8].copy_from_slice(&self.code.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.transaction_id.to_le_bytes());
+        bytes
+    }
+
+    /// Size of the payload
+    pub fn payload_length(&self) -> usize {
+        self.length as usize - HEADER_SIZE
+    }
+}
+
+/// A container with its payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Container {
+    pub container_type: ContainerType,
+    pub code: u16,
+    pub transaction_id: u32,
+    pub payload: Vec<u8>,
+}
+
+impl Container {
+    pub fn command(code: u16, transaction_id: u32, params: &[u32]) -> Container {
+        Container::with_params(ContainerType::Command, code, transaction_id, params)
+    }
+
+    pub fn response(code: u16, transaction_id: u32, params: &[u32]) -> Container {
+        Container::with_params(ContainerType::Response, code, transaction_id, params)
+    }
+
+    pub fn event(code: u16, transaction_id: u32, params: &[u32]) -> Container {
+        Container::with_params(ContainerType::Event, code, transaction_id, params)
+    }
+
+    fn with_params(
+        container_type: ContainerType,
+        code: u16,
+        transaction_id: u32,
+        params: &[u32],
+    ) -> Container {
+        assert!(params.len() <= MAX_PARAMS);
+        let mut payload = Vec::<u8>::with_capacity(params.len() * 4);
+        for param in params {
+            payload.extend_from_slice(&param.to_le_bytes());
+        }
+        Container {
+            container_type,
+            code,
+            transaction_id,
+            payload,
+        }
+    }
+
+    /// Returns parameters of the command, response or event container.
+    pub fn params(&self) -> Result<Vec<u32>, Box<dyn std::error::Error>> {
+        if self.container_type == ContainerType::Data {
+            return Err("data container has no parameters.".into());
+        }
+        if self.payload.len() % 4 != 0 || self.payload.len() > MAX_PARAMS * 4 {
+            return Err(format!("invalid parameter length: {}", self.payload.len()).into());
+        }
+        Ok(self
+            .payload
+            .chunks(4)
+            .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect())
+    }
+
+    /// Returns a parameter, or 0 if the parameter was omitted.
+    pub fn param(&self, index: usize) -> Result<u32, Box<dyn std::error::Error>> {
+        Ok(self.params()?.get(index).copied().unwrap_or(0))
+    }
+
+    pub fn header(&self) -> ContainerHeader {
+        ContainerHeader {
+            length: (HEADER_SIZE + self.payload.len()) as u32,
+            container_type: self.container_type,
+            code: self.code,
+            transaction_id: self.transaction_id,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::<u8>::with_capacity(HEADER_SIZE + self.payload.len());
+        bytes.extend_from_slice(&self.header().encode());
+        bytes.extend_from_slice(&self.payload);
+        bytes
+    }
+
+    /// Decodes a container.
+    /// `bytes` must contain exactly one container.
+    #[cfg(test)]
+    pub fn decode(bytes: &[u8]) -> Result<Container, Box<dyn std::error::Error>> {
+        let header = ContainerHeader::decode(bytes)?;
+        if header.length as usize != bytes.len() {
+            return Err(format!(
+                "container length mismatch: header says {} bytes, but {} bytes were given.",
+                header.length,
+                bytes.len()
+            )
+            .into());
+        }
+        Ok(Container {
+            container_type: header.container_type,
+            code: header.code,
+            transaction_id: header.transaction_id,
+            payload: bytes[HEADER_SIZE..].to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mtp::codes::{operation, response};
+
+    // OpenSession(SessionID=1), captured from a USB trace
+    const OPEN_SESSION_COMMAND: &[u8] = &[
+        0x10, 0x00, 0x00, 0x00, 0x01, 0x00, 0x02, 0x10, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00,
+        0x00,
+    ];
+
+    // OK response to GetObjectHandles, without parameters
+    const OK_RESPONSE: &[u8] = &[
+        0x0c, 0x00, 0x00, 0x00, 0x03, 0x00, 0x01, 0x20, 0x05, 0x00, 0x00, 0x00,
+    ];
+
+    // GetObjectHandles data phase: array of 2 handles
+    const OBJECT_HANDLES_DATA: &[u8] = &[
+        0x18, 0x00, 0x00, 0x00, 0x02, 0x00, 0x07, 0x10, 0x05, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00,
+        0x00, 0x01, 0x00, 0x00, 0x00, 0x2a, 0x00, 0x00, 0x00,
+    ];
+
+    // ObjectAdded event (ObjectHandle=0x2a)
+    const OBJECT_ADDED_EVENT: &[u8] = &[
+        0x10, 0x00, 0x00, 0x00, 0x04, 0x00, 0x02, 0x40, 0xff, 0xff, 0xff, 0xff, 0x2a, 0x00, 0x00,
+        0x00,
+    ];
+
+    #[test]
+    fn test_command() {
+        let container = Container::decode(OPEN_SESSION_COMMAND).unwrap();
+        assert_eq!(container.container_type, ContainerType::Command);
+        assert_eq!(container.code, operation::OPEN_SESSION);
+        assert_eq!(container.transaction_id, 0);
+        assert_eq!(container.params().unwrap(), vec![1]);
+        assert_eq!(container.param(1).unwrap(), 0);
+
+        let encoded = Container::command(operation::OPEN_SESSION, 0, &[1]).encode();
+        assert_eq!(encoded, OPEN_SESSION_COMMAND);
+    }
+
+    #[test]
+    fn test_response() {
+        let container = Container::decode(OK_RESPONSE).unwrap();
+        assert_eq!(container.container_type, ContainerType::Response);
+        assert_eq!(container.code, response::OK);
+        assert_eq!(container.transaction_id, 5);
+        assert!(container.params().unwrap().is_empty());
+
+        assert_eq!(
+            Container::response(response::OK, 5, &[]).encode(),
+            OK_RESPONSE
+        );
+    }
+
+    #[test]
+    fn test_data() {
+        let container = Container::decode(OBJECT_HANDLES_DATA).unwrap();
+        assert_eq!(container.container_type, ContainerType::Data);
+        assert_eq!(container.code, operation::GET_OBJECT_HANDLES);
+        assert_eq!(container.payload.len(), 12);
+        assert!(container.params().is_err());
+
+        assert_eq!(container.encode(), OBJECT_HANDLES_DATA);
+    }
+
+    #[test]
+    fn test_event() {
+        let container = Container::decode(OBJECT_ADDED_EVENT).unwrap();
+        assert_eq!(container.container_type, ContainerType::Event);
+        assert_eq!(container.transaction_id, 0xffffffff);
+        assert_eq!(container.params().unwrap(), vec![0x2a]);
+
+        let encoded = Container::event(container.code, 0xffffffff, &[0x2a]).encode();
+        assert_eq!(encoded, OBJECT_ADDED_EVENT);
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        // too short
+        assert!(Container::decode(&OK_RESPONSE[..8]).is_err());
+        // length mismatch
+        assert!(Container::decode(&OPEN_SESSION_COMMAND[..14]).is_err());
+        // unknown type
+        let mut bytes = OK_RESPONSE.to_vec();
+        bytes[4] = 9;
+        assert!(Container::decode(&bytes).is_err());
+        // too many parameters
+        let mut container = Container::command(operation::OPEN_SESSION, 0, &[1, 2, 3, 4, 5]);
+        container.payload.extend_from_slice(&[0, 0, 0, 0]);
+        assert!(container.params().is_err());
+    }
+}
@@ -0,0 +1,695 @@
+//! Datasets of PTP/MTP transferred in the data phase.
+
+use chrono::{NaiveDate, NaiveDateTime};
+use std::convert::TryInto;
+
+use super::codes::datatype;
+
+/// Reads values from a dataset.
+pub struct DataReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+macro_rules! read_le {
+    ($fn_name:ident, $t:ty) => {
+        pub fn $fn_name(&mut self) -> Result<$t, Box<dyn std::error::Error>> {
+            let bytes = self.take(std::mem::size_of::<$t>())?;
+            Ok(<$t>::from_le_bytes(bytes.try_into()?))
+        }
+    };
+}
+
+impl<'a> DataReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> DataReader<'a> {
+        DataReader { bytes, pos: 0 }
+    }
+
+    fn take(&mut self, size: usize) -> Result<&'a [u8], Box<dyn std::error::Error>> {
+        if self.bytes.len() - self.pos < size {
+            return Err(format!("unexpected end of the dataset at offset {}", self.pos).into());
+        }
+        let bytes = &self.bytes[self.pos..self.pos + size];
+        self.pos += size;
+        Ok(bytes)
+    }
+
+    read_le!(read_i8, i8);
+    read_le!(read_u8, u8);
+    read_le!(read_i16, i16);
+    read_le!(read_u16, u16);
+    read_le!(read_i32, i32);
+    read_le!(read_u32, u32);
+    read_le!(read_i64, i64);
+    read_le!(read_u64, u64);
+    read_le!(read_i128, i128);
+    read_le!(read_u128, u128);
+
+    pub fn read_string(&mut self) -> Result<String, Box<dyn std::error::Error>> {
+        let num_chars = self.read_u8()? as usize;
+        if num_chars == 0 {
+            return Ok(String::new());
+        }
+        let mut chars = Vec::<u16>::with_capacity(num_chars);
+        for _ in 0..num_chars {
+            chars.push(self.read_u16()?);
+        }
+        if chars.pop() != Some(0) {
+            return Err("string is not null-terminated.".into());
+        }
+        Ok(String::from_utf16(&chars)?)
+    }
+
+    pub fn read_u16_array(&mut self) -> Result<Vec<u16>, Box<dyn std::error::Error>> {
+        let count = self.read_u32()? as usize;
+        let mut values = Vec::<u16>::with_capacity(std::cmp::min(count, 1024));
+        for _ in 0..count {
+            values.push(self.read_u16()?);
+        }
+        Ok(values)
+    }
+
+    pub fn read_u32_array(&mut self) -> Result<Vec<u32>, Box<dyn std::error::Error>> {
+        let count = self.read_u32()? as usize;
+        let mut values = Vec::<u32>::with_capacity(std::cmp::min(count, 1024));
+        for _ in 0..count {
+            values.push(self.read_u32()?);
+        }
+        Ok(values)
+    }
+
+    /// Fails if there are remaining bytes.
+    pub fn finish(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.pos != self.bytes.len() {
+            return Err(format!(
+                "{} extra bytes at the end of the dataset",
+                self.bytes.len() - self.pos
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// Writes values to a dataset.
+pub struct DataWriter {
+    bytes: Vec<u8>,
+}
+
+macro_rules! write_le {
+    ($fn_name:ident, $t:ty) => {
+        pub fn $fn_name(&mut self, value: $t) {
+            self.bytes.extend_from_slice(&value.to_le_bytes());
+        }
+    };
+}
+
+impl DataWriter {
+    pub fn new() -> DataWriter {
+        DataWriter { bytes: Vec::new() }
+    }
+
+    write_le!(write_i8, i8);
+    write_le!(write_u8, u8);
+    write_le!(write_i16, i16);
+    write_le!(write_u16, u16);
+    write_le!(write_i32, i32);
+    write_le!(write_u32, u32);
+    write_le!(write_i64, i64);
+    write_le!(write_u64, u64);
+    write_le!(write_i128, i128);
+    write_le!(write_u128, u128);
+
+    /// Writes a string.
+    /// Characters that exceed the max length (254 characters) are truncated.
+    pub fn write_string(&mut self, value: &str) {
+        let mut chars: Vec<u16> = value.encode_utf16().take(254).collect();
+        if chars.is_empty() {
+            self.write_u8(0);
+            return;
+        }
+        chars.push(0);
+        self.write_u8(chars.len() as u8);
+        for c in chars {
+            self.write_u16(c);
+        }
+    }
+
+    pub fn write_u16_array(&mut self, values: &[u16]) {
+        self.write_u32(values.len() as u32);
+        for value in values {
+            self.write_u16(*value);
+        }
+    }
+
+    pub fn write_u32_array(&mut self, values: &[u32]) {
+        self.write_u32(values.len() as u32);
+        for value in values {
+            self.write_u32(*value);
+        }
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+/// Parses a DateTime string (`YYYYMMDDThhmmss[.s][Z|+hhmm|-hhmm]`).
+/// The fraction and the time zone are ignored.
+pub fn parse_datetime(value: &str) -> Option<NaiveDateTime> {
+    if value.len() < 15 || !value.is_char_boundary(15) {
+        return None;
+    }
+    let v = &value[..15];
+    if &v[8..9] != "T" {
+        return None;
+    }
+    let num = |range: std::ops::Range<usize>| v[range].parse::<u32>().ok();
+    NaiveDate::from_ymd_opt(num(0..4)? as i32, num(4..6)?, num(6..8)?)?.and_hms_opt(
+        num(9..11)?,
+        num(11..13)?,
+        num(13..15)?,
+    )
+}
+
+/// Formats a DateTime string (`YYYYMMDDThhmmss`), or an empty string.
+pub fn format_datetime(value: &Option<NaiveDateTime>) -> String {
+    match value {
+        Some(dt) => dt.format("%Y%m%dT%H%M%S").to_string(),
+        None => String::new(),
+    }
+}
+
+/// DeviceInfo dataset
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub standard_version: u16,
+    pub vendor_extension_id: u32,
+    pub vendor_extension_version: u16,
+    pub vendor_extension_desc: String,
+    pub functional_mode: u16,
+    pub operations_supported: Vec<u16>,
+    pub events_supported: Vec<u16>,
+    pub device_properties_supported: Vec<u16>,
+    pub capture_formats: Vec<u16>,
+    pub playback_formats: Vec<u16>,
+    pub manufacturer: String,
+    pub model: String,
+    pub device_version: String,
+    pub serial_number: String,
+}
+
+impl DeviceInfo {
+    pub fn decode(bytes: &[u8]) -> Result<DeviceInfo, Box<dyn std::error::Error>> {
+        let mut r = DataReader::new(bytes);
+        let device_info = DeviceInfo {
+            standard_version: r.read_u16()?,
+            vendor_extension_id: r.read_u32()?,
+            vendor_extension_version: r.read_u16()?,
+            vendor_extension_desc: r.read_string()?,
+            functional_mode: r.read_u16()?,
+            operations_supported: r.read_u16_array()?,
+            events_supported: r.read_u16_array()?,
+            device_properties_supported: r.read_u16_array()?,
+            capture_formats: r.read_u16_array()?,
+            playback_formats: r.read_u16_array()?,
+            manufacturer: r.read_string()?,
+            model: r.read_string()?,
+            device_version: r.read_string()?,
+            serial_number: r.read_string()?,
+        };
+        r.finish()?;
+        Ok(device_info)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut w = DataWriter::new();
+        w.write_u16(self.standard_version);
+        w.write_u32(self.vendor_extension_id);
+        w.write_u16(self.vendor_extension_version);
+        w.write_string(&self.vendor_extension_desc);
+        w.write_u16(self.functional_mode);
+        w.write_u16_array(&self.operations_supported);
+        w.write_u16_array(&self.events_supported);
+        w.write_u16_array(&self.device_properties_supported);
+        w.write_u16_array(&self.capture_formats);
+        w.write_u16_array(&self.playback_formats);
+        w.write_string(&self.manufacturer);
+        w.write_string(&self.model);
+        w.write_string(&self.device_version);
+        w.write_string(&self.serial_number);
+        w.into_bytes()
+    }
+}
+
+/// StorageInfo dataset
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageInfo {
+    pub storage_type: u16,
+    pub filesystem_type: u16,
+    pub access_capability: u16,
+    pub max_capacity: u64,
+    pub free_space_in_bytes: u64,
+    pub free_space_in_objects: u32,
+    pub storage_description: String,
+    pub volume_identifier: String,
+}
+
+impl StorageInfo {
+    pub fn decode(bytes: &[u8]) -> Result<StorageInfo, Box<dyn std::error::Error>> {
+        let mut r = DataReader::new(bytes);
+        let storage_info = StorageInfo {
+            storage_type: r.read_u16()?,
+            filesystem_type: r.read_u16()?,
+            access_capability: r.read_u16()?,
+            max_capacity: r.read_u64()?,
+            free_space_in_bytes: r.read_u64()?,
+            free_space_in_objects: r.read_u32()?,
+            storage_description: r.read_string()?,
+            volume_identifier: r.read_string()?,
+        };
+        r.finish()?;
+        Ok(storage_info)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut w = DataWriter::new();
+        w.write_u16(self.storage_type);
+        w.write_u16(self.filesystem_type);
+        w.write_u16(self.access_capability);
+        w.write_u64(self.max_capacity);
+        w.write_u64(self.free_space_in_bytes);
+        w.write_u32(self.free_space_in_objects);
+        w.write_string(&self.storage_description);
+        w.write_string(&self.volume_identifier);
+        w.into_bytes()
+    }
+}
+
+/// ObjectInfo dataset
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectInfo {
+    pub storage_id: u32,
+    pub object_format: u16,
+    pub protection_status: u16,
+    /// Object size (0xFFFFFFFF if the size is 4GB or larger)
+    pub object_compressed_size: u32,
+    pub thumb_format: u16,
+    pub thumb_compressed_size: u32,
+    pub thumb_pix_width: u32,
+    pub thumb_pix_height: u32,
+    pub image_pix_width: u32,
+    pub image_pix_height: u32,
+    pub image_bit_depth: u32,
+    pub parent_object: u32,
+    pub association_type: u16,
+    pub association_desc: u32,
+    pub sequence_number: u32,
+    pub filename: String,
+    pub date_created: Option<NaiveDateTime>,
+    pub date_modified: Option<NaiveDateTime>,
+    pub keywords: String,
+}
+
+impl ObjectInfo {
+    pub fn decode(bytes: &[u8]) -> Result<ObjectInfo, Box<dyn std::error::Error>> {
+        let mut r = DataReader::new(bytes);
+        let object_info = ObjectInfo {
+            storage_id: r.read_u32()?,
+            object_format: r.read_u16()?,
+            protection_status: r.read_u16()?,
+            object_compressed_size: r.read_u32()?,
+            thumb_format: r.read_u16()?,
+            thumb_compressed_size: r.read_u32()?,
+            thumb_pix_width: r.read_u32()?,
+            thumb_pix_height: r.read_u32()?,
+            image_pix_width: r.read_u32()?,
+            image_pix_height: r.read_u32()?,
+            image_bit_depth: r.read_u32()?,
+            parent_object: r.read_u32()?,
+            association_type: r.read_u16()?,
+            association_desc: r.read_u32()?,
+            sequence_number: r.read_u32()?,
+            filename: r.read_string()?,
+            date_created: parse_datetime(&r.read_string()?),
+            date_modified: parse_datetime(&r.read_string()?),
+            keywords: r.read_string()?,
+        };
+        r.finish()?;
+        Ok(object_info)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut w = DataWriter::new();
+        w.write_u32(self.storage_id);
+        w.write_u16(self.object_format);
+        w.write_u16(self.protection_status);
+        w.write_u32(self.object_compressed_size);
+        w.write_u16(self.thumb_format);
+        w.write_u32(self.thumb_compressed_size);
+        w.write_u32(self.thumb_pix_width);
+        w.write_u32(self.thumb_pix_height);
+        w.write_u32(self.image_pix_width);
+        w.write_u32(self.image_pix_height);
+        w.write_u32(self.image_bit_depth);
+        w.write_u32(self.parent_object);
+        w.write_u16(self.association_type);
+        w.write_u32(self.association_desc);
+        w.write_u32(self.sequence_number);
+        w.write_string(&self.filename);
+        w.write_string(&format_datetime(&self.date_created));
+        w.write_string(&format_datetime(&self.date_modified));
+        w.write_string(&self.keywords);
+        w.into_bytes()
+    }
+}
+
+/// A value of the object property.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(dead_code)] // GetObjectPropList is not used by the session yet
+pub enum PropValue {
+    Int8(i8),
+    UInt8(u8),
+    Int16(i16),
+    UInt16(u16),
+    Int32(i32),
+    UInt32(u32),
+    Int64(i64),
+    UInt64(u64),
+    Int128(i128),
+    UInt128(u128),
+    Str(String),
+}
+
+#[allow(dead_code)]
+impl PropValue {
+    pub fn datatype(&self) -> u16 {
+        match self {
+            PropValue::Int8(_) => datatype::INT8,
+            PropValue::UInt8(_) => datatype::UINT8,
+            PropValue::Int16(_) => datatype::INT16,
+            PropValue::UInt16(_) => datatype::UINT16,
+            PropValue::Int32(_) => datatype::INT32,
+            PropValue::UInt32(_) => datatype::UINT32,
+            PropValue::Int64(_) => datatype::INT64,
+            PropValue::UInt64(_) => datatype::UINT64,
+            PropValue::Int128(_) => datatype::INT128,
+            PropValue::UInt128(_) => datatype::UINT128,
+            PropValue::Str(_) => datatype::STR,
+        }
+    }
+
+    /// Returns the value as u64 if it is an unsigned integer.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            PropValue::UInt8(v) => Some(*v as u64),
+            PropValue::UInt16(v) => Some(*v as u64),
+            PropValue::UInt32(v) => Some(*v as u64),
+            PropValue::UInt64(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            PropValue::Str(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn read(
+        r: &mut DataReader,
+        value_datatype: u16,
+    ) -> Result<PropValue, Box<dyn std::error::Error>> {
+        Ok(match value_datatype {
+            datatype::INT8 => PropValue::Int8(r.read_i8()?),
+            datatype::UINT8 => PropValue::UInt8(r.read_u8()?),
+            datatype::INT16 => PropValue::Int16(r.read_i16()?),
+            datatype::UINT16 => PropValue::UInt16(r.read_u16()?),
+            datatype::INT32 => PropValue::Int32(r.read_i32()?),
+            datatype::UINT32 => PropValue::UInt32(r.read_u32()?),
+            datatype::INT64 => PropValue::Int64(r.read_i64()?),
+            datatype::UINT64 => PropValue::UInt64(r.read_u64()?),
+            datatype::INT128 => PropValue::Int128(r.read_i128()?),
+            datatype::UINT128 => PropValue::UInt128(r.read_u128()?),
+            datatype::STR => PropValue::Str(r.read_string()?),
+            _ => return Err(format!("unsupported datatype: 0x{:04x}", value_datatype).into()),
+        })
+    }
+
+    pub fn write(&self, w: &mut DataWriter) {
+        match self {
+            PropValue::Int8(v) => w.write_i8(*v),
+            PropValue::UInt8(v) => w.write_u8(*v),
+            PropValue::Int16(v) => w.write_i16(*v),
+            PropValue::UInt16(v) => w.write_u16(*v),
+            PropValue::Int32(v) => w.write_i32(*v),
+            PropValue::UInt32(v) => w.write_u32(*v),
+            PropValue::Int64(v) => w.write_i64(*v),
+            PropValue::UInt64(v) => w.write_u64(*v),
+            PropValue::Int128(v) => w.write_i128(*v),
+            PropValue::UInt128(v) => w.write_u128(*v),
+            PropValue::Str(v) => w.write_string(v),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(dead_code)]
+pub struct ObjectPropElement {
+    pub object_handle: u32,
+    pub property_code: u16,
+    pub value: PropValue,
+}
+
+/// ObjectPropList dataset
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(dead_code)]
+pub struct ObjectPropList {
+    pub elements: Vec<ObjectPropElement>,
+}
+
+#[allow(dead_code)]
+impl ObjectPropList {
+    pub fn decode(bytes: &[u8]) -> Result<ObjectPropList, Box<dyn std::error::Error>> {
+        let mut r = DataReader::new(bytes);
+        let count = r.read_u32()? as usize;
+        let mut elements = Vec::<ObjectPropElement>::with_capacity(std::cmp::min(count, 1024));
+        for _ in 0..count {
+            let object_handle = r.read_u32()?;
+            let property_code = r.read_u16()?;
+            let value_datatype = r.read_u16()?;
+            let value = PropValue::read(&mut r, value_datatype)?;
+            elements.push(ObjectPropElement {
+                object_handle,
+                property_code,
+                value,
+            });
+        }
+        r.finish()?;
+        Ok(ObjectPropList { elements })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut w = DataWriter::new();
+        w.write_u32(self.elements.len() as u32);
+        for element in self.elements.iter() {
+            w.write_u32(element.object_handle);
+            w.write_u16(element.property_code);
+            w.write_u16(element.value.datatype());
+            element.value.write(&mut w);
+        }
+        w.into_bytes()
+    }
+
+    /// Finds a property value of the object.
+    pub fn get(&self, object_handle: u32, property_code: u16) -> Option<&PropValue> {
+        self.elements
+            .iter()
+            .find(|e| e.object_handle == object_handle && e.property_code == property_code)
+            .map(|e| &e.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mtp::codes::{format, object_property, operation};
+
+    // captured from an Android device, and edited to shorten
+    const DEVICE_INFO: &[u8] = &[
+        0x64, 0x00, 0x06, 0x00, 0x00, 0x00, 0x64, 0x00, 0x26, 0x6d, 0x00, 0x69, 0x00, 0x63, 0x00,
+        0x72, 0x00, 0x6f, 0x00, 0x73, 0x00, 0x6f, 0x00, 0x66, 0x00, 0x74, 0x00, 0x2e, 0x00, 0x63,
+        0x00, 0x6f, 0x00, 0x6d, 0x00, 0x3a, 0x00, 0x20, 0x00, 0x31, 0x00, 0x2e, 0x00, 0x30, 0x00,
+        0x3b, 0x00, 0x20, 0x00, 0x61, 0x00, 0x6e, 0x00, 0x64, 0x00, 0x72, 0x00, 0x6f, 0x00, 0x69,
+        0x00, 0x64, 0x00, 0x2e, 0x00, 0x63, 0x00, 0x6f, 0x00, 0x6d, 0x00, 0x3a, 0x00, 0x20, 0x00,
+        0x31, 0x00, 0x2e, 0x00, 0x30, 0x00, 0x3b, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x00, 0x00,
+        0x00, 0x01, 0x10, 0x02, 0x10, 0x03, 0x10, 0x04, 0x10, 0x05, 0x10, 0x07, 0x10, 0x08, 0x10,
+        0x09, 0x10, 0x0b, 0x10, 0x0c, 0x10, 0x0d, 0x10, 0x05, 0x98, 0x02, 0x00, 0x00, 0x00, 0x02,
+        0x40, 0x03, 0x40, 0x01, 0x00, 0x00, 0x00, 0x02, 0xd4, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00,
+        0x00, 0x00, 0x00, 0x30, 0x01, 0x30, 0x01, 0x38, 0x07, 0x47, 0x00, 0x6f, 0x00, 0x6f, 0x00,
+        0x67, 0x00, 0x6c, 0x00, 0x65, 0x00, 0x00, 0x00, 0x09, 0x50, 0x00, 0x69, 0x00, 0x78, 0x00,
+        0x65, 0x00, 0x6c, 0x00, 0x20, 0x00, 0x33, 0x00, 0x61, 0x00, 0x00, 0x00, 0x04, 0x31, 0x00,
+        0x2e, 0x00, 0x30, 0x00, 0x00, 0x00, 0x0a, 0x38, 0x00, 0x41, 0x00, 0x42, 0x00, 0x58, 0x00,
+        0x30, 0x00, 0x59, 0x00, 0x31, 0x00, 0x32, 0x00, 0x33, 0x00, 0x00, 0x00,
+    ];
+    const STORAGE_INFO: &[u8] = &[
+        0x03, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x35,
+        0x1c, 0xdc, 0xdf, 0x02, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0x18, 0x49, 0x00, 0x6e,
+        0x00, 0x74, 0x00, 0x65, 0x00, 0x72, 0x00, 0x6e, 0x00, 0x61, 0x00, 0x6c, 0x00, 0x20, 0x00,
+        0x73, 0x00, 0x68, 0x00, 0x61, 0x00, 0x72, 0x00, 0x65, 0x00, 0x64, 0x00, 0x20, 0x00, 0x73,
+        0x00, 0x74, 0x00, 0x6f, 0x00, 0x72, 0x00, 0x61, 0x00, 0x67, 0x00, 0x65, 0x00, 0x00, 0x00,
+        0x00,
+    ];
+    const OBJECT_INFO: &[u8] = &[
+        0x01, 0x00, 0x01, 0x00, 0x01, 0x38, 0x00, 0x00, 0x40, 0xe2, 0x01, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x2a, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x00, 0x4d, 0x00, 0x47, 0x00, 0x5f,
+        0x00, 0x30, 0x00, 0x30, 0x00, 0x30, 0x00, 0x31, 0x00, 0x2e, 0x00, 0x4a, 0x00, 0x50, 0x00,
+        0x47, 0x00, 0x00, 0x00, 0x10, 0x32, 0x00, 0x30, 0x00, 0x32, 0x00, 0x31, 0x00, 0x30, 0x00,
+        0x34, 0x00, 0x30, 0x00, 0x31, 0x00, 0x54, 0x00, 0x31, 0x00, 0x32, 0x00, 0x33, 0x00, 0x34,
+        0x00, 0x35, 0x00, 0x36, 0x00, 0x00, 0x00, 0x10, 0x32, 0x00, 0x30, 0x00, 0x32, 0x00, 0x31,
+        0x00, 0x30, 0x00, 0x34, 0x00, 0x30, 0x00, 0x32, 0x00, 0x54, 0x00, 0x30, 0x00, 0x38, 0x00,
+        0x30, 0x00, 0x39, 0x00, 0x31, 0x00, 0x30, 0x00, 0x00, 0x00, 0x00,
+    ];
+    const OBJECT_PROP_LIST: &[u8] = &[
+        0x04, 0x00, 0x00, 0x00, 0x2b, 0x00, 0x00, 0x00, 0x07, 0xdc, 0xff, 0xff, 0x06, 0x61, 0x00,
+        0x2e, 0x00, 0x6d, 0x00, 0x70, 0x00, 0x33, 0x00, 0x00, 0x00, 0x2b, 0x00, 0x00, 0x00, 0x04,
+        0xdc, 0x08, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x2b, 0x00, 0x00, 0x00,
+        0x01, 0xdc, 0x06, 0x00, 0x01, 0x00, 0x01, 0x00, 0x2b, 0x00, 0x00, 0x00, 0x09, 0xdc, 0xff,
+        0xff, 0x10, 0x32, 0x00, 0x30, 0x00, 0x32, 0x00, 0x31, 0x00, 0x30, 0x00, 0x34, 0x00, 0x30,
+        0x00, 0x31, 0x00, 0x54, 0x00, 0x31, 0x00, 0x32, 0x00, 0x33, 0x00, 0x34, 0x00, 0x35, 0x00,
+        0x36, 0x00, 0x00, 0x00,
+    ];
+
+    fn datetime(
+        year: i32,
+        month: u32,
+        day: u32,
+        hour: u32,
+        min: u32,
+        sec: u32,
+    ) -> Option<NaiveDateTime> {
+        Some(NaiveDate::from_ymd(year, month, day).and_hms(hour, min, sec))
+    }
+
+    #[test]
+    fn test_device_info() {
+        let device_info = DeviceInfo::decode(DEVICE_INFO).unwrap();
+        assert_eq!(device_info.standard_version, 100);
+        assert_eq!(device_info.vendor_extension_id, 6);
+        assert_eq!(
+            device_info.vendor_extension_desc,
+            "microsoft.com: 1.0; android.com: 1.0;"
+        );
+        assert_eq!(device_info.operations_supported.len(), 12);
+        assert!(device_info
+            .operations_supported
+            .contains(&operation::GET_OBJECT_PROP_LIST));
+        assert_eq!(device_info.events_supported, vec![0x4002, 0x4003]);
+        assert!(device_info.capture_formats.is_empty());
+        assert_eq!(device_info.manufacturer, "Google");
+        assert_eq!(device_info.model, "Pixel 3a");
+        assert_eq!(device_info.device_version, "1.0");
+        assert_eq!(device_info.serial_number, "8ABX0Y123");
+
+        assert_eq!(device_info.encode(), DEVICE_INFO);
+    }
+
+    #[test]
+    fn test_storage_info() {
+        let storage_info = StorageInfo::decode(STORAGE_INFO).unwrap();
+        assert_eq!(storage_info.storage_type, 3);
+        assert_eq!(storage_info.max_capacity, 64 * 1024 * 1024 * 1024);
+        assert_eq!(storage_info.free_space_in_bytes, 12345678901);
+        assert_eq!(storage_info.free_space_in_objects, 0xffffffff);
+        assert_eq!(storage_info.storage_description, "Internal shared storage");
+        assert_eq!(storage_info.volume_identifier, "");
+
+        assert_eq!(storage_info.encode(), STORAGE_INFO);
+    }
+
+    #[test]
+    fn test_object_info() {
+        let object_info = ObjectInfo::decode(OBJECT_INFO).unwrap();
+        assert_eq!(object_info.storage_id, 0x00010001);
+        assert_eq!(object_info.object_format, format::EXIF_JPEG);
+        assert_eq!(object_info.object_compressed_size, 123456);
+        assert_eq!(object_info.parent_object, 0x2a);
+        assert_eq!(object_info.filename, "IMG_0001.JPG");
+        assert_eq!(object_info.date_created, datetime(2021, 4, 1, 12, 34, 56));
+        assert_eq!(object_info.date_modified, datetime(2021, 4, 2, 8, 9, 10));
+        assert_eq!(object_info.keywords, "");
+
+        assert_eq!(object_info.encode(), OBJECT_INFO);
+    }
+
+    #[test]
+    fn test_object_prop_list() {
+        let prop_list = ObjectPropList::decode(OBJECT_PROP_LIST).unwrap();
+        assert_eq!(prop_list.elements.len(), 4);
+        assert_eq!(
+            prop_list.get(0x2b, object_property::OBJECT_FILE_NAME),
+            Some(&PropValue::Str("a.mp3".to_string()))
+        );
+        assert_eq!(
+            prop_list
+                .get(0x2b, object_property::OBJECT_SIZE)
+                .and_then(|v| v.as_u64()),
+            Some(4)
+        );
+        assert_eq!(
+            prop_list.get(0x2b, object_property::STORAGE_ID),
+            Some(&PropValue::UInt32(0x10001))
+        );
+        assert_eq!(prop_list.get(0x2c, object_property::STORAGE_ID), None);
+
+        assert_eq!(prop_list.encode(), OBJECT_PROP_LIST);
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        assert!(DeviceInfo::decode(&DEVICE_INFO[..DEVICE_INFO.len() - 1]).is_err());
+        let mut bytes = STORAGE_INFO.to_vec();
+        bytes.push(0);
+        assert!(StorageInfo::decode(&bytes).is_err());
+        let mut bytes = OBJECT_PROP_LIST.to_vec();
+        bytes[10] = 0x0b; // unsupported datatype
+        assert!(ObjectPropList::decode(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_string() {
+        let mut w = DataWriter::new();
+        w.write_string("");
+        w.write_string("日本語");
+        w.write_string(&"x".repeat(300));
+        let bytes = w.into_bytes();
+        assert_eq!(&bytes[..3], &[0x00, 0x04, 0xe5]);
+
+        let mut r = DataReader::new(&bytes);
+        assert_eq!(r.read_string().unwrap(), "");
+        assert_eq!(r.read_string().unwrap(), "日本語");
+        assert_eq!(r.read_string().unwrap(), "x".repeat(254));
+        r.finish().unwrap();
+
+        // not null-terminated
+        let mut r = DataReader::new(&[0x01, 0x41, 0x00]);
+        assert!(r.read_string().is_err());
+    }
+
+    #[test]
+    fn test_datetime() {
+        let expected = datetime(2021, 4, 1, 12, 34, 56);
+        assert_eq!(parse_datetime("20210401T123456"), expected);
+        assert_eq!(parse_datetime("20210401T123456.7"), expected);
+        assert_eq!(parse_datetime("20210401T123456Z"), expected);
+        assert_eq!(parse_datetime("20210401T123456+0900"), expected);
+        assert_eq!(parse_datetime(""), None);
+        assert_eq!(parse_datetime("20210401 123456"), None);
+        assert_eq!(parse_datetime("20211301T123456"), None);
+        assert_eq!(format_datetime(&expected), "20210401T123456");
+        assert_eq!(format_datetime(&None), "");
+    }
+}
@@ -0,0 +1,952 @@
+use chrono::NaiveDateTime;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::backend::content_object::{ContentObject, ContentObjectInfo, ContentObjectKind};
+use crate::backend::device::{ContentObjectIterator, Device, DeviceEvent, EventSubscription};
+use crate::backend::resource::{ResourceReader, ResourceWriter};
+
+use super::codes::*;
+use super::dataset::ObjectInfo;
+use super::session::Session;
+use super::transport::Transport;
+
+const ROOT_OBJECT_ID: &str = "";
+const DEVICE_OBJECT_ID: &str = "DEVICE";
+const BUFFER_SIZE: u32 = 65536;
+const SESSION_ID: u32 = 1;
+
+/// A device accessed by the MTP initiator.
+///
+/// Object IDs are `S<storage-id>` for storages and `O<object-handle>` for files and folders,
+/// in 8-digit hexadecimal.
+pub struct MtpDevice {
+    name: String,
+    session: Rc<RefCell<Session>>,
+    /// Operations supported by the responder
+    operations_supported: Vec<u16>,
+}
+
+enum ObjectLocation {
+    Root,
+    Device,
+    Storage(u32),
+    Object(u32),
+}
+
+impl MtpDevice {
+    /// Opens a session on the transport.
+    /// If `name` is None, the model name reported by the device is used.
+    pub fn open(
+        transport: Box<dyn Transport>,
+        name: Option<&str>,
+    ) -> Result<MtpDevice, Box<dyn std::error::Error>> {
+        let mut session = Session::new(transport);
+        let device_info = session.get_device_info()?;
+        session.open_session(SESSION_ID)?;
+        let name = match name {
+            Some(name) => name.to_string(),
+            None => device_info.model,
+        };
+        Ok(MtpDevice {
+            name,
+            session: Rc::new(RefCell::new(session)),
+            operations_supported: device_info.operations_supported,
+        })
+    }
+
+    /// Returns the storage ID and the parent handle to create a new object in.
+    fn get_destination(
+        &self,
+        parent: &ContentObject,
+    ) -> Result<(u32, u32), Box<dyn std::error::Error>> {
+        match locate(parent)? {
+            ObjectLocation::Storage(storage_id) => Ok((storage_id, ALL)),
+            ObjectLocation::Object(handle) => {
+                let object_info = self.session.borrow_mut().get_object_info(handle)?;
+                if object_info.object_format != format::ASSOCIATION {
+                    return Err(format!("not a folder: {}", &object_info.filename).into());
+                }
+                Ok((object_info.storage_id, handle))
+            }
+            _ => Err(format!("cannot create an object in: {}", &parent.id).into()),
+        }
+    }
+
+    /// Returns the storage ID and the parent handle to copy or move an object into.
+    /// Unlike SendObjectInfo, the root folder of the storage is specified by 0.
+    fn get_on_device_destination(
+        &self,
+        parent: &ContentObject,
+    ) -> Result<(u32, u32), Box<dyn std::error::Error>> {
+        match self.get_destination(parent)? {
+            (storage_id, ALL) => Ok((storage_id, 0)),
+            destination => Ok(destination),
+        }
+    }
+}
+
+impl Drop for MtpDevice {
+    fn drop(&mut self) {
+        if let Ok(mut session) = self.session.try_borrow_mut() {
+            if let Err(err) = session.close_session() {
+                log::debug!("failed to close the session: {}", err);
+            }
+        }
+    }
+}
+
+fn locate(object: &ContentObject) -> Result<ObjectLocation, Box<dyn std::error::Error>> {
+    let id = object.id.as_str();
+    match id {
+        ROOT_OBJECT_ID => return Ok(ObjectLocation::Root),
+        DEVICE_OBJECT_ID => return Ok(ObjectLocation::Device),
+        _ => {}
+    }
+    let value = id
+        .get(1..)
+        .and_then(|hex| u32::from_str_radix(hex, 16).ok());
+    match (id.get(..1), value) {
+        (Some("S"), Some(storage_id)) => Ok(ObjectLocation::Storage(storage_id)),
+        (Some("O"), Some(handle)) => Ok(ObjectLocation::Object(handle)),
+        _ => Err(format!("invalid object id: {}", id).into()),
+    }
+}
+
+fn storage_object(storage_id: u32) -> ContentObject {
+    ContentObject::new(format!("S{:08X}", storage_id))
+}
+
+fn handle_object(handle: u32) -> ContentObject {
+    ContentObject::new(format!("O{:08X}", handle))
+}
+
+impl Device for MtpDevice {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn get_root_object(&self) -> ContentObject {
+        ContentObject::new(ROOT_OBJECT_ID.to_string())
+    }
+
+    fn get_object_iterator(
+        &self,
+        parent: &ContentObject,
+    ) -> Result<Box<dyn ContentObjectIterator>, Box<dyn std::error::Error>> {
+        let mut children: Vec<ContentObject> = match locate(parent)? {
+            ObjectLocation::Root => vec![ContentObject::new(DEVICE_OBJECT_ID.to_string())],
+            ObjectLocation::Device => self
+                .session
+                .borrow_mut()
+                .get_storage_ids()?
+                .into_iter()
+                .map(storage_object)
+                .collect(),
+            ObjectLocation::Storage(storage_id) => self
+                .session
+                .borrow_mut()
+                .get_object_handles(storage_id, ALL)?
+                .into_iter()
+                .map(handle_object)
+                .collect(),
+            ObjectLocation::Object(handle) => self
+                .session
+                .borrow_mut()
+                .get_object_handles(ALL, handle)?
+                .into_iter()
+                .map(handle_object)
+                .collect(),
+        };
+        children.reverse(); // for moving item out by pop()
+        Ok(Box::new(MtpObjectIterator { children }))
+    }
+
+    fn get_object_info(
+        &self,
+        object: ContentObject,
+    ) -> Result<ContentObjectInfo, Box<dyn std::error::Error>> {
+        let (name, kind) = match locate(&object)? {
+            ObjectLocation::Root => (String::new(), ContentObjectKind::FunctionalObject),
+            ObjectLocation::Device => (self.name.clone(), ContentObjectKind::Device),
+            ObjectLocation::Storage(storage_id) => {
+                let storage_info = self.session.borrow_mut().get_storage_info(storage_id)?;
+                let name = if storage_info.storage_description.is_empty() {
+                    storage_info.volume_identifier
+                } else {
+                    storage_info.storage_description
+                };
+                (name, ContentObjectKind::Storage)
+            }
+            ObjectLocation::Object(handle) => {
+                let object_info = self.session.borrow_mut().get_object_info(handle)?;
+                let is_folder = object_info.object_format == format::ASSOCIATION;
+                let data_size = if is_folder {
+                    0
+                } else if object_info.object_compressed_size == ALL {
+                    // 4GB or larger
+                    self.session.borrow_mut().get_object_size(handle)?
+                } else {
+                    object_info.object_compressed_size as u64
+                };
+                return Ok(ContentObjectInfo {
+                    content_object: object,
+                    name: object_info.filename,
+                    kind: if is_folder {
+                        ContentObjectKind::Folder
+                    } else {
+                        ContentObjectKind::File
+                    },
+                    data_size,
+                    is_hidden: false,
+                    is_system: false,
+                    can_delete: object_info.protection_status == PROTECTION_STATUS_NONE,
+                    time_created: object_info.date_created,
+                    time_modified: object_info.date_modified,
+                });
+            }
+        };
+        Ok(ContentObjectInfo {
+            content_object: object,
+            name,
+            kind,
+            data_size: 0,
+            is_hidden: false,
+            is_system: false,
+            can_delete: false,
+            time_created: None,
+            time_modified: None,
+        })
+    }
+
+    fn get_resoure(
+        &self,
+        object: &ContentObject,
+    ) -> Result<Box<dyn ResourceReader>, Box<dyn std::error::Error>> {
+        let handle = match locate(object)? {
+            ObjectLocation::Object(handle) => handle,
+            _ => return Err(format!("not a file: {}", &object.id).into()),
+        };
+        self.session.borrow_mut().get_object(handle)?;
+        Ok(Box::new(MtpResourceReader {
+            session: self.session.clone(),
+            buf: vec![0u8; BUFFER_SIZE as usize],
+            finished: false,
+        }))
+    }
+
+    fn create_file(
+        &self,
+        parent: &ContentObject,
+        name: &str,
+        size: u64,
+        created: &Option<NaiveDateTime>,
+        modified: &Option<NaiveDateTime>,
+    ) -> Result<Box<dyn ResourceWriter>, Box<dyn std::error::Error>> {
+        let (storage_id, parent_handle) = self.get_destination(parent)?;
+        let object_info = new_object_info(
+            storage_id,
+            parent_handle,
+            format::UNDEFINED,
+            name,
+            size,
+            created,
+            modified,
+        );
+        let mut session = self.session.borrow_mut();
+        let handle = session.send_object_info(storage_id, parent_handle, &object_info)?;
+        session.send_object(size)?;
+        Ok(Box::new(MtpResourceWriter {
+            session: self.session.clone(),
+            handle,
+            size,
+            written: 0,
+            committed: false,
+        }))
+    }
+
+    fn create_folder(
+        &self,
+        parent: &ContentObject,
+        name: &str,
+    ) -> Result<ContentObject, Box<dyn std::error::Error>> {
+        let (storage_id, parent_handle) = self.get_destination(parent)?;
+        let object_info = new_object_info(
+            storage_id,
+            parent_handle,
+            format::ASSOCIATION,
+            name,
+            0,
+            &None,
+            &None,
+        );
+        let handle =
+            self.session
+                .borrow_mut()
+                .send_object_info(storage_id, parent_handle, &object_info)?;
+        Ok(handle_object(handle))
+    }
+
+    fn delete(&self, object: &ContentObject) -> Result<(), Box<dyn std::error::Error>> {
+        match locate(object)? {
+            ObjectLocation::Object(handle) => self.session.borrow_mut().delete_object(handle),
+            _ => Err(format!("cannot delete: {}", &object.id).into()),
+        }
+    }
+
+    fn can_rename(&self, object: &ContentObject) -> bool {
+        matches!(locate(object), Ok(ObjectLocation::Object(_)))
+            && self
+                .operations_supported
+                .contains(&operation::SET_OBJECT_PROP_VALUE)
+    }
+
+    fn rename(
+        &self,
+        object: &ContentObject,
+        name: &str,
+    ) -> Result<ContentObject, Box<dyn std::error::Error>> {
+        match locate(object)? {
+            ObjectLocation::Object(handle) => {
+                self.session
+                    .borrow_mut()
+                    .set_object_file_name(handle, name)?;
+                Ok(object.clone())
+            }
+            _ => Err(format!("cannot rename: {}", &object.id).into()),
+        }
+    }
+
+    fn can_copy_on_device(&self, object: &ContentObject) -> bool {
+        matches!(locate(object), Ok(ObjectLocation::Object(_)))
+            && self.operations_supported.contains(&operation::COPY_OBJECT)
+    }
+
+    fn can_move_on_device(&self, object: &ContentObject) -> bool {
+        matches!(locate(object), Ok(ObjectLocation::Object(_)))
+            && self.operations_supported.contains(&operation::MOVE_OBJECT)
+    }
+
+    fn copy_object(
+        &self,
+        object: &ContentObject,
+        parent: &ContentObject,
+    ) -> Result<ContentObject, Box<dyn std::error::Error>> {
+        let handle = match locate(object)? {
+            ObjectLocation::Object(handle) => handle,
+            _ => return Err(format!("cannot copy: {}", &object.id).into()),
+        };
+        let (storage_id, parent_handle) = self.get_on_device_destination(parent)?;
+        let new_handle =
+            self.session
+                .borrow_mut()
+                .copy_object(handle, storage_id, parent_handle)?;
+        Ok(handle_object(new_handle))
+    }
+
+    fn move_object(
+        &self,
+        object: &ContentObject,
+        parent: &ContentObject,
+    ) -> Result<ContentObject, Box<dyn std::error::Error>> {
+        let handle = match locate(object)? {
+            ObjectLocation::Object(handle) => handle,
+            _ => return Err(format!("cannot move: {}", &object.id).into()),
+        };
+        let (storage_id, parent_handle) = self.get_on_device_destination(parent)?;
+        self.session
+            .borrow_mut()
+            .move_object(handle, storage_id, parent_handle)?;
+        Ok(object.clone())
+    }
+
+    fn subscribe_events(&self) -> Result<Box<dyn EventSubscription>, Box<dyn std::error::Error>> {
+        Ok(Box::new(MtpEventSubscription {
+            session: self.session.clone(),
+            removed: false,
+        }))
+    }
+}
+
+fn new_object_info(
+    storage_id: u32,
+    parent_handle: u32,
+    object_format: u16,
+    name: &str,
+    size: u64,
+    created: &Option<NaiveDateTime>,
+    modified: &Option<NaiveDateTime>,
+) -> ObjectInfo {
+    ObjectInfo {
+        storage_id,
+        object_format,
+        protection_status: PROTECTION_STATUS_NONE,
+        // 0xFFFFFFFF means 4GB or larger
+        object_compressed_size: std::cmp::min(size, ALL as u64) as u32,
+        thumb_format: 0,
+        thumb_compressed_size: 0,
+        thumb_pix_width: 0,
+        thumb_pix_height: 0,
+        image_pix_width: 0,
+        image_pix_height: 0,
+        image_bit_depth: 0,
+        parent_object: if parent_handle == ALL {
+            0
+        } else {
+            parent_handle
+        },
+        association_type: if object_format == format::ASSOCIATION {
+            ASSOCIATION_TYPE_GENERIC_FOLDER
+        } else {
+            0
+        },
+        association_desc: 0,
+        sequence_number: 0,
+        filename: name.to_string(),
+        date_created: *created,
+        date_modified: *modified,
+        keywords: String::new(),
+    }
+}
+
+struct MtpObjectIterator {
+    children: Vec<ContentObject>,
+}
+
+impl ContentObjectIterator for MtpObjectIterator {
+    fn next(&mut self) -> Result<Option<ContentObject>, Box<dyn std::error::Error>> {
+        Ok(self.children.pop())
+    }
+}
+
+struct MtpEventSubscription {
+    session: Rc<RefCell<Session>>,
+    removed: bool,
+}
+
+impl MtpEventSubscription {
+    fn to_device_event(
+        &self,
+        code: u16,
+        param: u32,
+    ) -> Result<Option<DeviceEvent>, Box<dyn std::error::Error>> {
+        let event = match code {
+            event::OBJECT_ADDED => {
+                // the parent is not notified by the event
+                let parent = match self.session.borrow_mut().get_object_info(param) {
+                    Ok(object_info) if object_info.parent_object == 0 => {
+                        Some(storage_object(object_info.storage_id))
+                    }
+                    Ok(object_info) => Some(handle_object(object_info.parent_object)),
+                    Err(err) => {
+                        log::debug!("failed to get the parent of the added object: {}", err);
+                        None
+                    }
+                };
+                DeviceEvent::ObjectAdded {
+                    object: handle_object(param),
+                    parent,
+                }
+            }
+            event::OBJECT_REMOVED => DeviceEvent::ObjectRemoved(handle_object(param)),
+            event::OBJECT_INFO_CHANGED | event::OBJECT_PROP_CHANGED => {
+                DeviceEvent::ObjectInfoChanged(handle_object(param))
+            }
+            event::STORE_ADDED => DeviceEvent::StorageAdded(storage_object(param)),
+            event::STORE_REMOVED => DeviceEvent::StorageRemoved(storage_object(param)),
+            _ => {
+                log::debug!("ignored event: {:04X} {:?}", code, event::name(code));
+                return Ok(None);
+            }
+        };
+        Ok(Some(event))
+    }
+}
+
+impl EventSubscription for MtpEventSubscription {
+    fn next_event(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Option<DeviceEvent>, Box<dyn std::error::Error>> {
+        if self.removed {
+            return Err("the device was removed.".into());
+        }
+        let received = self.session.borrow_mut().wait_event(timeout);
+        match received {
+            Ok(Some(container)) => self.to_device_event(container.code, container.param(0)?),
+            Ok(None) => Ok(None),
+            Err(err) => {
+                log::debug!("failed to receive the event: {}", err);
+                self.removed = true;
+                Ok(Some(DeviceEvent::DeviceRemoved))
+            }
+        }
+    }
+}
+
+struct MtpResourceReader {
+    session: Rc<RefCell<Session>>,
+    buf: Vec<u8>,
+    finished: bool,
+}
+
+impl ResourceReader for MtpResourceReader {
+    fn get_optimized_buffer_size(&self) -> u32 {
+        BUFFER_SIZE
+    }
+
+    fn next(&mut self, max_size: u32) -> Result<Option<&[u8]>, Box<dyn std::error::Error>> {
+        if self.finished {
+            return Ok(None);
+        }
+        self.buf.resize(max_size as usize, 0);
+        let len = self
+            .session
+            .borrow_mut()
+            .read_object(self.buf.as_mut_slice());
+        match len {
+            Ok(0) => {
+                self.finished = true;
+                Ok(None)
+            }
+            Ok(len) => Ok(Some(&self.buf[..len])),
+            Err(err) => {
+                self.finished = true;
+                Err(err)
+            }
+        }
+    }
+}
+
+impl Drop for MtpResourceReader {
+    fn drop(&mut self) {
+        // read the rest of the data to complete the transaction
+        while !self.finished {
+            match self
+                .session
+                .borrow_mut()
+                .read_object(self.buf.as_mut_slice())
+            {
+                Ok(0) | Err(_) => self.finished = true,
+                Ok(_) => {}
+            }
+        }
+    }
+}
+
+struct MtpResourceWriter {
+    session: Rc<RefCell<Session>>,
+    handle: u32,
+    size: u64,
+    written: u64,
+    committed: bool,
+}
+
+impl MtpResourceWriter {
+    /// Fills the rest of the data to complete the transaction, then deletes the broken object.
+    fn abort(&mut self) {
+        self.committed = true;
+        let mut session = self.session.borrow_mut();
+        let zeros = vec![0u8; BUFFER_SIZE as usize];
+        while self.written < self.size {
+            let len = std::cmp::min(self.size - self.written, zeros.len() as u64) as usize;
+            if session.write_object(&zeros[..len]).is_err() {
+                return;
+            }
+            self.written += len as u64;
+        }
+        if session.finish_send_object().is_ok() {
+            let _ = session.delete_object(self.handle);
+        }
+    }
+}
+
+impl ResourceWriter for MtpResourceWriter {
+    fn get_buffer_size(&self) -> u32 {
+        BUFFER_SIZE
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        if self.committed {
+            return Err("the object was already committed.".into());
+        }
+        if self.written + data.len() as u64 > self.size {
+            return Err(format!(
+                "size mismatch: expected {} bytes, but {} bytes were written.",
+                self.size,
+                self.written + data.len() as u64
+            )
+            .into());
+        }
+        self.session.borrow_mut().write_object(data)?;
+        self.written += data.len() as u64;
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<ContentObject, Box<dyn std::error::Error>> {
+        if self.committed {
+            return Err("the object was already committed.".into());
+        }
+        if self.written != self.size {
+            let written = self.written;
+            self.abort();
+            return Err(format!(
+                "size mismatch: expected {} bytes, but {} bytes were written.",
+                self.size, written
+            )
+            .into());
+        }
+        self.committed = true;
+        self.session.borrow_mut().finish_send_object()?;
+        Ok(handle_object(self.handle))
+    }
+}
+
+impl Drop for MtpResourceWriter {
+    fn drop(&mut self) {
+        if !self.committed {
+            self.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::manager::DeviceInfo;
+    use crate::copy::copy_processor::{CopyOptions, CopyProcessor};
+    use crate::copy::device_copy_processor::DeviceCopyProcessor;
+    use crate::copy::device_destination_folder::DeviceDestinationFolder;
+    use crate::copy::device_plan_endpoint::DevicePlanEndpoint;
+    use crate::copy::executor::Executor;
+    use crate::copy::plan::Planner;
+    use crate::copy::planned_folder::PlannedFolder;
+    use crate::copy::reporter::TextReporter;
+    use crate::finders::device_find_file_or_folder;
+    use crate::finders::device_find_storage_objects;
+    use crate::memory::device::MemoryDevice;
+    use crate::mtp::loopback::LoopbackTransport;
+    use chrono::NaiveDate;
+
+    fn setup() -> (MemoryDevice, MtpDevice) {
+        let memory_device = MemoryDevice::new("Phone");
+        let storage = memory_device.add_storage("Internal");
+        let music = memory_device.add_folder(&storage, "Music");
+        let file_a = memory_device.add_file(&music, "a.mp3", b"aaaa");
+        memory_device.set_time_modified(
+            &file_a,
+            Some(NaiveDate::from_ymd(2021, 4, 1).and_hms(12, 34, 56)),
+        );
+        let album = memory_device.add_folder(&music, "Album");
+        memory_device.add_file(&album, "b.mp3", b"bbbbbb");
+        memory_device.add_storage("SD Card");
+
+        let transport = LoopbackTransport::new(Box::new(memory_device.clone())).unwrap();
+        let device = MtpDevice::open(Box::new(transport), None).unwrap();
+        (memory_device, device)
+    }
+
+    #[test]
+    fn test_browse() {
+        let (_memory_device, device) = setup();
+        assert_eq!(device.name(), "Phone");
+
+        let storages = device_find_storage_objects(&device, None).unwrap();
+        let names: Vec<&str> = storages.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["Internal", "SD Card"]);
+
+        let device_info = DeviceInfo {
+            id: String::new(),
+            name: "Phone".to_string(),
+        };
+        let (info, path) =
+            device_find_file_or_folder(&device, &device_info, &storages[0], "\\Music\\a.mp3")
+                .unwrap()
+                .unwrap();
+        assert!(info.is_file());
+        assert_eq!(info.data_size, 4);
+        assert_eq!(
+            info.time_modified,
+            Some(NaiveDate::from_ymd(2021, 4, 1).and_hms(12, 34, 56))
+        );
+        assert_eq!(path, "Phone:Internal:\\Music\\a.mp3");
+
+        let mut reader = device.get_resoure(&info.content_object).unwrap();
+        let mut data = Vec::<u8>::new();
+        while let Some(bytes) = reader.next(3).unwrap() {
+            data.extend_from_slice(bytes);
+        }
+        assert_eq!(data, b"aaaa");
+    }
+
+    #[test]
+    fn test_copy_between_storages() {
+        let (memory_device, device) = setup();
+        let storages = device_find_storage_objects(&device, None).unwrap();
+        let device_info = DeviceInfo {
+            id: String::new(),
+            name: "Phone".to_string(),
+        };
+        let (music, _) = device_find_file_or_folder(&device, &device_info, &storages[0], "\\Music")
+            .unwrap()
+            .unwrap();
+
+        let options = CopyOptions {
+            recursive: true,
+            ..Default::default()
+        };
+        let mut planner =
+            Planner::new("Phone:Internal:\\Music", "Phone:SD Card:\\", &options).unwrap();
+        let processor = DeviceCopyProcessor::new(&device, music, "Phone:Internal:\\Music");
+        let destination_folder =
+            DeviceDestinationFolder::new(&device, storages[1].clone()).unwrap();
+        processor
+            .plan_as(
+                "Music",
+                &mut PlannedFolder::new(destination_folder, "Phone:SD Card:\\"),
+                true,
+                &mut planner,
+            )
+            .unwrap();
+        let plan = planner.into_plan();
+
+        let mut source = DevicePlanEndpoint::new(&device, storages[0].clone());
+        let mut destination = DevicePlanEndpoint::new(&device, storages[1].clone());
+        Executor::new(&mut source, &mut destination, &TextReporter::new(false))
+            .run(&plan)
+            .unwrap();
+
+        let copied = memory_device.find("SD Card\\Music\\Album\\b.mp3").unwrap();
+        assert_eq!(memory_device.data(&copied), b"bbbbbb");
+        let copied = memory_device.find("SD Card\\Music\\a.mp3").unwrap();
+        let copied_info = memory_device.get_object_info(copied).unwrap();
+        assert_eq!(
+            copied_info.time_modified,
+            Some(NaiveDate::from_ymd(2021, 4, 1).and_hms(12, 34, 56))
+        );
+    }
+
+    #[test]
+    fn test_uncompleted_transfers() {
+        let (memory_device, device) = setup();
+        let storages = device_find_storage_objects(&device, None).unwrap();
+        let sd_card = &storages[1].content_object;
+
+        // size mismatch
+        let mut writer = device
+            .create_file(sd_card, "c.txt", 5, &None, &None)
+            .unwrap();
+        writer.write(b"ccc").unwrap();
+        assert!(writer.commit().is_err());
+        drop(writer);
+
+        // dropped without commit
+        let mut writer = device
+            .create_file(sd_card, "d.txt", 5, &None, &None)
+            .unwrap();
+        writer.write(b"d").unwrap();
+        drop(writer);
+
+        let sd_card_object = memory_device.find("SD Card").unwrap();
+        assert!(memory_device.child_names(&sd_card_object).is_empty());
+
+        // reader dropped in the middle of the data
+        let file_a = device_find_file_or_folder(
+            &device,
+            &DeviceInfo {
+                id: String::new(),
+                name: "Phone".to_string(),
+            },
+            &storages[0],
+            "\\Music\\a.mp3",
+        )
+        .unwrap()
+        .unwrap()
+        .0;
+        let mut reader = device.get_resoure(&file_a.content_object).unwrap();
+        reader.next(1).unwrap();
+        drop(reader);
+
+        // the session is still usable
+        let folder = device.create_folder(sd_card, "Data").unwrap();
+        assert_eq!(device.get_object_info(folder).unwrap().name, "Data");
+    }
+
+    #[test]
+    fn test_large_file() {
+        let (memory_device, device) = setup();
+        let file_a = memory_device.find("Internal\\Music\\a.mp3").unwrap();
+        memory_device.set_reported_size(&file_a, 0x1_2345_6789);
+
+        let storages = device_find_storage_objects(&device, None).unwrap();
+        let device_info = DeviceInfo {
+            id: String::new(),
+            name: "Phone".to_string(),
+        };
+        let (info, _) =
+            device_find_file_or_folder(&device, &device_info, &storages[0], "\\Music\\a.mp3")
+                .unwrap()
+                .unwrap();
+        assert_eq!(info.data_size, 0x1_2345_6789);
+
+        // the size in ObjectInfo of the new file
+        let object_info = new_object_info(
+            0x10001,
+            ALL,
+            format::UNDEFINED,
+            "large.bin",
+            0x1_2345_6789,
+            &None,
+            &None,
+        );
+        assert_eq!(object_info.object_compressed_size, ALL);
+    }
+
+    #[test]
+    fn test_rename() {
+        let (memory_device, device) = setup();
+        memory_device.set_rename_supported(true);
+        let storages = device_find_storage_objects(&device, None).unwrap();
+        let device_info = DeviceInfo {
+            id: String::new(),
+            name: "Phone".to_string(),
+        };
+        let (file_a, _) =
+            device_find_file_or_folder(&device, &device_info, &storages[0], "\\Music\\a.mp3")
+                .unwrap()
+                .unwrap();
+        assert!(device.can_rename(&file_a.content_object));
+        assert!(!device.can_rename(&storages[0].content_object));
+
+        let renamed = device.rename(&file_a.content_object, "c.mp3").unwrap();
+        assert_eq!(device.get_object_info(renamed).unwrap().name, "c.mp3");
+        let music = memory_device.find("Internal\\Music").unwrap();
+        assert_eq!(memory_device.child_names(&music), vec!["c.mp3", "Album"]);
+
+        // folders are not renamed
+        let (album, _) =
+            device_find_file_or_folder(&device, &device_info, &storages[0], "\\Music\\Album")
+                .unwrap()
+                .unwrap();
+        assert!(device.rename(&album.content_object, "Single").is_err());
+        assert_eq!(memory_device.child_names(&music), vec!["c.mp3", "Album"]);
+    }
+
+    #[test]
+    fn test_copy_and_move_on_device() {
+        let (memory_device, device) = setup();
+        let storages = device_find_storage_objects(&device, None).unwrap();
+        let device_info = DeviceInfo {
+            id: String::new(),
+            name: "Phone".to_string(),
+        };
+        let (file_a, _) =
+            device_find_file_or_folder(&device, &device_info, &storages[0], "\\Music\\a.mp3")
+                .unwrap()
+                .unwrap();
+        let (album, _) =
+            device_find_file_or_folder(&device, &device_info, &storages[0], "\\Music\\Album")
+                .unwrap()
+                .unwrap();
+        assert!(device.can_copy_on_device(&file_a.content_object));
+        assert!(device.can_move_on_device(&file_a.content_object));
+        assert!(!device.can_copy_on_device(&storages[0].content_object));
+
+        let copied = device
+            .copy_object(&file_a.content_object, &storages[1].content_object)
+            .unwrap();
+        let copied_info = device.get_object_info(copied).unwrap();
+        assert_eq!(copied_info.name, "a.mp3");
+        assert_eq!(copied_info.time_modified, file_a.time_modified);
+        let copied = memory_device.find("SD Card\\a.mp3").unwrap();
+        assert_eq!(memory_device.data(&copied), b"aaaa");
+        assert_eq!(memory_device.resources_opened(), 0);
+
+        let moved = device
+            .move_object(&file_a.content_object, &album.content_object)
+            .unwrap();
+        assert_eq!(device.get_object_info(moved).unwrap().name, "a.mp3");
+        let music = memory_device.find("Internal\\Music").unwrap();
+        assert_eq!(memory_device.child_names(&music), vec!["Album"]);
+        assert!(memory_device
+            .find("Internal\\Music\\Album\\a.mp3")
+            .is_some());
+
+        // folders are not copied
+        assert!(device
+            .copy_object(&album.content_object, &storages[1].content_object)
+            .is_err());
+
+        // the device refuses copying
+        memory_device.set_copy_supported(false);
+        assert!(device
+            .copy_object(&copied_info.content_object, &storages[0].content_object)
+            .is_err());
+    }
+
+    #[test]
+    fn test_events() {
+        let (memory_device, device) = setup();
+        let storages = device_find_storage_objects(&device, None).unwrap();
+        let device_info = DeviceInfo {
+            id: String::new(),
+            name: "Phone".to_string(),
+        };
+        let find = |path: &str| {
+            device_find_file_or_folder(&device, &device_info, &storages[0], path)
+                .unwrap()
+                .unwrap()
+                .0
+                .content_object
+        };
+        let music = find("\\Music");
+        let album = find("\\Music\\Album");
+        let file_a = find("\\Music\\a.mp3");
+        let mut events = device.subscribe_events().unwrap();
+
+        // changes made by the initiator are not notified
+        device.delete(&file_a).unwrap();
+        device.get_object_info(music.clone()).unwrap();
+        assert_eq!(events.next_event(Duration::from_millis(0)).unwrap(), None);
+
+        let memory_music = memory_device.find("Internal\\Music").unwrap();
+        let memory_file_c = memory_device.add_file(&memory_music, "c.mp3", b"c");
+        memory_device.set_time_modified(&memory_file_c, None);
+        let memory_album = memory_device.find("Internal\\Music\\Album").unwrap();
+        memory_device.delete(&memory_album).unwrap();
+        memory_device.add_storage("USB");
+        // events are sent in the next transaction
+        device.get_object_info(music.clone()).unwrap();
+
+        let file_c = match events.next_event(Duration::from_millis(0)).unwrap() {
+            Some(DeviceEvent::ObjectAdded {
+                object,
+                parent: Some(parent),
+            }) => {
+                assert_eq!(parent, music);
+                object
+            }
+            other => panic!("unexpected event: {:?}", other),
+        };
+        assert_eq!(
+            device.get_object_info(file_c.clone()).unwrap().name,
+            "c.mp3"
+        );
+        assert_eq!(
+            events.next_event(Duration::from_millis(0)).unwrap(),
+            Some(DeviceEvent::ObjectInfoChanged(file_c))
+        );
+        assert_eq!(
+            events.next_event(Duration::from_millis(0)).unwrap(),
+            Some(DeviceEvent::ObjectRemoved(album))
+        );
+        match events.next_event(Duration::from_millis(0)).unwrap() {
+            Some(DeviceEvent::StorageAdded(storage)) => {
+                assert_eq!(device.get_object_info(storage).unwrap().name, "USB");
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert_eq!(events.next_event(Duration::from_millis(0)).unwrap(), None);
+    }
+}
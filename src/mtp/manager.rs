@@ -0,0 +1,135 @@
+use crate::backend::device::Device;
+use crate::backend::manager::{DeviceInfo, DeviceInfoIterator, Manager};
+
+use super::device::MtpDevice;
+use super::ptpip::{with_default_port, PtpIpTransport};
+
+/// Environment variable that specifies PTP/IP devices.
+/// Entries are in the form `<device-name>=<host>[:<port>]`, and separated by `;` or newline.
+pub const PTPIP_DEVICES_ENV: &str = "MTPCOPY_PTPIP_DEVICES";
+
+/// Name of the initiator told to the devices
+const INITIATOR_NAME: &str = "mtpcopy";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PtpIpDeviceConfig {
+    name: String,
+    /// `<host>:<port>`
+    address: String,
+}
+
+/// A manager of the network devices connected by PTP/IP.
+///
+/// Devices are not detected automatically; they are specified by `MTPCOPY_PTPIP_DEVICES`.
+/// Connection to the device is established when the device is opened.
+pub struct PtpIpManager {
+    devices: Vec<PtpIpDeviceConfig>,
+}
+
+impl PtpIpManager {
+    /// Creates a manager from the environment variable.
+    /// Returns None if no devices are specified.
+    pub fn from_env() -> Result<Option<PtpIpManager>, Box<dyn std::error::Error>> {
+        match std::env::var(PTPIP_DEVICES_ENV) {
+            Ok(text) => Ok(Some(PtpIpManager::parse(&text)?)),
+            Err(_) => Ok(None),
+        }
+    }
+
+    fn parse(text: &str) -> Result<PtpIpManager, Box<dyn std::error::Error>> {
+        let mut devices = Vec::<PtpIpDeviceConfig>::new();
+        for entry in text.split(['\n', ';']) {
+            let entry = entry.trim();
+            if entry.is_empty() || entry.starts_with('#') {
+                continue;
+            }
+            let (name, host) = match entry.rfind('=') {
+                Some(pos) => (entry[..pos].trim(), entry[pos + 1..].trim()),
+                None => return Err(format!("invalid PTP/IP device entry: {}", entry).into()),
+            };
+            if name.is_empty() || name.contains(':') || host.is_empty() {
+                return Err(format!("invalid PTP/IP device entry: {}", entry).into());
+            }
+            devices.push(PtpIpDeviceConfig {
+                name: name.to_string(),
+                address: with_default_port(host),
+            });
+        }
+        Ok(PtpIpManager { devices })
+    }
+}
+
+impl Manager for PtpIpManager {
+    fn get_device_iterator(
+        &self,
+    ) -> Result<Box<dyn DeviceInfoIterator + '_>, Box<dyn std::error::Error>> {
+        let mut device_infos: Vec<DeviceInfo> = self
+            .devices
+            .iter()
+            .map(|device| DeviceInfo {
+                id: device.address.clone(),
+                name: device.name.clone(),
+            })
+            .collect();
+        device_infos.reverse(); // for moving item out by pop()
+        Ok(Box::new(PtpIpDeviceInfoIterator { device_infos }))
+    }
+
+    fn open_device(
+        &self,
+        info: &DeviceInfo,
+    ) -> Result<Box<dyn Device>, Box<dyn std::error::Error>> {
+        let transport =
+            PtpIpTransport::connect(info.id.as_str(), INITIATOR_NAME).map_err(|err| {
+                format!(
+                    "failed to connect to {} ({}): {}",
+                    &info.name, &info.id, err
+                )
+            })?;
+        Ok(Box::new(MtpDevice::open(
+            Box::new(transport),
+            Some(&info.name),
+        )?))
+    }
+}
+
+struct PtpIpDeviceInfoIterator {
+    device_infos: Vec<DeviceInfo>,
+}
+
+impl DeviceInfoIterator for PtpIpDeviceInfoIterator {
+    fn next(&mut self) -> Result<Option<DeviceInfo>, Box<dyn std::error::Error>> {
+        Ok(self.device_infos.pop())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let manager = PtpIpManager::parse(
+            "Camera=192.168.1.10; Phone = phone.local:1234\n# comment\nV6=[::1]",
+        )
+        .unwrap();
+        let devices: Vec<(&str, &str)> = manager
+            .devices
+            .iter()
+            .map(|d| (d.name.as_str(), d.address.as_str()))
+            .collect();
+        assert_eq!(
+            devices,
+            vec![
+                ("Camera", "192.168.1.10:15740"),
+                ("Phone", "phone.local:1234"),
+                ("V6", "[::1]:15740"),
+            ]
+        );
+
+        assert!(PtpIpManager::parse("Camera").is_err());
+        assert!(PtpIpManager::parse("=192.168.1.10").is_err());
+        assert!(PtpIpManager::parse("Camera=").is_err());
+        assert!(PtpIpManager::parse("My:Camera=192.168.1.10").is_err());
+    }
+}
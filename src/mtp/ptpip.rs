@@ -0,0 +1,789 @@
+//! PTP/IP transport (CIPA DC-005).
+//!
+//! PTP/IP uses two TCP connections: the command/data connection and the event connection.
+//! Both are established with the handshake below, then the containers are carried
+//! in the PTP/IP packets.
+//!
+//! ```text
+//! initiator                          responder
+//!   | -- (command connection) --------> |
+//!   | -- Init_Command_Request --------> |
+//!   | <------------ Init_Command_Ack -- |
+//!   | -- (event connection) ----------> |
+//!   | -- Init_Event_Request ----------> |
+//!   | <-------------- Init_Event_Ack -- |
+//! ```
+
+use std::convert::TryInto;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use super::codes::has_data_out;
+use super::container::{Container, ContainerType, MAX_PARAMS};
+use super::transport::{Received, Transport};
+
+/// Default TCP port of PTP/IP
+pub const DEFAULT_PORT: u16 = 15740;
+
+/// Appends the default port to the host if the port is not specified.
+/// IPv6 addresses must be enclosed in brackets.
+pub fn with_default_port(host: &str) -> String {
+    if host.contains(':') && !host.ends_with(']') {
+        host.to_string()
+    } else {
+        format!("{}:{}", host, DEFAULT_PORT)
+    }
+}
+
+const PROTOCOL_VERSION: u32 = 0x0001_0000;
+
+/// GUID of mtpcopy.
+/// Some devices remember the GUID of the paired initiator, so a fixed value is used.
+pub const GUID: [u8; 16] = [
+    0x6d, 0x74, 0x70, 0x63, 0x6f, 0x70, 0x79, 0x00, 0x8a, 0x3e, 0x41, 0x5c, 0x9b, 0x07, 0x2d, 0xe1,
+];
+
+const HEADER_SIZE: usize = 8;
+/// Max size of the packet to receive
+const MAX_PACKET_SIZE: u32 = 16 * 1024 * 1024;
+/// Max payload size of the Data packet to send
+const MAX_DATA_CHUNK: usize = 65536;
+
+mod packet_type {
+    pub const INIT_COMMAND_REQUEST: u32 = 1;
+    pub const INIT_COMMAND_ACK: u32 = 2;
+    pub const INIT_EVENT_REQUEST: u32 = 3;
+    pub const INIT_EVENT_ACK: u32 = 4;
+    pub const INIT_FAIL: u32 = 5;
+    pub const OPERATION_REQUEST: u32 = 6;
+    pub const OPERATION_RESPONSE: u32 = 7;
+    pub const EVENT: u32 = 8;
+    pub const START_DATA: u32 = 9;
+    pub const DATA: u32 = 10;
+    pub const CANCEL: u32 = 11;
+    pub const END_DATA: u32 = 12;
+    pub const PROBE_REQUEST: u32 = 13;
+    pub const PROBE_RESPONSE: u32 = 14;
+}
+
+/// Data phase info of Operation_Request: no data phase or data-in phase
+const DATA_PHASE_NONE_OR_IN: u32 = 1;
+/// Data phase info of Operation_Request: data-out phase
+const DATA_PHASE_OUT: u32 = 2;
+
+/// Reason of Init_Fail: rejected initiator
+const FAIL_REJECTED_INITIATOR: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Packet {
+    packet_type: u32,
+    payload: Vec<u8>,
+}
+
+impl Packet {
+    fn new(packet_type: u32) -> Packet {
+        Packet {
+            packet_type,
+            payload: Vec::new(),
+        }
+    }
+
+    fn u16(mut self, value: u16) -> Packet {
+        self.payload.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn u32(mut self, value: u32) -> Packet {
+        self.payload.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn u64(mut self, value: u64) -> Packet {
+        self.payload.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn bytes(mut self, value: &[u8]) -> Packet {
+        self.payload.extend_from_slice(value);
+        self
+    }
+
+    /// Appends a null-terminated UTF-16LE string.
+    fn string(mut self, value: &str) -> Packet {
+        for c in value.encode_utf16().chain(std::iter::once(0)) {
+            self.payload.extend_from_slice(&c.to_le_bytes());
+        }
+        self
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::<u8>::with_capacity(HEADER_SIZE + self.payload.len());
+        bytes.extend_from_slice(&((HEADER_SIZE + self.payload.len()) as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.packet_type.to_le_bytes());
+        bytes.extend_from_slice(&self.payload);
+        bytes
+    }
+
+    fn write_to(&self, stream: &mut impl Write) -> Result<(), Box<dyn std::error::Error>> {
+        stream.write_all(&self.encode())?;
+        stream.flush()?;
+        Ok(())
+    }
+
+    /// Reads a packet. Returns None if the connection was closed.
+    fn read_from(stream: &mut impl Read) -> Result<Option<Packet>, Box<dyn std::error::Error>> {
+        let mut header = [0u8; HEADER_SIZE];
+        let mut len = 0;
+        while len < HEADER_SIZE {
+            let n = stream.read(&mut header[len..])?;
+            if n == 0 {
+                if len == 0 {
+                    return Ok(None);
+                }
+                return Err("connection was closed in the middle of the packet.".into());
+            }
+            len += n;
+        }
+        let length = u32::from_le_bytes(header[0..4].try_into()?);
+        let packet_type = u32::from_le_bytes(header[4..8].try_into()?);
+        if (length as usize) < HEADER_SIZE || length > MAX_PACKET_SIZE {
+            return Err(format!("invalid PTP/IP packet length: {}", length).into());
+        }
+        let mut payload = vec![0u8; length as usize - HEADER_SIZE];
+        stream.read_exact(&mut payload)?;
+        Ok(Some(Packet {
+            packet_type,
+            payload,
+        }))
+    }
+}
+
+/// Reads fields of the packet payload.
+struct PacketReader<'a> {
+    payload: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PacketReader<'a> {
+    fn new(packet: &'a Packet) -> PacketReader<'a> {
+        PacketReader {
+            payload: &packet.payload,
+            pos: 0,
+        }
+    }
+
+    fn take(&mut self, size: usize) -> Result<&'a [u8], Box<dyn std::error::Error>> {
+        if self.payload.len() - self.pos < size {
+            return Err("PTP/IP packet is too short.".into());
+        }
+        let bytes = &self.payload[self.pos..self.pos + size];
+        self.pos += size;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, Box<dyn std::error::Error>> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, Box<dyn std::error::Error>> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, Box<dyn std::error::Error>> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    fn guid(&mut self) -> Result<[u8; 16], Box<dyn std::error::Error>> {
+        Ok(self.take(16)?.try_into()?)
+    }
+
+    fn string(&mut self) -> Result<String, Box<dyn std::error::Error>> {
+        let mut chars = Vec::<u16>::new();
+        loop {
+            match self.u16()? {
+                0 => break,
+                c => chars.push(c),
+            }
+        }
+        Ok(String::from_utf16(&chars)?)
+    }
+
+    /// Reads the remaining bytes as parameters.
+    fn params(&mut self) -> Result<Vec<u32>, Box<dyn std::error::Error>> {
+        let mut params = Vec::<u32>::new();
+        while self.pos < self.payload.len() && params.len() < MAX_PARAMS {
+            params.push(self.u32()?);
+        }
+        Ok(params)
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        let bytes = &self.payload[self.pos..];
+        self.pos = self.payload.len();
+        bytes
+    }
+}
+
+fn expect_packet(
+    stream: &mut TcpStream,
+    packet_type: u32,
+) -> Result<Packet, Box<dyn std::error::Error>> {
+    match Packet::read_from(stream)? {
+        Some(packet) if packet.packet_type == packet_type => Ok(packet),
+        Some(packet) if packet.packet_type == packet_type::INIT_FAIL => {
+            let reason = PacketReader::new(&packet).u32().unwrap_or(0);
+            Err(format!("PTP/IP connection was rejected: reason={}", reason).into())
+        }
+        Some(packet) => {
+            Err(format!("unexpected PTP/IP packet: type={}", packet.packet_type).into())
+        }
+        None => Err("PTP/IP connection was closed.".into()),
+    }
+}
+
+/// State of the data phase being received
+struct DataReading {
+    transaction_id: u32,
+    /// Payload of the current Data packet
+    chunk: Vec<u8>,
+    offset: usize,
+    /// Whether End_Data was received
+    ended: bool,
+}
+
+/// A transport over PTP/IP.
+pub struct PtpIpTransport {
+    command_stream: TcpStream,
+    event_stream: TcpStream,
+    /// Name of the peer
+    peer_name: String,
+    /// Code of the latest operation, which is used as the code of the data container
+    operation_code: u16,
+    /// Remaining bytes of the data phase being sent, and the transaction ID
+    writing: Option<(u64, u32)>,
+    reading: Option<DataReading>,
+}
+
+impl PtpIpTransport {
+    /// Connects to the responder as the initiator.
+    pub fn connect(
+        address: impl ToSocketAddrs,
+        name: &str,
+    ) -> Result<PtpIpTransport, Box<dyn std::error::Error>> {
+        let mut command_stream = TcpStream::connect(&address)?;
+        command_stream.set_nodelay(true)?;
+        Packet::new(packet_type::INIT_COMMAND_REQUEST)
+            .bytes(&GUID)
+            .string(name)
+            .u32(PROTOCOL_VERSION)
+            .write_to(&mut command_stream)?;
+        let ack = expect_packet(&mut command_stream, packet_type::INIT_COMMAND_ACK)?;
+        let mut r = PacketReader::new(&ack);
+        let connection_number = r.u32()?;
+        r.guid()?;
+        let peer_name = r.string()?;
+
+        let mut event_stream = TcpStream::connect(command_stream.peer_addr()?)?;
+        Packet::new(packet_type::INIT_EVENT_REQUEST)
+            .u32(connection_number)
+            .write_to(&mut event_stream)?;
+        expect_packet(&mut event_stream, packet_type::INIT_EVENT_ACK)?;
+
+        log::debug!(
+            "PTP/IP connected: {} (connection number {})",
+            &peer_name,
+            connection_number
+        );
+        Ok(PtpIpTransport::new(command_stream, event_stream, peer_name))
+    }
+
+    /// Accepts a connection from the initiator as the responder.
+    pub fn accept(
+        listener: &TcpListener,
+        name: &str,
+    ) -> Result<PtpIpTransport, Box<dyn std::error::Error>> {
+        const CONNECTION_NUMBER: u32 = 1;
+
+        let (mut command_stream, peer_address) = listener.accept()?;
+        command_stream.set_nodelay(true)?;
+        let request = expect_packet(&mut command_stream, packet_type::INIT_COMMAND_REQUEST)?;
+        let mut r = PacketReader::new(&request);
+        r.guid()?;
+        let peer_name = r.string()?;
+        Packet::new(packet_type::INIT_COMMAND_ACK)
+            .u32(CONNECTION_NUMBER)
+            .bytes(&GUID)
+            .string(name)
+            .u32(PROTOCOL_VERSION)
+            .write_to(&mut command_stream)?;
+
+        let (mut event_stream, _) = listener.accept()?;
+        let request = expect_packet(&mut event_stream, packet_type::INIT_EVENT_REQUEST)?;
+        if PacketReader::new(&request).u32()? != CONNECTION_NUMBER {
+            Packet::new(packet_type::INIT_FAIL)
+                .u32(FAIL_REJECTED_INITIATOR)
+                .write_to(&mut event_stream)?;
+            return Err("PTP/IP: invalid connection number.".into());
+        }
+        Packet::new(packet_type::INIT_EVENT_ACK).write_to(&mut event_stream)?;
+
+        log::debug!("PTP/IP accepted: {} ({})", &peer_name, peer_address);
+        Ok(PtpIpTransport::new(command_stream, event_stream, peer_name))
+    }
+
+    fn new(command_stream: TcpStream, event_stream: TcpStream, peer_name: String) -> Self {
+        PtpIpTransport {
+            command_stream,
+            event_stream,
+            peer_name,
+            operation_code: 0,
+            writing: None,
+            reading: None,
+        }
+    }
+
+    /// Returns the name of the peer given in the handshake.
+    pub fn peer_name(&self) -> &str {
+        &self.peer_name
+    }
+
+    fn check_idle(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some((remaining, _)) = self.writing {
+            return Err(format!("{} bytes of the data phase were not sent.", remaining).into());
+        }
+        if self.reading.is_some() {
+            return Err("data phase was not read to the end.".into());
+        }
+        Ok(())
+    }
+}
+
+impl Transport for PtpIpTransport {
+    fn send(&mut self, container: &Container) -> Result<(), Box<dyn std::error::Error>> {
+        self.check_idle()?;
+        let params = container.params()?;
+        let mut packet = match container.container_type {
+            ContainerType::Command => {
+                self.operation_code = container.code;
+                let data_phase = if has_data_out(container.code) {
+                    DATA_PHASE_OUT
+                } else {
+                    DATA_PHASE_NONE_OR_IN
+                };
+                Packet::new(packet_type::OPERATION_REQUEST).u32(data_phase)
+            }
+            ContainerType::Response => Packet::new(packet_type::OPERATION_RESPONSE),
+            ContainerType::Event => Packet::new(packet_type::EVENT),
+            ContainerType::Data => return Err("use begin_data() to send data.".into()),
+        }
+        .u16(container.code)
+        .u32(container.transaction_id);
+        for param in params {
+            packet = packet.u32(param);
+        }
+        if container.container_type == ContainerType::Event {
+            packet.write_to(&mut self.event_stream)
+        } else {
+            packet.write_to(&mut self.command_stream)
+        }
+    }
+
+    fn begin_data(
+        &mut self,
+        _code: u16,
+        transaction_id: u32,
+        size: u64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.check_idle()?;
+        Packet::new(packet_type::START_DATA)
+            .u32(transaction_id)
+            .u64(size)
+            .write_to(&mut self.command_stream)?;
+        if size == 0 {
+            Packet::new(packet_type::END_DATA)
+                .u32(transaction_id)
+                .write_to(&mut self.command_stream)?;
+        } else {
+            self.writing = Some((size, transaction_id));
+        }
+        Ok(())
+    }
+
+    fn write_data(&mut self, bytes: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        let (remaining, transaction_id) = match self.writing {
+            Some(writing) => writing,
+            None if bytes.is_empty() => return Ok(()),
+            None => return Err("data exceeds the size of the data phase.".into()),
+        };
+        if bytes.len() as u64 > remaining {
+            return Err("data exceeds the size of the data phase.".into());
+        }
+        let mut remaining = remaining;
+        for chunk in bytes.chunks(MAX_DATA_CHUNK) {
+            remaining -= chunk.len() as u64;
+            let packet_type = if remaining == 0 {
+                packet_type::END_DATA
+            } else {
+                packet_type::DATA
+            };
+            Packet::new(packet_type)
+                .u32(transaction_id)
+                .bytes(chunk)
+                .write_to(&mut self.command_stream)?;
+        }
+        self.writing = if remaining == 0 {
+            None
+        } else {
+            Some((remaining, transaction_id))
+        };
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Option<Received>, Box<dyn std::error::Error>> {
+        self.check_idle()?;
+        loop {
+            let packet = match Packet::read_from(&mut self.command_stream)? {
+                Some(packet) => packet,
+                None => return Ok(None),
+            };
+            let mut r = PacketReader::new(&packet);
+            match packet.packet_type {
+                packet_type::OPERATION_REQUEST => {
+                    r.u32()?; // data phase info
+                    let code = r.u16()?;
+                    let transaction_id = r.u32()?;
+                    self.operation_code = code;
+                    return Ok(Some(Received::Container(Container::command(
+                        code,
+                        transaction_id,
+                        &r.params()?,
+                    ))));
+                }
+                packet_type::OPERATION_RESPONSE => {
+                    let code = r.u16()?;
+                    let transaction_id = r.u32()?;
+                    return Ok(Some(Received::Container(Container::response(
+                        code,
+                        transaction_id,
+                        &r.params()?,
+                    ))));
+                }
+                packet_type::START_DATA => {
+                    let transaction_id = r.u32()?;
+                    let size = r.u64()?;
+                    self.reading = Some(DataReading {
+                        transaction_id,
+                        chunk: Vec::new(),
+                        offset: 0,
+                        ended: false,
+                    });
+                    return Ok(Some(Received::Data {
+                        code: self.operation_code,
+                        transaction_id,
+                        size,
+                    }));
+                }
+                packet_type::PROBE_REQUEST => {
+                    Packet::new(packet_type::PROBE_RESPONSE).write_to(&mut self.command_stream)?;
+                }
+                packet_type::CANCEL => {
+                    log::debug!("PTP/IP: transaction was cancelled: {}", r.u32()?);
+                }
+                other => {
+                    return Err(format!("unexpected PTP/IP packet: type={}", other).into());
+                }
+            }
+        }
+    }
+
+    fn receive_event(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Option<Container>, Box<dyn std::error::Error>> {
+        // wait for the beginning of a packet, then read the whole packet without the timeout
+        let timeout = std::cmp::max(timeout, Duration::from_millis(1));
+        self.event_stream.set_read_timeout(Some(timeout))?;
+        let peeked = self.event_stream.peek(&mut [0u8; 1]);
+        self.event_stream.set_read_timeout(None)?;
+        match peeked {
+            Ok(0) => return Err("PTP/IP: event connection was closed.".into()),
+            Ok(_) => {}
+            Err(err)
+                if err.kind() == std::io::ErrorKind::WouldBlock
+                    || err.kind() == std::io::ErrorKind::TimedOut =>
+            {
+                return Ok(None)
+            }
+            Err(err) => return Err(err.into()),
+        }
+
+        let packet = match Packet::read_from(&mut self.event_stream)? {
+            Some(packet) => packet,
+            None => return Err("PTP/IP: event connection was closed.".into()),
+        };
+        let mut r = PacketReader::new(&packet);
+        match packet.packet_type {
+            packet_type::EVENT => {
+                let code = r.u16()?;
+                let transaction_id = r.u32()?;
+                Ok(Some(Container::event(code, transaction_id, &r.params()?)))
+            }
+            packet_type::PROBE_REQUEST => {
+                Packet::new(packet_type::PROBE_RESPONSE).write_to(&mut self.event_stream)?;
+                Ok(None)
+            }
+            other => Err(format!(
+                "unexpected PTP/IP packet on the event connection: type={}",
+                other
+            )
+            .into()),
+        }
+    }
+
+    fn read_data(&mut self, buf: &mut [u8]) -> Result<usize, Box<dyn std::error::Error>> {
+        let reading = match self.reading.as_mut() {
+            Some(reading) => reading,
+            None => return Ok(0),
+        };
+        while reading.offset == reading.chunk.len() {
+            if reading.ended {
+                self.reading = None;
+                return Ok(0);
+            }
+            let packet = match Packet::read_from(&mut self.command_stream)? {
+                Some(packet) => packet,
+                None => return Err("connection was closed in the middle of the data phase.".into()),
+            };
+            let mut r = PacketReader::new(&packet);
+            match packet.packet_type {
+                packet_type::DATA | packet_type::END_DATA => {
+                    if r.u32()? != reading.transaction_id {
+                        return Err("PTP/IP: transaction ID mismatch in the data phase.".into());
+                    }
+                    reading.chunk = r.rest().to_vec();
+                    reading.offset = 0;
+                    reading.ended = packet.packet_type == packet_type::END_DATA;
+                }
+                other => {
+                    return Err(format!(
+                        "unexpected PTP/IP packet in the data phase: type={}",
+                        other
+                    )
+                    .into());
+                }
+            }
+        }
+        let len = std::cmp::min(buf.len(), reading.chunk.len() - reading.offset);
+        buf[..len].copy_from_slice(&reading.chunk[reading.offset..reading.offset + len]);
+        reading.offset += len;
+        if reading.offset == reading.chunk.len() && reading.ended {
+            self.reading = None;
+        }
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::content_object::ContentObjectInfo;
+    use crate::backend::device::Device;
+    use crate::finders::device_find_storage_objects;
+    use crate::memory::device::MemoryDevice;
+    use crate::mtp::codes::{event, operation, response};
+    use crate::mtp::device::MtpDevice;
+    use crate::mtp::responder::Responder;
+
+    // Init_Command_Request with the GUID of mtpcopy and the name "ab"
+    const INIT_COMMAND_REQUEST: &[u8] = &[
+        0x22, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x6d, 0x74, 0x70, 0x63, 0x6f, 0x70, 0x79,
+        0x00, 0x8a, 0x3e, 0x41, 0x5c, 0x9b, 0x07, 0x2d, 0xe1, 0x61, 0x00, 0x62, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x01, 0x00,
+    ];
+
+    // Operation_Request of GetObjectInfo(0x2a) with TransactionID=3
+    const OPERATION_REQUEST: &[u8] = &[
+        0x16, 0x00, 0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x08, 0x10, 0x03,
+        0x00, 0x00, 0x00, 0x2a, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn test_packet() {
+        let packet = Packet::new(packet_type::INIT_COMMAND_REQUEST)
+            .bytes(&GUID)
+            .string("ab")
+            .u32(PROTOCOL_VERSION);
+        assert_eq!(packet.encode(), INIT_COMMAND_REQUEST);
+
+        let decoded = Packet::read_from(&mut &OPERATION_REQUEST[..])
+            .unwrap()
+            .unwrap();
+        assert_eq!(decoded.packet_type, packet_type::OPERATION_REQUEST);
+        let mut r = PacketReader::new(&decoded);
+        assert_eq!(r.u32().unwrap(), DATA_PHASE_NONE_OR_IN);
+        assert_eq!(r.u16().unwrap(), operation::GET_OBJECT_INFO);
+        assert_eq!(r.u32().unwrap(), 3);
+        assert_eq!(r.params().unwrap(), vec![0x2a]);
+
+        assert!(Packet::read_from(&mut &OPERATION_REQUEST[..10]).is_err());
+        assert!(Packet::read_from(&mut &[][..]).unwrap().is_none());
+    }
+
+    /// Starts a responder serving a simulated device,
+    /// and returns the address and the thread that returns names of the files on the device.
+    fn start_responder() -> (
+        std::net::SocketAddr,
+        std::thread::JoinHandle<Result<Vec<String>, String>>,
+    ) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let handle = std::thread::spawn(move || {
+            let device = MemoryDevice::new("Camera");
+            let storage = device.add_storage("SD Card");
+            let dcim = device.add_folder(&storage, "DCIM");
+            device.add_file(&dcim, "IMG_0001.JPG", &[0xff, 0xd8, 0xff, 0xd9]);
+
+            let mut transport =
+                PtpIpTransport::accept(&listener, "Camera").map_err(|e| e.to_string())?;
+            assert_eq!(transport.peer_name(), "test initiator");
+            let mut responder =
+                Responder::new(Box::new(device.clone())).map_err(|e| e.to_string())?;
+            responder.serve(&mut transport).map_err(|e| e.to_string())?;
+            let dcim = device.find("SD Card\\DCIM").unwrap();
+            Ok(device.child_names(&dcim))
+        });
+        (address, handle)
+    }
+
+    fn children(device: &dyn Device, parent: &ContentObjectInfo) -> Vec<ContentObjectInfo> {
+        let mut iter = device.get_object_iterator(&parent.content_object).unwrap();
+        let mut infos = Vec::<ContentObjectInfo>::new();
+        while let Some(object) = iter.next().unwrap() {
+            infos.push(device.get_object_info(object).unwrap());
+        }
+        infos
+    }
+
+    #[test]
+    fn test_session_over_tcp() {
+        let (address, handle) = start_responder();
+
+        {
+            let transport = PtpIpTransport::connect(address, "test initiator").unwrap();
+            assert_eq!(transport.peer_name(), "Camera");
+            let device = MtpDevice::open(Box::new(transport), None).unwrap();
+            assert_eq!(device.name(), "Camera");
+
+            let storages = device_find_storage_objects(&device, None).unwrap();
+            assert_eq!(storages.len(), 1);
+            assert_eq!(storages[0].name, "SD Card");
+            let dcim = children(&device, &storages[0]).pop().unwrap();
+            assert_eq!(dcim.name, "DCIM");
+            let image = children(&device, &dcim).pop().unwrap();
+            assert_eq!(image.name, "IMG_0001.JPG");
+
+            let mut reader = device.get_resoure(&image.content_object).unwrap();
+            assert_eq!(
+                reader.next(100).unwrap().unwrap(),
+                &[0xff, 0xd8, 0xff, 0xd9]
+            );
+            assert!(reader.next(100).unwrap().is_none());
+            drop(reader);
+
+            // data larger than a Data packet
+            let data: Vec<u8> = (0..MAX_DATA_CHUNK * 2 + 10).map(|i| i as u8).collect();
+            let mut writer = device
+                .create_file(
+                    &dcim.content_object,
+                    "large.bin",
+                    data.len() as u64,
+                    &None,
+                    &None,
+                )
+                .unwrap();
+            for chunk in data.chunks(100000) {
+                writer.write(chunk).unwrap();
+            }
+            let large = writer.commit().unwrap();
+            let mut reader = device.get_resoure(&large).unwrap();
+            let mut read_data = Vec::<u8>::new();
+            while let Some(bytes) = reader.next(7000).unwrap() {
+                read_data.extend_from_slice(bytes);
+            }
+            assert_eq!(read_data, data);
+            drop(reader);
+
+            // empty file
+            let mut writer = device
+                .create_file(&dcim.content_object, "empty.txt", 0, &None, &None)
+                .unwrap();
+            writer.commit().unwrap();
+        }
+
+        let names = handle.join().unwrap().unwrap();
+        assert_eq!(names, vec!["IMG_0001.JPG", "large.bin", "empty.txt"]);
+    }
+
+    #[test]
+    fn test_unsupported_operation_with_data() {
+        let (address, handle) = start_responder();
+        {
+            let mut transport = PtpIpTransport::connect(address, "test initiator").unwrap();
+            transport
+                .send(&Container::command(operation::OPEN_SESSION, 0, &[1]))
+                .unwrap();
+            match transport.receive().unwrap() {
+                Some(Received::Container(c)) => assert_eq!(c.code, response::OK),
+                other => panic!("unexpected: {:?}", other),
+            }
+            transport
+                .send(&Container::command(
+                    operation::SET_DEVICE_PROP_VALUE,
+                    1,
+                    &[0x5011],
+                ))
+                .unwrap();
+            transport
+                .send_data(operation::SET_DEVICE_PROP_VALUE, 1, &[0])
+                .unwrap();
+            match transport.receive().unwrap() {
+                Some(Received::Container(c)) => {
+                    assert_eq!(c.code, response::OPERATION_NOT_SUPPORTED)
+                }
+                other => panic!("unexpected: {:?}", other),
+            }
+        }
+        handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_event_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut transport = PtpIpTransport::accept(&listener, "Camera").unwrap();
+            transport
+                .send(&Container::event(event::OBJECT_ADDED, 0, &[0x2a]))
+                .unwrap();
+            // wait for the disconnection
+            assert!(transport.receive().unwrap().is_none());
+        });
+
+        {
+            let mut transport = PtpIpTransport::connect(address, "test initiator").unwrap();
+            let container = transport
+                .receive_event(Duration::from_secs(5))
+                .unwrap()
+                .unwrap();
+            assert_eq!(container.container_type, ContainerType::Event);
+            assert_eq!(container.code, event::OBJECT_ADDED);
+            assert_eq!(container.params().unwrap(), vec![0x2a]);
+            assert!(transport
+                .receive_event(Duration::from_millis(10))
+                .unwrap()
+                .is_none());
+        }
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,936 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::backend::content_object::{ContentObject, ContentObjectInfo};
+use crate::backend::device::{Device, DeviceEvent, EventSubscription};
+
+use super::codes::*;
+use super::container::{Container, ContainerType};
+use super::dataset::{DataReader, DataWriter, DeviceInfo, ObjectInfo, StorageInfo};
+use super::transport::{Received, Transport};
+
+/// Operations supported by the responder
+const SUPPORTED_OPERATIONS: &[u16] = &[
+    operation::GET_DEVICE_INFO,
+    operation::OPEN_SESSION,
+    operation::CLOSE_SESSION,
+    operation::GET_STORAGE_IDS,
+    operation::GET_STORAGE_INFO,
+    operation::GET_OBJECT_HANDLES,
+    operation::GET_OBJECT_INFO,
+    operation::GET_OBJECT,
+    operation::DELETE_OBJECT,
+    operation::SEND_OBJECT_INFO,
+    operation::SEND_OBJECT,
+    operation::GET_OBJECT_PROP_VALUE,
+    operation::SET_OBJECT_PROP_VALUE,
+    operation::MOVE_OBJECT,
+    operation::COPY_OBJECT,
+];
+
+/// Events notified to the initiator
+const SUPPORTED_EVENTS: &[u16] = &[
+    event::OBJECT_ADDED,
+    event::OBJECT_REMOVED,
+    event::STORE_ADDED,
+    event::STORE_REMOVED,
+    event::OBJECT_INFO_CHANGED,
+];
+
+/// Max size of the datasets received from the initiator. The object data is not limited.
+const MAX_DATASET_SIZE: u64 = 1024 * 1024;
+
+/// Result of the operation: response code and response parameters
+type Response = (u16, Vec<u32>);
+
+struct HandleEntry {
+    object: ContentObject,
+    storage_id: u32,
+    /// Handle of the parent folder, or 0 if the object is in the root folder of the storage
+    parent: u32,
+}
+
+/// An object announced by SendObjectInfo, which will be created by SendObject.
+struct PendingObject {
+    handle: u32,
+    storage_id: u32,
+    parent_handle: u32,
+    parent: ContentObject,
+    object_info: ObjectInfo,
+}
+
+/// MTP responder that exports the objects of a device.
+///
+/// Changes of the device are notified to the initiator as events between the transactions.
+/// Changes made by the operations of the initiator are not notified.
+pub struct Responder {
+    device: Box<dyn Device>,
+    events: Option<Box<dyn EventSubscription>>,
+    session_id: u32,
+    /// Storage IDs and storage objects
+    storages: Vec<(u32, ContentObject)>,
+    handles: HashMap<u32, HandleEntry>,
+    /// Object ID -> handle
+    handle_ids: HashMap<String, u32>,
+    next_handle: u32,
+    pending_object: Option<PendingObject>,
+}
+
+impl Responder {
+    pub fn new(device: Box<dyn Device>) -> Result<Responder, Box<dyn std::error::Error>> {
+        let mut storages = Vec::<(u32, ContentObject)>::new();
+        for device_object in children(device.as_ref(), &device.get_root_object())? {
+            if !device.get_object_info(device_object.clone())?.is_device() {
+                continue;
+            }
+            for storage_object in children(device.as_ref(), &device_object)? {
+                if device.get_object_info(storage_object.clone())?.is_storage() {
+                    let storage_id = ((storages.len() as u32 + 1) << 16) | 1;
+                    storages.push((storage_id, storage_object));
+                }
+            }
+        }
+        let events = match device.subscribe_events() {
+            Ok(events) => Some(events),
+            Err(err) => {
+                log::debug!("{}", err);
+                None
+            }
+        };
+        Ok(Responder {
+            device,
+            events,
+            session_id: 0,
+            storages,
+            handles: HashMap::new(),
+            handle_ids: HashMap::new(),
+            next_handle: 1,
+            pending_object: None,
+        })
+    }
+
+    /// Serves until the connection is closed.
+    pub fn serve(
+        &mut self,
+        transport: &mut dyn Transport,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        while self.process(transport)? {}
+        Ok(())
+    }
+
+    /// Processes a transaction.
+    /// Returns false if the connection was closed by the initiator.
+    pub fn process(
+        &mut self,
+        transport: &mut dyn Transport,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        if self.session_id != 0 {
+            self.send_events(transport)?;
+        }
+
+        let command = match transport.receive()? {
+            None => return Ok(false),
+            Some(Received::Container(container))
+                if container.container_type == ContainerType::Command =>
+            {
+                container
+            }
+            Some(Received::Data { code, .. }) => {
+                log::debug!("unexpected data phase: 0x{:04x}", code);
+                discard_data(transport)?;
+                return Ok(true);
+            }
+            Some(Received::Container(container)) => {
+                log::debug!("unexpected container: {:?}", &container);
+                return Ok(true);
+            }
+        };
+
+        log::trace!(
+            "MTP responder: {} tid={} params={:?}",
+            operation::name(command.code).unwrap_or("?"),
+            command.transaction_id,
+            command.params()
+        );
+        let (code, params) = match self.handle_command(&command, transport) {
+            Ok(response) => response,
+            Err(err) => {
+                log::warn!(
+                    "{} failed: {}",
+                    operation::name(command.code).unwrap_or("operation"),
+                    err
+                );
+                (response::GENERAL_ERROR, Vec::new())
+            }
+        };
+        transport.send(&Container::response(code, command.transaction_id, &params))?;
+
+        // the changes made by the operation are known to the initiator
+        self.discard_events();
+        Ok(true)
+    }
+
+    /// Sends the events of the changes made since the last transaction.
+    fn send_events(
+        &mut self,
+        transport: &mut dyn Transport,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        while let Some(event) = self.next_event() {
+            if let Some((code, param)) = self.convert_event(event) {
+                log::trace!(
+                    "MTP responder: event {} param={}",
+                    event::name(code).unwrap_or("?"),
+                    param
+                );
+                transport.send(&Container::event(code, 0, &[param]))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn discard_events(&mut self) {
+        while self.next_event().is_some() {}
+    }
+
+    fn next_event(&mut self) -> Option<DeviceEvent> {
+        let events = self.events.as_mut()?;
+        match events.next_event(Duration::from_millis(0)) {
+            Ok(event) => event,
+            Err(err) => {
+                log::debug!("failed to get the event: {}", err);
+                self.events = None;
+                None
+            }
+        }
+    }
+
+    /// Updates the handles for the event, and returns the event code and the parameter.
+    /// Returns None if the event is not related to the objects known by the initiator.
+    fn convert_event(&mut self, device_event: DeviceEvent) -> Option<(u16, u32)> {
+        match device_event {
+            DeviceEvent::ObjectAdded {
+                object,
+                parent: Some(parent),
+            } => {
+                let (storage_id, parent_handle) =
+                    match self.storages.iter().find(|(_, storage)| *storage == parent) {
+                        Some((storage_id, _)) => (*storage_id, 0),
+                        None => {
+                            let parent_handle = *self.handle_ids.get(&parent.id)?;
+                            (self.handles.get(&parent_handle)?.storage_id, parent_handle)
+                        }
+                    };
+                let handle = self.assign_handle(object, storage_id, parent_handle);
+                Some((event::OBJECT_ADDED, handle))
+            }
+            DeviceEvent::ObjectAdded { parent: None, .. } => None,
+            DeviceEvent::ObjectRemoved(object) => {
+                let handle = self.handle_ids.remove(&object.id)?;
+                self.handles.remove(&handle);
+                Some((event::OBJECT_REMOVED, handle))
+            }
+            DeviceEvent::ObjectInfoChanged(object) => {
+                let handle = *self.handle_ids.get(&object.id)?;
+                Some((event::OBJECT_INFO_CHANGED, handle))
+            }
+            DeviceEvent::StorageAdded(storage) => {
+                let max_index = self
+                    .storages
+                    .iter()
+                    .map(|(id, _)| id >> 16)
+                    .max()
+                    .unwrap_or(0);
+                let storage_id = ((max_index + 1) << 16) | 1;
+                self.storages.push((storage_id, storage));
+                Some((event::STORE_ADDED, storage_id))
+            }
+            DeviceEvent::StorageRemoved(storage) => {
+                let index = self.storages.iter().position(|(_, s)| *s == storage)?;
+                let (storage_id, _) = self.storages.remove(index);
+                Some((event::STORE_REMOVED, storage_id))
+            }
+            DeviceEvent::DeviceRemoved => None,
+        }
+    }
+
+    fn handle_command(
+        &mut self,
+        command: &Container,
+        transport: &mut dyn Transport,
+    ) -> Result<Response, Box<dyn std::error::Error>> {
+        let params = match command.params() {
+            Ok(params) => params,
+            Err(_) => return Ok((response::INVALID_PARAMETER, Vec::new())),
+        };
+        let param = |index: usize| params.get(index).copied().unwrap_or(0);
+
+        if self.session_id == 0
+            && command.code != operation::GET_DEVICE_INFO
+            && command.code != operation::OPEN_SESSION
+        {
+            if has_data_out(command.code) {
+                receive_data(transport, command.transaction_id)?;
+            }
+            return Ok((response::SESSION_NOT_OPEN, Vec::new()));
+        }
+
+        match command.code {
+            operation::GET_DEVICE_INFO => self.get_device_info(command, transport),
+            operation::OPEN_SESSION => self.open_session(param(0)),
+            operation::CLOSE_SESSION => {
+                self.session_id = 0;
+                self.pending_object = None;
+                Ok((response::OK, Vec::new()))
+            }
+            operation::GET_STORAGE_IDS => {
+                let mut w = DataWriter::new();
+                let storage_ids: Vec<u32> = self.storages.iter().map(|(id, _)| *id).collect();
+                w.write_u32_array(&storage_ids);
+                transport.send_data(command.code, command.transaction_id, &w.into_bytes())?;
+                Ok((response::OK, Vec::new()))
+            }
+            operation::GET_STORAGE_INFO => self.get_storage_info(command, transport, param(0)),
+            operation::GET_OBJECT_HANDLES => {
+                self.get_object_handles(command, transport, param(0), param(1), param(2))
+            }
+            operation::GET_OBJECT_INFO => self.get_object_info(command, transport, param(0)),
+            operation::GET_OBJECT => self.get_object(command, transport, param(0)),
+            operation::DELETE_OBJECT => self.delete_object(param(0), param(1)),
+            operation::SEND_OBJECT_INFO => {
+                self.send_object_info(command, transport, param(0), param(1))
+            }
+            operation::SEND_OBJECT => self.send_object(command, transport),
+            operation::GET_OBJECT_PROP_VALUE => {
+                self.get_object_prop_value(command, transport, param(0), param(1))
+            }
+            operation::SET_OBJECT_PROP_VALUE => {
+                self.set_object_prop_value(command, transport, param(0), param(1))
+            }
+            operation::MOVE_OBJECT => self.copy_or_move_object(param(0), param(1), param(2), false),
+            operation::COPY_OBJECT => self.copy_or_move_object(param(0), param(1), param(2), true),
+            _ => {
+                if has_data_out(command.code) {
+                    receive_data(transport, command.transaction_id)?;
+                }
+                Ok((response::OPERATION_NOT_SUPPORTED, Vec::new()))
+            }
+        }
+    }
+
+    fn get_device_info(
+        &mut self,
+        command: &Container,
+        transport: &mut dyn Transport,
+    ) -> Result<Response, Box<dyn std::error::Error>> {
+        let device_info = DeviceInfo {
+            standard_version: 100,
+            vendor_extension_id: 6,
+            vendor_extension_version: 100,
+            vendor_extension_desc: "microsoft.com: 1.0;".to_string(),
+            functional_mode: 0,
+            operations_supported: SUPPORTED_OPERATIONS.to_vec(),
+            events_supported: if self.events.is_some() {
+                SUPPORTED_EVENTS.to_vec()
+            } else {
+                Vec::new()
+            },
+            device_properties_supported: Vec::new(),
+            capture_formats: Vec::new(),
+            playback_formats: vec![format::UNDEFINED, format::ASSOCIATION],
+            manufacturer: env!("CARGO_PKG_NAME").to_string(),
+            model: self.device.name().to_string(),
+            device_version: env!("CARGO_PKG_VERSION").to_string(),
+            serial_number: String::new(),
+        };
+        transport.send_data(command.code, command.transaction_id, &device_info.encode())?;
+        Ok((response::OK, Vec::new()))
+    }
+
+    fn open_session(&mut self, session_id: u32) -> Result<Response, Box<dyn std::error::Error>> {
+        if session_id == 0 {
+            return Ok((response::INVALID_PARAMETER, Vec::new()));
+        }
+        if self.session_id != 0 {
+            return Ok((response::SESSION_ALREADY_OPEN, vec![self.session_id]));
+        }
+        self.session_id = session_id;
+        Ok((response::OK, Vec::new()))
+    }
+
+    fn get_storage_info(
+        &mut self,
+        command: &Container,
+        transport: &mut dyn Transport,
+        storage_id: u32,
+    ) -> Result<Response, Box<dyn std::error::Error>> {
+        let storage_object = match self.find_storage(storage_id) {
+            Some(storage_object) => storage_object.clone(),
+            None => return Ok((response::INVALID_STORAGE_ID, Vec::new())),
+        };
+        let info = self.device.get_object_info(storage_object)?;
+        let storage_info = StorageInfo {
+            storage_type: STORAGE_TYPE_FIXED_RAM,
+            filesystem_type: FILESYSTEM_TYPE_GENERIC_HIERARCHICAL,
+            access_capability: ACCESS_CAPABILITY_READ_WRITE,
+            max_capacity: 0,
+            free_space_in_bytes: 0,
+            free_space_in_objects: ALL,
+            storage_description: info.name,
+            volume_identifier: String::new(),
+        };
+        transport.send_data(command.code, command.transaction_id, &storage_info.encode())?;
+        Ok((response::OK, Vec::new()))
+    }
+
+    fn get_object_handles(
+        &mut self,
+        command: &Container,
+        transport: &mut dyn Transport,
+        storage_id: u32,
+        object_format: u32,
+        parent: u32,
+    ) -> Result<Response, Box<dyn std::error::Error>> {
+        if object_format != 0 {
+            return Ok((response::SPECIFICATION_BY_FORMAT_UNSUPPORTED, Vec::new()));
+        }
+        // (storage ID, parent handle, folder object)
+        let mut folders = Vec::<(u32, u32, ContentObject)>::new();
+        if parent == ALL {
+            for (id, storage_object) in self.storages.iter() {
+                if storage_id == ALL || storage_id == *id {
+                    folders.push((*id, 0, storage_object.clone()));
+                }
+            }
+            if folders.is_empty() {
+                return Ok((response::INVALID_STORAGE_ID, Vec::new()));
+            }
+        } else if parent == 0 {
+            return Ok((response::PARAMETER_NOT_SUPPORTED, Vec::new()));
+        } else {
+            match self.handles.get(&parent) {
+                Some(entry) => folders.push((entry.storage_id, parent, entry.object.clone())),
+                None => return Ok((response::INVALID_PARENT_OBJECT, Vec::new())),
+            }
+        }
+
+        let mut object_handles = Vec::<u32>::new();
+        for (storage_id, parent_handle, folder) in folders {
+            for object in children(self.device.as_ref(), &folder)? {
+                object_handles.push(self.assign_handle(object, storage_id, parent_handle));
+            }
+        }
+
+        let mut w = DataWriter::new();
+        w.write_u32_array(&object_handles);
+        transport.send_data(command.code, command.transaction_id, &w.into_bytes())?;
+        Ok((response::OK, Vec::new()))
+    }
+
+    fn get_object_info(
+        &mut self,
+        command: &Container,
+        transport: &mut dyn Transport,
+        handle: u32,
+    ) -> Result<Response, Box<dyn std::error::Error>> {
+        let entry = match self.handles.get(&handle) {
+            Some(entry) => entry,
+            None => return Ok((response::INVALID_OBJECT_HANDLE, Vec::new())),
+        };
+        let info = match self.device.get_object_info(entry.object.clone()) {
+            Ok(info) => info,
+            Err(_) => return Ok((response::INVALID_OBJECT_HANDLE, Vec::new())),
+        };
+        let object_info = to_object_info(&info, entry.storage_id, entry.parent);
+        transport.send_data(command.code, command.transaction_id, &object_info.encode())?;
+        Ok((response::OK, Vec::new()))
+    }
+
+    fn get_object(
+        &mut self,
+        command: &Container,
+        transport: &mut dyn Transport,
+        handle: u32,
+    ) -> Result<Response, Box<dyn std::error::Error>> {
+        let object = match self.handles.get(&handle) {
+            Some(entry) => entry.object.clone(),
+            None => return Ok((response::INVALID_OBJECT_HANDLE, Vec::new())),
+        };
+        let info = self.device.get_object_info(object.clone())?;
+        if !info.is_file() {
+            return Ok((response::INVALID_OBJECT_HANDLE, Vec::new()));
+        }
+        let mut reader = self.device.get_resoure(&object)?;
+        transport.begin_data(command.code, command.transaction_id, info.data_size)?;
+        let mut remaining = info.data_size;
+        while let Some(bytes) = reader.next(reader.get_optimized_buffer_size())? {
+            if bytes.len() as u64 > remaining {
+                return Err("object size was changed.".into());
+            }
+            transport.write_data(bytes)?;
+            remaining -= bytes.len() as u64;
+        }
+        if remaining != 0 {
+            return Err("object size was changed.".into());
+        }
+        Ok((response::OK, Vec::new()))
+    }
+
+    fn delete_object(
+        &mut self,
+        handle: u32,
+        object_format: u32,
+    ) -> Result<Response, Box<dyn std::error::Error>> {
+        if handle == ALL || object_format != 0 {
+            return Ok((response::PARAMETER_NOT_SUPPORTED, Vec::new()));
+        }
+        let object = match self.handles.get(&handle) {
+            Some(entry) => entry.object.clone(),
+            None => return Ok((response::INVALID_OBJECT_HANDLE, Vec::new())),
+        };
+        let info = self.device.get_object_info(object.clone())?;
+        if !info.can_delete {
+            return Ok((response::OBJECT_WRITE_PROTECTED, Vec::new()));
+        }
+        self.device.delete(&object)?;
+        self.handles.remove(&handle);
+        self.handle_ids.remove(&object.id);
+        Ok((response::OK, Vec::new()))
+    }
+
+    fn send_object_info(
+        &mut self,
+        command: &Container,
+        transport: &mut dyn Transport,
+        storage_id: u32,
+        parent_handle: u32,
+    ) -> Result<Response, Box<dyn std::error::Error>> {
+        let data = receive_data(transport, command.transaction_id)?;
+        self.pending_object = None;
+
+        let object_info = match ObjectInfo::decode(&data) {
+            Ok(object_info) => object_info,
+            Err(_) => return Ok((response::INVALID_DATASET, Vec::new())),
+        };
+
+        let (storage_id, parent_handle, parent) = match self.find_parent(storage_id, parent_handle)
+        {
+            Ok(parent) => parent,
+            Err(code) => return Ok((code, Vec::new())),
+        };
+        let response_parent = if parent_handle == 0 {
+            ALL
+        } else {
+            parent_handle
+        };
+
+        if object_info.object_format == format::ASSOCIATION {
+            let folder = self.device.create_folder(&parent, &object_info.filename)?;
+            let handle = self.assign_handle(folder, storage_id, parent_handle);
+            return Ok((response::OK, vec![storage_id, response_parent, handle]));
+        }
+
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        self.pending_object = Some(PendingObject {
+            handle,
+            storage_id,
+            parent_handle,
+            parent,
+            object_info,
+        });
+        Ok((response::OK, vec![storage_id, response_parent, handle]))
+    }
+
+    fn send_object(
+        &mut self,
+        command: &Container,
+        transport: &mut dyn Transport,
+    ) -> Result<Response, Box<dyn std::error::Error>> {
+        let size = match transport.receive()? {
+            Some(Received::Data {
+                transaction_id,
+                size,
+                ..
+            }) if transaction_id == command.transaction_id => size,
+            _ => return Err("data phase was expected.".into()),
+        };
+        let pending_object = match self.pending_object.take() {
+            Some(pending_object) => pending_object,
+            None => {
+                discard_data(transport)?;
+                return Ok((response::NO_VALID_OBJECT_INFO, Vec::new()));
+            }
+        };
+
+        let mut writer = self.device.create_file(
+            &pending_object.parent,
+            &pending_object.object_info.filename,
+            size,
+            &pending_object.object_info.date_created,
+            &pending_object.object_info.date_modified,
+        )?;
+        let mut buf = vec![0u8; writer.get_buffer_size() as usize];
+        loop {
+            let len = transport.read_data(&mut buf)?;
+            if len == 0 {
+                break;
+            }
+            writer.write(&buf[..len])?;
+        }
+        let object = writer.commit()?;
+        self.bind_handle(
+            pending_object.handle,
+            object,
+            pending_object.storage_id,
+            pending_object.parent_handle,
+        );
+        Ok((response::OK, Vec::new()))
+    }
+
+    /// Returns the size of the object, which doesn't fit in ObjectInfo if it is 4GB or larger.
+    /// Other properties are not supported.
+    fn get_object_prop_value(
+        &mut self,
+        command: &Container,
+        transport: &mut dyn Transport,
+        handle: u32,
+        property_code: u32,
+    ) -> Result<Response, Box<dyn std::error::Error>> {
+        let object = match self.handles.get(&handle) {
+            Some(entry) => entry.object.clone(),
+            None => return Ok((response::INVALID_OBJECT_HANDLE, Vec::new())),
+        };
+        if property_code != object_property::OBJECT_SIZE as u32 {
+            return Ok((response::INVALID_OBJECT_PROP_CODE, Vec::new()));
+        }
+        let info = self.device.get_object_info(object)?;
+        let mut w = DataWriter::new();
+        w.write_u64(info.data_size);
+        transport.send_data(command.code, command.transaction_id, &w.into_bytes())?;
+        Ok((response::OK, Vec::new()))
+    }
+
+    /// Changes the file name of the object. Other properties are not supported.
+    fn set_object_prop_value(
+        &mut self,
+        command: &Container,
+        transport: &mut dyn Transport,
+        handle: u32,
+        property_code: u32,
+    ) -> Result<Response, Box<dyn std::error::Error>> {
+        let data = receive_data(transport, command.transaction_id)?;
+        let object = match self.handles.get(&handle) {
+            Some(entry) => entry.object.clone(),
+            None => return Ok((response::INVALID_OBJECT_HANDLE, Vec::new())),
+        };
+        if property_code != object_property::OBJECT_FILE_NAME as u32 {
+            return Ok((response::INVALID_OBJECT_PROP_CODE, Vec::new()));
+        }
+        let name = match DataReader::new(&data).read_string() {
+            Ok(name) if !name.is_empty() => name,
+            _ => return Ok((response::INVALID_OBJECT_PROP_VALUE, Vec::new())),
+        };
+        // folders are not renamed, because the IDs of their contents may be changed
+        let info = self.device.get_object_info(object.clone())?;
+        if !info.is_file() || !self.device.can_rename(&object) {
+            return Ok((response::ACCESS_DENIED, Vec::new()));
+        }
+        let renamed = self.device.rename(&object, &name)?;
+        self.handle_ids.remove(&object.id);
+        self.handle_ids.insert(renamed.id.clone(), handle);
+        if let Some(entry) = self.handles.get_mut(&handle) {
+            entry.object = renamed;
+        }
+        Ok((response::OK, Vec::new()))
+    }
+
+    /// Copies or moves the file on the device. Folders are not copied nor moved,
+    /// because the IDs of their contents may be changed.
+    fn copy_or_move_object(
+        &mut self,
+        handle: u32,
+        storage_id: u32,
+        parent_handle: u32,
+        copy: bool,
+    ) -> Result<Response, Box<dyn std::error::Error>> {
+        let object = match self.handles.get(&handle) {
+            Some(entry) => entry.object.clone(),
+            None => return Ok((response::INVALID_OBJECT_HANDLE, Vec::new())),
+        };
+        let (storage_id, parent_handle, parent) = match self.find_parent(storage_id, parent_handle)
+        {
+            Ok(parent) => parent,
+            Err(code) => return Ok((code, Vec::new())),
+        };
+        let info = self.device.get_object_info(object.clone())?;
+        let supported = if copy {
+            self.device.can_copy_on_device(&object)
+        } else {
+            self.device.can_move_on_device(&object)
+        };
+        if !info.is_file() || !supported {
+            return Ok((response::ACCESS_DENIED, Vec::new()));
+        }
+        if copy {
+            let copied = self.device.copy_object(&object, &parent)?;
+            let new_handle = self.assign_handle(copied, storage_id, parent_handle);
+            return Ok((response::OK, vec![new_handle]));
+        }
+        let moved = self.device.move_object(&object, &parent)?;
+        self.handle_ids.remove(&object.id);
+        self.bind_handle(handle, moved, storage_id, parent_handle);
+        Ok((response::OK, Vec::new()))
+    }
+
+    /// Returns the storage ID, the parent handle and the parent object
+    /// to create an object in, or the response code if they are invalid.
+    /// The root folder of the storage is specified by 0 or 0xFFFFFFFF.
+    fn find_parent(
+        &self,
+        storage_id: u32,
+        parent_handle: u32,
+    ) -> Result<(u32, u32, ContentObject), u16> {
+        if parent_handle == 0 || parent_handle == ALL {
+            match self.find_storage(storage_id) {
+                Some(storage_object) => Ok((storage_id, 0, storage_object.clone())),
+                None => Err(response::INVALID_STORAGE_ID),
+            }
+        } else {
+            match self.handles.get(&parent_handle) {
+                Some(entry) => Ok((entry.storage_id, parent_handle, entry.object.clone())),
+                None => Err(response::INVALID_PARENT_OBJECT),
+            }
+        }
+    }
+
+    fn find_storage(&self, storage_id: u32) -> Option<&ContentObject> {
+        self.storages
+            .iter()
+            .find(|(id, _)| *id == storage_id)
+            .map(|(_, storage_object)| storage_object)
+    }
+
+    fn assign_handle(&mut self, object: ContentObject, storage_id: u32, parent: u32) -> u32 {
+        if let Some(handle) = self.handle_ids.get(&object.id) {
+            return *handle;
+        }
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        self.bind_handle(handle, object, storage_id, parent);
+        handle
+    }
+
+    fn bind_handle(&mut self, handle: u32, object: ContentObject, storage_id: u32, parent: u32) {
+        self.handle_ids.insert(object.id.clone(), handle);
+        self.handles.insert(
+            handle,
+            HandleEntry {
+                object,
+                storage_id,
+                parent,
+            },
+        );
+    }
+}
+
+fn receive_data(
+    transport: &mut dyn Transport,
+    transaction_id: u32,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    match transport.receive()? {
+        Some(Received::Data {
+            transaction_id: data_transaction_id,
+            size,
+            ..
+        }) if data_transaction_id == transaction_id => {
+            if size > MAX_DATASET_SIZE {
+                discard_data(transport)?;
+                return Err(format!("dataset is too large: {} bytes", size).into());
+            }
+            transport.read_data_to_end()
+        }
+        _ => Err("data phase was expected.".into()),
+    }
+}
+
+/// Reads the rest of the data phase without keeping it.
+fn discard_data(transport: &mut dyn Transport) -> Result<(), Box<dyn std::error::Error>> {
+    let mut buf = [0u8; 4096];
+    while transport.read_data(&mut buf)? != 0 {}
+    Ok(())
+}
+
+fn children(
+    device: &dyn Device,
+    parent: &ContentObject,
+) -> Result<Vec<ContentObject>, Box<dyn std::error::Error>> {
+    let mut iter = device.get_object_iterator(parent)?;
+    let mut objects = Vec::<ContentObject>::new();
+    while let Some(object) = iter.next()? {
+        objects.push(object);
+    }
+    Ok(objects)
+}
+
+fn to_object_info(info: &ContentObjectInfo, storage_id: u32, parent: u32) -> ObjectInfo {
+    let is_folder = info.is_folder();
+    ObjectInfo {
+        storage_id,
+        object_format: if is_folder {
+            format::ASSOCIATION
+        } else {
+            format::UNDEFINED
+        },
+        protection_status: if info.can_delete {
+            PROTECTION_STATUS_NONE
+        } else {
+            PROTECTION_STATUS_READ_ONLY
+        },
+        object_compressed_size: std::cmp::min(info.data_size, ALL as u64) as u32,
+        thumb_format: 0,
+        thumb_compressed_size: 0,
+        thumb_pix_width: 0,
+        thumb_pix_height: 0,
+        image_pix_width: 0,
+        image_pix_height: 0,
+        image_bit_depth: 0,
+        parent_object: parent,
+        association_type: if is_folder {
+            ASSOCIATION_TYPE_GENERIC_FOLDER
+        } else {
+            0
+        },
+        association_desc: 0,
+        sequence_number: 0,
+        filename: info.name.clone(),
+        date_created: info.time_created,
+        date_modified: info.time_modified,
+        keywords: String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::device::MemoryDevice;
+    use crate::mtp::loopback::LoopbackTransport;
+    use std::convert::TryInto;
+
+    fn setup() -> (MemoryDevice, LoopbackTransport) {
+        let device = MemoryDevice::new("Phone");
+        let storage = device.add_storage("Internal");
+        let file = device.add_file(&storage, "a.txt", b"a");
+        device.set_can_delete(&file, false);
+        let transport = LoopbackTransport::new(Box::new(device.clone())).unwrap();
+        (device, transport)
+    }
+
+    /// Runs a transaction, and returns the data and the response.
+    fn transact(
+        transport: &mut LoopbackTransport,
+        code: u16,
+        transaction_id: u32,
+        params: &[u32],
+    ) -> (Vec<u8>, Container) {
+        transport
+            .send(&Container::command(code, transaction_id, params))
+            .unwrap();
+        let mut data = Vec::<u8>::new();
+        loop {
+            match transport.receive().unwrap().unwrap() {
+                Received::Data { .. } => data = transport.read_data_to_end().unwrap(),
+                Received::Container(container) => {
+                    assert_eq!(container.transaction_id, transaction_id);
+                    return (data, container);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_session() {
+        let (_device, mut transport) = setup();
+        let (_, r) = transact(&mut transport, operation::GET_STORAGE_IDS, 0, &[]);
+        assert_eq!(r.code, response::SESSION_NOT_OPEN);
+        let (_, r) = transact(&mut transport, operation::OPEN_SESSION, 0, &[0]);
+        assert_eq!(r.code, response::INVALID_PARAMETER);
+        let (_, r) = transact(&mut transport, operation::OPEN_SESSION, 0, &[7]);
+        assert_eq!(r.code, response::OK);
+        let (_, r) = transact(&mut transport, operation::OPEN_SESSION, 0, &[8]);
+        assert_eq!(r.code, response::SESSION_ALREADY_OPEN);
+        assert_eq!(r.params().unwrap(), vec![7]);
+        let (_, r) = transact(&mut transport, operation::CLOSE_SESSION, 1, &[]);
+        assert_eq!(r.code, response::OK);
+        let (_, r) = transact(&mut transport, operation::GET_STORAGE_IDS, 2, &[]);
+        assert_eq!(r.code, response::SESSION_NOT_OPEN);
+    }
+
+    #[test]
+    fn test_unsupported_requests() {
+        let (device, mut transport) = setup();
+        transact(&mut transport, operation::OPEN_SESSION, 0, &[1]);
+
+        let (_, r) = transact(&mut transport, operation::FORMAT_STORE, 1, &[0x10001]);
+        assert_eq!(r.code, response::OPERATION_NOT_SUPPORTED);
+
+        let (_, r) = transact(
+            &mut transport,
+            operation::GET_OBJECT_HANDLES,
+            2,
+            &[ALL, format::MP3 as u32, ALL],
+        );
+        assert_eq!(r.code, response::SPECIFICATION_BY_FORMAT_UNSUPPORTED);
+
+        let (_, r) = transact(
+            &mut transport,
+            operation::GET_OBJECT_HANDLES,
+            3,
+            &[ALL, 0, 0],
+        );
+        assert_eq!(r.code, response::PARAMETER_NOT_SUPPORTED);
+
+        let (data, r) = transact(
+            &mut transport,
+            operation::GET_OBJECT_HANDLES,
+            4,
+            &[ALL, 0, ALL],
+        );
+        assert_eq!(r.code, response::OK);
+        let handle = u32::from_le_bytes(data[4..8].try_into().unwrap());
+
+        let (_, r) = transact(&mut transport, operation::DELETE_OBJECT, 5, &[handle]);
+        assert_eq!(r.code, response::OBJECT_WRITE_PROTECTED);
+        assert!(device.find("Internal\\a.txt").is_some());
+
+        let (_, r) = transact(&mut transport, operation::DELETE_OBJECT, 6, &[ALL]);
+        assert_eq!(r.code, response::PARAMETER_NOT_SUPPORTED);
+    }
+
+    #[test]
+    fn test_too_large_dataset() {
+        let (device, mut transport) = setup();
+        transact(&mut transport, operation::OPEN_SESSION, 0, &[1]);
+
+        transport
+            .send(&Container::command(
+                operation::SEND_OBJECT_INFO,
+                1,
+                &[0x10001, ALL],
+            ))
+            .unwrap();
+        let data = vec![0u8; MAX_DATASET_SIZE as usize + 1];
+        transport
+            .send_data(operation::SEND_OBJECT_INFO, 1, &data)
+            .unwrap();
+        match transport.receive().unwrap().unwrap() {
+            Received::Container(r) => assert_eq!(r.code, response::GENERAL_ERROR),
+            other => panic!("unexpected: {:?}", other),
+        }
+
+        // the session is still usable
+        let (_, r) = transact(&mut transport, operation::GET_STORAGE_IDS, 2, &[]);
+        assert_eq!(r.code, response::OK);
+        let storage = device.find("Internal").unwrap();
+        assert_eq!(device.child_names(&storage), vec!["a.txt"]);
+    }
+}
@@ -0,0 +1,680 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+use super::codes::{object_property, operation, response};
+use super::container::{Container, ContainerType};
+use super::dataset::{DataReader, DataWriter, DeviceInfo, ObjectInfo, StorageInfo};
+use super::transport::{Received, Transport};
+
+/// Error returned when the responder did not respond with OK.
+#[derive(Debug)]
+pub struct ResponseError {
+    pub operation_code: u16,
+    pub response_code: u16,
+}
+
+impl fmt::Display for ResponseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operation_name = operation::name(self.operation_code).unwrap_or("operation");
+        match response::name(self.response_code) {
+            Some(name) => write!(f, "{} failed: {}", operation_name, name),
+            None => write!(
+                f,
+                "{} failed: response code 0x{:04x}",
+                operation_name, self.response_code
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ResponseError {}
+
+/// State of the operation which has a data phase across method calls.
+enum Pending {
+    None,
+    /// Receiving the object in GetObject
+    GetObject(u32),
+    /// Sending the object in SendObject
+    SendObject(u32),
+}
+
+/// A session of the MTP initiator.
+pub struct Session {
+    transport: Box<dyn Transport>,
+    session_id: u32,
+    next_transaction_id: u32,
+    pending: Pending,
+    /// Object data of GetObject which was completed before it was read
+    spool: Option<Spool>,
+    /// Events received in the transactions
+    events: VecDeque<Container>,
+}
+
+const SPOOL_BUFFER_SIZE: usize = 0x10000;
+
+/// Counter to make the names of the spool files unique in the process
+static SPOOL_COUNT: AtomicU32 = AtomicU32::new(0);
+
+/// Object data kept in a temporary file, since the object may be too large for the memory.
+struct Spool {
+    file: File,
+    path: PathBuf,
+    /// Error message if GetObject failed
+    error: Option<String>,
+}
+
+impl Spool {
+    fn new() -> Result<Spool, Box<dyn std::error::Error>> {
+        let path = std::env::temp_dir().join(format!(
+            "mtpcopy-{}-{}.spool",
+            std::process::id(),
+            SPOOL_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok(Spool {
+            file,
+            path,
+            error: None,
+        })
+    }
+}
+
+impl Drop for Spool {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+impl Session {
+    pub fn new(transport: Box<dyn Transport>) -> Session {
+        Session {
+            transport,
+            session_id: 0,
+            next_transaction_id: 0,
+            pending: Pending::None,
+            spool: None,
+            events: VecDeque::new(),
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.session_id != 0
+    }
+
+    fn transaction_id(&mut self) -> u32 {
+        let transaction_id = self.next_transaction_id;
+        self.next_transaction_id = match transaction_id {
+            0 | 0xFFFFFFFE => 1,
+            n => n + 1,
+        };
+        transaction_id
+    }
+
+    /// Prepares for the new transaction.
+    ///
+    /// Since transactions cannot be interleaved, the rest of the object being received
+    /// is written to a temporary file (the spool), and it is read from there.
+    /// This happens when the object is copied within the same device.
+    fn prepare_transaction(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        match self.pending {
+            Pending::None => Ok(()),
+            Pending::GetObject(transaction_id) => {
+                log::debug!("GetObject is spooled to start another transaction.");
+                let mut spool = Spool::new()?;
+                let mut buf = vec![0u8; SPOOL_BUFFER_SIZE];
+                loop {
+                    let len = self.transport.read_data(&mut buf)?;
+                    if len == 0 {
+                        break;
+                    }
+                    spool.file.write_all(&buf[..len])?;
+                }
+                spool.file.seek(SeekFrom::Start(0))?;
+                self.pending = Pending::None;
+                spool.error = self
+                    .receive_response(operation::GET_OBJECT, transaction_id)
+                    .err()
+                    .map(|err| err.to_string());
+                self.spool = Some(spool);
+                Ok(())
+            }
+            Pending::SendObject(_) => Err("SendObject is in progress.".into()),
+        }
+    }
+
+    fn send_command(
+        &mut self,
+        code: u16,
+        params: &[u32],
+    ) -> Result<u32, Box<dyn std::error::Error>> {
+        self.prepare_transaction()?;
+        let transaction_id = if self.is_open() {
+            self.transaction_id()
+        } else {
+            0
+        };
+        log::trace!(
+            "MTP command {} tid={} params={:?}",
+            operation::name(code).unwrap_or("?"),
+            transaction_id,
+            params
+        );
+        self.transport
+            .send(&Container::command(code, transaction_id, params))?;
+        Ok(transaction_id)
+    }
+
+    /// Receives the data phase (if any) and the response.
+    fn receive_response(
+        &mut self,
+        code: u16,
+        transaction_id: u32,
+    ) -> Result<(Vec<u8>, Vec<u32>), Box<dyn std::error::Error>> {
+        let mut data = Vec::<u8>::new();
+        loop {
+            match self.transport.receive()? {
+                None => return Err("connection was closed by the responder.".into()),
+                Some(Received::Data {
+                    transaction_id: data_transaction_id,
+                    ..
+                }) => {
+                    check_transaction_id(transaction_id, data_transaction_id)?;
+                    data = self.transport.read_data_to_end()?;
+                }
+                Some(Received::Container(container)) => {
+                    if container.container_type == ContainerType::Event {
+                        self.events.push_back(container);
+                        continue;
+                    }
+                    if container.container_type != ContainerType::Response {
+                        log::debug!("ignored container: {:?}", &container);
+                        continue;
+                    }
+                    check_transaction_id(transaction_id, container.transaction_id)?;
+                    if container.code != response::OK {
+                        return Err(ResponseError {
+                            operation_code: code,
+                            response_code: container.code,
+                        }
+                        .into());
+                    }
+                    return Ok((data, container.params()?));
+                }
+            }
+        }
+    }
+
+    fn transaction(
+        &mut self,
+        code: u16,
+        params: &[u32],
+        data_out: Option<&[u8]>,
+    ) -> Result<(Vec<u8>, Vec<u32>), Box<dyn std::error::Error>> {
+        let transaction_id = self.send_command(code, params)?;
+        if let Some(data) = data_out {
+            self.transport.send_data(code, transaction_id, data)?;
+        }
+        self.receive_response(code, transaction_id)
+    }
+
+    /// Waits for the next event from the responder.
+    /// Returns None if no event arrived within the timeout.
+    pub fn wait_event(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Option<Container>, Box<dyn std::error::Error>> {
+        if let Some(container) = self.events.pop_front() {
+            return Ok(Some(container));
+        }
+        self.transport.receive_event(timeout)
+    }
+
+    pub fn get_device_info(&mut self) -> Result<DeviceInfo, Box<dyn std::error::Error>> {
+        let (data, _) = self.transaction(operation::GET_DEVICE_INFO, &[], None)?;
+        DeviceInfo::decode(&data)
+    }
+
+    pub fn open_session(&mut self, session_id: u32) -> Result<(), Box<dyn std::error::Error>> {
+        if self.is_open() {
+            return Err("session is already open.".into());
+        }
+        self.next_transaction_id = 0;
+        self.transaction(operation::OPEN_SESSION, &[session_id], None)?;
+        self.session_id = session_id;
+        self.next_transaction_id = 1;
+        Ok(())
+    }
+
+    pub fn close_session(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.is_open() {
+            return Ok(());
+        }
+        let result = self.transaction(operation::CLOSE_SESSION, &[], None);
+        self.session_id = 0;
+        self.next_transaction_id = 0;
+        result.map(|_| ())
+    }
+
+    pub fn get_storage_ids(&mut self) -> Result<Vec<u32>, Box<dyn std::error::Error>> {
+        let (data, _) = self.transaction(operation::GET_STORAGE_IDS, &[], None)?;
+        decode_u32_array(&data)
+    }
+
+    pub fn get_storage_info(
+        &mut self,
+        storage_id: u32,
+    ) -> Result<StorageInfo, Box<dyn std::error::Error>> {
+        let (data, _) = self.transaction(operation::GET_STORAGE_INFO, &[storage_id], None)?;
+        StorageInfo::decode(&data)
+    }
+
+    /// Returns handles of the objects in the folder.
+    /// `parent` is 0xFFFFFFFF for the root folder of the storage.
+    pub fn get_object_handles(
+        &mut self,
+        storage_id: u32,
+        parent: u32,
+    ) -> Result<Vec<u32>, Box<dyn std::error::Error>> {
+        let (data, _) = self.transaction(
+            operation::GET_OBJECT_HANDLES,
+            &[storage_id, 0, parent],
+            None,
+        )?;
+        decode_u32_array(&data)
+    }
+
+    pub fn get_object_info(
+        &mut self,
+        handle: u32,
+    ) -> Result<ObjectInfo, Box<dyn std::error::Error>> {
+        let (data, _) = self.transaction(operation::GET_OBJECT_INFO, &[handle], None)?;
+        ObjectInfo::decode(&data)
+    }
+
+    /// Returns the size of the object by GetObjectPropValue.
+    /// ObjectInfo has 0xFFFFFFFF as the size if the object is 4GB or larger.
+    pub fn get_object_size(&mut self, handle: u32) -> Result<u64, Box<dyn std::error::Error>> {
+        let (data, _) = self.transaction(
+            operation::GET_OBJECT_PROP_VALUE,
+            &[handle, object_property::OBJECT_SIZE as u32],
+            None,
+        )?;
+        let mut r = DataReader::new(&data);
+        let size = r.read_u64()?;
+        r.finish()?;
+        Ok(size)
+    }
+
+    /// Begins GetObject, and returns the size of the object.
+    /// The object data must be read by `read_object()` until it returns 0.
+    pub fn get_object(&mut self, handle: u32) -> Result<u64, Box<dyn std::error::Error>> {
+        if self.spool.is_some() {
+            return Err("the previous object has not been read.".into());
+        }
+        let transaction_id = self.send_command(operation::GET_OBJECT, &[handle])?;
+        match self.transport.receive()? {
+            Some(Received::Data {
+                transaction_id: data_transaction_id,
+                size,
+                ..
+            }) => {
+                check_transaction_id(transaction_id, data_transaction_id)?;
+                self.pending = Pending::GetObject(transaction_id);
+                Ok(size)
+            }
+            Some(Received::Container(container))
+                if container.container_type == ContainerType::Response =>
+            {
+                check_transaction_id(transaction_id, container.transaction_id)?;
+                Err(ResponseError {
+                    operation_code: operation::GET_OBJECT,
+                    response_code: container.code,
+                }
+                .into())
+            }
+            Some(other) => Err(format!("unexpected container: {:?}", other).into()),
+            None => Err("connection was closed by the responder.".into()),
+        }
+    }
+
+    /// Reads the object data.
+    /// Returns 0 after the whole data was read and the transaction was completed.
+    pub fn read_object(&mut self, buf: &mut [u8]) -> Result<usize, Box<dyn std::error::Error>> {
+        if let Some(spool) = self.spool.as_mut() {
+            let len = spool.file.read(buf)?;
+            if len == 0 {
+                if let Some(message) = self.spool.take().and_then(|mut spool| spool.error.take()) {
+                    return Err(message.into());
+                }
+            }
+            return Ok(len);
+        }
+        let transaction_id = match self.pending {
+            Pending::GetObject(transaction_id) => transaction_id,
+            _ => return Err("GetObject is not in progress.".into()),
+        };
+        let len = self.transport.read_data(buf)?;
+        if len == 0 {
+            self.pending = Pending::None;
+            self.receive_response(operation::GET_OBJECT, transaction_id)?;
+        }
+        Ok(len)
+    }
+
+    /// Sends ObjectInfo of the new object, and returns the handle of the object.
+    /// `parent` is 0xFFFFFFFF for the root folder of the storage.
+    pub fn send_object_info(
+        &mut self,
+        storage_id: u32,
+        parent: u32,
+        object_info: &ObjectInfo,
+    ) -> Result<u32, Box<dyn std::error::Error>> {
+        let data = object_info.encode();
+        let (_, params) = self.transaction(
+            operation::SEND_OBJECT_INFO,
+            &[storage_id, parent],
+            Some(&data),
+        )?;
+        match params.get(2) {
+            Some(handle) => Ok(*handle),
+            None => Err("SEND_OBJECT_INFO: object handle was not returned.".into()),
+        }
+    }
+
+    /// Begins SendObject for the object specified by the preceding SendObjectInfo.
+    /// The object data must be written by `write_object()`, then `finish_send_object()`
+    /// must be called.
+    pub fn send_object(&mut self, size: u64) -> Result<(), Box<dyn std::error::Error>> {
+        let transaction_id = self.send_command(operation::SEND_OBJECT, &[])?;
+        self.transport
+            .begin_data(operation::SEND_OBJECT, transaction_id, size)?;
+        self.pending = Pending::SendObject(transaction_id);
+        Ok(())
+    }
+
+    pub fn write_object(&mut self, bytes: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        match self.pending {
+            Pending::SendObject(_) => self.transport.write_data(bytes),
+            _ => Err("SendObject is not in progress.".into()),
+        }
+    }
+
+    pub fn finish_send_object(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let transaction_id = match self.pending {
+            Pending::SendObject(transaction_id) => transaction_id,
+            _ => return Err("SendObject is not in progress.".into()),
+        };
+        self.pending = Pending::None;
+        self.receive_response(operation::SEND_OBJECT, transaction_id)?;
+        Ok(())
+    }
+
+    pub fn delete_object(&mut self, handle: u32) -> Result<(), Box<dyn std::error::Error>> {
+        self.transaction(operation::DELETE_OBJECT, &[handle, 0], None)?;
+        Ok(())
+    }
+
+    /// Copies the object on the device, and returns the handle of the new object.
+    /// `parent` is 0 for the root folder of the storage.
+    pub fn copy_object(
+        &mut self,
+        handle: u32,
+        storage_id: u32,
+        parent: u32,
+    ) -> Result<u32, Box<dyn std::error::Error>> {
+        let (_, params) =
+            self.transaction(operation::COPY_OBJECT, &[handle, storage_id, parent], None)?;
+        match params.first() {
+            Some(handle) => Ok(*handle),
+            None => Err("COPY_OBJECT: object handle was not returned.".into()),
+        }
+    }
+
+    /// Moves the object on the device. The handle of the object is not changed.
+    /// `parent` is 0 for the root folder of the storage.
+    pub fn move_object(
+        &mut self,
+        handle: u32,
+        storage_id: u32,
+        parent: u32,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.transaction(operation::MOVE_OBJECT, &[handle, storage_id, parent], None)?;
+        Ok(())
+    }
+
+    /// Renames the object by SetObjectPropValue.
+    pub fn set_object_file_name(
+        &mut self,
+        handle: u32,
+        name: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut w = DataWriter::new();
+        w.write_string(name);
+        self.transaction(
+            operation::SET_OBJECT_PROP_VALUE,
+            &[handle, object_property::OBJECT_FILE_NAME as u32],
+            Some(&w.into_bytes()),
+        )?;
+        Ok(())
+    }
+}
+
+fn check_transaction_id(expected: u32, actual: u32) -> Result<(), Box<dyn std::error::Error>> {
+    if expected != actual {
+        return Err(format!(
+            "transaction ID mismatch: expected {}, but got {}",
+            expected, actual
+        )
+        .into());
+    }
+    Ok(())
+}
+
+fn decode_u32_array(data: &[u8]) -> Result<Vec<u32>, Box<dyn std::error::Error>> {
+    let mut r = DataReader::new(data);
+    let values = r.read_u32_array()?;
+    r.finish()?;
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::device::MemoryDevice;
+    use crate::mtp::codes::{format, ALL};
+    use crate::mtp::loopback::LoopbackTransport;
+    use chrono::NaiveDate;
+
+    fn setup() -> (MemoryDevice, Session) {
+        let device = MemoryDevice::new("Phone");
+        let storage = device.add_storage("Internal");
+        let music = device.add_folder(&storage, "Music");
+        device.add_file(&music, "a.mp3", b"aaaa");
+        device.add_storage("SD Card");
+        let transport = LoopbackTransport::new(Box::new(device.clone())).unwrap();
+        (device, Session::new(Box::new(transport)))
+    }
+
+    fn new_object_info(storage_id: u32, object_format: u16, name: &str, size: u32) -> ObjectInfo {
+        ObjectInfo {
+            storage_id,
+            object_format,
+            protection_status: 0,
+            object_compressed_size: size,
+            thumb_format: 0,
+            thumb_compressed_size: 0,
+            thumb_pix_width: 0,
+            thumb_pix_height: 0,
+            image_pix_width: 0,
+            image_pix_height: 0,
+            image_bit_depth: 0,
+            parent_object: 0,
+            association_type: 0,
+            association_desc: 0,
+            sequence_number: 0,
+            filename: name.to_string(),
+            date_created: None,
+            date_modified: Some(NaiveDate::from_ymd(2021, 4, 1).and_hms(12, 34, 56)),
+            keywords: String::new(),
+        }
+    }
+
+    fn response_code(err: Box<dyn std::error::Error>) -> u16 {
+        err.downcast_ref::<ResponseError>().unwrap().response_code
+    }
+
+    #[test]
+    fn test_session_flow() {
+        let (device, mut session) = setup();
+
+        let device_info = session.get_device_info().unwrap();
+        assert_eq!(device_info.model, "Phone");
+        assert!(device_info
+            .operations_supported
+            .contains(&operation::SEND_OBJECT));
+
+        session.open_session(1).unwrap();
+        assert!(session.is_open());
+
+        let storage_ids = session.get_storage_ids().unwrap();
+        assert_eq!(storage_ids.len(), 2);
+        let storage_info = session.get_storage_info(storage_ids[0]).unwrap();
+        assert_eq!(storage_info.storage_description, "Internal");
+
+        let handles = session.get_object_handles(storage_ids[0], ALL).unwrap();
+        assert_eq!(handles.len(), 1);
+        let music_info = session.get_object_info(handles[0]).unwrap();
+        assert_eq!(music_info.filename, "Music");
+        assert_eq!(music_info.object_format, format::ASSOCIATION);
+        assert_eq!(music_info.parent_object, 0);
+
+        let handles = session.get_object_handles(ALL, handles[0]).unwrap();
+        assert_eq!(handles.len(), 1);
+        let file_info = session.get_object_info(handles[0]).unwrap();
+        assert_eq!(file_info.filename, "a.mp3");
+        assert_eq!(file_info.object_compressed_size, 4);
+
+        // GetObject
+        assert_eq!(session.get_object(handles[0]).unwrap(), 4);
+        let mut data = Vec::<u8>::new();
+        let mut buf = [0u8; 3];
+        loop {
+            let len = session.read_object(&mut buf).unwrap();
+            if len == 0 {
+                break;
+            }
+            data.extend_from_slice(&buf[..len]);
+        }
+        assert_eq!(data, b"aaaa");
+
+        // SendObjectInfo / SendObject
+        let folder_info = new_object_info(storage_ids[1], format::ASSOCIATION, "Data", 0);
+        let folder = session
+            .send_object_info(storage_ids[1], ALL, &folder_info)
+            .unwrap();
+        let object_info = new_object_info(storage_ids[1], format::UNDEFINED, "b.txt", 5);
+        let handle = session
+            .send_object_info(storage_ids[1], folder, &object_info)
+            .unwrap();
+        session.send_object(5).unwrap();
+        session.write_object(b"bb").unwrap();
+        session.write_object(b"bbb").unwrap();
+        session.finish_send_object().unwrap();
+
+        let created = device.find("SD Card\\Data\\b.txt").unwrap();
+        assert_eq!(device.data(&created), b"bbbbb");
+        let created_info = session.get_object_info(handle).unwrap();
+        assert_eq!(created_info.filename, "b.txt");
+        assert_eq!(created_info.date_modified, object_info.date_modified);
+
+        // DeleteObject
+        session.delete_object(folder).unwrap();
+        assert!(device.find("SD Card\\Data").is_none());
+
+        session.close_session().unwrap();
+        assert!(!session.is_open());
+    }
+
+    #[test]
+    fn test_error_responses() {
+        let (_device, mut session) = setup();
+
+        let err = session.get_storage_ids().unwrap_err();
+        assert_eq!(response_code(err), response::SESSION_NOT_OPEN);
+
+        session.open_session(1).unwrap();
+        let err = session.get_object_info(0x1234).unwrap_err();
+        assert_eq!(response_code(err), response::INVALID_OBJECT_HANDLE);
+        let err = session.get_storage_info(0x1234).unwrap_err();
+        assert_eq!(response_code(err), response::INVALID_STORAGE_ID);
+        let err = session.get_object(0x1234).unwrap_err();
+        assert_eq!(response_code(err), response::INVALID_OBJECT_HANDLE);
+        assert_eq!(
+            err_message(session.get_object(0x1234).unwrap_err()),
+            "GET_OBJECT failed: INVALID_OBJECT_HANDLE"
+        );
+
+        // SendObject without SendObjectInfo
+        session.send_object(1).unwrap();
+        session.write_object(b"x").unwrap();
+        let err = session.finish_send_object().unwrap_err();
+        assert_eq!(response_code(err), response::NO_VALID_OBJECT_INFO);
+
+        // the session is still usable
+        assert_eq!(session.get_storage_ids().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_spooled_get_object() {
+        let (_device, mut session) = setup();
+        session.open_session(1).unwrap();
+        let storage_ids = session.get_storage_ids().unwrap();
+        let music = session.get_object_handles(storage_ids[0], ALL).unwrap()[0];
+        let file_a = session.get_object_handles(ALL, music).unwrap()[0];
+
+        assert_eq!(session.get_object(file_a).unwrap(), 4);
+        let mut buf = [0u8; 1];
+        assert_eq!(session.read_object(&mut buf).unwrap(), 1);
+
+        // another transaction in the middle of the object
+        assert_eq!(session.get_storage_ids().unwrap().len(), 2);
+        let spool_path = session.spool.as_ref().unwrap().path.clone();
+        assert!(spool_path.exists());
+
+        let mut data = buf.to_vec();
+        let mut buf = [0u8; 2];
+        loop {
+            let len = session.read_object(&mut buf).unwrap();
+            if len == 0 {
+                break;
+            }
+            data.extend_from_slice(&buf[..len]);
+        }
+        assert_eq!(data, b"aaaa");
+        assert!(!spool_path.exists());
+    }
+
+    fn err_message(err: Box<dyn std::error::Error>) -> String {
+        err.to_string()
+    }
+
+    #[test]
+    fn test_transaction_id() {
+        let (_device, mut session) = setup();
+        assert_eq!(session.transaction_id(), 0);
+        assert_eq!(session.transaction_id(), 1);
+        assert_eq!(session.transaction_id(), 2);
+        session.next_transaction_id = 0xFFFFFFFE;
+        assert_eq!(session.transaction_id(), 0xFFFFFFFE);
+        assert_eq!(session.transaction_id(), 1);
+    }
+}
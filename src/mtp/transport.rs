@@ -0,0 +1,272 @@
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use super::container::{Container, ContainerHeader, ContainerType, HEADER_SIZE, MAX_PARAMS};
+
+/// A container or the beginning of the data phase received from the transport.
+#[derive(Debug)]
+pub enum Received {
+    /// Command, response or event container
+    Container(Container),
+    /// Beginning of the data phase.
+    /// The payload must be read by `Transport::read_data()`.
+    Data {
+        code: u16,
+        transaction_id: u32,
+        size: u64,
+    },
+}
+
+/// Carries PTP/MTP containers between an initiator and a responder.
+pub trait Transport {
+    /// Sends a command, response or event container.
+    fn send(&mut self, container: &Container) -> Result<(), Box<dyn std::error::Error>>;
+
+    /// Begins the data phase.
+    /// `size` bytes of the payload must be written by `write_data()`.
+    fn begin_data(
+        &mut self,
+        code: u16,
+        transaction_id: u32,
+        size: u64,
+    ) -> Result<(), Box<dyn std::error::Error>>;
+
+    fn write_data(&mut self, bytes: &[u8]) -> Result<(), Box<dyn std::error::Error>>;
+
+    /// Receives the next container.
+    /// Returns None if the connection was closed by the peer.
+    fn receive(&mut self) -> Result<Option<Received>, Box<dyn std::error::Error>>;
+
+    /// Reads the payload of the data phase.
+    /// Returns 0 at the end of the payload.
+    fn read_data(&mut self, buf: &mut [u8]) -> Result<usize, Box<dyn std::error::Error>>;
+
+    /// Receives an event container from the event channel.
+    /// Returns None if no event arrived within the timeout.
+    ///
+    /// Transports that carry events on the same stream as the responses don't have
+    /// the event channel; their events are received by `receive()` in the transactions.
+    fn receive_event(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Option<Container>, Box<dyn std::error::Error>> {
+        std::thread::sleep(timeout);
+        Ok(None)
+    }
+
+    /// Sends the whole data phase.
+    fn send_data(
+        &mut self,
+        code: u16,
+        transaction_id: u32,
+        payload: &[u8],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.begin_data(code, transaction_id, payload.len() as u64)?;
+        self.write_data(payload)
+    }
+
+    /// Reads the whole payload of the data phase.
+    fn read_data_to_end(&mut self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut payload = Vec::<u8>::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let len = self.read_data(&mut buf)?;
+            if len == 0 {
+                return Ok(payload);
+            }
+            payload.extend_from_slice(&buf[..len]);
+        }
+    }
+}
+
+/// A transport that sends containers on a byte stream as they are, like USB bulk pipes.
+pub struct StreamTransport<S: Read + Write> {
+    stream: S,
+    /// Remaining bytes of the data phase being sent
+    writing: u64,
+    /// Remaining bytes of the data phase being received
+    reading: u64,
+}
+
+impl<S: Read + Write> StreamTransport<S> {
+    pub fn new(stream: S) -> StreamTransport<S> {
+        StreamTransport {
+            stream,
+            writing: 0,
+            reading: 0,
+        }
+    }
+
+    fn check_idle(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.writing != 0 {
+            return Err(format!("{} bytes of the data phase were not sent.", self.writing).into());
+        }
+        if self.reading != 0 {
+            return Err(format!("{} bytes of the data phase were not read.", self.reading).into());
+        }
+        Ok(())
+    }
+}
+
+impl<S: Read + Write> Transport for StreamTransport<S> {
+    fn send(&mut self, container: &Container) -> Result<(), Box<dyn std::error::Error>> {
+        self.check_idle()?;
+        self.stream.write_all(&container.encode())?;
+        self.stream.flush()?;
+        Ok(())
+    }
+
+    fn begin_data(
+        &mut self,
+        code: u16,
+        transaction_id: u32,
+        size: u64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.check_idle()?;
+        if size > (u32::MAX as usize - HEADER_SIZE) as u64 {
+            return Err(format!("data is too large: {} bytes", size).into());
+        }
+        let header = ContainerHeader {
+            length: HEADER_SIZE as u32 + size as u32,
+            container_type: ContainerType::Data,
+            code,
+            transaction_id,
+        };
+        self.stream.write_all(&header.encode())?;
+        self.writing = size;
+        if size == 0 {
+            self.stream.flush()?;
+        }
+        Ok(())
+    }
+
+    fn write_data(&mut self, bytes: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        if bytes.len() as u64 > self.writing {
+            return Err("data exceeds the size of the data phase.".into());
+        }
+        self.stream.write_all(bytes)?;
+        self.writing -= bytes.len() as u64;
+        if self.writing == 0 {
+            self.stream.flush()?;
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Option<Received>, Box<dyn std::error::Error>> {
+        self.check_idle()?;
+        let mut header_bytes = [0u8; HEADER_SIZE];
+        let mut len = 0;
+        while len < HEADER_SIZE {
+            let n = self.stream.read(&mut header_bytes[len..])?;
+            if n == 0 {
+                if len == 0 {
+                    return Ok(None);
+                }
+                return Err("connection was closed in the middle of the container.".into());
+            }
+            len += n;
+        }
+        let header = ContainerHeader::decode(&header_bytes)?;
+        if header.container_type == ContainerType::Data {
+            self.reading = header.payload_length() as u64;
+            return Ok(Some(Received::Data {
+                code: header.code,
+                transaction_id: header.transaction_id,
+                size: self.reading,
+            }));
+        }
+        // the length is checked before the buffer is allocated for it
+        if header.payload_length() > MAX_PARAMS * 4 {
+            return Err(format!("container is too long: {} bytes", header.length).into());
+        }
+        let mut payload = vec![0u8; header.payload_length()];
+        self.stream.read_exact(&mut payload)?;
+        Ok(Some(Received::Container(Container {
+            container_type: header.container_type,
+            code: header.code,
+            transaction_id: header.transaction_id,
+            payload,
+        })))
+    }
+
+    fn read_data(&mut self, buf: &mut [u8]) -> Result<usize, Box<dyn std::error::Error>> {
+        if self.reading == 0 {
+            return Ok(0);
+        }
+        let max_len = std::cmp::min(buf.len() as u64, self.reading) as usize;
+        let len = self.stream.read(&mut buf[..max_len])?;
+        if len == 0 {
+            return Err("connection was closed in the middle of the data phase.".into());
+        }
+        self.reading -= len as u64;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mtp::codes::{operation, response};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_stream_transport() {
+        let mut transport = StreamTransport::new(Cursor::new(Vec::<u8>::new()));
+        transport
+            .send(&Container::command(operation::GET_OBJECT, 3, &[0x2a]))
+            .unwrap();
+        transport.begin_data(operation::GET_OBJECT, 3, 5).unwrap();
+        transport.write_data(b"abc").unwrap();
+        assert!(transport.receive().is_err()); // data phase is not completed
+        transport.write_data(b"de").unwrap();
+        assert!(transport.write_data(b"f").is_err());
+        transport
+            .send(&Container::response(response::OK, 3, &[]))
+            .unwrap();
+
+        let bytes = transport.stream.into_inner();
+        assert_eq!(bytes.len(), 16 + 17 + 12);
+        let mut transport = StreamTransport::new(Cursor::new(bytes));
+
+        match transport.receive().unwrap() {
+            Some(Received::Container(container)) => {
+                assert_eq!(container.code, operation::GET_OBJECT);
+                assert_eq!(container.params().unwrap(), vec![0x2a]);
+            }
+            other => panic!("unexpected: {:?}", other),
+        }
+        match transport.receive().unwrap() {
+            Some(Received::Data {
+                code,
+                transaction_id,
+                size,
+            }) => {
+                assert_eq!(code, operation::GET_OBJECT);
+                assert_eq!(transaction_id, 3);
+                assert_eq!(size, 5);
+            }
+            other => panic!("unexpected: {:?}", other),
+        }
+        assert_eq!(transport.read_data_to_end().unwrap(), b"abcde");
+        match transport.receive().unwrap() {
+            Some(Received::Container(container)) => assert_eq!(container.code, response::OK),
+            other => panic!("unexpected: {:?}", other),
+        }
+        assert!(transport.receive().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_too_long_container() {
+        let header = ContainerHeader {
+            length: 0xFFFFFFFF,
+            container_type: ContainerType::Response,
+            code: response::OK,
+            transaction_id: 1,
+        };
+        let mut transport = StreamTransport::new(Cursor::new(header.encode().to_vec()));
+        assert_eq!(
+            transport.receive().unwrap_err().to_string(),
+            "container is too long: 4294967295 bytes"
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use std::path::Path;
+
 pub const SEPARATORS: &[char] = &['\\', '/'];
 pub const WILDCARD_CHARACTERS: &[char] = &['*', '?'];
 
@@ -95,9 +97,37 @@ impl DeviceStoragePath {
     }
 }
 
+/// Appends the name to the path on the device or the local path.
+pub fn join_any_path(base_path: &str, name: &str) -> String {
+    match get_path_type(base_path) {
+        PathType::DeviceStorage => crate::finders::join_path(base_path, name),
+        _ => Path::new(base_path)
+            .join(name)
+            .to_string_lossy()
+            .to_string(),
+    }
+}
+
+/// Splits the path on the device or the local path into the parent path and the name.
+pub fn split_any_path(path: &str) -> Option<(String, String)> {
+    match get_path_type(path) {
+        PathType::DeviceStorage => {
+            let storage_path = DeviceStoragePath::from(path).ok()?;
+            let name = storage_path.file_name()?.to_string();
+            Some((storage_path.parent()?.full_path(), name))
+        }
+        PathType::Local => {
+            let path = Path::new(path);
+            let name = path.file_name()?.to_str()?.to_string();
+            Some((path.parent()?.to_str()?.to_string(), name))
+        }
+        PathType::Invalid => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::DeviceStoragePath;
+    use super::{join_any_path, split_any_path, DeviceStoragePath};
 
     #[test]
     fn test_invalid_format() {
@@ -193,4 +223,25 @@ mod tests {
             .is_none());
         assert!(DeviceStoragePath::from("a:b:/").unwrap().parent().is_none());
     }
+
+    #[test]
+    fn test_join_and_split_any_path() {
+        assert_eq!(join_any_path("a:b:", "c"), "a:b:\\c");
+        assert_eq!(join_any_path("a:b:\\c", "d"), "a:b:\\c\\d");
+        assert_eq!(
+            split_any_path("a:b:\\c\\d"),
+            Some(("a:b:\\c".to_string(), "d".to_string()))
+        );
+        assert_eq!(
+            split_any_path("a:b:\\c"),
+            Some(("a:b:\\".to_string(), "c".to_string()))
+        );
+        assert_eq!(split_any_path("a:b:\\"), None);
+
+        let local = join_any_path("dir", "file");
+        assert_eq!(
+            split_any_path(&local),
+            Some(("dir".to_string(), "file".to_string()))
+        );
+    }
 }
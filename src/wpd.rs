@@ -1,4 +1,5 @@
 pub mod device;
+pub mod event;
 pub mod guids;
 pub mod manager;
 pub mod property_keys;
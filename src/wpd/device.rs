@@ -1,8 +1,8 @@
 use bindings::Windows::Win32::Devices::PortableDevices::{
-    IEnumPortableDeviceObjectIDs, IPortableDevice, IPortableDeviceContent,
-    IPortableDeviceKeyCollection, IPortableDevicePropVariantCollection, IPortableDeviceProperties,
-    IPortableDeviceResources, IPortableDeviceValues, PortableDevice, PortableDeviceKeyCollection,
-    PortableDevicePropVariantCollection, PortableDeviceValues,
+    IEnumPortableDeviceObjectIDs, IPortableDevice, IPortableDeviceCapabilities,
+    IPortableDeviceContent, IPortableDeviceKeyCollection, IPortableDevicePropVariantCollection,
+    IPortableDeviceProperties, IPortableDeviceResources, IPortableDeviceValues, PortableDevice,
+    PortableDeviceKeyCollection, PortableDevicePropVariantCollection, PortableDeviceValues,
     PORTABLE_DEVICE_DELETE_WITH_RECURSION,
 };
 use bindings::Windows::Win32::Foundation::{BOOL, S_OK};
@@ -13,111 +13,31 @@ use bindings::Windows::Win32::System::PropertiesSystem::PROPERTYKEY;
 use chrono::format::strftime::StrftimeItems;
 use chrono::format::Parsed;
 use chrono::naive::NaiveDateTime;
-use std::{fmt::Debug, sync::Once};
+use std::cell::OnceCell;
+use std::sync::Once;
 use windows::Error;
 use windows::Guid;
 
+use crate::backend::content_object::{ContentObject, ContentObjectInfo, ContentObjectKind};
+use crate::backend::device::EventSubscription;
+use crate::backend::manager::DeviceInfo;
+use crate::backend::resource;
+use crate::finders::device_find_storage_objects;
+
+use super::event::Subscription;
 use super::guids::*;
-use super::manager::DeviceInfo;
 use super::property_keys::*;
 use super::resource_stream::{ResourceReader, ResourceWriter};
 use super::utils::*;
 
-pub struct ContentObject {
-    pub id: IDStr,
-}
-
-impl ContentObject {
-    pub fn new(id: IDStr) -> ContentObject {
-        ContentObject { id }
-    }
-}
-
-impl Clone for ContentObject {
-    fn clone(&self) -> Self {
-        ContentObject {
-            id: self.id.clone(),
-        }
-    }
-}
-
-impl Debug for ContentObject {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("ContentObject")
-            .field("id", &self.id)
-            .finish()
-    }
-}
-
-#[derive(Debug)]
-pub struct ContentObjectInfo {
-    pub content_object: ContentObject,
-    /// Name to display
-    pub name: String,
-    /// Content type GUID
-    content_type: Guid,
-    /// Category GUID of the functional object.
-    /// Zeroes if the object was not a functional object.
-    functional_object_category: Guid,
-    /// Size of the resource data
-    pub data_size: u64,
-    /// Hidden flag
-    pub is_hidden: bool,
-    /// System flag
-    pub is_system: bool,
-    /// Whether the object can be deleted
-    pub can_delete: bool,
-    /// Time created (or None if not provided)
-    pub time_created: Option<NaiveDateTime>,
-    /// Time modified (or None if not provided)
-    pub time_modified: Option<NaiveDateTime>,
-}
-
-impl Clone for ContentObjectInfo {
-    fn clone(&self) -> Self {
-        ContentObjectInfo {
-            content_object: self.content_object.clone(),
-            name: self.name.clone(),
-            content_type: self.content_type.clone(),
-            functional_object_category: self.functional_object_category.clone(),
-            data_size: self.data_size,
-            is_hidden: self.is_hidden,
-            is_system: self.is_system,
-            can_delete: self.can_delete,
-            time_created: self.time_created.clone(),
-            time_modified: self.time_modified.clone(),
-        }
-    }
-}
-
-impl ContentObjectInfo {
-    pub fn is_functional_object(&self) -> bool {
-        self.content_type == WPD_CONTENT_TYPE_FUNCTIONAL_OBJECT
-    }
-
-    pub fn is_device(&self) -> bool {
-        self.functional_object_category == WPD_FUNCTIONAL_CATEGORY_DEVICE
-    }
-
-    pub fn is_storage(&self) -> bool {
-        self.functional_object_category == WPD_FUNCTIONAL_CATEGORY_STORAGE
-    }
-
-    pub fn is_folder(&self) -> bool {
-        self.content_type == WPD_CONTENT_TYPE_FOLDER
-    }
-
-    pub fn is_file(&self) -> bool {
-        !self.is_functional_object() && !self.is_folder()
-    }
-}
-
 pub struct Device {
     device: IPortableDevice,
     content: IPortableDeviceContent,
     properties: IPortableDeviceProperties,
     resources: IPortableDeviceResources,
     pub name: String,
+    /// Commands supported by the device, which are read when they are needed first
+    supported_commands: OnceCell<Vec<PROPERTYKEY>>,
 }
 
 impl Device {
@@ -127,7 +47,9 @@ impl Device {
         let device: IPortableDevice = windows::create_instance(&PortableDevice)?;
         let values: IPortableDeviceValues = windows::create_instance(&PortableDeviceValues)?;
         unsafe {
-            device.Open(info.id.clone().as_pwstr(), values).ok()?;
+            device
+                .Open(IDStr::create_from_str(&info.id).as_pwstr(), values)
+                .ok()?;
         }
 
         let mut content_receptor: Option<IPortableDeviceContent> = None;
@@ -154,23 +76,175 @@ impl Device {
             properties,
             resources,
             name: info.name.clone(),
+            supported_commands: OnceCell::new(),
         })
     }
 
-    pub fn get_root_object(&self) -> ContentObject {
-        ContentObject::new(IDStr::create_empty())
+    #[allow(dead_code)]
+    pub fn get_resource_keys(&self, object: &ContentObject) -> Result<Vec<PROPERTYKEY>, Error> {
+        let mut key_collection_receptor: Option<IPortableDeviceKeyCollection> = None;
+        unsafe {
+            self.resources
+                .GetSupportedResources(
+                    IDStr::create_from_str(&object.id).as_pwstr(),
+                    &mut key_collection_receptor,
+                )
+                .ok()?;
+        }
+        let key_collection = key_collection_receptor.unwrap();
+
+        let mut count = 0u32;
+        unsafe {
+            key_collection.GetCount(&mut count).ok()?;
+        }
+
+        let mut property_keys = Vec::<PROPERTYKEY>::new();
+        for i in 0..count as u32 {
+            let mut propkey = make_empty_propertykey();
+            unsafe {
+                key_collection.GetAt(i, &mut propkey).ok()?;
+            }
+            property_keys.push(propkey);
+        }
+
+        Ok(property_keys)
+    }
+}
+
+impl Device {
+    fn get_supported_commands(&self) -> Result<Vec<PROPERTYKEY>, Error> {
+        let mut capabilities_receptor: Option<IPortableDeviceCapabilities> = None;
+        unsafe {
+            self.device.Capabilities(&mut capabilities_receptor).ok()?;
+        }
+        let capabilities = capabilities_receptor.unwrap();
+
+        let mut key_collection_receptor: Option<IPortableDeviceKeyCollection> = None;
+        unsafe {
+            capabilities
+                .GetSupportedCommands(&mut key_collection_receptor)
+                .ok()?;
+        }
+        let key_collection = key_collection_receptor.unwrap();
+
+        let mut count = 0u32;
+        unsafe {
+            key_collection.GetCount(&mut count).ok()?;
+        }
+
+        let mut commands = Vec::<PROPERTYKEY>::new();
+        for i in 0..count {
+            let mut propkey = make_empty_propertykey();
+            unsafe {
+                key_collection.GetAt(i, &mut propkey).ok()?;
+            }
+            commands.push(propkey);
+        }
+        Ok(commands)
+    }
+
+    /// Checks whether the device supports the command.
+    fn supports_command(&self, command: &PROPERTYKEY) -> bool {
+        self.supported_commands
+            .get_or_init(|| match self.get_supported_commands() {
+                Ok(commands) => commands,
+                Err(err) => {
+                    log::debug!("failed to get the supported commands: {}", err);
+                    Vec::new()
+                }
+            })
+            .contains(command)
+    }
+
+    /// Checks whether the property of the object can be changed.
+    fn can_write_property(&self, object: &ContentObject, key: &PROPERTYKEY) -> bool {
+        let mut attributes_receptor: Option<IPortableDeviceValues> = None;
+        let mut can_write = BOOL::from(false);
+        unsafe {
+            if self
+                .properties
+                .GetPropertyAttributes(
+                    IDStr::create_from_str(&object.id).as_pwstr(),
+                    key,
+                    &mut attributes_receptor,
+                )
+                .is_err()
+            {
+                return false;
+            }
+            if let Some(attributes) = attributes_receptor {
+                let _ = attributes.GetBoolValue(&WPD_PROPERTY_ATTRIBUTE_CAN_WRITE, &mut can_write);
+            }
+        }
+        can_write.as_bool()
+    }
+
+    /// Finds the object copied or moved into the folder.
+    /// The ID of the new object is not returned by WPD.
+    fn find_child(
+        &self,
+        parent: &ContentObject,
+        name: &str,
+    ) -> Result<ContentObject, Box<dyn std::error::Error>> {
+        use crate::backend::device::Device;
+
+        let mut iter = self.get_object_iterator(parent)?;
+        while let Some(object) = iter.next()? {
+            if self.get_object_info(object.clone())?.name == name {
+                return Ok(object);
+            }
+        }
+        Err(format!("copied object was not found: {}", name).into())
+    }
+}
+
+/// Creates the collection which contains the object ID.
+fn create_object_id_collection(
+    object: &ContentObject,
+) -> Result<IPortableDevicePropVariantCollection, Error> {
+    let collection: IPortableDevicePropVariantCollection =
+        windows::create_instance(&PortableDevicePropVariantCollection)?;
+    let mut object_id = IDStr::create_from_str(&object.id);
+    let propvar = PROPVARIANT {
+        Anonymous: PROPVARIANT_0 {
+            Anonymous: PROPVARIANT_0_0_abi {
+                vt: 31, // VT_LPWSTR
+                wReserved1: 0,
+                wReserved2: 0,
+                wReserved3: 0,
+                Anonymous: PROPVARIANT_0_0_0_abi {
+                    pwszVal: object_id.as_pwstr(),
+                },
+            },
+        },
+    };
+    // the value is copied into the collection
+    unsafe {
+        collection.Add(&propvar).ok()?;
+    }
+    Ok(collection)
+}
+
+impl crate::backend::device::Device for Device {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn get_root_object(&self) -> ContentObject {
+        ContentObject::new(String::new())
     }
 
-    pub fn get_object_iterator(
+    fn get_object_iterator(
         &self,
         parent: &ContentObject,
-    ) -> Result<ContentObjectIterator, Error> {
+    ) -> Result<Box<dyn crate::backend::device::ContentObjectIterator>, Box<dyn std::error::Error>>
+    {
         let mut enum_object_ids_receptor: Option<IEnumPortableDeviceObjectIDs> = None;
         unsafe {
             self.content
                 .EnumObjects(
                     0,
-                    parent.id.clone().as_pwstr(),
+                    IDStr::create_from_str(&parent.id).as_pwstr(),
                     None,
                     &mut enum_object_ids_receptor,
                 )
@@ -178,10 +252,13 @@ impl Device {
         }
         let enum_object_ids = enum_object_ids_receptor.unwrap();
 
-        Ok(ContentObjectIterator::new(enum_object_ids))
+        Ok(Box::new(ContentObjectIterator::new(enum_object_ids)))
     }
 
-    pub fn get_object_info(&self, object: ContentObject) -> Result<ContentObjectInfo, Error> {
+    fn get_object_info(
+        &self,
+        object: ContentObject,
+    ) -> Result<ContentObjectInfo, Box<dyn std::error::Error>> {
         let key_collection: IPortableDeviceKeyCollection =
             windows::create_instance(&PortableDeviceKeyCollection)?;
         unsafe {
@@ -201,7 +278,7 @@ impl Device {
         unsafe {
             self.properties
                 .GetValues(
-                    object.id.clone().as_pwstr(),
+                    IDStr::create_from_str(&object.id).as_pwstr(),
                     Some(key_collection),
                     &mut values_receptor,
                 )
@@ -319,11 +396,24 @@ impl Device {
 
         let name = object_orig_name.unwrap_or(object_name);
 
+        let kind = if content_type == WPD_CONTENT_TYPE_FUNCTIONAL_OBJECT {
+            if functional_object_category == WPD_FUNCTIONAL_CATEGORY_DEVICE {
+                ContentObjectKind::Device
+            } else if functional_object_category == WPD_FUNCTIONAL_CATEGORY_STORAGE {
+                ContentObjectKind::Storage
+            } else {
+                ContentObjectKind::FunctionalObject
+            }
+        } else if content_type == WPD_CONTENT_TYPE_FOLDER {
+            ContentObjectKind::Folder
+        } else {
+            ContentObjectKind::File
+        };
+
         Ok(ContentObjectInfo {
             content_object: object,
             name,
-            content_type,
-            functional_object_category,
+            kind,
             data_size,
             is_hidden,
             is_system,
@@ -333,41 +423,17 @@ impl Device {
         })
     }
 
-    #[allow(dead_code)]
-    pub fn get_resource_keys(&self, object: &ContentObject) -> Result<Vec<PROPERTYKEY>, Error> {
-        let mut key_collection_receptor: Option<IPortableDeviceKeyCollection> = None;
-        unsafe {
-            self.resources
-                .GetSupportedResources(object.id.clone().as_pwstr(), &mut key_collection_receptor)
-                .ok()?;
-        }
-        let key_collection = key_collection_receptor.unwrap();
-
-        let mut count = 0u32;
-        unsafe {
-            key_collection.GetCount(&mut count).ok()?;
-        }
-
-        let mut property_keys = Vec::<PROPERTYKEY>::new();
-        for i in 0..count as u32 {
-            let mut propkey = make_empty_propertykey();
-            unsafe {
-                key_collection.GetAt(i, &mut propkey).ok()?;
-            }
-            property_keys.push(propkey);
-        }
-
-        Ok(property_keys)
-    }
-
-    pub fn get_resoure(&self, object: &ContentObject) -> Result<ResourceReader, Error> {
+    fn get_resoure(
+        &self,
+        object: &ContentObject,
+    ) -> Result<Box<dyn resource::ResourceReader>, Box<dyn std::error::Error>> {
         const STGM_READ: u32 = 0;
         let mut buff_size: u32 = 0;
         let mut stream_receptor: Option<IStream> = None;
         unsafe {
             self.resources
                 .GetStream(
-                    object.id.clone().as_pwstr(),
+                    IDStr::create_from_str(&object.id).as_pwstr(),
                     &WPD_RESOURCE_DEFAULT,
                     STGM_READ,
                     &mut buff_size,
@@ -376,22 +442,25 @@ impl Device {
                 .ok()?;
         }
         let stream = stream_receptor.unwrap();
-        Ok(ResourceReader::new(stream, buff_size))
+        Ok(Box::new(ResourceReader::new(stream, buff_size)))
     }
 
-    pub fn create_file(
+    fn create_file(
         &self,
         parent: &ContentObject,
         name: &str,
         size: u64,
         created: &Option<NaiveDateTime>,
         modified: &Option<NaiveDateTime>,
-    ) -> Result<ResourceWriter, Error> {
+    ) -> Result<Box<dyn resource::ResourceWriter>, Box<dyn std::error::Error>> {
         let values: IPortableDeviceValues = windows::create_instance(&PortableDeviceValues)?;
         let mut name_buf = WStrBuf::from(name, true);
         unsafe {
             values
-                .SetStringValue(&WPD_OBJECT_PARENT_ID, parent.id.clone().as_pwstr())
+                .SetStringValue(
+                    &WPD_OBJECT_PARENT_ID,
+                    IDStr::create_from_str(&parent.id).as_pwstr(),
+                )
                 .ok()?;
             values
                 .SetStringValue(&WPD_OBJECT_NAME, name_buf.as_pwstr())
@@ -444,19 +513,22 @@ impl Device {
 
         let stream = stream_receptor.unwrap();
 
-        Ok(ResourceWriter::new(stream, buffer_size))
+        Ok(Box::new(ResourceWriter::new(stream, buffer_size)))
     }
 
-    pub fn create_folder(
+    fn create_folder(
         &self,
         parent: &ContentObject,
         name: &str,
-    ) -> Result<ContentObject, Error> {
+    ) -> Result<ContentObject, Box<dyn std::error::Error>> {
         let values: IPortableDeviceValues = windows::create_instance(&PortableDeviceValues)?;
         let mut name_buf = WStrBuf::from(name, true);
         unsafe {
             values
-                .SetStringValue(&WPD_OBJECT_PARENT_ID, parent.id.clone().as_pwstr())
+                .SetStringValue(
+                    &WPD_OBJECT_PARENT_ID,
+                    IDStr::create_from_str(&parent.id).as_pwstr(),
+                )
                 .ok()?;
             values
                 .SetStringValue(&WPD_OBJECT_NAME, name_buf.as_pwstr())
@@ -475,29 +547,14 @@ impl Device {
                 .CreateObjectWithPropertiesOnly(Some(values), object_id.as_pwstr_mut_ptr())
                 .ok()?;
         }
-        let content_object = ContentObject::new(object_id.to_idstr());
+        let content_object = ContentObject::new(object_id.to_string());
 
         Ok(content_object)
     }
 
-    pub fn delete(&self, object: &ContentObject) -> Result<(), Error> {
-        let collection: IPortableDevicePropVariantCollection =
-            windows::create_instance(&PortableDevicePropVariantCollection)?;
-        let propvar = PROPVARIANT {
-            Anonymous: PROPVARIANT_0 {
-                Anonymous: PROPVARIANT_0_0_abi {
-                    vt: 31, // VT_LPWSTR
-                    wReserved1: 0,
-                    wReserved2: 0,
-                    wReserved3: 0,
-                    Anonymous: PROPVARIANT_0_0_0_abi {
-                        pwszVal: object.id.clone().as_pwstr(),
-                    },
-                },
-            },
-        };
+    fn delete(&self, object: &ContentObject) -> Result<(), Box<dyn std::error::Error>> {
+        let collection = create_object_id_collection(object)?;
         unsafe {
-            collection.Add(&propvar).ok()?;
             self.content
                 .Delete(
                     PORTABLE_DEVICE_DELETE_WITH_RECURSION.0 as u32,
@@ -508,6 +565,124 @@ impl Device {
         }
         Ok(())
     }
+
+    fn can_rename(&self, object: &ContentObject) -> bool {
+        // both names are changed by renaming
+        [&WPD_OBJECT_NAME, &WPD_OBJECT_ORIGINAL_FILE_NAME]
+            .iter()
+            .all(|key| self.can_write_property(object, key))
+    }
+
+    fn rename(
+        &self,
+        object: &ContentObject,
+        name: &str,
+    ) -> Result<ContentObject, Box<dyn std::error::Error>> {
+        let values: IPortableDeviceValues = windows::create_instance(&PortableDeviceValues)?;
+        let mut name_buf = WStrBuf::from(name, true);
+        unsafe {
+            values
+                .SetStringValue(&WPD_OBJECT_NAME, name_buf.as_pwstr())
+                .ok()?;
+            values
+                .SetStringValue(&WPD_OBJECT_ORIGINAL_FILE_NAME, name_buf.as_pwstr())
+                .ok()?;
+        }
+
+        let mut results_receptor: Option<IPortableDeviceValues> = None;
+        let err;
+        unsafe {
+            err = self.properties.SetValues(
+                IDStr::create_from_str(&object.id).as_pwstr(),
+                Some(values),
+                &mut results_receptor,
+            );
+        }
+        err.ok()?;
+        // S_FALSE is returned if some of the values were not set
+        if err != S_OK {
+            return Err(format!("cannot rename \"{}\"", &object.id).into());
+        }
+        // the result of each value is returned as VT_ERROR
+        if let Some(results) = results_receptor {
+            for key in [&WPD_OBJECT_NAME, &WPD_OBJECT_ORIGINAL_FILE_NAME].iter() {
+                let mut result = S_OK;
+                unsafe {
+                    results.GetErrorValue(*key, &mut result).ok()?;
+                }
+                result.ok()?;
+            }
+        }
+
+        // some devices accept the values without renaming the object
+        let new_name = self.get_object_info(object.clone())?.name;
+        if new_name != name {
+            return Err(format!(
+                "cannot rename \"{}\": the name remains \"{}\"",
+                &object.id, &new_name
+            )
+            .into());
+        }
+        // the object ID is not changed by renaming
+        Ok(object.clone())
+    }
+
+    fn can_copy_on_device(&self, _object: &ContentObject) -> bool {
+        self.supports_command(&WPD_COMMAND_OBJECT_MANAGEMENT_COPY_OBJECTS)
+    }
+
+    fn can_move_on_device(&self, _object: &ContentObject) -> bool {
+        self.supports_command(&WPD_COMMAND_OBJECT_MANAGEMENT_MOVE_OBJECTS)
+    }
+
+    fn copy_object(
+        &self,
+        object: &ContentObject,
+        parent: &ContentObject,
+    ) -> Result<ContentObject, Box<dyn std::error::Error>> {
+        let name = self.get_object_info(object.clone())?.name;
+        let collection = create_object_id_collection(object)?;
+        let mut results_receptor: Option<IPortableDevicePropVariantCollection> = None;
+        unsafe {
+            self.content
+                .Copy(
+                    &collection,
+                    IDStr::create_from_str(&parent.id).as_pwstr(),
+                    &mut results_receptor,
+                )
+                .ok()?;
+        }
+        self.find_child(parent, &name)
+    }
+
+    fn move_object(
+        &self,
+        object: &ContentObject,
+        parent: &ContentObject,
+    ) -> Result<ContentObject, Box<dyn std::error::Error>> {
+        let name = self.get_object_info(object.clone())?.name;
+        let collection = create_object_id_collection(object)?;
+        let mut results_receptor: Option<IPortableDevicePropVariantCollection> = None;
+        unsafe {
+            self.content
+                .Move(
+                    &collection,
+                    IDStr::create_from_str(&parent.id).as_pwstr(),
+                    &mut results_receptor,
+                )
+                .ok()?;
+        }
+        self.find_child(parent, &name)
+    }
+
+    fn subscribe_events(&self) -> Result<Box<dyn EventSubscription>, Box<dyn std::error::Error>> {
+        let storages = device_find_storage_objects(self, None)?
+            .into_iter()
+            .map(|storage| storage.content_object.id)
+            .collect();
+        let subscription = Subscription::register(&self.device, &self.properties, storages)?;
+        Ok(Box::new(subscription))
+    }
 }
 
 impl Drop for Device {
@@ -533,11 +708,13 @@ impl ContentObjectIterator {
             completed: false,
         }
     }
+}
 
-    pub fn next(&mut self) -> Result<Option<ContentObject>, Error> {
+impl crate::backend::device::ContentObjectIterator for ContentObjectIterator {
+    fn next(&mut self) -> Result<Option<ContentObject>, Box<dyn std::error::Error>> {
         if let Some(object_ids_ref) = self.object_ids.as_mut() {
             if let Some(id) = object_ids_ref.pop() {
-                return Ok(Some(ContentObject::new(id)));
+                return Ok(Some(ContentObject::new(id.to_string_lossy())));
             }
         }
 
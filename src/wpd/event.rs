@@ -0,0 +1,273 @@
+use bindings::Windows::Win32::Devices::PortableDevices::{
+    IPortableDevice, IPortableDeviceEventCallback, IPortableDeviceEventCallback_abi,
+    IPortableDeviceKeyCollection, IPortableDeviceProperties, IPortableDeviceValues,
+    PortableDeviceKeyCollection,
+};
+use bindings::Windows::Win32::Foundation::S_OK;
+use std::collections::HashSet;
+use std::mem::ManuallyDrop;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::time::Duration;
+use windows::{Abi, Guid, Interface, RawPtr, HRESULT};
+
+use crate::backend::content_object::ContentObject;
+use crate::backend::device::{DeviceEvent, EventSubscription};
+
+use super::guids::*;
+use super::property_keys::*;
+use super::utils::*;
+
+const E_NOINTERFACE: HRESULT = HRESULT(0x8000_4002);
+
+/// Parameters of the event notified by the device
+struct EventParameters {
+    event_id: Guid,
+    object_id: Option<String>,
+    parent_id: Option<String>,
+}
+
+/// Implementation of IPortableDeviceEventCallback.
+///
+/// The events are notified on the thread of the WPD API,
+/// so they are passed to the subscription through the channel.
+#[repr(C)]
+struct EventCallback {
+    vtable: *const IPortableDeviceEventCallback_abi,
+    ref_count: AtomicU32,
+    sender: Mutex<Sender<EventParameters>>,
+}
+
+static EVENT_CALLBACK_VTABLE: IPortableDeviceEventCallback_abi = IPortableDeviceEventCallback_abi(
+    event_callback_query_interface,
+    event_callback_add_ref,
+    event_callback_release,
+    event_callback_on_event,
+);
+
+impl EventCallback {
+    fn create(sender: Sender<EventParameters>) -> IPortableDeviceEventCallback {
+        let callback = Box::new(EventCallback {
+            vtable: &EVENT_CALLBACK_VTABLE,
+            ref_count: AtomicU32::new(1),
+            sender: Mutex::new(sender),
+        });
+        // the reference is released by the drop of IPortableDeviceEventCallback
+        unsafe { std::mem::transmute(Box::into_raw(callback)) }
+    }
+}
+
+unsafe extern "system" fn event_callback_query_interface(
+    this: RawPtr,
+    iid: &Guid,
+    interface: *mut RawPtr,
+) -> HRESULT {
+    if *iid == windows::IUnknown::IID || *iid == IPortableDeviceEventCallback::IID {
+        event_callback_add_ref(this);
+        *interface = this;
+        S_OK
+    } else {
+        *interface = std::ptr::null_mut();
+        E_NOINTERFACE
+    }
+}
+
+unsafe extern "system" fn event_callback_add_ref(this: RawPtr) -> u32 {
+    let callback = &*(this as *const EventCallback);
+    callback.ref_count.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+unsafe extern "system" fn event_callback_release(this: RawPtr) -> u32 {
+    let callback = &*(this as *const EventCallback);
+    let count = callback.ref_count.fetch_sub(1, Ordering::Release) - 1;
+    if count == 0 {
+        std::sync::atomic::fence(Ordering::Acquire);
+        drop(Box::from_raw(this as *mut EventCallback));
+    }
+    count
+}
+
+unsafe extern "system" fn event_callback_on_event(
+    this: RawPtr,
+    event_parameters: RawPtr,
+) -> HRESULT {
+    let callback = &*(this as *const EventCallback);
+    // the parameters are borrowed from the caller
+    let values = match IPortableDeviceValues::from_abi(event_parameters) {
+        Ok(values) => ManuallyDrop::new(values),
+        Err(_) => return S_OK,
+    };
+    if let Some(parameters) = read_event_parameters(&values) {
+        if let Ok(sender) = callback.sender.lock() {
+            let _ = sender.send(parameters);
+        }
+    }
+    S_OK
+}
+
+fn read_event_parameters(values: &IPortableDeviceValues) -> Option<EventParameters> {
+    let mut event_id = Guid::zeroed();
+    unsafe {
+        values
+            .GetGuidValue(&WPD_EVENT_PARAMETER_EVENT_ID, &mut event_id)
+            .ok()
+            .ok()?;
+    }
+
+    let mut object_id: Option<String> = None;
+    let mut object_id_ptr = WStrPtr::create();
+    unsafe {
+        let _ = values
+            .GetStringValue(&WPD_OBJECT_ID, object_id_ptr.as_pwstr_mut_ptr())
+            .and_then(|| object_id = Some(object_id_ptr.to_string()));
+    }
+
+    let mut parent_id: Option<String> = None;
+    let mut parent_id_ptr = WStrPtr::create();
+    unsafe {
+        let _ = values
+            .GetStringValue(&WPD_OBJECT_PARENT_ID, parent_id_ptr.as_pwstr_mut_ptr())
+            .and_then(|| parent_id = Some(parent_id_ptr.to_string()));
+    }
+
+    Some(EventParameters {
+        event_id,
+        object_id,
+        parent_id,
+    })
+}
+
+/// Event subscription registered by IPortableDevice::Advise().
+pub struct Subscription {
+    device: IPortableDevice,
+    properties: IPortableDeviceProperties,
+    cookie: IDStr,
+    receiver: Receiver<EventParameters>,
+    /// IDs of the storage objects
+    storages: HashSet<String>,
+    removed: bool,
+}
+
+impl Subscription {
+    pub fn register(
+        device: &IPortableDevice,
+        properties: &IPortableDeviceProperties,
+        storages: HashSet<String>,
+    ) -> Result<Subscription, windows::Error> {
+        let (sender, receiver) = channel::<EventParameters>();
+        let callback = EventCallback::create(sender);
+        let mut cookie_ptr = WStrPtr::create();
+        unsafe {
+            device
+                .Advise(0, &callback, None, cookie_ptr.as_pwstr_mut_ptr())
+                .ok()?;
+        }
+        let cookie = IDStr::from(unsafe { *cookie_ptr.as_pwstr_mut_ptr() });
+        Ok(Subscription {
+            device: device.clone(),
+            properties: properties.clone(),
+            cookie,
+            receiver,
+            storages,
+            removed: false,
+        })
+    }
+
+    fn is_storage(&self, object_id: &str) -> bool {
+        let key_collection: IPortableDeviceKeyCollection =
+            match windows::create_instance(&PortableDeviceKeyCollection) {
+                Ok(key_collection) => key_collection,
+                Err(_) => return false,
+            };
+        let mut values_receptor: Option<IPortableDeviceValues> = None;
+        unsafe {
+            if key_collection.Add(&WPD_FUNCTIONAL_OBJECT_CATEGORY).is_err()
+                || self
+                    .properties
+                    .GetValues(
+                        IDStr::create_from_str(object_id).as_pwstr(),
+                        Some(key_collection),
+                        &mut values_receptor,
+                    )
+                    .is_err()
+            {
+                return false;
+            }
+        }
+        let values = match values_receptor {
+            Some(values) => values,
+            None => return false,
+        };
+        let mut category = Guid::zeroed();
+        unsafe {
+            if values
+                .GetGuidValue(&WPD_FUNCTIONAL_OBJECT_CATEGORY, &mut category)
+                .is_err()
+            {
+                return false;
+            }
+        }
+        category == WPD_FUNCTIONAL_CATEGORY_STORAGE
+    }
+
+    fn convert_event(&mut self, parameters: EventParameters) -> Option<DeviceEvent> {
+        let event_id = parameters.event_id;
+        if event_id == WPD_EVENT_DEVICE_REMOVED {
+            self.removed = true;
+            return Some(DeviceEvent::DeviceRemoved);
+        }
+
+        let object_id = parameters.object_id?;
+        if event_id == WPD_EVENT_OBJECT_ADDED {
+            if self.is_storage(&object_id) {
+                self.storages.insert(object_id.clone());
+                Some(DeviceEvent::StorageAdded(ContentObject::new(object_id)))
+            } else {
+                Some(DeviceEvent::ObjectAdded {
+                    object: ContentObject::new(object_id),
+                    parent: parameters.parent_id.map(ContentObject::new),
+                })
+            }
+        } else if event_id == WPD_EVENT_OBJECT_REMOVED {
+            if self.storages.remove(&object_id) {
+                Some(DeviceEvent::StorageRemoved(ContentObject::new(object_id)))
+            } else {
+                Some(DeviceEvent::ObjectRemoved(ContentObject::new(object_id)))
+            }
+        } else if event_id == WPD_EVENT_OBJECT_UPDATED {
+            Some(DeviceEvent::ObjectInfoChanged(ContentObject::new(
+                object_id,
+            )))
+        } else {
+            log::debug!("ignored event: {:?}", event_id);
+            None
+        }
+    }
+}
+
+impl EventSubscription for Subscription {
+    fn next_event(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Option<DeviceEvent>, Box<dyn std::error::Error>> {
+        if self.removed {
+            return Err("the device was removed.".into());
+        }
+        match self.receiver.recv_timeout(timeout) {
+            Ok(parameters) => Ok(self.convert_event(parameters)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err("event callback was released.".into()),
+        }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        unsafe {
+            if let Err(err) = self.device.Unadvise(self.cookie.as_pwstr()).ok() {
+                log::debug!("failed to unregister the event callback: {}", err);
+            }
+        }
+    }
+}
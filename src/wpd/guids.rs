@@ -21,6 +21,34 @@ pub static WPD_CONTENT_TYPE_GENERIC_FILE: Guid = Guid::from_values(
     [0xBC, 0x5C, 0x44, 0x7E, 0x59, 0xC7, 0x3D, 0x48],
 );
 
+pub static WPD_EVENT_DEVICE_REMOVED: Guid = Guid::from_values(
+    0xE4CBCA1B,
+    0x6918,
+    0x48B9,
+    [0x85, 0xEE, 0x02, 0xBE, 0x7C, 0x85, 0x0A, 0xF9],
+);
+
+pub static WPD_EVENT_OBJECT_ADDED: Guid = Guid::from_values(
+    0xA726DA95,
+    0xE207,
+    0x4B02,
+    [0x8D, 0x44, 0xBE, 0xF2, 0xE8, 0x6C, 0xBF, 0xFC],
+);
+
+pub static WPD_EVENT_OBJECT_REMOVED: Guid = Guid::from_values(
+    0xBE82AB88,
+    0xA52C,
+    0x4823,
+    [0x96, 0xE5, 0xD0, 0x27, 0x26, 0x71, 0xFC, 0x38],
+);
+
+pub static WPD_EVENT_OBJECT_UPDATED: Guid = Guid::from_values(
+    0x1445A759,
+    0x2E01,
+    0x485D,
+    [0x9F, 0x27, 0xFF, 0x07, 0xDA, 0xE6, 0x97, 0xAB],
+);
+
 pub static WPD_FUNCTIONAL_CATEGORY_DEVICE: Guid = Guid::from_values(
     0x08EA466B,
     0xE3A4,
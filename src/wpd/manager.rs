@@ -1,30 +1,31 @@
-use std::fmt::Debug;
-
 use bindings::Windows::Win32::Devices::PortableDevices::{
     IPortableDeviceManager, PortableDeviceManager,
 };
 use bindings::Windows::Win32::Foundation::PWSTR;
 use windows::Error;
 
+use crate::backend;
+use crate::backend::manager::DeviceInfo;
+
+use super::device::Device;
 use super::utils::*;
 
 pub struct Manager {
     manager: IPortableDeviceManager,
 }
 
-#[derive(Debug)]
-pub struct DeviceInfo {
-    pub id: IDStr,
-    pub name: String,
-}
-
 impl Manager {
     pub fn get_portable_device_manager() -> Result<Manager, Error> {
         let manager: IPortableDeviceManager = windows::create_instance(&PortableDeviceManager)?;
         Ok(Manager { manager })
     }
+}
 
-    pub fn get_device_iterator<'a>(&'a self) -> Result<DeviceInfoIterator<'a>, Error> {
+impl backend::manager::Manager for Manager {
+    fn get_device_iterator(
+        &self,
+    ) -> Result<Box<dyn backend::manager::DeviceInfoIterator + '_>, Box<dyn std::error::Error>>
+    {
         // get number of devices
         let mut device_id_count = 0u32;
         unsafe {
@@ -41,10 +42,17 @@ impl Manager {
                 .ok()?;
         }
 
-        Ok(DeviceInfoIterator::new(
+        Ok(Box::new(DeviceInfoIterator::new(
             &self.manager,
             device_ids.to_vec_all(),
-        ))
+        )))
+    }
+
+    fn open_device(
+        &self,
+        info: &DeviceInfo,
+    ) -> Result<Box<dyn backend::device::Device>, Box<dyn std::error::Error>> {
+        Ok(Box::new(Device::open(info)?))
     }
 }
 
@@ -64,8 +72,10 @@ impl<'a> DeviceInfoIterator<'a> {
             device_ids,
         }
     }
+}
 
-    pub fn next(&mut self) -> Result<Option<DeviceInfo>, Error> {
+impl<'a> backend::manager::DeviceInfoIterator for DeviceInfoIterator<'a> {
+    fn next(&mut self) -> Result<Option<DeviceInfo>, Box<dyn std::error::Error>> {
         let mut device_id = match self.device_ids.pop() {
             Some(id) => id,
             None => return Ok(None),
@@ -98,7 +108,7 @@ impl<'a> DeviceInfoIterator<'a> {
         let name = name_buf.to_string(name_buf_len - 1); // exclude null terminator
 
         Ok(Some(DeviceInfo {
-            id: device_id,
+            id: device_id.to_string_lossy(),
             name,
         }))
     }
@@ -1,6 +1,7 @@
 use bindings::Windows::Win32::System::PropertiesSystem::PROPERTYKEY;
 use windows::Guid;
 
+#[allow(clippy::too_many_arguments)]
 const fn define_propertykey(
     l: u32,
     w1: u16,
@@ -21,6 +22,18 @@ const fn define_propertykey(
     }
 }
 
+pub static WPD_COMMAND_OBJECT_MANAGEMENT_MOVE_OBJECTS: PROPERTYKEY = define_propertykey(
+    0xEF1E43DD, 0xA9ED, 0x4341, 0x8B, 0xCC, 0x18, 0x61, 0x92, 0xAE, 0xA0, 0x89, 8,
+);
+
+pub static WPD_COMMAND_OBJECT_MANAGEMENT_COPY_OBJECTS: PROPERTYKEY = define_propertykey(
+    0xEF1E43DD, 0xA9ED, 0x4341, 0x8B, 0xCC, 0x18, 0x61, 0x92, 0xAE, 0xA0, 0x89, 9,
+);
+
+pub static WPD_EVENT_PARAMETER_EVENT_ID: PROPERTYKEY = define_propertykey(
+    0x15AB1953, 0xF817, 0x4FEF, 0xA9, 0x21, 0x56, 0x76, 0xE8, 0x38, 0xF6, 0xE0, 3,
+);
+
 pub static WPD_FUNCTIONAL_OBJECT_CATEGORY: PROPERTYKEY = define_propertykey(
     0x8F052D93, 0xABCA, 0x4FC5, 0xA5, 0xAC, 0xB0, 0x1D, 0xF4, 0xDB, 0xE5, 0x98, 2,
 );
@@ -45,6 +58,10 @@ pub static WPD_OBJECT_FORMAT: PROPERTYKEY = define_propertykey(
     0xEF6B490D, 0x5CD8, 0x437A, 0xAF, 0xFC, 0xDA, 0x8B, 0x60, 0xEE, 0x4A, 0x3C, 6,
 );
 
+pub static WPD_OBJECT_ID: PROPERTYKEY = define_propertykey(
+    0xEF6B490D, 0x5CD8, 0x437A, 0xAF, 0xFC, 0xDA, 0x8B, 0x60, 0xEE, 0x4A, 0x3C, 2,
+);
+
 pub static WPD_OBJECT_ISHIDDEN: PROPERTYKEY = define_propertykey(
     0xEF6B490D, 0x5CD8, 0x437A, 0xAF, 0xFC, 0xDA, 0x8B, 0x60, 0xEE, 0x4A, 0x3C, 9,
 );
@@ -69,6 +86,10 @@ pub static WPD_OBJECT_SIZE: PROPERTYKEY = define_propertykey(
     0xEF6B490D, 0x5CD8, 0x437A, 0xAF, 0xFC, 0xDA, 0x8B, 0x60, 0xEE, 0x4A, 0x3C, 11,
 );
 
+pub static WPD_PROPERTY_ATTRIBUTE_CAN_WRITE: PROPERTYKEY = define_propertykey(
+    0xAB7943D8, 0x6332, 0x445F, 0xA0, 0x0D, 0x8D, 0x5E, 0xF1, 0xE9, 0x6F, 0x37, 4,
+);
+
 pub static WPD_RESOURCE_DEFAULT: PROPERTYKEY = define_propertykey(
     0xE81E79BE, 0x34F0, 0x41BF, 0xB5, 0x3F, 0xF1, 0xA0, 0x6A, 0xE8, 0x78, 0x42, 0,
 );
@@ -1,9 +1,11 @@
-use bindings::Windows::Win32::Storage::StructuredStorage::{IStream, STGC_DEFAULT};
 use bindings::Windows::Win32::Devices::PortableDevices::IPortableDeviceDataStream;
-use windows::Error;
+use bindings::Windows::Win32::Storage::StructuredStorage::{IStream, STGC_DEFAULT};
 use windows::Interface;
 
-use super::{device::ContentObject, utils::WStrPtr};
+use crate::backend::content_object::ContentObject;
+use crate::backend::resource;
+
+use super::utils::WStrPtr;
 
 pub struct ResourceReader {
     stream: IStream,
@@ -12,12 +14,17 @@ pub struct ResourceReader {
 
 impl ResourceReader {
     pub fn new(stream: IStream, buff_size: u32) -> ResourceReader {
-        let mut buffer = Vec::<u8>::with_capacity(buff_size as usize);
-        buffer.resize(buff_size as usize, 0);
+        let buffer = vec![0; buff_size as usize];
         ResourceReader { stream, buffer }
     }
+}
 
-    pub fn next(&mut self, max_size: u32) -> Result<Option<&[u8]>, Error> {
+impl resource::ResourceReader for ResourceReader {
+    fn get_optimized_buffer_size(&self) -> u32 {
+        self.buffer.len() as u32
+    }
+
+    fn next(&mut self, max_size: u32) -> Result<Option<&[u8]>, Box<dyn std::error::Error>> {
         let available_buffer_size = std::cmp::min(self.buffer.len() as u32, max_size);
         let mut read: u32 = 0;
         unsafe {
@@ -35,10 +42,6 @@ impl ResourceReader {
             Ok(Some(&self.buffer[..read as usize]))
         }
     }
-
-    pub fn get_optimized_buffer_size(&self) -> u32 {
-        self.buffer.len() as u32
-    }
 }
 
 pub struct ResourceWriter {
@@ -55,16 +58,18 @@ impl ResourceWriter {
             committed: false,
         }
     }
+}
 
-    pub fn get_buffer_size(&self) -> u32 {
+impl resource::ResourceWriter for ResourceWriter {
+    fn get_buffer_size(&self) -> u32 {
         self.buff_size
     }
 
-    pub fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+    fn write(&mut self, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
         let data_len = data.len() as u32;
         let mut data_offset: u32 = 0;
         while data_offset < data_len {
-            let write_len = std::cmp::min(data_len - data_offset, self.buff_size as u32);
+            let write_len = std::cmp::min(data_len - data_offset, self.buff_size);
             let mut written: u32 = 0;
             unsafe {
                 self.stream
@@ -80,7 +85,7 @@ impl ResourceWriter {
         Ok(())
     }
 
-    pub fn commit(&mut self) -> Result<ContentObject, Error> {
+    fn commit(&mut self) -> Result<ContentObject, Box<dyn std::error::Error>> {
         self.committed = true;
         unsafe {
             self.stream.Commit(STGC_DEFAULT.0 as u32).ok()?;
@@ -92,6 +97,6 @@ impl ResourceWriter {
         unsafe {
             data_stream.GetObjectID(object_id.as_pwstr_mut_ptr()).ok()?;
         }
-        Ok(ContentObject::new(object_id.to_idstr()))
+        Ok(ContentObject::new(object_id.to_string()))
     }
 }
@@ -10,12 +10,6 @@ pub struct IDStr {
 }
 
 impl IDStr {
-    pub fn create_empty() -> IDStr {
-        let mut vec = Vec::<WChar>::with_capacity(1);
-        vec.extend([0].iter());
-        IDStr { vec }
-    }
-
     pub fn from(p: PWSTR) -> IDStr {
         let len = get_wstr_length(p);
         let vec: Vec<WChar>;
@@ -25,10 +19,20 @@ impl IDStr {
         IDStr { vec }
     }
 
+    pub fn create_from_str(s: &str) -> IDStr {
+        let mut vec: Vec<WChar> = s.encode_utf16().collect();
+        vec.push(0); // terminator
+        IDStr { vec }
+    }
+
     pub fn as_pwstr(&mut self) -> PWSTR {
         PWSTR(self.vec.as_mut_ptr())
     }
 
+    pub fn to_string_lossy(&self) -> String {
+        String::from_utf16_lossy(&self.vec[..self.vec.len() - 1]) // exclude null terminator
+    }
+
     // TODO: need as_pcwstr(&self) -> PCWSTR
 }
 
@@ -69,14 +73,13 @@ impl WStrPtr {
     pub fn as_pwstr_mut_ptr(&mut self) -> *mut PWSTR {
         &mut self.ptr
     }
+}
 
-    pub fn to_idstr(&self) -> IDStr {
-        IDStr::from(self.ptr)
-    }
-
-    pub fn to_string(&self) -> String {
+impl std::fmt::Display for WStrPtr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let len = get_wstr_length(self.ptr);
-        unsafe { String::from_utf16_lossy(std::slice::from_raw_parts(self.ptr.0, len)) }
+        let s = unsafe { String::from_utf16_lossy(std::slice::from_raw_parts(self.ptr.0, len)) };
+        f.write_str(&s)
     }
 }
 
@@ -138,8 +141,7 @@ pub struct WStrBuf {
 
 impl WStrBuf {
     pub fn create(size: u32) -> WStrBuf {
-        let mut buf = Vec::<WChar>::new();
-        buf.resize(size as usize, 0);
+        let buf = vec![0; size as usize];
         WStrBuf { buf }
     }
 